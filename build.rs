@@ -0,0 +1,12 @@
+fn main() {
+    // Generate the gRPC service code for the messages in the proto module
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/simplepir.proto");
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        tonic_build::configure()
+            .extern_path(".simplepir", "crate::proto")
+            .compile_protos(&["proto/simplepir.proto"], &["proto"])
+            .unwrap();
+    }
+}
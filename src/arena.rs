@@ -0,0 +1,76 @@
+use alloc::vec::Vec;
+use core::mem;
+
+/// Scratch memory for the temporaries of answering a query, such as the query reduced to plain
+/// words. Each call to alloc() hands out zeroed slices carved from one block, which is reused
+/// from query to query instead of being allocated and freed each time. A query needing more than
+/// the capacity grows the block for its duration, and reset() shrinks it back, so the capacity
+/// bounds the memory held between queries.
+#[derive(Debug, Default)]
+pub struct Arena {
+    words: Vec<u64>,
+    capacity: usize,
+}
+
+impl Arena {
+    /// An arena holding on to up to capacity words between queries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { words: Vec::with_capacity(capacity), capacity }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Carve zeroed slices of the given lengths from the arena, invalidating any handed out
+    /// before.
+    pub fn alloc<const N: usize>(&mut self, lens: [usize; N]) -> [&mut [u64]; N] {
+        let total = lens.iter().sum();
+        if self.words.len() < total {
+            self.words.resize(total, 0);
+        }
+        let mut rest = &mut self.words[..total];
+        rest.fill(0);
+        lens.map(|len| {
+            let (slice, tail) = mem::take(&mut rest).split_at_mut(len);
+            rest = tail;
+            slice
+        })
+    }
+
+    /// Release the memory a large query grew the arena by.
+    pub fn reset(&mut self) {
+        if self.words.len() > self.capacity {
+            self.words.truncate(self.capacity);
+            self.words.shrink_to(self.capacity);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arena() {
+        let mut arena = Arena::with_capacity(16);
+        let [a, b] = arena.alloc([3, 5]);
+        assert_eq!((a.len(), b.len()), (3, 5));
+        a.fill(7);
+        b.fill(9);
+
+        // Slices are zeroed when handed out again, and reuse the same block
+        let ptr = arena.words.as_ptr();
+        let [c] = arena.alloc([8]);
+        assert_eq!(c, &[0; 8]);
+        assert_eq!(arena.words.as_ptr(), ptr);
+
+        // A large query grows the arena only until it is reset
+        let [d] = arena.alloc([100]);
+        assert_eq!(d.len(), 100);
+        arena.reset();
+        assert!(arena.words.capacity() <= 16);
+        let [e] = arena.alloc([16]);
+        assert_eq!(e.len(), 16);
+    }
+}
@@ -0,0 +1,149 @@
+//! An audit log of query volume which only ever releases differentially private counts.
+//!
+//! The log holds one count per epoch of the queries answered in it, and nothing about the queries
+//! themselves. A count is released once its epoch has ended, with two-sided geometric noise of
+//! parameter exp(-epsilon) added: one more or one fewer query changes the probability of any
+//! released value by a factor of at most e^epsilon, so the counts give epsilon-differential
+//! privacy per epoch for each query. Every ended epoch is released, including those without
+//! queries, as skipping them would reveal that their count was zero, and no epoch is released
+//! twice, as averaging repeated releases would remove the noise.
+
+use std::collections::BTreeMap;
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use rand_distr::{Distribution, Geometric};
+
+use crate::error::SimplePirError;
+
+/// A query count released by a QueryLog, with noise added.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoisyCount {
+    pub epoch: u64,
+    /// The noised count, which may be negative for epochs with few queries.
+    pub count: i64,
+}
+
+/// The number of queries answered in each epoch, released with noise once each epoch ends.
+#[derive(Debug, Clone)]
+pub struct QueryLog {
+    epsilon: f64,
+    // The first epoch not yet released
+    next_epoch: u64,
+    // The true counts of the epochs not yet released, by epoch
+    counts: BTreeMap<u64, u64>,
+}
+
+impl QueryLog {
+    /// A log of the queries answered from the given epoch on, released with privacy budget
+    /// epsilon per epoch. Smaller budgets give more privacy and noisier counts.
+    pub fn new(epsilon: f64, epoch: u64) -> Result<Self, SimplePirError> {
+        if !(epsilon.is_finite() && epsilon > 0.0) {
+            return Err(SimplePirError::InvalidEpsilon(epsilon));
+        }
+        Ok(Self { epsilon, next_epoch: epoch, counts: BTreeMap::new() })
+    }
+
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Count queries answered in the given epoch. Queries of epochs already released are
+    /// dropped, since their counts cannot change.
+    pub fn record(&mut self, epoch: u64, queries: u64) {
+        if epoch >= self.next_epoch {
+            *self.counts.entry(epoch).or_default() += queries;
+        }
+    }
+
+    /// Release the noised counts of the epochs which ended before the current one and were not
+    /// released yet, in order of epoch.
+    pub fn release(&mut self, current_epoch: u64) -> Vec<NoisyCount> {
+        self.release_with_rng(current_epoch, &mut StdRng::from_entropy())
+    }
+
+    /// Like release(), but using the given RNG.
+    pub fn release_with_rng<R: RngCore>(
+        &mut self,
+        current_epoch: u64,
+        rng: &mut R,
+    ) -> Vec<NoisyCount> {
+        let mut released = vec![];
+        for epoch in self.next_epoch..current_epoch {
+            let count = self.counts.remove(&epoch).unwrap_or(0);
+            let noise = geometric_noise_with_rng(self.epsilon, rng);
+            released.push(NoisyCount { epoch, count: (count as i64).saturating_add(noise) });
+        }
+        self.next_epoch = self.next_epoch.max(current_epoch);
+        released
+    }
+}
+
+/// Sample noise k from the two-sided geometric distribution, with probability proportional to
+/// exp(-epsilon |k|), as the difference of two geometric samples. This is the discrete Laplace
+/// mechanism for counts with sensitivity 1.
+pub fn geometric_noise_with_rng<R: RngCore>(epsilon: f64, rng: &mut R) -> i64 {
+    assert!(epsilon.is_finite() && epsilon > 0.0);
+    let geometric = Geometric::new(1.0 - (-epsilon).exp()).unwrap();
+    let pos = geometric.sample(rng).min(i64::MAX as u64) as i64;
+    let neg = geometric.sample(rng).min(i64::MAX as u64) as i64;
+    pos - neg
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_geometric_noise() {
+        let mut rng = ChaCha20Rng::from_seed([3; 32]);
+        let epsilon = 0.5;
+        let samples: Vec<i64> =
+            (0..20000).map(|_| geometric_noise_with_rng(epsilon, &mut rng)).collect();
+
+        // The variance is 2 alpha / (1 - alpha)^2 for alpha = exp(-epsilon)
+        let alpha = (-epsilon).exp();
+        let variance = 2.0 * alpha / ((1.0 - alpha) * (1.0 - alpha));
+        let mean = samples.iter().sum::<i64>() as f64 / samples.len() as f64;
+        let sample_variance =
+            samples.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+        assert!(mean.abs() < 0.2);
+        assert!((sample_variance / variance - 1.0).abs() < 0.1);
+
+        // P(0) / P(1) = e^epsilon
+        let zeros = samples.iter().filter(|&&x| x == 0).count() as f64;
+        let ones = samples.iter().filter(|&&x| x == 1).count() as f64;
+        assert!((zeros / ones / epsilon.exp() - 1.0).abs() < 0.15);
+    }
+
+    #[test]
+    fn test_query_log() {
+        assert_eq!(QueryLog::new(0.0, 0).unwrap_err(), SimplePirError::InvalidEpsilon(0.0));
+        assert!(QueryLog::new(f64::NAN, 0).is_err());
+
+        let mut rng = ChaCha20Rng::from_seed([5; 32]);
+        let mut log = QueryLog::new(10.0, 2).unwrap();
+        log.record(2, 100);
+        log.record(2, 50);
+        log.record(4, 7);
+        assert_eq!(log.release_with_rng(2, &mut rng), vec![]);
+
+        // Every ended epoch is released once, the empty one included
+        let released = log.release_with_rng(5, &mut rng);
+        let epochs: Vec<u64> = released.iter().map(|count| count.epoch).collect();
+        assert_eq!(epochs, vec![2, 3, 4]);
+        for (count, expected) in released.iter().zip([150, 0, 7]) {
+            assert!((count.count - expected).abs() <= 2);
+        }
+        assert_eq!(log.release_with_rng(5, &mut rng), vec![]);
+
+        // Late queries of released epochs are dropped
+        log.record(4, 1000);
+        log.record(5, 3);
+        let released = log.release_with_rng(6, &mut rng);
+        assert_eq!(released.len(), 1);
+        assert!((released[0].count - 3).abs() <= 2);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::hash::mix;
+use crate::pir::{Answer, Client, Query, Server};
+use crate::simplepir::{self, Hint, SimplePIRParams};
+
+// The number of candidate buckets for each item
+const NUM_HASHES: usize = 3;
+
+// The number of evictions after which cuckoo placement gives up
+const MAX_EVICTIONS: usize = 500;
+
+/// The public assignment of database items to buckets. Every item is stored in each of its
+/// NUM_HASHES candidate buckets, so a batch of k distinct indices can be spread over distinct
+/// buckets and each query only scans one bucket, i.e. roughly 3N / 1.5k items.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BucketLayout {
+    pub num_items: usize,
+    pub num_buckets: usize,
+    pub seed: u64,
+    // The sorted item indices stored in each bucket
+    buckets: Vec<Vec<usize>>,
+}
+
+impl BucketLayout {
+    /// Create a layout of ⌈1.5 * batch_size⌉ buckets for num_items items.
+    pub fn new(num_items: usize, batch_size: usize, seed: u64) -> Result<Self, SimplePirError> {
+        if batch_size == 0 {
+            return Err(SimplePirError::Empty("batch"));
+        }
+        let num_buckets = (3 * batch_size).div_ceil(2);
+        let mut layout = Self { num_items, num_buckets, seed, buckets: vec![] };
+
+        let mut buckets = vec![vec![]; num_buckets];
+        for idx in 0..num_items {
+            let mut candidates = layout.candidates(idx).to_vec();
+            candidates.sort();
+            candidates.dedup();
+            for b in candidates {
+                buckets[b].push(idx);
+            }
+        }
+        layout.buckets = buckets;
+        Ok(layout)
+    }
+
+    /// The buckets in which item idx is stored.
+    pub fn candidates(&self, idx: usize) -> [usize; NUM_HASHES] {
+        let mut candidates = [0; NUM_HASHES];
+        for (j, c) in candidates.iter_mut().enumerate() {
+            let h = mix(self.seed ^ mix(((j as u64) << 56) ^ idx as u64));
+            *c = (h % self.num_buckets as u64) as usize;
+        }
+        candidates
+    }
+
+    /// The item indices stored in bucket b, in the order they appear in its database.
+    pub fn bucket(&self, b: usize) -> &[usize] {
+        &self.buckets[b]
+    }
+
+    /// The position of item idx within bucket b, or None if the bucket does not store it.
+    pub fn position_in_bucket(&self, b: usize, idx: usize) -> Option<usize> {
+        self.buckets.get(b)?.binary_search(&idx).ok()
+    }
+
+    /// Assign each of the distinct indices to one of its candidate buckets such that no bucket
+    /// holds more than one index, using cuckoo hashing. Returns the index assigned to each bucket,
+    /// or UnplaceableBatch if no assignment was found within MAX_EVICTIONS evictions.
+    pub fn place(&self, idxs: &[usize]) -> Result<Vec<Option<usize>>, SimplePirError> {
+        let mut slots: Vec<Option<usize>> = vec![None; self.num_buckets];
+
+        for &idx in idxs {
+            if idx >= self.num_items {
+                return Err(SimplePirError::IndexOutOfRange { idx, len: self.num_items });
+            }
+            if slots.contains(&Some(idx)) {
+                continue;
+            }
+
+            let mut current = idx;
+            let mut choice = 0;
+            let mut placed = false;
+            for _ in 0..MAX_EVICTIONS {
+                let candidates = self.candidates(current);
+                if let Some(&b) = candidates.iter().find(|&&b| slots[b].is_none()) {
+                    slots[b] = Some(current);
+                    placed = true;
+                    break;
+                }
+
+                // Every candidate is taken: evict an occupant and re-place it, cycling through the
+                // candidates so that the same item is not evicted straight back
+                let b = candidates[choice % NUM_HASHES];
+                current = slots[b].replace(current).unwrap();
+                choice = self.candidates(current).iter().position(|&c| c == b).unwrap() + 1;
+            }
+            if !placed {
+                return Err(SimplePirError::UnplaceableBatch);
+            }
+        }
+        Ok(slots)
+    }
+}
+
+/// A SimplePIR server per bucket.
+pub struct BatchServer {
+    layout: BucketLayout,
+    servers: Vec<Server>,
+}
+
+impl BatchServer {
+    /// Set up one SimplePIR database per bucket, each holding the items assigned to it, with
+    /// params of DEFAULT_SECURITY bits of security.
+    pub fn setup(
+        items: &[Element],
+        batch_size: usize,
+        p: u64,
+        seed: u64,
+    ) -> Result<Self, SimplePirError> {
+        let layout = BucketLayout::new(items.len(), batch_size, seed)?;
+        let servers = (0..layout.num_buckets).map(|b| {
+            let bucket_items: Vec<Element> = layout.bucket(b).iter()
+                .map(|&idx| items[idx].clone())
+                .collect();
+            let params = simplepir::gen_params_for_size(bucket_items.len(), p)?;
+            Server::setup(params, &bucket_items)
+        }).collect::<Result<_, SimplePirError>>()?;
+        Ok(Self { layout, servers })
+    }
+
+    pub fn layout(&self) -> &BucketLayout {
+        &self.layout
+    }
+
+    /// The params and hint of every bucket, which clients must download before querying.
+    pub fn bucket_hints(&self) -> Vec<(SimplePIRParams, Hint)> {
+        self.servers.iter().map(|s| (s.params().clone(), s.hint().clone())).collect()
+    }
+
+    /// Answer one query per bucket.
+    pub fn answer(&self, queries: &[Query]) -> Result<Vec<Answer>, SimplePirError> {
+        if queries.len() != self.servers.len() {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.servers.len(),
+                actual: queries.len(),
+            });
+        }
+        self.servers.iter().zip(queries).map(|(server, query)| server.answer(query)).collect()
+    }
+}
+
+/// The client side of cuckoo-hashed batch PIR.
+pub struct BatchClient {
+    layout: BucketLayout,
+    clients: Vec<Client>,
+    pending: Vec<usize>,
+    pending_slots: Vec<Option<usize>>,
+}
+
+impl BatchClient {
+    /// Create a client from the layout and the params and hint of each bucket's server.
+    pub fn new(
+        layout: BucketLayout,
+        bucket_hints: Vec<(SimplePIRParams, Hint)>,
+    ) -> Result<Self, SimplePirError> {
+        if bucket_hints.len() != layout.num_buckets {
+            return Err(SimplePirError::LengthMismatch {
+                expected: layout.num_buckets,
+                actual: bucket_hints.len(),
+            });
+        }
+        let clients = bucket_hints.into_iter()
+            .map(|(params, hint)| Client::new(params, hint))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { layout, clients, pending: vec![], pending_slots: vec![] })
+    }
+
+    /// Generate one query per bucket for the given indices. Buckets which were not assigned an
+    /// index receive a dummy query so that the server cannot tell which buckets are in use.
+    /// Returns UnplaceableBatch if the indices could not be placed, in which case the batch should
+    /// be split.
+    pub fn query(&mut self, idxs: &[usize]) -> Result<Vec<Query>, SimplePirError> {
+        let slots = self.layout.place(idxs)?;
+        let queries = slots.iter().enumerate().map(|(b, slot)| {
+            let pos = match slot {
+                Some(idx) => self.layout.position_in_bucket(b, *idx).ok_or(
+                    SimplePirError::IndexOutOfRange { idx: *idx, len: self.layout.num_items },
+                )?,
+                None => 0,
+            };
+            self.clients[b].query(pos)
+        }).collect::<Result<_, _>>()?;
+
+        self.pending = idxs.to_vec();
+        self.pending_slots = slots;
+        Ok(queries)
+    }
+
+    /// Recover the items of the outstanding batch, in the order they were queried.
+    pub fn recover(&mut self, answers: &[Answer]) -> Result<Vec<Element>, SimplePirError> {
+        if answers.len() != self.layout.num_buckets {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.layout.num_buckets,
+                actual: answers.len(),
+            });
+        }
+        let slots = std::mem::take(&mut self.pending_slots);
+        let idxs = std::mem::take(&mut self.pending);
+
+        let recovered = self.clients.iter_mut().zip(answers)
+            .map(|(client, answer)| client.recover(answer))
+            .collect::<Result<Vec<Element>, _>>()?;
+
+        Ok(idxs.iter().map(|idx| {
+            let b = slots.iter().position(|slot| *slot == Some(*idx)).unwrap();
+            recovered[b].clone()
+        }).collect())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let layout = BucketLayout::new(100, 8, 1).unwrap();
+        assert_eq!(layout.num_buckets, 12);
+        for idx in 0..100 {
+            for b in layout.candidates(idx) {
+                assert!(b < layout.num_buckets);
+                assert_eq!(layout.bucket(b)[layout.position_in_bucket(b, idx).unwrap()], idx);
+            }
+        }
+        assert_eq!(layout, BucketLayout::new(100, 8, 1).unwrap());
+        assert_eq!(layout.position_in_bucket(12, 0), None);
+        assert_eq!(BucketLayout::new(100, 0, 1), Err(SimplePirError::Empty("batch")));
+    }
+
+    #[test]
+    fn test_place() {
+        let layout = BucketLayout::new(1000, 16, 7).unwrap();
+        let idxs: Vec<usize> = (0..16).map(|i| i * 61).collect();
+        let slots = layout.place(&idxs).unwrap();
+
+        for idx in idxs {
+            let b = slots.iter().position(|slot| *slot == Some(idx)).unwrap();
+            assert!(layout.candidates(idx).contains(&b));
+        }
+        assert_eq!(slots.iter().filter(|slot| slot.is_some()).count(), 16);
+
+        assert_eq!(
+            layout.place(&[1, 1000]),
+            Err(SimplePirError::IndexOutOfRange { idx: 1000, len: 1000 }),
+        );
+        // More distinct indices than buckets can never be placed
+        let idxs: Vec<usize> = (0..25).collect();
+        assert_eq!(layout.place(&idxs), Err(SimplePirError::UnplaceableBatch));
+    }
+
+    #[test]
+    fn test_batch_pir() {
+        let db_size = 64;
+        let items: Vec<Element> = (0..db_size).map(|_| Element::gen_uniform_rand(2)).collect();
+        let server = BatchServer::setup(&items, 4, 2, 42).unwrap();
+        let mut client = BatchClient::new(server.layout().clone(), server.bucket_hints()).unwrap();
+
+        for idxs in [[0, 1, 2, 3], [63, 10, 10, 20], [5, 50, 33, 12]] {
+            let queries = client.query(&idxs).unwrap();
+            assert_eq!(queries.len(), server.layout().num_buckets);
+
+            let answers = server.answer(&queries).unwrap();
+            let recovered = client.recover(&answers).unwrap();
+            for (i, idx) in idxs.iter().enumerate() {
+                assert_eq!(recovered[i], items[*idx]);
+            }
+        }
+
+        assert_eq!(
+            client.query(&[64]).err(),
+            Some(SimplePirError::IndexOutOfRange { idx: 64, len: 64 }),
+        );
+    }
+}
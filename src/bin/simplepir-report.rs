@@ -0,0 +1,102 @@
+//! Sweep a grid of parameter presets and database sizes, and print a report of the security,
+//! failure probability, message sizes and throughput of each. See the report module.
+//!
+//! Usage: simplepir-report [--presets <name,...>] [--records <n,...>] [--record-len <bytes>]
+//!     [--trials <n>] [--rounds <n>] [--format json|csv]
+
+use std::process::exit;
+
+use simplepir_rs::error::SimplePirError;
+use simplepir_rs::report::{self, Preset};
+
+const USAGE: &str = "usage: simplepir-report [--presets <name,...>] [--records <n,...>] \
+    [--record-len <bytes>] [--trials <n>] [--rounds <n>] [--format json|csv]";
+
+// The database sizes swept by default, in records
+const DEFAULT_RECORDS: [usize; 3] = [1 << 10, 1 << 14, 1 << 18];
+
+enum Format {
+    Json,
+    Csv,
+}
+
+struct Args {
+    presets: Vec<Preset>,
+    records: Vec<usize>,
+    record_len: usize,
+    trials: usize,
+    rounds: usize,
+    format: Format,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut presets = Preset::all();
+    let mut records = DEFAULT_RECORDS.to_vec();
+    let mut record_len = 32;
+    let mut trials = 10000;
+    let mut rounds = 5;
+    let mut format = Format::Json;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--presets" => {
+                presets = value()?
+                    .split(',')
+                    .map(|name| Preset::from_name(name.trim()).ok_or(format!("no preset {}", name)))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--records" => {
+                records = value()?
+                    .split(',')
+                    .map(|n| n.trim().parse().map_err(|_| format!("invalid size {}", n)))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--record-len" => record_len = value()?.parse().map_err(|_| "invalid record length")?,
+            "--trials" => trials = value()?.parse().map_err(|_| "invalid number of trials")?,
+            "--rounds" => rounds = value()?.parse().map_err(|_| "invalid number of rounds")?,
+            "--format" => {
+                format = match value()?.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    other => return Err(format!("unknown format {}", other)),
+                };
+            }
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    if record_len == 0 || records.contains(&0) {
+        return Err("record lengths and database sizes must be positive".to_string());
+    }
+    Ok(Args { presets, records, record_len, trials, rounds, format })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        exit(2);
+    });
+
+    let mut rows = vec![];
+    for &num_records in &args.records {
+        for &preset in &args.presets {
+            match report::measure(preset, num_records, args.record_len, args.trials, args.rounds) {
+                Ok(row) => rows.push(row),
+                // Presets only cover databases up to some size
+                Err(err @ SimplePirError::TooManySamples { .. }) => {
+                    eprintln!("skipping {} for {} records: {}", preset.name(), num_records, err);
+                }
+                Err(err) => {
+                    eprintln!("{} failed for {} records: {}", preset.name(), num_records, err);
+                    exit(1);
+                }
+            }
+        }
+    }
+    match args.format {
+        Format::Json => print!("{}", report::to_json(&rows)),
+        Format::Csv => print!("{}", report::to_csv(&rows)),
+    }
+}
@@ -0,0 +1,118 @@
+//! Serve a database file over HTTP. See the http module for the endpoints.
+//!
+//! Usage: simplepir-server <db-file> --record-len <bytes> [--addr <host:port>] [--security <bits>]
+
+use std::process::exit;
+use std::time::Duration;
+
+use simplepir_rs::{compression, http, simplepir};
+
+const USAGE: &str =
+    "usage: simplepir-server <db-file> --record-len <bytes> [--addr <host:port>] [--security <bits>]";
+
+// The retrievals run before serving, and the mean answer time they must stay within
+const SELF_TEST_ROUNDS: usize = 3;
+const SELF_TEST_MAX_ANSWER_TIME: Duration = Duration::from_secs(10);
+
+struct Args {
+    db_path: String,
+    record_len: usize,
+    addr: String,
+    security: u32,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut db_path = None;
+    let mut record_len = None;
+    let mut addr = "127.0.0.1:8080".to_string();
+    let mut security = simplepir::DEFAULT_SECURITY;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--record-len" => {
+                record_len = Some(value()?.parse().map_err(|_| "invalid record length")?);
+            }
+            "--addr" => addr = value()?,
+            "--security" => security = value()?.parse().map_err(|_| "invalid security level")?,
+            _ if db_path.is_none() && !arg.starts_with("--") => db_path = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    let record_len: usize = record_len.ok_or("--record-len is required")?;
+    if record_len == 0 {
+        return Err("the record length must be positive".to_string());
+    }
+    Ok(Args { db_path: db_path.ok_or("no database file given")?, record_len, addr, security })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        exit(2);
+    });
+
+    let db = std::fs::read(&args.db_path).unwrap_or_else(|err| {
+        eprintln!("cannot read {}: {}", args.db_path, err);
+        exit(1);
+    });
+    if db.is_empty() {
+        eprintln!("{} is empty", args.db_path);
+        exit(1);
+    }
+
+    let server = http::setup_server(&db, args.record_len, args.security).unwrap_or_else(|err| {
+        eprintln!("cannot set up the server: {}", err);
+        exit(1);
+    });
+    match server.self_test(SELF_TEST_ROUNDS, SELF_TEST_MAX_ANSWER_TIME) {
+        Ok(mean) => eprintln!("self-test passed, answering in {:?}", mean),
+        Err(err) => {
+            eprintln!("the self-test failed: {}", err);
+            exit(1);
+        }
+    }
+    let listener = tiny_http::Server::http(&args.addr).unwrap_or_else(|err| {
+        eprintln!("cannot listen on {}: {}", args.addr, err);
+        exit(1);
+    });
+    eprintln!("serving {} records on {}", server.layout().unwrap().num_records, args.addr);
+
+    let max_body_len = http::max_body_len(&server);
+    for mut request in listener.incoming_requests() {
+        // Bodies which declare a length over the limit are refused without reading them
+        let response = if request.body_length().is_some_and(|len| len > max_body_len) {
+            http::Response::error(413, "the body is too long")
+        } else {
+            let offered = request.headers().iter()
+                .find(|header| header.field.equiv(compression::ACCEPT_HEADER))
+                .map(|header| header.value.to_string());
+            match http::read_body(request.as_reader(), max_body_len) {
+                Ok(body) => {
+                    let (method, url) = (request.method().as_str(), request.url());
+                    match &offered {
+                        Some(offered) => {
+                            http::handle_compressed(&server, method, url, &body, offered)
+                        }
+                        None => http::handle(&server, method, url, &body),
+                    }
+                }
+                Err(response) => response,
+            }
+        };
+        // Streamed bodies are encoded as the socket accepts them, so a slow client holds back
+        // the encoding instead of making the server buffer it. Those of unknown length, such as
+        // compressed hints, are sent with chunked transfer encoding.
+        let len = response.body.len();
+        let response = tiny_http::Response::new(
+            tiny_http::StatusCode(response.status),
+            vec![],
+            response.body.into_reader(),
+            len,
+            None,
+        );
+        let _ = request.respond(response);
+    }
+}
@@ -0,0 +1,204 @@
+// Bit-packed storage for databases mod a small p.
+//
+// A Matrix spends 16 bytes on every value, although a database mod p only holds ⌈log2(p)⌉ bits
+// of information per value: 1 bit for p = 2 and 8 for p = 256. A BitPackedMatrix packs the
+// values of each column into u64 words, as many as fit whole, which cuts the memory of the
+// database (and the memory traffic of scanning it) by a factor of 16 to 128 for small p. The
+// answer loop reads the packed words directly, shifting each value out of its word.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+use crate::element::Element;
+use crate::matrix::Matrix;
+
+/// The number of bits a value mod p is stored in, i.e. ⌈log2(p)⌉.
+pub fn bits_per_value(p: u64) -> usize {
+    assert!(p >= 2);
+    (u64::BITS - (p - 1).leading_zeros()) as usize
+}
+
+/// A matrix mod p whose values are packed into the bits of u64 words, column by column. The
+/// layout matches Matrix: data[i] is column i.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitPackedMatrix {
+    p: u64,
+    num_rows: usize,
+    // The bits of each value
+    bits: usize,
+    // The packed words of each column, with value j in bits (j % per_word) * bits of word
+    // j / per_word
+    data: Vec<Vec<u64>>,
+}
+
+impl BitPackedMatrix {
+    /// Convert a matrix whose values are all mod p. Panics if p exceeds 2^32.
+    pub fn from_matrix(m: &Matrix, p: u64) -> Self {
+        assert!(p <= 1 << 32, "the modulus does not fit in 32 bits");
+        let bits = bits_per_value(p);
+        let per_word = 64 / bits;
+        assert!(m.has_modulus(p));
+        let data = m.cols().map(|col| {
+            let mut words = vec![0u64; m.num_rows().div_ceil(per_word)];
+            for (j, &v) in col.iter().enumerate() {
+                words[j / per_word] |= v << ((j % per_word) * bits);
+            }
+            words
+        }).collect();
+        Self { p, num_rows: m.num_rows(), bits, data }
+    }
+
+    pub fn to_matrix(&self) -> Matrix {
+        let data: Vec<Vec<Element>> = (0..self.num_cols()).map(|i| self.column(i)).collect();
+        Matrix::from(&data)
+    }
+
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+
+    pub fn num_cols(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.num_cols(), self.num_rows())
+    }
+
+    fn per_word(&self) -> usize {
+        64 / self.bits
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    /// Unpack column i.
+    pub fn column(&self, i: usize) -> Vec<Element> {
+        let (per_word, mask) = (self.per_word(), self.mask());
+        (0..self.num_rows).map(|j| {
+            let v = (self.data[i][j / per_word] >> ((j % per_word) * self.bits)) & mask;
+            Element::from(self.p, v)
+        }).collect()
+    }
+
+    pub fn set(&mut self, col: usize, row: usize, value: &Element) {
+        assert_eq!(value.q, self.p);
+        assert!(row < self.num_rows);
+        let (per_word, mask) = (self.per_word(), self.mask());
+        let shift = (row % per_word) * self.bits;
+        let word = &mut self.data[col][row / per_word];
+        *word = (*word & !(mask << shift)) | (value.uint << shift);
+    }
+
+    /// Multiply by a vector mod the vector's modulus, with the same output shape as
+    /// Matrix::mul_vec(). Values are reinterpreted mod that modulus, which must not be below p.
+    pub fn mul_vec(&self, rhs: &[Element]) -> Matrix {
+        self.mul_vec_cols(rhs, 0..self.num_cols())
+    }
+
+    /// Like mul_vec(), but only for the given range of columns.
+    pub fn mul_vec_cols(&self, rhs: &[Element], cols: Range<usize>) -> Matrix {
+        self.mul_batch(&[rhs.to_vec()], cols).remove(0)
+    }
+
+    /// Multiply by a vector of values mod q given as plain words, with the same result as
+    /// mul_vec() but without converting the vector or allocating per column.
+    pub fn mul_words(&self, rhs: &[u64], q: u64) -> Matrix {
+        assert_eq!(self.num_rows, rhs.len());
+        assert!(q >= self.p);
+        let (per_word, mask) = (self.per_word(), self.mask());
+        let data = self.data.iter().map(|col| {
+            // Each product is below 2^96, so fewer than 2^32 of them sum exactly in a u128
+            let mut sum = 0u128;
+            for (w, &word) in col.iter().enumerate() {
+                let rows = w * per_word..((w + 1) * per_word).min(self.num_rows);
+                for (k, j) in rows.enumerate() {
+                    sum += ((word >> (k * self.bits)) & mask) as u128 * rhs[j] as u128;
+                }
+            }
+            (sum % q as u128) as u64
+        }).collect();
+        Matrix::from_values(q, self.num_cols(), 1, data)
+    }
+
+    /// Multiply the given range of columns by each of a batch of vectors mod the same modulus,
+    /// reading each packed word once for the whole batch.
+    pub fn mul_batch(&self, rhs: &[Vec<Element>], cols: Range<usize>) -> Vec<Matrix> {
+        assert!(!rhs.is_empty() && !rhs[0].is_empty());
+        let q = rhs[0][0].q;
+        assert!(q >= self.p);
+        let rhs: Vec<Vec<u64>> = rhs.iter().map(|v| {
+            assert_eq!(self.num_rows, v.len());
+            v.iter().map(|e| {
+                assert_eq!(e.q, q);
+                e.uint
+            }).collect()
+        }).collect();
+
+        let (per_word, mask) = (self.per_word(), self.mask());
+        let mut results = vec![Vec::with_capacity(cols.len()); rhs.len()];
+        for col in &self.data[cols] {
+            // Each product is below 2^96, so fewer than 2^32 of them sum exactly in a u128
+            let mut sums = vec![0u128; rhs.len()];
+            for (w, &word) in col.iter().enumerate() {
+                let rows = w * per_word..((w + 1) * per_word).min(self.num_rows);
+                for (k, j) in rows.enumerate() {
+                    let v = ((word >> (k * self.bits)) & mask) as u128;
+                    if v != 0 {
+                        sums.iter_mut().zip(&rhs).for_each(|(sum, r)| *sum += v * r[j] as u128);
+                    }
+                }
+            }
+            for (result, sum) in results.iter_mut().zip(sums) {
+                result.push(vec![Element::from(q, (sum % q as u128) as u64)]);
+            }
+        }
+        results.iter().map(Matrix::from).collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitpacked_mul_vec() {
+        for p in [2, 3, 256, 991, 65537] {
+            let m = Matrix::gen_uniform_rand(p, 7, 300);
+            let vs: Vec<Vec<Element>> =
+                (0..3).map(|_| Matrix::gen_uniform_rand(4294967291, 1, 300).col(0)).collect();
+            let mut m_q = m.clone();
+            m_q.change_q(4294967291);
+
+            let packed = BitPackedMatrix::from_matrix(&m, p);
+            assert_eq!(packed.dimensions(), m.dimensions());
+            assert_eq!(packed.to_matrix(), m);
+            assert_eq!(packed.mul_vec(&vs[0]), m_q.clone().mul_vec(&vs[0]));
+            let words: Vec<u64> = vs[0].iter().map(|e| e.uint).collect();
+            assert_eq!(packed.mul_words(&words, 4294967291), packed.mul_vec(&vs[0]));
+            assert_eq!(m.mul_words(&words, 4294967291), packed.mul_vec(&vs[0]));
+            for (answer, v) in packed.mul_batch(&vs, 2..5).iter().zip(&vs) {
+                assert_eq!(*answer, m_q.clone().mul_vec(v).select_cols(2..5));
+            }
+        }
+        assert_eq!((bits_per_value(2), bits_per_value(256), bits_per_value(991)), (1, 8, 10));
+    }
+
+    #[test]
+    fn test_bitpacked_set() {
+        let p = 991;
+        let mut m = Matrix::gen_uniform_rand(p, 4, 10);
+        let mut packed = BitPackedMatrix::from_matrix(&m, p);
+        for (col, row, value) in [(1, 4, 7), (1, 4, 0), (3, 9, 990), (0, 5, 1), (0, 6, 990)] {
+            m.set(col, row, &Element::from(p, value));
+            packed.set(col, row, &Element::from(p, value));
+            assert_eq!(packed.to_matrix(), m);
+        }
+    }
+}
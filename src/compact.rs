@@ -0,0 +1,109 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::element::Element;
+use crate::matrix::Matrix;
+
+/// A matrix mod q ≤ 2^32 whose values are stored as u32s, using a quarter of the memory of a
+/// Matrix. The layout matches Matrix: data[i] is column i.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactMatrix {
+    q: u64,
+    data: Vec<Vec<u32>>,
+}
+
+impl CompactMatrix {
+    /// Convert a matrix whose values are all mod q. Panics if q exceeds 2^32.
+    pub fn from_matrix(m: &Matrix, q: u64) -> Self {
+        assert!(q <= 1 << 32, "the modulus does not fit in 32 bits");
+        assert!(m.has_modulus(q));
+        let data = m.cols().map(|col| col.iter().map(|&v| v as u32).collect()).collect();
+        Self { q, data }
+    }
+
+    pub fn to_matrix(&self) -> Matrix {
+        let data: Vec<Vec<Element>> = self.data.iter()
+            .map(|col| col.iter().map(|&v| Element::from(self.q, v as u64)).collect())
+            .collect();
+        Matrix::from(&data)
+    }
+
+    pub fn q(&self) -> u64 {
+        self.q
+    }
+
+    pub fn num_cols(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.data.first().map_or(0, |col| col.len())
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.num_cols(), self.num_rows())
+    }
+
+    /// Multiply by a vector mod the vector's modulus, with the same output shape as
+    /// Matrix::mul_vec(). Values are reinterpreted mod that modulus, which must not exceed 2^32.
+    pub fn mul_vec(&self, rhs: &[Element]) -> Matrix {
+        assert!(!rhs.is_empty());
+        assert_eq!(self.num_rows(), rhs.len());
+        let q = rhs[0].q;
+        assert!(q <= 1 << 32, "the modulus does not fit in 32 bits");
+
+        let rhs: Vec<u32> = rhs.iter().map(|e| {
+            assert_eq!(e.q, q);
+            e.uint as u32
+        }).collect();
+        let data: Vec<Vec<Element>> = self.data.iter()
+            .map(|col| vec![Element::from(q, dot_lazy(col, &rhs, q))])
+            .collect();
+        Matrix::from(&data)
+    }
+}
+
+// Compute sum(a[i] * b[i]) mod q, only reducing the u64 accumulator when an addition overflows.
+// Since every product is below 2^64, folding the lost 2^64 back in mod q keeps the sum exact.
+fn dot_lazy(a: &[u32], b: &[u32], q: u64) -> u64 {
+    let wrap = ((1u128 << 64) % q as u128) as u64;
+    let mut acc = 0u64;
+    for (&x, &y) in a.iter().zip(b) {
+        let (sum, overflowed) = acc.overflowing_add(x as u64 * y as u64);
+        acc = if overflowed { sum % q + wrap } else { sum };
+    }
+    acc % q
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_mul_vec() {
+        for q in [3329, 4294967291, 1 << 32] {
+            let m = Matrix::gen_uniform_rand(q, 7, 300);
+            let v = Matrix::gen_uniform_rand(q, 1, 300).col(0);
+            let compact = CompactMatrix::from_matrix(&m, q);
+            assert_eq!(compact.dimensions(), m.dimensions());
+            assert_eq!(compact.to_matrix(), m);
+
+            let expected: Vec<u64> = (0..7).map(|i| {
+                m.col_values(i).iter().zip(&v).fold(0u128, |acc, (&x, y)| {
+                    (acc + x as u128 * y.uint as u128) % q as u128
+                }) as u64
+            }).collect();
+            let result = compact.mul_vec(&v);
+            assert_eq!(result.dimensions(), (7, 1));
+            assert_eq!(result.values(), expected);
+        }
+    }
+
+    #[test]
+    fn test_dot_lazy_overflow() {
+        let q = 1 << 32;
+        let max = vec![u32::MAX; 5];
+        let expected = (5 * (u32::MAX as u128).pow(2) % q as u128) as u64;
+        assert_eq!(dot_lazy(&max, &max, q), expected);
+    }
+}
@@ -0,0 +1,294 @@
+//! Optional compression of hints and answers on the wire. The hint of a random database is spread
+//! over all of Z_q and does not compress, but a database with structure gives a hint which does:
+//! e.g. zero columns of the database (padding, or a sparse database) give zero hint columns.
+//!
+//! The codecs a build supports depend on the `zstd` and `lz4` features. The client offers the
+//! codecs it supports by name in its ACCEPT_HEADER header, and the server picks the first of its own which was offered with
+//! Codec::negotiate(), falling back to sending chunks as they are.
+//!
+//! A compressed message is a sequence of frames, each holding one chunk of at most FRAME_LEN
+//! bytes of the message: a codec byte, the length of the chunk and the length of the payload
+//! (u64 each) and the payload. Chunks are compressed on their own, so that a hint can be
+//! compressed as it is streamed. Chunks shorter than MIN_COMPRESS_LEN, chunks whose first
+//! SAMPLE_LEN bytes do not compress, and chunks which do not shrink to 7/8 of their length are
+//! sent as they are, with the identity codec.
+
+use std::io::{self, Read};
+
+use crate::wire::WireError;
+
+/// The header (or gRPC metadata key) in which a client lists the codecs it accepts.
+pub const ACCEPT_HEADER: &str = "simplepir-accept-compression";
+
+/// The bytes of a message held by each frame.
+pub const FRAME_LEN: usize = 1 << 20;
+
+/// Chunks shorter than this are never compressed, as the frame header outweighs any saving.
+pub const MIN_COMPRESS_LEN: usize = 512;
+
+// The prefix of a chunk compressed first, to skip compressing chunks of random-looking entries
+const SAMPLE_LEN: usize = 1 << 14;
+
+// The zstd level, which favours speed since hints are compressed as they are sent
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+/// A compression codec.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Codec {
+    Identity,
+    Lz4,
+    Zstd,
+}
+
+impl Codec {
+    /// The codecs of this build, most preferred first. Identity is always supported.
+    pub fn supported() -> Vec<Codec> {
+        let mut codecs = vec![];
+        if cfg!(feature = "zstd") {
+            codecs.push(Codec::Zstd);
+        }
+        if cfg!(feature = "lz4") {
+            codecs.push(Codec::Lz4);
+        }
+        codecs.push(Codec::Identity);
+        codecs
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Identity => "identity",
+            Codec::Lz4 => "lz4",
+            Codec::Zstd => "zstd",
+        }
+    }
+
+    /// The supported codec with the given name.
+    pub fn from_name(name: &str) -> Option<Codec> {
+        Self::supported().into_iter().find(|codec| codec.name() == name)
+    }
+
+    /// The names of the supported codecs separated by commas, for a client to offer.
+    pub fn offer() -> String {
+        Self::supported().iter().map(|codec| codec.name()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// The most preferred supported codec named in a list offered by the other party, which
+    /// separates names with commas.
+    pub fn negotiate(offered: &str) -> Codec {
+        let offered: Vec<&str> = offered.split(',').map(str::trim).collect();
+        Self::supported().into_iter()
+            .find(|codec| offered.contains(&codec.name()))
+            .unwrap_or(Codec::Identity)
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Codec::Identity => 0,
+            Codec::Lz4 => 1,
+            Codec::Zstd => 2,
+        }
+    }
+
+    // The supported codec with the given id
+    fn from_id(id: u8) -> Option<Codec> {
+        Self::supported().into_iter().find(|codec| codec.id() == id)
+    }
+}
+
+/// Compress a message with the codec, as frames of at most FRAME_LEN bytes each.
+pub fn compress(codec: Codec, message: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    for chunk in message.chunks(FRAME_LEN) {
+        write_frame(&mut out, codec, chunk);
+    }
+    out
+}
+
+// Append the frame of a chunk, compressed with the codec if it helps
+fn write_frame(out: &mut Vec<u8>, codec: Codec, chunk: &[u8]) {
+    let compressed = match worth_compressing(codec, chunk) {
+        true => compress_chunk(codec, chunk).filter(|payload| shrinks(payload.len(), chunk.len())),
+        false => None,
+    };
+    let (codec, payload) = match &compressed {
+        Some(payload) => (codec, payload.as_slice()),
+        None => (Codec::Identity, chunk),
+    };
+    out.push(codec.id());
+    out.extend_from_slice(&(chunk.len() as u64).to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+// Whether a chunk should be compressed at all, judged by its length and a sample of it
+fn worth_compressing(codec: Codec, chunk: &[u8]) -> bool {
+    if codec == Codec::Identity || chunk.len() < MIN_COMPRESS_LEN {
+        return false;
+    }
+    if chunk.len() <= SAMPLE_LEN {
+        return true;
+    }
+    let sample = &chunk[..SAMPLE_LEN];
+    compress_chunk(codec, sample).is_some_and(|payload| shrinks(payload.len(), sample.len()))
+}
+
+// Whether a payload is at most 7/8 of the length of the chunk it was compressed from
+fn shrinks(payload_len: usize, chunk_len: usize) -> bool {
+    payload_len * 8 <= chunk_len * 7
+}
+
+// Compress a chunk with the codec, or None for the identity codec
+#[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+fn compress_chunk(codec: Codec, chunk: &[u8]) -> Option<Vec<u8>> {
+    match codec {
+        Codec::Identity => None,
+        #[cfg(feature = "lz4")]
+        Codec::Lz4 => Some(lz4_flex::block::compress(chunk)),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => zstd::bulk::compress(chunk, ZSTD_LEVEL).ok(),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+// Decompress the payload of a frame holding a chunk of len bytes
+fn decompress_chunk(codec: Codec, payload: &[u8], len: usize) -> Result<Vec<u8>, WireError> {
+    let chunk = match codec {
+        Codec::Identity => Ok(payload.to_vec()),
+        #[cfg(feature = "lz4")]
+        Codec::Lz4 => lz4_flex::block::decompress(payload, len).map_err(|_| WireError::Corrupt),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => zstd::bulk::decompress(payload, len).map_err(|_| WireError::Corrupt),
+        #[allow(unreachable_patterns)]
+        _ => Err(WireError::UnsupportedCodec(codec.id())),
+    }?;
+    match chunk.len() == len {
+        true => Ok(chunk),
+        false => Err(WireError::Corrupt),
+    }
+}
+
+/// Decompress a message encoded by compress() or a CompressReader. Messages which would
+/// decompress to more than max_len bytes are rejected before they are decompressed, so that a
+/// small message cannot make the decoder allocate without bound.
+pub fn decompress(mut bytes: &[u8], max_len: usize) -> Result<Vec<u8>, WireError> {
+    let mut out = vec![];
+    while !bytes.is_empty() {
+        if bytes.len() < 17 {
+            return Err(WireError::Truncated);
+        }
+        let codec = Codec::from_id(bytes[0]).ok_or(WireError::UnsupportedCodec(bytes[0]))?;
+        let len = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
+        let payload_len = u64::from_le_bytes(bytes[9..17].try_into().unwrap());
+        bytes = &bytes[17..];
+        let len = usize::try_from(len).map_err(|_| WireError::TooLong(max_len))?;
+        if len > FRAME_LEN || len > max_len - out.len() {
+            return Err(WireError::TooLong(max_len));
+        }
+        let payload_len = usize::try_from(payload_len).map_err(|_| WireError::Truncated)?;
+        if bytes.len() < payload_len {
+            return Err(WireError::Truncated);
+        }
+        let (payload, rest) = bytes.split_at(payload_len);
+        out.extend_from_slice(&decompress_chunk(codec, payload, len)?);
+        bytes = rest;
+    }
+    Ok(out)
+}
+
+/// Reads the frames of a message read from another reader, compressing a chunk of FRAME_LEN
+/// bytes at a time as they are read, so that a streamed hint is compressed without holding it
+/// whole.
+pub struct CompressReader<R> {
+    inner: R,
+    codec: Codec,
+    // The frame bytes not yet read start at pos
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> CompressReader<R> {
+    pub fn new(inner: R, codec: Codec) -> Self {
+        Self { inner, codec, buf: vec![], pos: 0 }
+    }
+}
+
+impl<R: Read> Read for CompressReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            let mut chunk = Vec::with_capacity(FRAME_LEN);
+            (&mut self.inner).take(FRAME_LEN as u64).read_to_end(&mut chunk)?;
+            self.buf.clear();
+            self.pos = 0;
+            if !chunk.is_empty() {
+                write_frame(&mut self.buf, self.codec, &chunk);
+            }
+        }
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Codec::negotiate(""), Codec::Identity);
+        assert_eq!(Codec::negotiate("brotli, identity"), Codec::Identity);
+        assert_eq!(Codec::from_name("identity"), Some(Codec::Identity));
+        assert_eq!(Codec::negotiate(&Codec::offer()), Codec::supported()[0]);
+        #[cfg(all(feature = "zstd", feature = "lz4"))]
+        {
+            assert_eq!(Codec::negotiate("lz4"), Codec::Lz4);
+            assert_eq!(Codec::negotiate("lz4,zstd"), Codec::Zstd);
+        }
+        #[cfg(not(feature = "lz4"))]
+        assert_eq!(Codec::from_name("lz4"), None);
+    }
+
+    #[test]
+    fn test_compress() {
+        // Small entries packed into wide coefficients, and random bytes
+        let structured: Vec<u8> = (0..3 * FRAME_LEN + 5).map(|i| (i % 4 * (i % 3)) as u8).collect();
+        let random: Vec<u8> = (0..FRAME_LEN).map(|_| rand::random()).collect();
+        for codec in Codec::supported() {
+            let messages: [&[u8]; 4] = [&structured, &random, &[1, 2, 3], &[]];
+            for message in messages {
+                let compressed = compress(codec, message);
+                assert_eq!(decompress(&compressed, message.len()).unwrap(), message);
+
+                let mut streamed = vec![];
+                CompressReader::new(message, codec).read_to_end(&mut streamed).unwrap();
+                assert_eq!(streamed, compressed);
+            }
+            // Only chunks which shrink are compressed
+            let compressed = compress(codec, &structured);
+            assert_eq!(compressed.len() < structured.len() / 4, codec != Codec::Identity);
+            assert_eq!(compress(codec, &random).len(), random.len() + 17);
+            assert_eq!(compress(codec, &[7; 100]).len(), 117);
+        }
+    }
+
+    #[test]
+    fn test_decompress_errors() {
+        let message = vec![5; 10000];
+        let codec = Codec::supported()[0];
+        let compressed = compress(codec, &message);
+        assert_eq!(decompress(&compressed, 9999), Err(WireError::TooLong(9999)));
+        assert_eq!(decompress(&compressed[..20], 10000), Err(WireError::Truncated));
+
+        let mut unknown = compressed.clone();
+        unknown[0] = 9;
+        assert_eq!(decompress(&unknown, 10000), Err(WireError::UnsupportedCodec(9)));
+
+        // A frame claiming a longer chunk than its payload holds
+        let mut frame = compress(Codec::Identity, &[1, 2, 3]);
+        frame[1] = 4;
+        assert_eq!(decompress(&frame, 10), Err(WireError::Corrupt));
+    }
+}
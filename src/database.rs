@@ -0,0 +1,352 @@
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+#[cfg(feature = "std")]
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+use crate::bitpacked::BitPackedMatrix;
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::matrix::Matrix;
+#[cfg(feature = "std")]
+use crate::record::{encode_record, RecordLayout};
+use crate::sparse::SparseMatrix;
+
+/// Where the answer computation reads the database from. The database is a matrix mod p in the
+/// Matrix layout (column i holds the items which answer entry i is computed from), which is read
+/// a run of consecutive columns at a time, so that backends need not hold it in memory.
+pub trait Database {
+    /// The number of database columns, which is the length of an answer.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of database rows, which is the length of a query.
+    fn num_rows(&self) -> usize;
+
+    /// The columns in range, as a matrix of range.len() columns.
+    fn chunk(&self, range: Range<usize>) -> Result<Matrix, SimplePirError>;
+
+    /// Column idx.
+    fn record(&self, idx: usize) -> Result<Vec<Element>, SimplePirError> {
+        Ok(self.chunk(idx..idx + 1)?.col(0))
+    }
+}
+
+impl Database for Matrix {
+    fn len(&self) -> usize {
+        self.num_cols()
+    }
+
+    fn num_rows(&self) -> usize {
+        Matrix::num_rows(self)
+    }
+
+    fn chunk(&self, range: Range<usize>) -> Result<Matrix, SimplePirError> {
+        check_range(&range, self.num_cols())?;
+        Ok(self.select_cols(range))
+    }
+
+    fn record(&self, idx: usize) -> Result<Vec<Element>, SimplePirError> {
+        check_range(&(idx..idx + 1), self.num_cols())?;
+        Ok(self.col(idx))
+    }
+}
+
+impl Database for SparseMatrix {
+    fn len(&self) -> usize {
+        self.num_cols()
+    }
+
+    fn num_rows(&self) -> usize {
+        SparseMatrix::num_rows(self)
+    }
+
+    fn chunk(&self, range: Range<usize>) -> Result<Matrix, SimplePirError> {
+        check_range(&range, self.num_cols())?;
+        Ok(Matrix::from(&range.map(|i| self.column(i)).collect()))
+    }
+}
+
+impl Database for BitPackedMatrix {
+    fn len(&self) -> usize {
+        self.num_cols()
+    }
+
+    fn num_rows(&self) -> usize {
+        BitPackedMatrix::num_rows(self)
+    }
+
+    fn chunk(&self, range: Range<usize>) -> Result<Matrix, SimplePirError> {
+        check_range(&range, self.num_cols())?;
+        Ok(Matrix::from(&range.map(|i| self.column(i)).collect()))
+    }
+}
+
+// Check that a range of columns is within a database of len columns
+pub(crate) fn check_range(range: &Range<usize>, len: usize) -> Result<(), SimplePirError> {
+    if range.start > range.end {
+        return Err(SimplePirError::InvalidRange { start: range.start, end: range.end });
+    }
+    if range.end > len {
+        return Err(SimplePirError::IndexOutOfRange { idx: range.end - 1, len });
+    }
+    Ok(())
+}
+
+/// A database streamed from a file, which holds each item as a little-endian u64, column by
+/// column. Only the columns of the chunk being read are in memory at once.
+#[cfg(feature = "std")]
+pub struct FileDatabase {
+    // Locked for each read, since a read seeks the cursor shared by every thread
+    file: Mutex<File>,
+    p: u64,
+    num_cols: usize,
+    num_rows: usize,
+}
+
+#[cfg(feature = "std")]
+impl FileDatabase {
+    /// Write a database mod p in the layout read by open().
+    pub fn write<P: AsRef<Path>>(path: P, db: &Matrix) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        for v in db.values() {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        out.flush()
+    }
+
+    /// Open a database of items mod p with num_rows rows. The number of columns follows from the
+    /// length of the file. The file may be larger than a usize can address, as on 32-bit targets,
+    /// as long as its number of columns is not.
+    pub fn open<P: AsRef<Path>>(path: P, p: u64, num_rows: usize) -> io::Result<Self> {
+        assert!(num_rows > 0);
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let col_len = 8 * num_rows as u64;
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if !len.is_multiple_of(col_len) {
+            return Err(invalid("the file does not hold a whole number of columns"));
+        }
+        let num_cols = usize::try_from(len / col_len)
+            .map_err(|_| invalid("the file has too many columns for this target"))?;
+        Ok(Self { file: Mutex::new(file), p, num_cols, num_rows })
+    }
+
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+}
+
+#[cfg(feature = "std")]
+impl Database for FileDatabase {
+    fn len(&self) -> usize {
+        self.num_cols
+    }
+
+    fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    fn chunk(&self, range: Range<usize>) -> Result<Matrix, SimplePirError> {
+        check_range(&range, self.num_cols)?;
+        let storage = |err: io::Error| SimplePirError::Storage(err.to_string());
+        let len = range.len().checked_mul(self.num_rows * 8);
+        let mut bytes = vec![0u8; len.ok_or(SimplePirError::TooLarge("chunk"))?];
+        {
+            let mut file = self.file.lock().unwrap();
+            // The offset can pass 4 GiB even where a usize is 32 bits
+            let offset = range.start as u64 * self.num_rows as u64 * 8;
+            file.seek(SeekFrom::Start(offset)).map_err(storage)?;
+            file.read_exact(&mut bytes).map_err(storage)?;
+        }
+
+        let data = bytes.chunks(self.num_rows * 8).map(|col| {
+            col.chunks(8)
+                .map(|b| Element::checked_from(self.p, u64::from_le_bytes(b.try_into().unwrap())))
+                .collect()
+        }).collect::<Result<_, _>>()?;
+        Ok(Matrix::from(&data))
+    }
+}
+
+/// Writes byte records, pushed one at a time, as a database in the layout read by
+/// FileDatabase::open() and encoded by RecordLayout::encode_db(). The records of a block of
+/// `limbs` columns are consecutive, so each block is written out as soon as its last record is
+/// pushed, and only one block is held in memory rather than the records or the whole database.
+#[cfg(feature = "std")]
+pub struct DbWriter<W: Write> {
+    out: W,
+    layout: RecordLayout,
+    p: u64,
+    // The limbs of the block being filled, column by column
+    block: Vec<u64>,
+    // The number of records pushed so far
+    len: usize,
+}
+
+#[cfg(feature = "std")]
+impl DbWriter<BufWriter<File>> {
+    /// Create the file at path and write the database to it.
+    pub fn create<P: AsRef<Path>>(path: P, layout: RecordLayout, p: u64) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), layout, p))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> DbWriter<W> {
+    /// Write a database of records mod p in the given layout to out.
+    pub fn new(out: W, layout: RecordLayout, p: u64) -> Self {
+        let block = vec![0; layout.limbs * layout.rows];
+        Self { out, layout, p, block, len: 0 }
+    }
+
+    /// Append the next record, writing out its block once the block is full.
+    pub fn push(&mut self, record: &[u8]) -> Result<(), SimplePirError> {
+        if self.len == self.layout.num_records {
+            let len = self.layout.num_records;
+            return Err(SimplePirError::IndexOutOfRange { idx: self.len, len });
+        }
+        if record.len() != self.layout.record_len {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.layout.record_len,
+                actual: record.len(),
+            });
+        }
+        let row = self.len % self.layout.rows;
+        for (i, limb) in encode_record(record, self.p).into_iter().enumerate() {
+            self.block[i * self.layout.rows + row] = limb.uint;
+        }
+        self.len += 1;
+        if row == self.layout.rows - 1 {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Write out the last block, padded with zeros, and return the writer once every record of
+    /// the layout was pushed.
+    pub fn finish(mut self) -> Result<W, SimplePirError> {
+        if self.len != self.layout.num_records {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.layout.num_records,
+                actual: self.len,
+            });
+        }
+        if !self.len.is_multiple_of(self.layout.rows) {
+            self.flush_block()?;
+        }
+        self.out.flush().map_err(|err| SimplePirError::Storage(err.to_string()))?;
+        Ok(self.out)
+    }
+
+    fn flush_block(&mut self) -> Result<(), SimplePirError> {
+        let bytes: Vec<u8> = self.block.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.out.write_all(&bytes).map_err(|err| SimplePirError::Storage(err.to_string()))?;
+        self.block.fill(0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_databases() {
+        let db = Matrix::gen_uniform_rand(991, 5, 8);
+        assert_eq!((Database::len(&db), Database::num_rows(&db)), (5, 8));
+        assert_eq!(db.chunk(1..3).unwrap(), db.select_cols(1..3));
+        assert_eq!(db.record(4).unwrap(), db.col(4));
+        assert_eq!(db.chunk(4..6), Err(SimplePirError::IndexOutOfRange { idx: 5, len: 5 }));
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = db.chunk(3..1);
+        assert_eq!(reversed, Err(SimplePirError::InvalidRange { start: 3, end: 1 }));
+
+        let sparse = SparseMatrix::from_matrix(&db, 991);
+        assert_eq!(sparse.chunk(0..5).unwrap(), db);
+        assert_eq!(sparse.record(2).unwrap(), db.col(2));
+    }
+
+    #[test]
+    fn test_file_database() {
+        let path = std::env::temp_dir().join(format!("simplepir-db-{}", std::process::id()));
+        let db = Matrix::gen_uniform_rand(991, 7, 6);
+        FileDatabase::write(&path, &db).unwrap();
+
+        let file_db = FileDatabase::open(&path, 991, 6).unwrap();
+        assert_eq!((file_db.len(), file_db.num_rows()), (7, 6));
+        assert_eq!(file_db.chunk(0..7).unwrap(), db);
+        assert_eq!(file_db.chunk(2..5).unwrap(), db.select_cols(2..5));
+        assert_eq!(file_db.record(6).unwrap(), db.col(6));
+
+        // Concurrent reads each see their own columns
+        std::thread::scope(|scope| {
+            for start in 0..7 {
+                let (file_db, db) = (&file_db, &db);
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        assert_eq!(file_db.chunk(start..7).unwrap(), db.select_cols(start..7));
+                    }
+                });
+            }
+        });
+
+        // Values which are not mod p are rejected rather than silently reduced
+        let file_db = FileDatabase::open(&path, 7, 6).unwrap();
+        assert!(matches!(file_db.chunk(0..7), Err(SimplePirError::OutOfRange { q: 7, .. })));
+        assert!(FileDatabase::open(&path, 991, 5).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_db_writer() {
+        let records: Vec<Vec<u8>> = (0..23).map(|i| vec![i as u8; 5]).collect();
+        let layout = RecordLayout::new(records.len(), 5, 991).unwrap();
+        let mut writer = DbWriter::new(vec![], layout.clone(), 991);
+        for record in &records[..22] {
+            writer.push(record).unwrap();
+        }
+        let short = SimplePirError::LengthMismatch { expected: 5, actual: 4 };
+        assert_eq!(writer.push(&[0; 4]), Err(short));
+        writer.push(&records[22]).unwrap();
+        let full = SimplePirError::IndexOutOfRange { idx: 23, len: 23 };
+        assert_eq!(writer.push(&records[0]), Err(full));
+
+        let bytes = writer.finish().unwrap();
+        let db = layout.encode_db(&records, 991).unwrap();
+        let expected: Vec<u8> = db.values().iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(bytes, expected);
+
+        let writer = DbWriter::new(vec![], layout, 991);
+        let missing = SimplePirError::LengthMismatch { expected: 23, actual: 0 };
+        assert_eq!(writer.finish(), Err(missing));
+    }
+
+    #[test]
+    fn test_db_writer_file() {
+        let path = std::env::temp_dir().join(format!("simplepir-writer-{}", std::process::id()));
+        let records: Vec<Vec<u8>> = (0..40).map(|i| vec![i as u8; 3]).collect();
+        let layout = RecordLayout::new(records.len(), 3, 256).unwrap();
+        let mut writer = DbWriter::create(&path, layout.clone(), 256).unwrap();
+        for record in &records {
+            writer.push(record).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let file_db = FileDatabase::open(&path, 256, layout.rows).unwrap();
+        let db = layout.encode_db(&records, 256).unwrap();
+        assert_eq!(file_db.chunk(0..file_db.len()).unwrap(), db);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
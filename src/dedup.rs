@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use crate::error::SimplePirError;
+use crate::pir::{Answer, Client, PublicHint, Query, Server};
+use crate::record::RecordLayout;
+use crate::simplepir::SimplePIRParams;
+
+/// The public description of a deduplicated database. Each distinct payload is stored once in a
+/// payload table, and each record holds the slot of its payload in that table, so that a
+/// database with heavy duplication (e.g. a breach corpus) only pays for its distinct payloads.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DedupIndex {
+    pub num_records: usize,
+    pub num_payloads: usize,
+    pub payload_len: usize,
+}
+
+impl DedupIndex {
+    /// The length in bytes of a record, which holds a payload slot in little-endian order.
+    pub fn pointer_len(&self) -> usize {
+        let bits = usize::BITS - self.num_payloads.saturating_sub(1).leading_zeros();
+        (bits as usize).div_ceil(8).max(1)
+    }
+
+    // The record pointing to slot
+    fn encode_slot(&self, slot: usize) -> Vec<u8> {
+        slot.to_le_bytes()[..self.pointer_len()].to_vec()
+    }
+
+    // The slot a record points to
+    fn decode_slot(&self, pointer: &[u8]) -> usize {
+        let mut bytes = [0; 8];
+        bytes[..pointer.len()].copy_from_slice(pointer);
+        u64::from_le_bytes(bytes) as usize
+    }
+}
+
+/// The server side of deduplicated PIR, answering queries against the pointer and payload
+/// tables, each held by its own pir::Server.
+pub struct DedupServer {
+    index: DedupIndex,
+    pointers: Server,
+    payloads: Server,
+}
+
+impl DedupServer {
+    /// Build the deduplicated database of the records, with params of at least `bits` of security
+    /// for each table. Every record must be payload_len bytes long. Payloads are numbered in the
+    /// order they first appear.
+    pub fn setup(
+        records: &[Vec<u8>],
+        payload_len: usize,
+        bits: u32,
+    ) -> Result<Self, SimplePirError> {
+        if records.is_empty() {
+            return Err(SimplePirError::Empty("records"));
+        }
+        let mut slots: HashMap<&[u8], usize> = HashMap::new();
+        let mut payloads = vec![];
+        let mut pointers = Vec::with_capacity(records.len());
+        for record in records {
+            if record.len() != payload_len {
+                return Err(SimplePirError::LengthMismatch {
+                    expected: payload_len,
+                    actual: record.len(),
+                });
+            }
+            let slot = *slots.entry(record).or_insert_with(|| {
+                payloads.push(record.clone());
+                payloads.len() - 1
+            });
+            pointers.push(slot);
+        }
+
+        let index = DedupIndex {
+            num_records: records.len(),
+            num_payloads: payloads.len(),
+            payload_len,
+        };
+        let pointers: Vec<Vec<u8>> = pointers.iter().map(|&slot| index.encode_slot(slot)).collect();
+        let pointers = table_server(&pointers, index.pointer_len(), bits)?;
+        let payloads = table_server(&payloads, payload_len, bits)?;
+        Ok(Self { index, pointers, payloads })
+    }
+
+    pub fn index(&self) -> &DedupIndex {
+        &self.index
+    }
+
+    /// The public hint of the pointer table, which clients query first.
+    pub fn pointer_hint(&self) -> PublicHint {
+        self.pointers.public_hint()
+    }
+
+    /// The public hint of the payload table.
+    pub fn payload_hint(&self) -> PublicHint {
+        self.payloads.public_hint()
+    }
+
+    pub fn answer_pointer(&self, query: &Query) -> Result<Answer, SimplePirError> {
+        self.pointers.answer(query)
+    }
+
+    pub fn answer_payload(&self, query: &Query) -> Result<Answer, SimplePirError> {
+        self.payloads.answer(query)
+    }
+}
+
+// A server for a table of records of record_len bytes
+fn table_server(
+    records: &[Vec<u8>],
+    record_len: usize,
+    bits: u32,
+) -> Result<Server, SimplePirError> {
+    let params = SimplePIRParams::for_security(bits, records.len(), 8 * record_len)?;
+    let layout = RecordLayout::new(records.len(), record_len, params.p)?;
+    Server::setup_records(params, layout, records)
+}
+
+/// The client side of deduplicated PIR. A retrieval takes two chained lookups: query() asks for
+/// the record's payload slot, query_payload() recovers the slot and asks for the payload, and
+/// recover() recovers the payload. Neither query reveals which record or payload is retrieved.
+pub struct DedupClient {
+    index: DedupIndex,
+    pointers: Client,
+    payloads: Client,
+}
+
+impl DedupClient {
+    /// Create a client from the index and the two public hints published by the server.
+    pub fn new(
+        index: DedupIndex,
+        pointer_hint: PublicHint,
+        payload_hint: PublicHint,
+    ) -> Result<Self, SimplePirError> {
+        if index.num_records == 0 || index.num_payloads == 0 {
+            return Err(SimplePirError::Empty("index"));
+        }
+        for (hint, num_records, record_len) in [
+            (&pointer_hint, index.num_records, index.pointer_len()),
+            (&payload_hint, index.num_payloads, index.payload_len),
+        ] {
+            let layout = hint.layout.as_ref().ok_or(SimplePirError::NoRecordLayout)?;
+            if layout.num_records != num_records {
+                return Err(SimplePirError::LengthMismatch {
+                    expected: num_records,
+                    actual: layout.num_records,
+                });
+            }
+            if layout.record_len != record_len {
+                return Err(SimplePirError::LengthMismatch {
+                    expected: record_len,
+                    actual: layout.record_len,
+                });
+            }
+        }
+        Ok(Self {
+            index,
+            pointers: Client::from_public_hint(pointer_hint)?,
+            payloads: Client::from_public_hint(payload_hint)?,
+        })
+    }
+
+    /// Generate the query for the payload slot of record idx, to be answered by answer_pointer().
+    pub fn query(&mut self, idx: usize) -> Result<Query, SimplePirError> {
+        self.pointers.query_record(idx)
+    }
+
+    /// Recover the payload slot from the answer to query(), and generate the query for the
+    /// payload, to be answered by answer_payload().
+    pub fn query_payload(&mut self, answer: &Answer) -> Result<Query, SimplePirError> {
+        let slot = self.index.decode_slot(&self.pointers.recover_record(answer)?);
+        if slot >= self.index.num_payloads {
+            return Err(SimplePirError::MalformedAnswer);
+        }
+        self.payloads.query_record(slot)
+    }
+
+    /// Recover the payload from the answer to query_payload().
+    pub fn recover(&mut self, answer: &Answer) -> Result<Vec<u8>, SimplePirError> {
+        self.payloads.recover_record(answer)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup() {
+        // 300 records sharing 7 distinct payloads
+        let records: Vec<Vec<u8>> = (0..300).map(|i| vec![(i % 7) as u8; 16]).collect();
+        let server = DedupServer::setup(&records, 16, 128).unwrap();
+        assert_eq!(server.index().num_payloads, 7);
+        assert_eq!(server.index().pointer_len(), 1);
+        assert_eq!(server.payload_hint().layout.unwrap().num_records, 7);
+
+        let mut client = DedupClient::new(
+            server.index().clone(),
+            server.pointer_hint(),
+            server.payload_hint(),
+        ).unwrap();
+        for idx in [0, 1, 6, 7, 150, 299] {
+            let answer = server.answer_pointer(&client.query(idx).unwrap()).unwrap();
+            let query = client.query_payload(&answer).unwrap();
+            let answer = server.answer_payload(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), records[idx]);
+        }
+        assert_eq!(client.query(300).err(), Some(SimplePirError::IndexOutOfRange {
+            idx: 300,
+            len: 300,
+        }));
+    }
+
+    #[test]
+    fn test_pointer_len() {
+        let index = |num_payloads| DedupIndex { num_records: 1, num_payloads, payload_len: 1 };
+        assert_eq!(index(1).pointer_len(), 1);
+        assert_eq!(index(256).pointer_len(), 1);
+        assert_eq!(index(257).pointer_len(), 2);
+        assert_eq!(index(1 << 16).pointer_len(), 2);
+        assert_eq!(index((1 << 16) + 1).pointer_len(), 3);
+
+        let index = index(70000);
+        for slot in [0, 255, 256, 69999] {
+            assert_eq!(index.decode_slot(&index.encode_slot(slot)), slot);
+        }
+    }
+
+    #[test]
+    fn test_dedup_errors() {
+        assert_eq!(DedupServer::setup(&[], 4, 128).err(), Some(SimplePirError::Empty("records")));
+        assert_eq!(
+            DedupServer::setup(&[vec![1; 4], vec![2; 5]], 4, 128).err(),
+            Some(SimplePirError::LengthMismatch { expected: 4, actual: 5 }),
+        );
+
+        let server = DedupServer::setup(&[vec![1; 4], vec![2; 4], vec![1; 4]], 4, 128).unwrap();
+        let mut index = server.index().clone();
+        index.num_payloads = 3;
+        assert_eq!(
+            DedupClient::new(index, server.pointer_hint(), server.payload_hint()).err(),
+            Some(SimplePirError::LengthMismatch { expected: 3, actual: 2 }),
+        );
+        // The hints are in the wrong order
+        assert!(DedupClient::new(
+            server.index().clone(),
+            server.payload_hint(),
+            server.pointer_hint(),
+        ).is_err());
+
+        let mut client = DedupClient::new(
+            server.index().clone(),
+            server.pointer_hint(),
+            server.payload_hint(),
+        ).unwrap();
+        let answer = server.answer_pointer(&client.query(0).unwrap()).unwrap();
+        assert_eq!(client.recover(&answer).err(), Some(SimplePirError::NoPendingQuery));
+    }
+}
@@ -1,7 +1,16 @@
+//! DoublePIR: SimplePIR applied twice. The first level answer, one entry per database column,
+//! is itself treated as a database and queried with a second LWE query, so that the client
+//! downloads a hint and an answer whose sizes do not depend on the number of database rows.
+
 use crate::matrix::Matrix;
 use crate::element::Element;
-use crate::regev::gen_error_vec;
+use crate::error::SimplePirError;
+use crate::regev::{check_matrix_mod, check_vector_mod, gen_error_vec, QuerySecret, SecretKey};
+use crate::scaling::{delta_floor, unscale_round};
+use crate::simplepir::{max_plaintext_mod, security_level};
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DoublePIRParams {
     // Public A matrices
     pub a_1: Matrix,
@@ -26,20 +35,78 @@ pub struct DoublePIRParams {
     pub std_dev: f64,
 }
 
-pub fn gen_params() -> DoublePIRParams {
-    // Database size: l x m
-    let l = 4;
-    let m = 8;
-    let n = 32;
-    let p = 2;
-    let q = 3329;
-    let std_dev = 6.4;
-    // rows, cols - TODO: change gen_uniform_rand
+impl DoublePIRParams {
+    /// Choose parameters with at least `bits` of security for a database of l columns and m
+    /// rows. The LWE dimension, modulus and error are taken from the vetted sets of SimplePIR
+    /// (see SimplePIRParams::for_security()), and p is the largest plaintext modulus for which
+    /// both levels decrypt correctly except with probability 2^-40 per entry. The first level
+    /// sums m rows and the second l columns, so p is chosen for the larger of the two.
+    pub fn for_security(bits: u32, l: usize, m: usize) -> Result<Self, SimplePirError> {
+        if l == 0 || m == 0 {
+            return Err(SimplePirError::Empty("database"));
+        }
+        let level = security_level(bits)?;
+        let rows = l.max(m);
+        if rows > level.max_samples {
+            return Err(SimplePirError::TooManySamples { rows, max: level.max_samples });
+        }
+        let (q, n, std_dev) = (level.q, level.n, level.std_dev);
+        let p = max_plaintext_mod(q, rows, std_dev);
+        let a_1 = Matrix::gen_uniform_rand(q, m, n);
+        let a_2 = Matrix::gen_uniform_rand(q, l, n);
+        Ok(Self { a_1, a_2, q, p, n, l, m, std_dev })
+    }
+
+    /// The number of digits mod p which an element mod q is decomposed into between the levels.
+    pub fn num_digits(&self) -> usize {
+        ((self.q - 1) as f64).log(self.p as f64).ceil() as usize
+    }
+}
+
+/// Generate small, insecure parameters with n = 32 and q = 3329 for a 4 x 8 database, which keep
+/// tests fast. Never use these outside of tests.
+#[cfg(any(test, feature = "testing"))]
+pub fn gen_insecure_test_params() -> DoublePIRParams {
+    let (l, m, n, p, q, std_dev) = (4, 8, 32, 2, 3329, 6.4);
     let a_1 = Matrix::gen_uniform_rand(q, m, n);
     let a_2 = Matrix::gen_uniform_rand(q, l, n);
     DoublePIRParams { a_1, a_2, q, l, p, n, m, std_dev }
 }
 
+/// The hint kept by the server, the first level hint decomposed into digits mod p.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerHint(pub Matrix);
+
+/// The hint downloaded by the client, whose size does not depend on the database size.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientHint(pub Matrix);
+
+/// A query: c_1 has one entry per database row and c_2 one per database column.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DoubleQuery {
+    pub c_1: Matrix,
+    pub c_2: Matrix,
+}
+
+/// An answer: h is the first level answer, decomposed and multiplied by A_2, and ans is the
+/// second level answer over the server's hint and the decomposed first level answer.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DoubleAnswer {
+    pub h: Matrix,
+    pub ans: Matrix,
+}
+
+/// The secrets of a query, which recover() consumes.
+pub struct DoubleSecret {
+    s_1: QuerySecret,
+    s_2: QuerySecret,
+}
+
+/// Generate a database of random values mod the plaintext modulus p
 pub fn gen_db(params: &DoublePIRParams) -> Matrix {
     Matrix::gen_uniform_rand(
         params.p,
@@ -48,155 +115,157 @@ pub fn gen_db(params: &DoublePIRParams) -> Matrix {
     )
 }
 
-pub fn gen_hints(params: &DoublePIRParams, db: &Matrix) -> (Matrix, Matrix) {
+/// Generate the server-side hint hint_s = decompose(A_1^T * db^T) and the client's hint
+/// hint_c = hint_s * A_2. The client only ever downloads hint_c, whose size does not depend on the
+/// database size.
+pub fn gen_hints(params: &DoublePIRParams, db: &Matrix) -> (ServerHint, ClientHint) {
     let mut db_q = db.clone();
     db_q.change_q(params.q);
 
     // hint_s = A transposed * db transposed
-    let mut hint_s =  params.a_1.to_owned().rotated() * db_q.to_owned().rotated();
+    let mut hint_s =  params.a_1.transpose() * db_q.transpose();
     hint_s = hint_s.decomposed(params.p);
 
     // hint_c = hint_s * A_2
-    let hint_c = hint_s.to_owned() * params.a_2.to_owned();
+    let hint_c = &hint_s * &params.a_2;
 
-    (hint_s, hint_c)
+    (ServerHint(hint_s), ClientHint(hint_c))
 }
 
+/// Generate the two LWE queries: c_1 selects the database row (as in SimplePIR) and c_2 selects
+/// the database column within the first-level answer. The secrets are moved into the returned
+/// DoubleSecret, which recover() consumes. Indices past the database and secrets which do not
+/// match the params are rejected.
 pub fn query(
     params: &DoublePIRParams,
     col_i: usize,
     row_i: usize,
-    s_1: &Vec<Element>,
-    s_2: &Vec<Element>,
-) -> (Matrix, Matrix) {
-    assert!(row_i < params.m);
-    assert!(col_i < params.l);
+    s_1: SecretKey,
+    s_2: SecretKey,
+) -> Result<(DoubleQuery, DoubleSecret), SimplePirError> {
+    if row_i >= params.m {
+        return Err(SimplePirError::IndexOutOfRange { idx: row_i, len: params.m });
+    }
+    if col_i >= params.l {
+        return Err(SimplePirError::IndexOutOfRange { idx: col_i, len: params.l });
+    }
+    for s in [&s_1, &s_2] {
+        if s.len() != params.n {
+            return Err(SimplePirError::LengthMismatch { expected: params.n, actual: s.len() });
+        }
+        check_vector_mod(s, params.q)?;
+    }
 
     // q / p
-    let floor = Element::from(params.q, params.q / params.p);
+    let floor = Element::from(params.q, delta_floor(params.q, params.p));
 
     // Generate error vectors
-    let e_1 = Matrix::from_col(&gen_error_vec(params.q, params.m));
-    let e_2 = Matrix::from_col(&gen_error_vec(params.q, params.l));
+    let e_1 = Matrix::from_col(&gen_error_vec(params.q, params.m, params.std_dev).to_elements());
+    let e_2 = Matrix::from_col(&gen_error_vec(params.q, params.l, params.std_dev).to_elements());
 
-    // Compute c_1 = A_1 * s_1 + e_1 + floor * u_i_row
-    // NOTE: perhaps due to a bug in our Matrix implementation, row and col are reversed. As such,
-    // while the paper notes that c_1 contains floor at u_i_col, we instead use row_i.
-    let mut c_1 = params.a_1.to_owned().mul_vec(s_1) + e_1.rotated();
-    c_1[row_i][0] += floor.clone();
+    // Compute c_1 = A_1 * s_1 + e_1 + floor * u_row_i. Vectors are matrices with a single row, so
+    // c_1 has one column per database row and entry (row_i, 0) selects row row_i.
+    let mut c_1 = params.a_1.to_owned().mul_vec(&s_1.elements()) + e_1.rotated();
+    c_1.set(row_i, 0, &(c_1.get(row_i, 0) + floor.clone()));
 
-    assert_eq!(c_1.num_cols(), params.m);
+    // Compute c_2 = A_2 * s_2 + e_2 + floor * u_col_i, with one column per database column
+    let mut c_2 = params.a_2.to_owned().mul_vec(&s_2.elements()) + e_2.rotated();
+    c_2.set(col_i, 0, &(c_2.get(col_i, 0) + floor));
 
-    // Compute c_2 = A_2 * s_2 + e_2 + floor * u_i_col
-    // NOTE: same bug as above
-    let mut c_2 = params.a_2.to_owned().mul_vec(s_2) + e_2.rotated();
-    c_2[col_i][0] += floor;
-    assert_eq!(c_2.num_cols(), params.l);
-
-    (c_1, c_2)
+    let secret = DoubleSecret { s_1: QuerySecret::new(s_1), s_2: QuerySecret::new(s_2) };
+    Ok((DoubleQuery { c_1, c_2 }, secret))
 }
 
+/// Answer a query in two levels. The first-level SimplePIR answer c_1 * db is decomposed and
+/// treated as a second database, which is queried with c_2 together with hint_s. Queries which
+/// do not match the params, database or hint are rejected, so queries can be answered as
+/// received from untrusted clients.
 pub fn answer(
     params: &DoublePIRParams,
     db: &Matrix,
-    hint_s: &Matrix,
-    query: &(Matrix, Matrix),
-) -> (Matrix, Matrix) {
-    let k = ((params.q - 1) as f64).log(params.p as f64).ceil() as usize;
-    let c_1 = query.to_owned().0;
-    let c_2 = query.to_owned().1;
+    hint_s: &ServerHint,
+    query: &DoubleQuery,
+) -> Result<DoubleAnswer, SimplePirError> {
+    let k = params.num_digits();
+    let (c_1, c_2) = (&query.c_1, &query.c_2);
+    if c_1.dimensions() != (params.m, 1) || c_2.dimensions() != (params.l, 1) {
+        return Err(SimplePirError::InvalidShape("query"));
+    }
+    check_matrix_mod(c_1, params.q)?;
+    check_matrix_mod(c_2, params.q)?;
+    if db.dimensions() != (params.l, params.m) {
+        return Err(SimplePirError::InvalidShape("database"));
+    }
+    check_matrix_mod(db, params.p)?;
+    if hint_s.0.dimensions() != (k * params.n, params.l) {
+        return Err(SimplePirError::InvalidShape("hint"));
+    }
+    check_matrix_mod(&hint_s.0, params.q)?;
 
     let mut db_q = db.clone();
     db_q.change_q(params.q);
-    let ans_1 = (c_1.rotated() * db_q.rotated()).decomposed(params.p);
-
-    assert_eq!(ans_1.num_cols(), k);
-    assert_eq!(ans_1.num_rows(), params.l);
-
-    let h = ans_1.to_owned() * params.a_2.to_owned();
+    // ans_1: one row per database column, k digits each
+    let ans_1 = (c_1.clone().rotated() * db_q.rotated()).decomposed(params.p);
 
-    // (ans_h || ans_2) = (hint_s || ans_1) * c_2
-    let mut hint_s_ans_1 = hint_s.clone();
-    for col in ans_1.data {
-        hint_s_ans_1.append_col(col.clone());
-    }
-
-    let ans_h_ans_2 = hint_s_ans_1 * c_2;
-
-    // Check the dimensions of the answer
-    assert_eq!(h.num_cols(), k);
-    assert_eq!(h.num_rows(), params.n);
+    // h: k x n
+    let h = &ans_1 * &params.a_2;
 
-    assert_eq!(ans_h_ans_2.num_cols(), k * (params.n + 1));
-    assert_eq!(ans_h_ans_2.num_rows(), 1);
+    // (ans_h || ans_2) = (hint_s || ans_1) * c_2, of k(n+1) x 1
+    let mut hint_s_ans_1 = hint_s.0.clone();
+    hint_s_ans_1.append(ans_1);
+    let ans = hint_s_ans_1 * c_2.clone();
 
-    (h, ans_h_ans_2)
+    Ok(DoubleAnswer { h, ans })
 }
 
+/// Undo both levels: recover the first-level answer and its hint component using s_2 and hint_c,
+/// then decrypt the selected item using s_1. Hints and answers which do not match the params are
+/// rejected.
 pub fn recover(
     params: &DoublePIRParams,
-    hint_c: &Matrix,
-    answer: &(Matrix, Matrix),
-    s_1: &Vec<Element>,
-    s_2: &Vec<Element>,
-) -> Element {
-    let k = ((params.q - 1) as f64).log(params.p as f64).ceil() as usize;
+    hint_c: &ClientHint,
+    answer: &DoubleAnswer,
+    secret: DoubleSecret,
+) -> Result<Element, SimplePirError> {
+    let (s_1, s_2) = (secret.s_1.secret(), secret.s_2.secret());
+    let k = params.num_digits();
     let p = params.p;
-    let q = params.q as f64;
-    let h = answer.to_owned().0;
-    let ans_h_ans_2 = answer.to_owned().1;
-
-    assert_eq!(hint_c.num_cols(), k * params.n);
-    assert_eq!(hint_c.num_rows(), params.n);
-
-    assert_eq!(h.num_cols(), k);
-    assert_eq!(h.num_rows(), params.n);
+    let (h, ans) = (&answer.h, &answer.ans);
 
-    // hint_c_h =  hint_c || h
-    let mut hint_c_h = hint_c.to_owned();
-    for i in 0..h.num_cols() {
-        hint_c_h.append_col(h[i].to_owned());
+    if hint_c.0.dimensions() != (k * params.n, params.n) {
+        return Err(SimplePirError::InvalidShape("hint"));
     }
+    if h.dimensions() != (k, params.n) || ans.dimensions() != (k * (params.n + 1), 1) {
+        return Err(SimplePirError::InvalidShape("answer"));
+    }
+    check_matrix_mod(&hint_c.0, params.q)?;
+    check_matrix_mod(h, params.q)?;
+    check_matrix_mod(ans, params.q)?;
 
-    // hint_c_h * s_2
-    let hhs = hint_c_h.mul_vec(s_2);
-    assert_eq!(hhs.num_cols(), k * (params.n + 1));
-    assert_eq!(hhs.num_rows(), 1);
-
-    // h1_hat_a1_hat = (ans_h || ans_2) - (hint_c || h) *  s_2
-    let mut h1_hat_a1_hat = ans_h_ans_2 - hhs;
+    // hint_c_h = hint_c || h, of k(n+1) x n
+    let mut hint_c_h = hint_c.0.clone();
+    hint_c_h.append(h.clone());
 
-    assert_eq!(h1_hat_a1_hat.num_cols(), k * (params.n + 1));
-    assert_eq!(h1_hat_a1_hat.num_rows(), 1);
+    // h1_hat_a1_hat = (ans_h || ans_2) - (hint_c || h) * s_2, of k(n+1) x 1
+    let hhs = hint_c_h.mul_vec(&s_2.elements());
+    let mut h1_hat_a1_hat = ans.clone() - hhs;
 
     for i in 0..h1_hat_a1_hat.num_cols() {
         for j in 0..h1_hat_a1_hat.num_rows() {
-            h1_hat_a1_hat[i][j].uint =
-                ((h1_hat_a1_hat[i][j].uint * p) as f64 / q).round() as u64 % p;
+            let value = unscale_round(&h1_hat_a1_hat.get(i, j), p).uint;
+            h1_hat_a1_hat.set(i, j, &Element::from(h1_hat_a1_hat.q(), value));
         }
     }
 
-    // ans_h: k x n
-    // ans_2: k x 1
-    // ans_h_ans_2: k(n+1) x 1
-    // hint_c: kn x n
-    // h: k x n
-    // hint_c_h: k(n+1) x n
-    // s2: n x 1
-    // h1_hat_a1_hat: (k(n+1) x 1) - (k(n+1) x 1)
+    // Recomposing the digits gives (h_1 || a_1), of (n+1) x 1
     let h1_a1 = h1_hat_a1_hat.recompose(p, params.q);
 
-    let mut h_1 = Vec::with_capacity(params.n);
-    for i in 0..params.n {
-        h_1.push(h1_a1[i].clone());
-    }
-    let h_1 = Matrix::from(&h_1);
-    let a_1 = Matrix::from_col(&h1_a1[params.n]);
+    let h_1 = h1_a1.select_cols(0..params.n);
+    let a_1 = h1_a1.select_cols(params.n..params.n + 1);
 
-    let d_hat = a_1 - Matrix::from_col(s_1) * h_1;
+    let d_hat = a_1 - Matrix::from_col(&s_1.elements()) * h_1;
 
-    let d = ((d_hat[0][0].uint * p) as f64 / q).round() as u64 % p;
-    Element::from (params.p, d)
+    Ok(unscale_round(&d_hat.get(0, 0), p))
 }
 
 #[cfg(test)]
@@ -206,7 +275,7 @@ mod tests {
 
     #[test]
     pub fn test_doublepir() {
-        let params = gen_params();
+        let params = gen_insecure_test_params();
         let db = gen_db(&params);
 
         for i in 0..db.num_cols() {
@@ -216,18 +285,75 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn test_doublepir_secure_params() {
+        let params = DoublePIRParams::for_security(128, 8, 16).unwrap();
+        assert_eq!((params.n, params.l, params.m), (1024, 8, 16));
+        assert!(params.p > 2);
+        let db = gen_db(&params);
+
+        for (col, row) in [(0, 0), (3, 11), (7, 15)] {
+            test_doublepir_impl(&params, &db, col, row);
+        }
+    }
+
+    #[test]
+    pub fn test_for_security_errors() {
+        let result = DoublePIRParams::for_security(128, 0, 16);
+        assert_eq!(result.err(), Some(SimplePirError::Empty("database")));
+        let result = DoublePIRParams::for_security(1024, 8, 16);
+        assert_eq!(result.err(), Some(SimplePirError::UnsupportedSecurity(1024)));
+        let result = DoublePIRParams::for_security(128, 8, 1 << 30);
+        assert!(matches!(result, Err(SimplePirError::TooManySamples { .. })));
+    }
+
     pub fn test_doublepir_impl(params: &DoublePIRParams, db: &Matrix, col: usize, row: usize) {
-        // Generate (hint_s, hint_c)
-        let hints = gen_hints(&params, &db);
+        let (hint_s, hint_c) = gen_hints(params, db);
 
         let s_1 = gen_secret(params.q, params.n);
         let s_2 = gen_secret(params.q, params.n);
 
-        let query = query(&params, col, row, &s_1, &s_2);
+        let (query, secret) = query(params, col, row, s_1, s_2).unwrap();
+
+        let answer = answer(params, db, &hint_s, &query).unwrap();
 
-        let answer = answer(&params, &db, &hints.0, &query);
+        let recovered = recover(params, &hint_c, &answer, secret).unwrap();
+        assert_eq!(recovered, db.get(col, row));
+    }
 
-        let recovered = recover(&params, &hints.1, &answer, &s_1, &s_2);
-        assert_eq!(recovered, db[col][row]);
+    #[test]
+    pub fn test_doublepir_errors() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let (hint_s, hint_c) = gen_hints(&params, &db);
+        let secret = || gen_secret(params.q, params.n);
+
+        let err = SimplePirError::IndexOutOfRange { idx: params.m, len: params.m };
+        assert_eq!(query(&params, 0, params.m, secret(), secret()).err(), Some(err));
+        let err = SimplePirError::IndexOutOfRange { idx: params.l, len: params.l };
+        assert_eq!(query(&params, params.l, 0, secret(), secret()).err(), Some(err));
+        let short = gen_secret(params.q, params.n - 1);
+        let err = SimplePirError::LengthMismatch { expected: params.n, actual: params.n - 1 };
+        assert_eq!(query(&params, 0, 0, secret(), short).err(), Some(err));
+
+        // Queries, hints and answers of the wrong shape are rejected rather than multiplied
+        let (query, secret) = query(&params, 1, 2, secret(), secret()).unwrap();
+        let mut truncated = query.clone();
+        truncated.c_1 = query.c_1.select_cols(0..params.m - 1);
+        let result = answer(&params, &db, &hint_s, &truncated);
+        assert_eq!(result, Err(SimplePirError::InvalidShape("query")));
+        let mut truncated = query.clone();
+        truncated.c_2 = query.c_2.select_cols(0..params.l - 1);
+        let result = answer(&params, &db, &hint_s, &truncated);
+        assert_eq!(result, Err(SimplePirError::InvalidShape("query")));
+        let result = answer(&params, &db.select_cols(0..1), &hint_s, &query);
+        assert_eq!(result, Err(SimplePirError::InvalidShape("database")));
+        let result = answer(&params, &db, &ServerHint(hint_c.0.clone()), &query);
+        assert_eq!(result, Err(SimplePirError::InvalidShape("hint")));
+
+        let mut truncated = answer(&params, &db, &hint_s, &query).unwrap();
+        truncated.ans = truncated.ans.select_cols(1..truncated.ans.num_cols());
+        let result = recover(&params, &hint_c, &truncated, secret);
+        assert_eq!(result, Err(SimplePirError::InvalidShape("answer")));
     }
 }
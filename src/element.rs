@@ -1,20 +1,96 @@
+use crate::error::SimplePirError;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::{Ordering, PartialOrd};
+use core::fmt::{Display, Formatter};
+use core::ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign};
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, SeedableRng};
+use rand::{Rng, RngCore};
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
 use rand_distr::num_traits::Zero;
-use std::fmt::{Display, Formatter};
-use std::ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign};
-use rand_distr::{Normal, Distribution};
-use std::cmp::{Ordering, PartialOrd};
-use rand::{
-    RngCore,
-    rngs::StdRng,
-    SeedableRng,
-};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, ConstantTimeLess};
+use zeroize::Zeroize;
+
+pub mod ops;
+
+/// The number of standard deviations at which Gaussian samples are truncated.
+pub const TAIL_CUT: f64 = 6.0;
+
+/// The power-of-two modulus 2^32. Arithmetic mod Q32 wraps u32 values instead of dividing.
+pub const Q32: u64 = 1 << 32;
+
+// Compute (a + b) mod q for a, b < q. The reduction is a constant-time selection rather than a
+// branch, so that the timing does not depend on secret values.
+fn add_mod(a: u64, b: u64, q: u64) -> u64 {
+    if q == Q32 {
+        (a as u32).wrapping_add(b as u32) as u64
+    } else {
+        // Written so that a + b cannot overflow for q close to 2^64
+        let t = q - b;
+        u64::conditional_select(&a.wrapping_sub(t), &a.wrapping_add(b), a.ct_lt(&t))
+    }
+}
+
+// Compute (a - b) mod q for a, b < q, also without branching on a or b
+fn sub_mod(a: u64, b: u64, q: u64) -> u64 {
+    let d = a.wrapping_sub(b);
+    u64::conditional_select(&d, &d.wrapping_add(q), a.ct_lt(&b))
+}
+
+// Compute (a * b) mod q for a, b < q. Hardware division takes time depending on its operands, so
+// the product is never divided: products mod q below 2^32 fit in a u64 and are reduced with a
+// Barrett estimate of the quotient, whose constant only divides the public q, and products mod
+// larger q are accumulated by doubling and adding, one bit of b at a time, so for q of at least
+// 2^32 b may be any u64.
+fn mul_mod(a: u64, b: u64, q: u64) -> u64 {
+    if q == Q32 {
+        (a as u32).wrapping_mul(b as u32) as u64
+    } else if q < Q32 {
+        // q * ⌊(2^64 - 1) / q⌋ > 2^64 - q, so the estimate of ⌊ab / q⌋ is low by at most 1
+        let barrett = u64::MAX / q;
+        let x = a * b;
+        let estimate = ((x as u128 * barrett as u128) >> 64) as u64;
+        let r = x - estimate * q;
+        let d = r.wrapping_sub(q);
+        u64::conditional_select(&d, &r, r.ct_lt(&q))
+    } else {
+        let mut r = 0;
+        for i in (0..64).rev() {
+            r = add_mod(r, r, q);
+            let bit = Choice::from(((b >> i) & 1) as u8);
+            r = u64::conditional_select(&r, &add_mod(r, a, q), bit);
+        }
+        r
+    }
+}
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ElementRepr"))]
 pub struct Element {
     pub(crate) q: u64,
     pub(crate) uint: u64,
 }
 
+// The serialized form of an Element, which is checked to be in range when deserializing
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ElementRepr {
+    q: u64,
+    uint: u64,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ElementRepr> for Element {
+    type Error = SimplePirError;
+
+    fn try_from(repr: ElementRepr) -> Result<Self, Self::Error> {
+        Element::checked_from(repr.q, repr.uint)
+    }
+}
+
 impl Element {
     pub fn new(q: u64) -> Self {
         Self {
@@ -30,49 +106,116 @@ impl Element {
         Self { q, uint }
     }
 
-    pub fn zero(q: u64) -> Self {
-        Element {
-            q,
-            uint: 0u64,
+    /// Like from(), but returning an error instead of panicking if q is not a valid modulus or uint
+    /// is not below q. Use this for values which come from untrusted input.
+    pub fn checked_from(q: u64, uint: u64) -> Result<Self, SimplePirError> {
+        if q == 0 || q == u64::MAX {
+            return Err(SimplePirError::InvalidModulus(q));
+        }
+        if uint >= q {
+            return Err(SimplePirError::OutOfRange { q, uint });
         }
+        Ok(Self { q, uint })
+    }
+
+    pub fn zero(q: u64) -> Self {
+        Element { q, uint: 0u64 }
     }
 
     pub fn is_zero(&self) -> bool {
         self.uint == 0u64
     }
 
-    /// Generate a random Element following a normal (Gaussian) distribution.
+    /// Return b if choice is set and a otherwise, in constant time. The moduli are public and must
+    /// be equal.
+    pub fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        assert_eq!(a.q, b.q);
+        Self {
+            q: a.q,
+            uint: u64::conditional_select(&a.uint, &b.uint, choice),
+        }
+    }
+
+    /// Set self to other if choice is set, in constant time.
+    pub fn conditional_assign(&mut self, other: &Self, choice: Choice) {
+        *self = Self::conditional_select(self, other, choice);
+    }
+
+    /// Generate a random Element following a centered discrete Gaussian distribution, truncated
+    /// at TAIL_CUT standard deviations. Negative values are represented mod q.
     ///
-    /// # Parameters 
+    /// # Parameters
     ///
     /// - `q`: The element modulus
     /// - `std_dev`: The standard deviation of the distribution.
+    #[cfg(feature = "std")]
     pub fn gen_normal_rand(q: u64, std_dev: f64) -> Self {
-        assert!(std_dev < q as f64);
-        let mean = (&q / 2u64) as f64;
-        let normal = Normal::new(mean, std_dev).unwrap();
-
         let mut rng = StdRng::from_entropy();
-        let v = normal.sample(&mut rng) as u64;
+        Self::gen_normal_rand_with_rng(q, std_dev, &mut rng)
+    }
 
-        Self::from(q, v)
+    /// Like gen_normal_rand(), but using the given RNG.
+    pub fn gen_normal_rand_with_rng<R: RngCore>(q: u64, std_dev: f64, rng: &mut R) -> Self {
+        let bound = (TAIL_CUT * std_dev).ceil() as u64;
+        Self::gen_discrete_gaussian_with_rng(q, std_dev, bound, rng)
+    }
+
+    /// Sample x from the discrete Gaussian over the integers in [-bound, bound], i.e. with
+    /// probability proportional to exp(-x^2 / 2 std_dev^2), and return x mod q. Candidates are
+    /// drawn uniformly from the range and accepted with that probability.
+    pub fn gen_discrete_gaussian_with_rng<R: RngCore>(
+        q: u64,
+        std_dev: f64,
+        bound: u64,
+        rng: &mut R,
+    ) -> Self {
+        assert!(std_dev > 0.0);
+        assert!(2 * bound < q, "the tail bound must be below q / 2");
+
+        let bound = bound as i64;
+        loop {
+            let x = rng.gen_range(-bound..=bound);
+            let p = (-((x * x) as f64) / (2.0 * std_dev * std_dev)).exp();
+            if rng.gen::<f64>() < p {
+                let uint = if x < 0 {
+                    q - x.unsigned_abs()
+                } else {
+                    x as u64
+                };
+                return Self::from(q, uint);
+            }
+        }
     }
 
     /// Generate a random element using a uniform distribution.
     /// The value will be an Element mod q.
-    pub fn gen_uniform_rand(q: u64) -> Self  {
+    #[cfg(feature = "std")]
+    pub fn gen_uniform_rand(q: u64) -> Self {
         let mut rng = StdRng::from_entropy();
+        Self::gen_uniform_rand_with_rng(q, &mut rng)
+    }
+
+    /// Generate a random element mod q using a uniform distribution and the given RNG.
+    pub fn gen_uniform_rand_with_rng<R: RngCore>(q: u64, rng: &mut R) -> Self {
         let min = (u64::MAX - q) % q;
         let mut r;
         loop {
             r = rng.next_u64();
             if r >= min {
-                break
+                break;
             }
         }
         Self::from(q, r % q)
     }
 
+    /// Rescale this element from Z_q to Z_new_q by computing round(uint * new_q / q). Noise is
+    /// scaled down along with the value, plus at most 1/2 of rounding error.
+    pub fn mod_switch(&self, new_q: u64) -> Self {
+        let q = self.q as u128;
+        let x = (2 * self.uint as u128 * new_q as u128 + q) / (2 * q);
+        Self::from(new_q, (x % new_q as u128) as u64)
+    }
+
     pub fn recompose(p: u64, q: u64, vals: &Vec<u64>) -> Self {
         let mut result = 0u64;
         let mut r = 1;
@@ -92,18 +235,31 @@ impl Element {
         while n > 0 {
             digits[i] = n % p;
             n /= p;
-            i += 1; 
+            i += 1;
         }
         digits
     }
 }
 
+impl ConstantTimeEq for Element {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.q.ct_eq(&other.q) & self.uint.ct_eq(&other.uint)
+    }
+}
+
 impl PartialOrd for Element {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.uint.cmp(&other.uint))
     }
 }
 
+// Only the value is secret; the modulus is left intact so that a wiped element stays valid
+impl Zeroize for Element {
+    fn zeroize(&mut self) {
+        self.uint.zeroize();
+    }
+}
+
 impl Clone for Element {
     fn clone(&self) -> Self {
         Self {
@@ -124,7 +280,7 @@ impl Mul for Element {
         assert_eq!(self.q, rhs.q);
         Self {
             q: self.q,
-            uint: (self.uint * rhs.uint) % self.q,
+            uint: mul_mod(self.uint, rhs.uint, self.q),
         }
     }
 }
@@ -134,7 +290,7 @@ impl MulAssign for Element {
         assert_eq!(self.q, rhs.q);
         *self = Self {
             q: self.q,
-            uint: (self.uint * rhs.uint) % self.q,
+            uint: mul_mod(self.uint, rhs.uint, self.q),
         }
     }
 }
@@ -145,7 +301,7 @@ impl Add for Element {
         assert_eq!(self.q, rhs.q);
         Self {
             q: self.q,
-            uint: (self.uint + rhs.uint) % self.q,
+            uint: add_mod(self.uint, rhs.uint, self.q),
         }
     }
 }
@@ -155,7 +311,7 @@ impl AddAssign for Element {
         assert_eq!(self.q, rhs.q);
         *self = Self {
             q: self.q,
-            uint: (self.uint + rhs.uint) % self.q,
+            uint: add_mod(self.uint, rhs.uint, self.q),
         }
     }
 }
@@ -164,17 +320,9 @@ impl Sub for Element {
     type Output = Self;
     fn sub(self, other: Self) -> Self::Output {
         assert_eq!(self.q, other.q);
-        if self.uint < other.uint {
-            let d = other.uint - self.uint;
-            return Self {
-                q: self.q,
-                uint: self.q - d,
-            };
-        }
-
         Self {
             q: self.q,
-            uint: self.uint - other.uint,
+            uint: sub_mod(self.uint, other.uint, self.q),
         }
     }
 }
@@ -182,33 +330,143 @@ impl Sub for Element {
 impl SubAssign for Element {
     fn sub_assign(&mut self, other: Self) {
         assert_eq!(self.q, other.q);
-        if self.uint < other.uint {
-            let d = other.uint - self.uint;
-            *self = Self {
-                q: self.q,
-                uint: self.q - d,
-            }
-        } else {
-            *self = Self {
-                q: self.q,
-                uint: self.uint - other.uint,
-            }
+        *self = Self {
+            q: self.q,
+            uint: sub_mod(self.uint, other.uint, self.q),
         }
     }
 }
 
+impl Add<u64> for Element {
+    type Output = Self;
+    fn add(self, rhs: u64) -> Self::Output {
+        let rhs = Element::from(self.q, rhs % self.q);
+        self + rhs
+    }
+}
+
+impl Sub<u64> for Element {
+    type Output = Self;
+    fn sub(self, rhs: u64) -> Self::Output {
+        let rhs = Element::from(self.q, rhs % self.q);
+        self - rhs
+    }
+}
+
+impl Mul<u64> for Element {
+    type Output = Self;
+    fn mul(self, rhs: u64) -> Self::Output {
+        let rhs = Element::from(self.q, rhs % self.q);
+        self * rhs
+    }
+}
+
 impl Display for Element {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.uint)
+    }
+}
+
+/// An element mod the compile-time constant Q. Unlike Element, the modulus is not stored with
+/// each value, reductions can be constant folded, and mixing moduli is a type error rather than a
+/// runtime panic. Convert to and from Element at API boundaries.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ConstElement<const Q: u64> {
+    uint: u64,
+}
+
+impl<const Q: u64> ConstElement<Q> {
+    pub fn from(uint: u64) -> Self {
+        const { assert!(Q >= 2 && Q < u64::MAX, "invalid modulus") };
+        assert!(uint < Q);
+        Self { uint }
+    }
+
+    pub fn zero() -> Self {
+        Self::from(0)
+    }
+
+    pub fn uint(&self) -> u64 {
+        self.uint
+    }
+
+    /// Generate a random element mod Q using a uniform distribution and the given RNG.
+    pub fn gen_uniform_rand_with_rng<R: RngCore>(rng: &mut R) -> Self {
+        Self::from(Element::gen_uniform_rand_with_rng(Q, rng).uint)
+    }
+
+    /// Convert an Element, which must be mod Q.
+    pub fn from_element(e: &Element) -> Self {
+        assert_eq!(e.q, Q);
+        Self::from(e.uint)
+    }
+
+    pub fn to_element(self) -> Element {
+        Element::from(Q, self.uint)
+    }
+}
+
+impl<const Q: u64> Add for ConstElement<Q> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            uint: add_mod(self.uint, rhs.uint, Q),
+        }
+    }
+}
+
+impl<const Q: u64> AddAssign for ConstElement<Q> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl<const Q: u64> Sub for ConstElement<Q> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            uint: add_mod(self.uint, Q - rhs.uint, Q),
+        }
+    }
+}
+
+impl<const Q: u64> SubAssign for ConstElement<Q> {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl<const Q: u64> Mul for ConstElement<Q> {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            uint: mul_mod(self.uint, rhs.uint, Q),
+        }
+    }
+}
+
+impl<const Q: u64> MulAssign for ConstElement<Q> {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl<const Q: u64> Display for ConstElement<Q> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", self.uint)
     }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use super::Element;
+    use super::{ConstElement, Element, Q32};
+    use crate::error::SimplePirError;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use subtle::{Choice, ConstantTimeEq};
 
     fn gen_q() -> u64 {
-        return 101u64;
+        101u64
     }
 
     #[test]
@@ -268,6 +526,26 @@ pub mod tests {
         assert_eq!(r.uint, 15u64);
     }
 
+    #[test]
+    fn test_mul_mod() {
+        let mut rng = ChaCha20Rng::from_seed([5u8; 32]);
+        for q in [2, 3, 3329, 65536, (1 << 32) - 5, (1 << 32) + 15, (1 << 56) - 5, u64::MAX - 58] {
+            let mut values = vec![0, 1, q / 2, q - 2, q - 1];
+            values.extend((0..20).map(|_| rng.gen_range(0..q)));
+            for &a in &values {
+                for &b in &values {
+                    let expected = (a as u128 * b as u128 % q as u128) as u64;
+                    assert_eq!(super::mul_mod(a, b, q), expected, "{} * {} mod {}", a, b, q);
+                }
+                // Moduli of at least 2^32 also take multipliers above q
+                if q > 1 << 32 {
+                    let expected = (a as u128 * u64::MAX as u128 % q as u128) as u64;
+                    assert_eq!(super::mul_mod(a, u64::MAX, q), expected);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_mul_assign() {
         let mut f = Element::from(gen_q(), 100u64);
@@ -276,6 +554,200 @@ pub mod tests {
         assert_eq!(f.uint, 99u64);
     }
 
+    #[test]
+    fn test_large_q() {
+        // Moduli just above 2^32, at 2^40 and 2^56, and the largest prime below 2^64
+        for q in [
+            (1u64 << 32) + 15,
+            (1 << 40) - 87,
+            (1 << 56) - 5,
+            u64::MAX - 58,
+        ] {
+            let max = q - 1;
+            assert_eq!(
+                Element::from(q, max) + Element::from(q, max),
+                Element::from(q, q - 2)
+            );
+            assert_eq!(
+                Element::from(q, max) * Element::from(q, max),
+                Element::from(q, 1)
+            );
+            assert_eq!(
+                Element::from(q, max) * Element::from(q, 2),
+                Element::from(q, q - 2)
+            );
+            assert_eq!(
+                Element::from(q, max) * u64::MAX,
+                Element::from(q, q - u64::MAX % q)
+            );
+
+            let mut f = Element::from(q, max);
+            f *= Element::from(q, max);
+            f += Element::from(q, max);
+            assert_eq!(f, Element::from(q, 0));
+
+            let half = q / 2 + 1;
+            let expected = ((half as u128 * half as u128) % q as u128) as u64;
+            assert_eq!(
+                Element::from(q, half) * Element::from(q, half),
+                Element::from(q, expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_const_element() {
+        type E = ConstElement<101>;
+        assert_eq!(E::from(100) + E::from(3), E::from(2));
+        assert_eq!(E::from(1) - E::from(3), E::from(99));
+        assert_eq!(E::from(3) - E::from(3), E::zero());
+        assert_eq!(E::from(50) * E::from(3), E::from(49));
+
+        let mut f = E::from(100);
+        f *= E::from(2);
+        f += E::from(5);
+        f -= E::from(1);
+        assert_eq!(f.uint(), 2);
+
+        let e = Element::from(101, 42);
+        assert_eq!(E::from_element(&e).to_element(), e);
+
+        type W = ConstElement<Q32>;
+        assert_eq!(W::from(Q32 - 1) * W::from(Q32 - 1), W::from(1));
+        assert_eq!(W::zero() - W::from(1), W::from(Q32 - 1));
+
+        const BIG: u64 = u64::MAX - 58;
+        type B = ConstElement<BIG>;
+        assert_eq!(B::from(BIG - 1) * B::from(BIG - 1), B::from(1));
+        assert_eq!(B::from(BIG - 1) + B::from(BIG - 1), B::from(BIG - 2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_const_element_wrong_modulus() {
+        ConstElement::<101>::from_element(&Element::from(103, 1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let e = Element::from(3329, 1234);
+        let json = serde_json::to_string(&e).unwrap();
+        assert_eq!(json, r#"{"q":3329,"uint":1234}"#);
+        assert_eq!(serde_json::from_str::<Element>(&json).unwrap(), e);
+
+        assert!(serde_json::from_str::<Element>(r#"{"q":3329,"uint":3329}"#).is_err());
+        assert!(serde_json::from_str::<Element>(r#"{"q":3329}"#).is_err());
+    }
+
+    #[test]
+    fn test_q32() {
+        let max = Q32 - 1;
+        assert_eq!(
+            Element::from(Q32, max) + Element::from(Q32, 2),
+            Element::from(Q32, 1)
+        );
+        assert_eq!(
+            Element::from(Q32, max) * Element::from(Q32, max),
+            Element::from(Q32, 1)
+        );
+        assert_eq!(
+            Element::from(Q32, 0) - Element::from(Q32, 1),
+            Element::from(Q32, max)
+        );
+        assert_eq!(Element::from(Q32, 1 << 31) * 2u64, Element::from(Q32, 0));
+        assert_eq!(Element::from(Q32, 5) + (Q32 + 3), Element::from(Q32, 8));
+    }
+
+    #[test]
+    fn test_checked_from() {
+        assert_eq!(
+            Element::checked_from(3329, 3328),
+            Ok(Element::from(3329, 3328))
+        );
+        assert_eq!(
+            Element::checked_from(3329, 3329),
+            Err(SimplePirError::OutOfRange {
+                q: 3329,
+                uint: 3329
+            }),
+        );
+        assert_eq!(
+            Element::checked_from(0, 0),
+            Err(SimplePirError::InvalidModulus(0))
+        );
+        assert_eq!(
+            Element::checked_from(u64::MAX, 1),
+            Err(SimplePirError::InvalidModulus(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_conditional_select() {
+        let a = Element::from(3329, 5);
+        let b = Element::from(3329, 3000);
+        assert_eq!(Element::conditional_select(&a, &b, Choice::from(0)), a);
+        assert_eq!(Element::conditional_select(&a, &b, Choice::from(1)), b);
+
+        let mut c = a.clone();
+        c.conditional_assign(&b, Choice::from(0));
+        assert!(bool::from(c.ct_eq(&a)));
+        c.conditional_assign(&b, Choice::from(1));
+        assert!(bool::from(c.ct_eq(&b)));
+        assert!(!bool::from(c.ct_eq(&Element::from(3331, 3000))));
+
+        // The branchless reductions at both ends of the range
+        let q = u64::MAX - 58;
+        assert_eq!(
+            Element::from(q, 0) - Element::from(q, q - 1),
+            Element::from(q, 1)
+        );
+        assert_eq!(
+            Element::from(q, q - 1) - Element::from(q, q - 1),
+            Element::from(q, 0)
+        );
+        assert_eq!(
+            Element::from(q, 1) + Element::from(q, q - 1),
+            Element::from(q, 0)
+        );
+    }
+
+    #[test]
+    fn test_scalar_ops() {
+        let q = gen_q();
+        assert_eq!(Element::from(q, 100u64) + 3u64, Element::from(q, 2u64));
+        assert_eq!(Element::from(q, 1u64) + 202u64, Element::from(q, 1u64));
+        assert_eq!(Element::from(q, 1u64) - 3u64, Element::from(q, 99u64));
+        assert_eq!(Element::from(q, 1u64) - 102u64, Element::from(q, 0u64));
+        assert_eq!(Element::from(q, 50u64) * 3u64, Element::from(q, 49u64));
+        assert_eq!(Element::from(q, 2u64) * 102u64, Element::from(q, 2u64));
+    }
+
+    #[test]
+    fn test_mod_switch() {
+        let q = gen_q();
+        assert_eq!(
+            Element::from(q, 0u64).mod_switch(10),
+            Element::from(10, 0u64)
+        );
+        assert_eq!(
+            Element::from(q, 50u64).mod_switch(10),
+            Element::from(10, 5u64)
+        );
+        assert_eq!(
+            Element::from(q, 56u64).mod_switch(10),
+            Element::from(10, 6u64)
+        );
+        assert_eq!(
+            Element::from(q, 100u64).mod_switch(10),
+            Element::from(10, 0u64)
+        );
+        assert_eq!(
+            Element::from(q, 100u64).mod_switch(1000),
+            Element::from(1000, 990u64)
+        );
+    }
+
     #[test]
     fn test_recompose() {
         let q = gen_q();
@@ -291,21 +763,53 @@ pub mod tests {
     #[test]
     fn test_decomposed() {
         let q = gen_q();
-        assert_eq!(Element::from(q, 1u64).decomposed(2), vec![1, 0, 0, 0, 0, 0, 0]);
-        assert_eq!(Element::from(q, 2u64).decomposed(2), vec![0, 1, 0, 0, 0, 0, 0]);
-        assert_eq!(Element::from(q, 3u64).decomposed(2), vec![1, 1, 0, 0, 0, 0, 0]);
-        assert_eq!(Element::from(q, 4u64).decomposed(2), vec![0, 0, 1, 0, 0, 0, 0]);
-        assert_eq!(Element::from(q, 100u64).decomposed(2), vec![0, 0, 1, 0, 0, 1, 1]);
+        assert_eq!(
+            Element::from(q, 1u64).decomposed(2),
+            vec![1, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            Element::from(q, 2u64).decomposed(2),
+            vec![0, 1, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            Element::from(q, 3u64).decomposed(2),
+            vec![1, 1, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            Element::from(q, 4u64).decomposed(2),
+            vec![0, 0, 1, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            Element::from(q, 100u64).decomposed(2),
+            vec![0, 0, 1, 0, 0, 1, 1]
+        );
     }
 
-    /*
     #[test]
-    fn test_gen_normal_rand() {
+    fn test_discrete_gaussian() {
+        let mut rng = ChaCha20Rng::from_seed([4u8; 32]);
         let q = gen_q();
-        for i in 0..100 {
-            let e = Element::gen_normal_rand(q.clone(), 6.4 as f64);
-            println!("{}", e);
-        }
+        let (std_dev, bound) = (3.2, 20);
+        let num_samples = 20000;
+
+        let samples: Vec<i64> = (0..num_samples)
+            .map(|_| {
+                let e = Element::gen_discrete_gaussian_with_rng(q, std_dev, bound, &mut rng);
+                if e.uint > q / 2 {
+                    e.uint as i64 - q as i64
+                } else {
+                    e.uint as i64
+                }
+            })
+            .collect();
+        assert!(samples.iter().all(|x| x.unsigned_abs() <= bound));
+
+        let mean = samples.iter().sum::<i64>() as f64 / num_samples as f64;
+        let var = samples.iter().map(|x| (x * x) as f64).sum::<f64>() / num_samples as f64;
+        assert!(mean.abs() < 0.1);
+        assert!((var - std_dev * std_dev).abs() < 0.1 * std_dev * std_dev);
+
+        let e = Element::gen_normal_rand(q, 6.4);
+        assert!(e.uint <= 39 || e.uint >= q - 39);
     }
-    */
 }
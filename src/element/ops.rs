@@ -0,0 +1,117 @@
+// Arithmetic on slices of values which share one modulus.
+//
+// The Element operators check that both operands have the same modulus on every operation, and
+// carry the modulus along with each value, which dominates the cost of the long loops of the
+// server. These functions work on plain u64 values mod q instead, so that the modulus is checked
+// once per slice by the caller. They add and subtract with the same constant-time helpers as
+// Element. Products are reduced with the Barrett constant of q, computed once per slice, for q
+// below 2^32, and wrap natively for q = 2^32; only larger moduli fall back to mul_mod().
+
+use super::{add_mod, mul_mod, sub_mod, Q32};
+use crate::modulus::Modulus;
+
+// The Barrett reduction for q, if q is small enough for products of two values below q to fit in
+// a u64. Products mod 2^32 wrap natively and need no reduction.
+fn barrett(q: u64) -> Option<Modulus> {
+    (2..Q32).contains(&q).then(|| Modulus::new(q))
+}
+
+/// Compute a[i] = a[i] + b[i] mod q.
+pub fn add_assign_slices(a: &mut [u64], b: &[u64], q: u64) {
+    assert_eq!(a.len(), b.len());
+    a.iter_mut().zip(b).for_each(|(x, &y)| *x = add_mod(*x, y, q));
+}
+
+/// Compute a[i] = a[i] - b[i] mod q.
+pub fn sub_assign_slices(a: &mut [u64], b: &[u64], q: u64) {
+    assert_eq!(a.len(), b.len());
+    a.iter_mut().zip(b).for_each(|(x, &y)| *x = sub_mod(*x, y, q));
+}
+
+/// Compute a[i] = c * a[i] mod q, where c need not be below q.
+pub fn scale_assign(a: &mut [u64], c: u64, q: u64) {
+    match barrett(q) {
+        Some(modulus) => {
+            let c = modulus.reduce(c);
+            a.iter_mut().for_each(|x| *x = modulus.mul(*x, c));
+        }
+        // mul_mod() takes b above q for these moduli
+        None => a.iter_mut().for_each(|x| *x = mul_mod(*x, c, q)),
+    }
+}
+
+/// Compute acc[i] = acc[i] + c * x[i] mod q, where c need not be below q.
+pub fn scale_accumulate(acc: &mut [u64], x: &[u64], c: u64, q: u64) {
+    assert_eq!(acc.len(), x.len());
+    match barrett(q) {
+        Some(modulus) => {
+            let c = modulus.reduce(c);
+            acc.iter_mut().zip(x).for_each(|(sum, &y)| *sum = modulus.add(*sum, modulus.mul(c, y)));
+        }
+        None => {
+            acc.iter_mut().zip(x).for_each(|(sum, &y)| *sum = add_mod(*sum, mul_mod(y, c, q), q));
+        }
+    }
+}
+
+/// Compute sum(a[i] * b[i]) mod q.
+pub fn dot(a: &[u64], b: &[u64], q: u64) -> u64 {
+    assert_eq!(a.len(), b.len());
+    match barrett(q) {
+        // Each reduced product is below 2^32, so fewer than 2^32 of them sum exactly in a u64
+        Some(modulus) if (a.len() as u64) < 1 << 32 => {
+            let sum = a.iter().zip(b).fold(0u64, |acc, (&x, &y)| acc + modulus.mul(x, y));
+            modulus.reduce(sum)
+        }
+        _ => {
+            a.iter().zip(b).fold(0, |acc, (&x, &y)| add_mod(acc, mul_mod(x, y, q), q))
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::element::{Element, Q32};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_slice_ops() {
+        for q in [2, 101, 3329, Q32 - 5, Q32, 4294967311, u64::MAX - 58] {
+            let a: Vec<Element> = (0..50).map(|_| Element::gen_uniform_rand(q)).collect();
+            let b: Vec<Element> = (0..50).map(|_| Element::gen_uniform_rand(q)).collect();
+            let c = Element::gen_uniform_rand(q);
+            let uints = |v: &[Element]| -> Vec<u64> { v.iter().map(|e| e.uint).collect() };
+
+            let mut sum = uints(&a);
+            add_assign_slices(&mut sum, &uints(&b), q);
+            let mut diff = uints(&a);
+            sub_assign_slices(&mut diff, &uints(&b), q);
+            let mut scaled = uints(&a);
+            scale_assign(&mut scaled, c.uint, q);
+            let mut acc = uints(&a);
+            scale_accumulate(&mut acc, &uints(&b), c.uint, q);
+            let mut expected_dot = Element::zero(q);
+            for i in 0..50 {
+                assert_eq!(sum[i], (a[i].clone() + b[i].clone()).uint);
+                assert_eq!(diff[i], (a[i].clone() - b[i].clone()).uint);
+                assert_eq!(scaled[i], (a[i].clone() * c.clone()).uint);
+                assert_eq!(acc[i], (a[i].clone() + c.clone() * b[i].clone()).uint);
+                expected_dot += a[i].clone() * b[i].clone();
+            }
+            assert_eq!(dot(&uints(&a), &uints(&b), q), expected_dot.uint);
+
+            // Scalars above q are reduced first
+            let big = u64::MAX - c.uint;
+            let mut scaled = uints(&a);
+            scale_assign(&mut scaled, big, q);
+            let mut acc = uints(&a);
+            scale_accumulate(&mut acc, &uints(&b), big, q);
+            let big = Element::from(q, big % q);
+            for i in 0..50 {
+                assert_eq!(scaled[i], (a[i].clone() * big.clone()).uint);
+                assert_eq!(acc[i], (a[i].clone() + big.clone() * b[i].clone()).uint);
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// Per-record encryption, so that one PIR server can hold records of differing sensitivity.
+//
+// The database owner seals each record before setup, either under one of its record keys or
+// in the clear, and hands the keys to the clients entitled to them out of band. A sealed record
+// holds the id of its key, a random nonce and the record encrypted with ChaCha20-Poly1305, with
+// the record's index as associated data so that the server cannot swap sealed records around.
+// All sealed records have the same length whether encrypted or not, so the layout (and so every
+// query and answer) is the same for all of them, and PIR hides which kind was retrieved.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag};
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+use crate::error::SimplePirError;
+
+/// The key id of records which are not encrypted.
+pub const PUBLIC: u32 = 0;
+
+/// The number of bytes which sealing adds to a record: the key id, nonce and tag.
+pub const OVERHEAD: usize = 4 + 12 + 16;
+
+/// The length of a sealed record of record_len bytes, which is the record length to lay out a
+/// database of sealed records with.
+pub fn sealed_len(record_len: usize) -> usize {
+    record_len + OVERHEAD
+}
+
+/// A key which records are encrypted under.
+#[derive(Clone)]
+pub struct RecordKey([u8; 32]);
+
+impl RecordKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn gen_with_rng<R: CryptoRng + RngCore>(rng: &mut R) -> Self {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+impl Drop for RecordKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Seal record idx, encrypting it under the key with the given id, or leaving it in the clear
+/// for key None.
+pub fn seal<R: CryptoRng + RngCore>(
+    idx: usize,
+    record: &[u8],
+    key: Option<(u32, &RecordKey)>,
+    rng: &mut R,
+) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(sealed_len(record.len()));
+    let Some((id, key)) = key else {
+        sealed.extend_from_slice(&PUBLIC.to_le_bytes());
+        sealed.extend_from_slice(&[0; 28]);
+        sealed.extend_from_slice(record);
+        return sealed;
+    };
+    assert_ne!(id, PUBLIC, "key id {} is reserved for public records", PUBLIC);
+    let mut nonce = [0u8; 12];
+    rng.fill_bytes(&mut nonce);
+    let mut body = record.to_vec();
+    let aad = (idx as u64).to_le_bytes();
+    let tag = key.cipher()
+        .encrypt_in_place_detached(Nonce::from_slice(&nonce), &aad, &mut body)
+        .expect("the record is too long to encrypt");
+
+    sealed.extend_from_slice(&id.to_le_bytes());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&tag);
+    sealed.extend_from_slice(&body);
+    sealed
+}
+
+/// The key a client expects a record to be under. The key id is stored in the record, which
+/// the server controls, so a client which accepted whatever it found would open a public record
+/// substituted by the server for a sealed one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// The record is in the clear.
+    Public,
+    /// The record is sealed under any key in the keyring.
+    Sealed,
+    /// The record is sealed under the key with this id.
+    Key(u32),
+}
+
+/// The record keys held by a client, by key id.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: BTreeMap<u32, RecordKey>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: u32, key: RecordKey) {
+        assert_ne!(id, PUBLIC, "key id {} is reserved for public records", PUBLIC);
+        self.keys.insert(id, key);
+    }
+
+    /// Open sealed record idx, which must be under the expected key. Records under another key,
+    /// or public records when a sealed one was expected, are rejected with UnexpectedRecordKey.
+    /// Records under a key which is not in the keyring are rejected with UnknownRecordKey, and
+    /// records which fail to decrypt with MalformedAnswer.
+    pub fn open(
+        &self,
+        idx: usize,
+        sealed: &[u8],
+        expected: Expected,
+    ) -> Result<Vec<u8>, SimplePirError> {
+        if sealed.len() < OVERHEAD {
+            return Err(SimplePirError::LengthMismatch { expected: OVERHEAD, actual: sealed.len() });
+        }
+        let (header, body) = sealed.split_at(OVERHEAD);
+        let id = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let is_expected = match expected {
+            Expected::Public => id == PUBLIC,
+            Expected::Sealed => id != PUBLIC,
+            Expected::Key(expected) => id == expected,
+        };
+        if !is_expected {
+            return Err(SimplePirError::UnexpectedRecordKey(id));
+        }
+        if id == PUBLIC {
+            return Ok(body.to_vec());
+        }
+        let key = self.keys.get(&id).ok_or(SimplePirError::UnknownRecordKey(id))?;
+        let (nonce, tag) = header[4..].split_at(12);
+        let mut record = body.to_vec();
+        let (nonce, tag) = (Nonce::from_slice(nonce), Tag::from_slice(tag));
+        let aad = (idx as u64).to_le_bytes();
+        key.cipher()
+            .decrypt_in_place_detached(nonce, &aad, &mut record, tag)
+            .map_err(|_| SimplePirError::MalformedAnswer)?;
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_seal_open() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let (key1, key2) = (RecordKey::gen_with_rng(&mut rng), RecordKey::gen_with_rng(&mut rng));
+        let record = b"a record of some length".to_vec();
+        let public = seal(0, &record, None, &mut rng);
+        let secret = seal(1, &record, Some((1, &key1)), &mut rng);
+        assert_eq!((public.len(), secret.len()), (sealed_len(23), sealed_len(23)));
+        assert_ne!(&secret[OVERHEAD..], record.as_slice());
+
+        let mut keyring = Keyring::new();
+        assert_eq!(keyring.open(0, &public, Expected::Public), Ok(record.clone()));
+        let unknown = keyring.open(1, &secret, Expected::Sealed);
+        assert_eq!(unknown, Err(SimplePirError::UnknownRecordKey(1)));
+        keyring.insert(1, key1);
+        keyring.insert(2, key2);
+        assert_eq!(keyring.open(1, &secret, Expected::Sealed), Ok(record.clone()));
+        assert_eq!(keyring.open(1, &secret, Expected::Key(1)), Ok(record.clone()));
+
+        // Records under another key than the expected one are rejected, so that the server cannot
+        // pass off a public record as a sealed one
+        let unexpected = Err(SimplePirError::UnexpectedRecordKey(PUBLIC));
+        assert_eq!(keyring.open(0, &public, Expected::Sealed), unexpected);
+        assert_eq!(keyring.open(0, &public, Expected::Key(1)), unexpected);
+        let unexpected = Err(SimplePirError::UnexpectedRecordKey(1));
+        assert_eq!(keyring.open(1, &secret, Expected::Key(2)), unexpected);
+        assert_eq!(keyring.open(1, &secret, Expected::Public), unexpected);
+
+        // Records which were modified, or retrieved for another index, do not open
+        let mut modified = secret.clone();
+        modified[OVERHEAD] ^= 1;
+        let malformed = Err(SimplePirError::MalformedAnswer);
+        assert_eq!(keyring.open(1, &modified, Expected::Sealed), malformed);
+        assert_eq!(keyring.open(2, &secret, Expected::Sealed), malformed);
+        assert!(keyring.open(1, &secret[..OVERHEAD - 1], Expected::Sealed).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use alloc::string::String;
+use core::time::Duration;
+use thiserror::Error;
+
+/// Why an operation was rejected. Inputs which can come from the other party (queries, answers)
+/// or from the caller (indices, secrets, plaintexts) are checked and reported with this error,
+/// while violated internal invariants still panic.
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum SimplePirError {
+    // A value is not below its modulus
+    #[error("{uint} is not a valid element mod {q}")]
+    OutOfRange { q: u64, uint: u64 },
+    // The modulus cannot be used for elements
+    #[error("{0} is not a valid modulus")]
+    InvalidModulus(u64),
+    // An element has a different modulus than the params or the other operand
+    #[error("expected an element mod {expected}, got one mod {actual}")]
+    ModulusMismatch { expected: u64, actual: u64 },
+    // A vector or matrix has the wrong number of elements
+    #[error("expected {expected} elements, got {actual}")]
+    LengthMismatch { expected: usize, actual: usize },
+    // An item index is past the end of the database
+    #[error("index {idx} is out of range for {len} items")]
+    IndexOutOfRange { idx: usize, len: usize },
+    // A range of indices ends before it starts
+    #[error("the range {start}..{end} ends before it starts")]
+    InvalidRange { start: usize, end: usize },
+    // The named query, answer or database has no elements
+    #[error("the {0} is empty")]
+    Empty(&'static str),
+    // The client has no outstanding query to recover
+    #[error("there is no outstanding query")]
+    NoPendingQuery,
+    // No vetted parameter set reaches the requested security level
+    #[error("no parameter set reaches {0}-bit security")]
+    UnsupportedSecurity(u32),
+    // The database needs more LWE samples than the parameter set is vetted for
+    #[error("a database of {rows} rows exceeds the {max} LWE samples of the parameter set")]
+    TooManySamples { rows: usize, max: usize },
+    // The bytes do not fit in a matrix, which holds ⌊log2(p)⌋ bits per element
+    #[error("{len} bytes do not fit in a matrix holding {capacity} bytes")]
+    TooManyBytes { len: usize, capacity: usize },
+    // A database backend failed to read the database
+    #[error("failed to read the database: {0}")]
+    Storage(String),
+    // The NTT needs a prime q ≡ 1 mod 2d and a power of two d
+    #[error("the NTT does not support q = {q} with ring dimension {d}")]
+    UnsupportedRing { q: u64, d: usize },
+    // A decrypted answer carries more noise than an honest one can, or a record fails its check
+    #[error("the answer is malformed")]
+    MalformedAnswer,
+    // The bytes of an OPRF request or response do not encode a point of the group
+    #[error("the bytes are not a valid group element")]
+    InvalidGroupElement,
+    // A record is encrypted under a key which the client does not hold
+    #[error("the record is encrypted under the unknown key {0}")]
+    UnknownRecordKey(u32),
+    // A record is public or sealed under another key than the one the client expected, which a
+    // server could use to downgrade or substitute records
+    #[error("the record is under the key {0}, which is not the key the client expected")]
+    UnexpectedRecordKey(u32),
+    // A retrieved record does not hash to the Merkle root published by the server
+    #[error("the record does not match the published Merkle root")]
+    Unauthenticated,
+    // The client and server were set up with params with different digests
+    #[error("the params do not match those of the other party")]
+    ParamsMismatch,
+    // The indices of a batch could not be assigned to distinct buckets, so the batch should be
+    // split
+    #[error("the batch could not be spread over distinct buckets")]
+    UnplaceableBatch,
+    // A key-value database holds the same key twice
+    #[error("the key is stored more than once")]
+    DuplicateKey,
+    // Distinct keys of a key-value bucket share a tag under every seed that was tried
+    #[error("the key tags collide under every seed that was tried")]
+    KeyTagCollision,
+    // An item was updated on a server which holds byte records, which must be updated whole
+    #[error("the server holds byte records, not items")]
+    HasRecordLayout,
+    // The named records or database cannot be updated in place
+    #[error("the {0} cannot be updated")]
+    ReadOnly(&'static str),
+    // An item was recovered with a partial hint which does not cover its column
+    #[error("column {idx} is not covered by the partial hint of columns {start}..{end}")]
+    UncoveredColumn { idx: usize, start: usize, end: usize },
+    // A tagged answer is for another query than the outstanding one, or from another epoch than
+    // it claims
+    #[error("the answer is tagged for another query")]
+    AnswerMismatch,
+    // The answers of a server's self-test took longer on average than the limit
+    #[error("the self-test answered in {elapsed:?} on average, over the limit of {limit:?}")]
+    SlowSelfTest { elapsed: Duration, limit: Duration },
+    // A signed answer or manifest was requested from a server without a signing key, or checked
+    // by a client without a verifying key
+    #[error("no signing key is set")]
+    NoSigningKey,
+    // A signature does not verify under the server's key
+    #[error("the signature is invalid")]
+    InvalidSignature,
+    // Signed data was built longer ago than the client's freshness policy allows
+    #[error("the data was built {age:?} ago, over the limit of {max_age:?}")]
+    Stale { age: Duration, max_age: Duration },
+    // A differential privacy budget is not finite and positive
+    #[error("{0} is not a valid privacy budget")]
+    InvalidEpsilon(f64),
+    // The named size does not fit in a usize on this target, e.g. on wasm32
+    #[error("the {0} is too large for this target")]
+    TooLarge(&'static str),
+    // A record was queried from or answered by a server or client without a record layout
+    #[error("the database has no record layout")]
+    NoRecordLayout,
+    // A range of a record was queried by a client which checks or opens whole records
+    #[error("ranges of checked or sealed records cannot be recovered")]
+    UncheckableRange,
+    // A symmetric query was sent to a server which was not set up with symmetric records
+    #[error("the server has no symmetric records")]
+    NoSymmetricRecords,
+    // A partial answer is resumed with another query or database than it was started with
+    #[error("the partial answer was computed for another query or database")]
+    PartialAnswerMismatch,
+    // The named params, hint or matrix do not have the dimensions they must have
+    #[error("the {0} has invalid dimensions")]
+    InvalidShape(&'static str),
+    // A standard deviation is not finite and positive
+    #[error("{0} is not a valid standard deviation")]
+    InvalidStdDev(f64),
+    // The operands of a matrix operation have incompatible dimensions
+    #[error(transparent)]
+    Shape(#[from] ShapeError),
+}
+
+/// The operands of a matrix operation have incompatible dimensions, given as returned by
+/// Matrix::dimensions(). A vector operand counts as a matrix of dimensions (len, 1).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Error)]
+#[error("cannot {op} matrices of dimensions {lhs:?} and {rhs:?}")]
+pub struct ShapeError {
+    pub op: &'static str,
+    pub lhs: (usize, usize),
+    pub rhs: (usize, usize),
+}
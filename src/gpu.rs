@@ -0,0 +1,186 @@
+// The database scan of an answer on the GPU, through wgpu.
+//
+// The database is uploaded once as u32s and each query runs the compute shader in gpu.wgsl,
+// which sums the products of each column with the query exactly. Only values below 2^32 fit, so
+// the GPU serves q ≤ 2^32; callers fall back to the CPU when no GPU is available.
+
+use wgpu::util::DeviceExt;
+use crate::element::Element;
+use crate::matrix::Matrix;
+
+// The largest modulus supported by the shader
+pub(crate) const MAX_Q: u64 = 1 << 32;
+
+// Must match the workgroup size in gpu.wgsl
+const WORKGROUP_SIZE: usize = 64;
+
+/// A database mod p ≤ 2^32 held in GPU memory.
+pub struct GpuDatabase {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    db: wgpu::Buffer,
+    num_cols: usize,
+    num_rows: usize,
+}
+
+fn to_bytes(values: impl IntoIterator<Item = u32>) -> Vec<u8> {
+    values.into_iter().flat_map(u32::to_le_bytes).collect()
+}
+
+impl GpuDatabase {
+    /// Upload a database whose values are all mod p ≤ 2^32. Returns None if no GPU is available,
+    /// or if the database does not fit in the GPU's buffers.
+    pub fn new(db: &Matrix) -> Option<Self> {
+        let (num_cols, num_rows) = db.dimensions();
+        if num_cols == 0 || num_rows == 0 || db.q() > MAX_Q {
+            return None;
+        }
+
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(
+            instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+        ).ok()?;
+        let limits = adapter.limits();
+        let db_len = (num_cols * num_rows * 4) as u64;
+        if db_len > limits.max_storage_buffer_binding_size as u64
+            || db_len > limits.max_buffer_size
+            || num_cols.div_ceil(WORKGROUP_SIZE) > limits.max_compute_workgroups_per_dimension as usize
+        {
+            return None;
+        }
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_limits: limits,
+            ..Default::default()
+        })).ok()?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("simplepir answer"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("simplepir answer"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let values = db.values().iter().map(|&v| v as u32);
+        let db = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("simplepir database"),
+            contents: &to_bytes(values),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        Some(Self { device, queue, pipeline, db, num_cols, num_rows })
+    }
+
+    pub fn num_cols(&self) -> usize {
+        self.num_cols
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Overwrite the value at [col][row], e.g. after the server updated an item.
+    pub fn set(&self, col: usize, row: usize, value: &Element) {
+        assert!(col < self.num_cols && row < self.num_rows);
+        assert!(value.q <= MAX_Q);
+        let offset = ((col * self.num_rows + row) * 4) as u64;
+        self.queue.write_buffer(&self.db, offset, &(value.uint as u32).to_le_bytes());
+    }
+
+    /// Multiply by a vector mod the vector's modulus, which must not exceed 2^32, with the same
+    /// output shape as Matrix::mul_vec().
+    pub fn mul_vec(&self, rhs: &[Element]) -> Matrix {
+        assert!(!rhs.is_empty());
+        assert_eq!(self.num_rows, rhs.len());
+        let q = rhs[0].q;
+        assert!(q <= MAX_Q);
+
+        let query = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("simplepir query"),
+            contents: &to_bytes(rhs.iter().map(|e| {
+                assert_eq!(e.q, q);
+                e.uint as u32
+            })),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let dims = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("simplepir dims"),
+            contents: &to_bytes([self.num_cols as u32, self.num_rows as u32, 0, 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let sums_len = (self.num_cols * 3 * 4) as u64;
+        let sums = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("simplepir sums"),
+            size: sums_len,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("simplepir staging"),
+            size: sums_len,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[&self.db, &query, &sums, &dims].iter().enumerate().map(|(i, buffer)| {
+                wgpu::BindGroupEntry { binding: i as u32, resource: buffer.as_entire_binding() }
+            }).collect::<Vec<_>>(),
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(self.num_cols.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&sums, 0, &staging, 0, sums_len);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.expect("failed to read the answer"));
+        self.device.poll(wgpu::PollType::Wait).expect("the GPU stopped responding");
+        let bytes = slice.get_mapped_range();
+        let limb = |i: usize| u32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap()) as u128;
+        let data: Vec<Vec<Element>> = (0..self.num_cols).map(|col| {
+            let sum = limb(3 * col) | limb(3 * col + 1) << 32 | limb(3 * col + 2) << 64;
+            vec![Element::from(q, (sum % q as u128) as u64)]
+        }).collect();
+        Matrix::from(&data)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_mul_vec() {
+        for q in [3329, 4294967291, MAX_Q] {
+            let m = Matrix::gen_uniform_rand(q, 100, 37);
+            let Some(gpu) = GpuDatabase::new(&m) else {
+                eprintln!("no GPU available, skipping");
+                return;
+            };
+            let mut v = Matrix::gen_uniform_rand(q, 1, 37).col(0);
+            assert_eq!(gpu.mul_vec(&v), m.clone().mul_vec(&v));
+
+            // The largest values exercise every carry of the shader's accumulation
+            v.iter_mut().for_each(|e| *e = Element::from(q, q - 1));
+            let mut m = Matrix::from_val(100, 37, Element::from(q, q - 1));
+            let gpu = GpuDatabase::new(&m).unwrap();
+            assert_eq!(gpu.mul_vec(&v), m.clone().mul_vec(&v));
+
+            m.set(3, 5, &Element::from(q, 1));
+            gpu.set(3, 5, &m.get(3, 5));
+            assert_eq!(gpu.mul_vec(&v), m.clone().mul_vec(&v));
+        }
+    }
+}
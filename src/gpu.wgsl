@@ -0,0 +1,48 @@
+// The database scan of SimplePIR's answer: one invocation per database column computes the exact
+// inner product of the column with the query. WGSL has no 64-bit integers, so each product is
+// formed as two u32 halves and the sum is accumulated in three u32 limbs (96 bits, which cannot
+// overflow for fewer than 2^32 terms). The sums are reduced mod q on the CPU.
+
+struct Dims {
+    num_cols: u32,
+    num_rows: u32,
+}
+
+@group(0) @binding(0) var<storage, read> db: array<u32>;
+@group(0) @binding(1) var<storage, read> query: array<u32>;
+@group(0) @binding(2) var<storage, read_write> sums: array<u32>;
+@group(0) @binding(3) var<uniform> dims: Dims;
+
+// The 64-bit product of a and b, as its low and high u32 halves
+fn mul_wide(a: u32, b: u32) -> vec2<u32> {
+    let a0 = a & 0xffffu;
+    let a1 = a >> 16u;
+    let b0 = b & 0xffffu;
+    let b1 = b >> 16u;
+    let lo = a0 * b0;
+    let t = a1 * b0 + (lo >> 16u);
+    let u = a0 * b1 + (t & 0xffffu);
+    return vec2<u32>((u << 16u) | (lo & 0xffffu), a1 * b1 + (t >> 16u) + (u >> 16u));
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let col = id.x;
+    if (col >= dims.num_cols) {
+        return;
+    }
+
+    let base = col * dims.num_rows;
+    var acc = vec3<u32>(0u, 0u, 0u);
+    for (var row = 0u; row < dims.num_rows; row++) {
+        let p = mul_wide(db[base + row], query[row]);
+        let lo = acc.x + p.x;
+        let mid = acc.y + p.y;
+        let mid_carried = mid + select(0u, 1u, lo < p.x);
+        let carry = select(0u, 1u, mid < p.y) + select(0u, 1u, mid_carried < mid);
+        acc = vec3<u32>(lo, mid_carried, acc.z + carry);
+    }
+    sums[3u * col] = acc.x;
+    sums[3u * col + 1u] = acc.y;
+    sums[3u * col + 2u] = acc.z;
+}
@@ -0,0 +1,272 @@
+//! A gRPC Pir service built on pir::Server, and an async client for it. The service and its
+//! messages are defined in proto/simplepir.proto.
+
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Response, Status};
+
+use crate::compression::{self, Codec, CompressReader};
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::merkle::Digest;
+use crate::pir;
+use crate::proto::{self, Empty, ProtoError};
+use crate::wire;
+
+mod generated {
+    tonic::include_proto!("simplepir");
+}
+
+pub use generated::pir_client::PirClient;
+pub use generated::pir_server::{Pir, PirServer};
+
+/// The Pir service, answering queries against a shared pir::Server.
+pub struct PirService {
+    server: Arc<pir::Server>,
+}
+
+impl PirService {
+    pub fn new(server: pir::Server) -> Self {
+        Self { server: Arc::new(server) }
+    }
+
+    /// Wrap the service for registering with a tonic transport server.
+    pub fn into_server(self) -> PirServer<Self> {
+        PirServer::new(self)
+    }
+}
+
+/// The bytes of the hint encoding sent in each GetHintChunks message.
+pub const HINT_CHUNK_LEN: usize = 1 << 20;
+
+// The chunks encoded ahead of the client, after which the encoder waits for it to catch up
+const HINT_CHUNKS_IN_FLIGHT: usize = 4;
+
+fn invalid_argument(err: ProtoError) -> Status {
+    Status::invalid_argument(err.to_string())
+}
+
+#[tonic::async_trait]
+impl Pir for PirService {
+    async fn get_params(&self, _: Request<Empty>) -> Result<Response<proto::Params>, Status> {
+        Ok(Response::new(self.server.params().into()))
+    }
+
+    async fn get_hint(&self, _: Request<Empty>) -> Result<Response<proto::Hint>, Status> {
+        Ok(Response::new(self.server.hint().into()))
+    }
+
+    type GetHintChunksStream = ReceiverStream<Result<proto::HintChunk, Status>>;
+
+    async fn get_hint_chunks(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<Self::GetHintChunksStream>, Status> {
+        // Clients which offer codecs get the hint compressed, see the compression module
+        let codec = request.metadata().get(compression::ACCEPT_HEADER)
+            .map(|offered| Codec::negotiate(offered.to_str().unwrap_or("")));
+        // The bounded channel blocks the encoder once HINT_CHUNKS_IN_FLIGHT chunks are waiting,
+        // so a slow client never makes the server hold more of the encoding than that
+        let (sender, receiver) = mpsc::channel(HINT_CHUNKS_IN_FLIGHT);
+        let server = self.server.clone();
+        tokio::task::spawn_blocking(move || {
+            let hint = wire::MatrixReader::new(&server.hint().0);
+            let mut reader: Box<dyn Read> = match codec {
+                Some(codec) => Box::new(CompressReader::new(hint, codec)),
+                None => Box::new(hint),
+            };
+            loop {
+                let mut data = Vec::with_capacity(HINT_CHUNK_LEN);
+                let chunk = match (&mut reader).take(HINT_CHUNK_LEN as u64).read_to_end(&mut data) {
+                    Ok(0) => break,
+                    Ok(_) => Ok(proto::HintChunk { data }),
+                    Err(err) => Err(Status::internal(err.to_string())),
+                };
+                // Stop once the client has gone away
+                if sender.blocking_send(chunk).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn answer(
+        &self,
+        request: Request<proto::Query>,
+    ) -> Result<Response<proto::Answer>, Status> {
+        let request = request.into_inner();
+        let digest = Digest::try_from(request.params_digest.as_slice())
+            .map_err(|_| Status::invalid_argument("the params digest is not 32 bytes"))?;
+        let query: pir::Query = request.try_into().map_err(invalid_argument)?;
+
+        // Scanning the database is CPU-bound, so keep it off the async worker threads
+        let server = self.server.clone();
+        let answer = tokio::task::spawn_blocking(move || server.answer_checked(&digest, &query))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?
+            .map_err(|err| match err {
+                SimplePirError::ParamsMismatch => Status::failed_precondition(err.to_string()),
+                err => Status::invalid_argument(err.to_string()),
+            })?;
+        Ok(Response::new((&answer).into()))
+    }
+}
+
+/// Why a remote retrieval failed.
+#[derive(Debug)]
+pub enum GrpcError {
+    // The connection could not be established
+    Transport(tonic::transport::Error),
+    // The server returned an error
+    Status(Status),
+    // The server sent a malformed message
+    Proto(ProtoError),
+    // The server sent a malformed hint encoding
+    Wire(wire::WireError),
+    // The query could not be generated or the answer could not be recovered
+    Pir(SimplePirError),
+}
+
+impl Display for GrpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GrpcError::Transport(err) => write!(f, "transport error: {}", err),
+            GrpcError::Status(status) => write!(f, "server error: {}", status),
+            GrpcError::Proto(err) => write!(f, "malformed message: {}", err),
+            GrpcError::Wire(err) => write!(f, "malformed hint: {}", err),
+            GrpcError::Pir(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for GrpcError {}
+
+impl From<tonic::transport::Error> for GrpcError {
+    fn from(err: tonic::transport::Error) -> Self {
+        GrpcError::Transport(err)
+    }
+}
+
+impl From<Status> for GrpcError {
+    fn from(status: Status) -> Self {
+        GrpcError::Status(status)
+    }
+}
+
+impl From<ProtoError> for GrpcError {
+    fn from(err: ProtoError) -> Self {
+        GrpcError::Proto(err)
+    }
+}
+
+impl From<wire::WireError> for GrpcError {
+    fn from(err: wire::WireError) -> Self {
+        GrpcError::Wire(err)
+    }
+}
+
+impl From<SimplePirError> for GrpcError {
+    fn from(err: SimplePirError) -> Self {
+        GrpcError::Pir(err)
+    }
+}
+
+/// A pir::Client talking to a remote Pir service.
+pub struct RemoteClient {
+    rpc: PirClient<Channel>,
+    client: pir::Client,
+}
+
+impl RemoteClient {
+    /// Connect to the service at dst (e.g. "http://127.0.0.1:50051") and download the params
+    /// and hint. The hint is streamed in chunks, as a single message could exceed the message
+    /// size limits of the transport, and compressed with any codec both sides support.
+    pub async fn connect(dst: String) -> Result<Self, GrpcError> {
+        let channel = Endpoint::from_shared(dst)?.connect().await?;
+        let mut rpc = PirClient::new(channel);
+        let params = rpc.get_params(Empty {}).await?.into_inner().try_into()?;
+        let mut request = Request::new(Empty {});
+        request.metadata_mut().insert(compression::ACCEPT_HEADER, Codec::offer().parse().unwrap());
+        let mut chunks = rpc.get_hint_chunks(request).await?.into_inner();
+        let mut hint = vec![];
+        while let Some(chunk) = chunks.next().await {
+            hint.extend_from_slice(&chunk?.data);
+        }
+        // The server streams as much of the hint as it likes either way, so its decompressed
+        // length is not bounded here
+        let hint = wire::decode_hint(&compression::decompress(&hint, usize::MAX)?)?;
+        Ok(Self { rpc, client: pir::Client::new(params, hint)? })
+    }
+
+    /// Privately retrieve item idx.
+    pub async fn retrieve(&mut self, idx: usize) -> Result<Element, GrpcError> {
+        let mut query = proto::Query::from(&self.client.query(idx)?);
+        query.params_digest = self.client.params_digest().to_vec();
+        let answer: pir::Answer = self.rpc.answer(query).await?.into_inner().try_into()?;
+        Ok(self.client.recover(&answer)?)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::simplepir;
+    use tonic::transport::server::TcpIncoming;
+
+    #[test]
+    fn test_grpc() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let db_size = 20;
+            let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+            let items: Vec<Element> = (0..db_size).map(|_| Element::gen_uniform_rand(2)).collect();
+            let service = PirService::new(pir::Server::setup(params, &items).unwrap());
+            let hint = wire::encode_hint(service.server.hint());
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+            tokio::spawn(
+                tonic::transport::Server::builder()
+                    .add_service(service.into_server())
+                    .serve_with_incoming(incoming),
+            );
+
+            let mut client = RemoteClient::connect(format!("http://{}", addr)).await.unwrap();
+            for (idx, item) in items.iter().enumerate() {
+                assert_eq!(client.retrieve(idx).await.unwrap(), *item);
+            }
+
+            // The streamed chunks make up the hint encoding
+            let mut chunks = client.rpc.get_hint_chunks(Empty {}).await.unwrap().into_inner();
+            let mut streamed = vec![];
+            while let Some(chunk) = chunks.next().await {
+                let chunk = chunk.unwrap().data;
+                assert!(!chunk.is_empty() && chunk.len() <= HINT_CHUNK_LEN);
+                streamed.extend_from_slice(&chunk);
+            }
+            assert_eq!(streamed, hint);
+
+            // A query of the wrong length is rejected rather than crashing the service
+            let mut query = proto::Query::from(&pir::Query(vec![Element::from(3329, 1)]));
+            let status = client.rpc.answer(query.clone()).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+            query.params_digest = client.client.params_digest().to_vec();
+            let status = client.rpc.answer(query).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+            // As is a query generated with other params
+            let mut query = proto::Query::from(&client.client.query(0).unwrap());
+            query.params_digest = vec![0; 32];
+            let status = client.rpc.answer(query).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        });
+    }
+}
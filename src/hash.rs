@@ -0,0 +1,72 @@
+//! Hashing used to derive public, deterministic layouts (bucket choices, keyword slots) which
+//! client and server must agree on.
+
+use core::hash::Hasher;
+use sha2::{Digest as _, Sha256};
+use siphasher::sip::SipHasher13;
+
+/// A keyed hash of byte strings. Every use of a keyed hash gets its own key, derived with
+/// derive_key() from a domain naming the use and public context, so that hashes for different
+/// purposes are independent and client and server derive the same ones.
+pub trait KeyedHash {
+    fn new(key: &[u8; 32]) -> Self;
+
+    fn hash(&self, input: &[u8]) -> u64;
+}
+
+/// SipHash-1-3 keyed with the first 16 bytes of the key, the default keyed hash.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SipHash([u8; 16]);
+
+impl KeyedHash for SipHash {
+    fn new(key: &[u8; 32]) -> Self {
+        Self(key[..16].try_into().unwrap())
+    }
+
+    fn hash(&self, input: &[u8]) -> u64 {
+        let mut hasher = SipHasher13::new_with_key(&self.0);
+        hasher.write(input);
+        hasher.finish()
+    }
+}
+
+/// Derive the key of the hash for a domain (e.g. "kvpir/bucket") from public context such as a
+/// seed or the digest of the params, as SHA-256 over the length of the domain (u64), the domain
+/// and the context.
+pub fn derive_key(domain: &str, context: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update((domain.len() as u64).to_le_bytes());
+    hasher.update(domain);
+    hasher.update(context);
+    hasher.finalize().into()
+}
+
+// SplitMix64 finalizer
+pub(crate) fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyed_hash() {
+        let key = derive_key("kvpir/bucket", &9u64.to_le_bytes());
+        let hash = SipHash::new(&key);
+        assert_eq!(hash.hash(b"key"), SipHash::new(&key).hash(b"key"));
+        assert_ne!(hash.hash(b"key"), hash.hash(b"kez"));
+
+        // Distinct domains and contexts give independent keys
+        assert_ne!(key, derive_key("kvpir/tag", &9u64.to_le_bytes()));
+        assert_ne!(key, derive_key("kvpir/bucket", &10u64.to_le_bytes()));
+        assert_ne!(derive_key("ab", b"c"), derive_key("a", b"bc"));
+
+        // The SipHash-1-3 reference output for the key 0..16 and the empty input
+        let key: Vec<u8> = (0..32).collect();
+        assert_eq!(SipHash::new(&key.try_into().unwrap()).hash(b""), 0xabac0158050fc4dc);
+    }
+}
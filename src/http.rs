@@ -0,0 +1,338 @@
+//! Request handling for the simplepir-server binary, kept separate from the HTTP library so that it
+//! can be tested without a network. All bodies use the encodings of the wire module.
+//!
+//! - `GET /params` returns the SimplePIR params
+//! - `GET /layout` returns the record layout
+//! - `GET /hint` returns the hint
+//! - `GET /public-hint` returns the params, layout and hint as one public hint
+//!
+//! The hint and public hint, which can run to gigabytes, are encoded a chunk at a time as they
+//! are sent rather than up front.
+//! - `GET /public-hint/digest` returns the digest which clients check the public hint against
+//! - `POST /query` takes the digest of the client's params followed by a query, and returns its
+//!   answer. Clients whose params differ from the server's are refused with 409, and bodies longer
+//!   than max_body_len() with 413.
+//!
+//! A client may list the codecs it accepts in a `simplepir-accept-compression` header, which
+//! handle_compressed() negotiates as compression::Codec::negotiate() does. Successful responses
+//! to such a client are sent as compressed frames, see the compression module.
+
+use std::fmt::{Debug, Formatter};
+use std::io::{Cursor, Read};
+
+use sha2::{Digest as _, Sha256};
+
+use crate::compression::{self, Codec, CompressReader};
+use crate::error::SimplePirError;
+use crate::pir::Server;
+use crate::record::RecordLayout;
+use crate::simplepir::SimplePIRParams;
+use crate::wire;
+
+/// A response status and body.
+#[derive(Debug)]
+pub struct Response<'a> {
+    pub status: u16,
+    pub body: Body<'a>,
+}
+
+impl<'a> Response<'a> {
+    fn ok(body: Vec<u8>) -> Self {
+        Self { status: 200, body: Body::Bytes(body) }
+    }
+
+    fn stream(len: Option<usize>, reader: impl Read + 'a) -> Self {
+        Self { status: 200, body: Body::Stream(len, Box::new(reader)) }
+    }
+
+    /// A response with a message as its body.
+    pub fn error(status: u16, message: &str) -> Self {
+        Self { status, body: Body::Bytes(message.as_bytes().to_vec()) }
+    }
+}
+
+/// A response body, either already in memory or produced by a reader as it is sent, of the given
+/// length if it is known up front. Copying a streamed body into a socket with blocking writes
+/// keeps at most one chunk of it in memory, however slowly the client reads.
+pub enum Body<'a> {
+    Bytes(Vec<u8>),
+    Stream(Option<usize>, Box<dyn Read + 'a>),
+}
+
+impl<'a> Body<'a> {
+    /// The length of the body in bytes, or None for a streamed body whose length is not known
+    /// until it ends, which is sent with chunked transfer encoding.
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len()),
+            Body::Stream(len, _) => *len,
+        }
+    }
+
+    /// Whether the body is known to be empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// A reader of the body.
+    pub fn into_reader(self) -> Box<dyn Read + 'a> {
+        match self {
+            Body::Bytes(bytes) => Box::new(Cursor::new(bytes)),
+            Body::Stream(_, reader) => reader,
+        }
+    }
+
+    /// Read the whole body into memory.
+    pub fn into_bytes(self) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.len().unwrap_or(0));
+        self.into_reader().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Debug for Body<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            Body::Stream(len, _) => f.debug_tuple("Stream").field(len).finish(),
+        }
+    }
+}
+
+/// Split the contents of a database file into records of record_len bytes. The last record is
+/// padded with zeros. A record_len of 0 is rejected as an empty record.
+pub fn load_records(db: &[u8], record_len: usize) -> Result<Vec<Vec<u8>>, SimplePirError> {
+    if record_len == 0 {
+        return Err(SimplePirError::Empty("record"));
+    }
+    Ok(db.chunks(record_len).map(|chunk| {
+        let mut record = chunk.to_vec();
+        record.resize(record_len, 0);
+        record
+    }).collect())
+}
+
+/// Set up a server for the records in a database file, with params of at least `bits` of
+/// security chosen by SimplePIRParams::for_security().
+pub fn setup_server(db: &[u8], record_len: usize, bits: u32) -> Result<Server, SimplePirError> {
+    if db.is_empty() {
+        return Err(SimplePirError::Empty("database"));
+    }
+    let records = load_records(db, record_len)?;
+    let params = SimplePIRParams::for_security(bits, records.len(), 8 * record_len)?;
+    let layout = RecordLayout::new(records.len(), record_len, params.p)?;
+    Server::setup_records(params, layout, &records)
+}
+
+/// The longest request body the server accepts, which is that of a query for its params.
+pub fn max_body_len(server: &Server) -> usize {
+    wire::encoded_query_with_digest_len(server.params().m, server.params().q)
+}
+
+/// Read a request body of at most max_len bytes, without reading any further than one byte past
+/// max_len, so that clients cannot make the server buffer arbitrarily long bodies.
+pub fn read_body<R: Read>(reader: R, max_len: usize) -> Result<Vec<u8>, Response<'static>> {
+    let mut body = vec![];
+    let limit = max_len as u64 + 1;
+    if reader.take(limit).read_to_end(&mut body).is_err() {
+        return Err(Response::error(400, "cannot read the body"));
+    }
+    if body.len() > max_len {
+        return Err(Response::error(413, "the body is too long"));
+    }
+    Ok(body)
+}
+
+/// Handle one request.
+pub fn handle<'a>(server: &'a Server, method: &str, path: &str, body: &[u8]) -> Response<'a> {
+    match (method, path) {
+        ("GET", "/params") => Response::ok(wire::encode_params(server.params())),
+        ("GET", "/layout") => match server.layout() {
+            Some(layout) => Response::ok(wire::encode_layout(layout)),
+            None => Response::error(404, "the database has no record layout"),
+        },
+        ("GET", "/hint") => {
+            let hint = server.hint();
+            let len = wire::encoded_hint_len(hint);
+            Response::stream(Some(len), wire::MatrixReader::new(&hint.0))
+        }
+        ("GET", "/public-hint") => {
+            let (len, reader) = public_hint_reader(server);
+            Response::stream(Some(len), reader)
+        }
+        ("GET", "/public-hint/digest") => {
+            // Hash the encoding as it streams past, rather than encoding a copy of the hint
+            let (_, mut reader) = public_hint_reader(server);
+            let mut hasher = Sha256::new();
+            let mut chunk = vec![0; 1 << 16];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(len) => hasher.update(&chunk[..len]),
+                    Err(err) => return Response::error(500, &err.to_string()),
+                }
+            }
+            Response::ok(hasher.finalize().to_vec())
+        }
+        ("POST", "/query") => {
+            let (digest, query) = match wire::decode_query_with_digest(body) {
+                Ok(query) => query,
+                Err(err) => return Response::error(400, &err.to_string()),
+            };
+            match server.answer_checked(&digest, &query) {
+                Ok(answer) => Response::ok(wire::encode_answer(&answer)),
+                Err(err @ SimplePirError::ParamsMismatch) => Response::error(409, &err.to_string()),
+                Err(err) => Response::error(400, &err.to_string()),
+            }
+        }
+        (
+            _,
+            "/params" | "/layout" | "/hint" | "/public-hint" | "/public-hint/digest" | "/query",
+        ) => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found"),
+    }
+}
+
+/// Handle one request from a client which offered the given codecs in its
+/// compression::ACCEPT_HEADER header. A successful response is compressed with the negotiated
+/// codec, and a streamed one as it is sent, so that its length is not known up front. Errors are
+/// sent as they are.
+pub fn handle_compressed<'a>(
+    server: &'a Server,
+    method: &str,
+    path: &str,
+    body: &[u8],
+    offered: &str,
+) -> Response<'a> {
+    let response = handle(server, method, path, body);
+    if response.status != 200 {
+        return response;
+    }
+    let codec = Codec::negotiate(offered);
+    match response.body {
+        Body::Bytes(bytes) => Response::ok(compression::compress(codec, &bytes)),
+        body => Response::stream(None, CompressReader::new(body.into_reader(), codec)),
+    }
+}
+
+// A reader of the server's public hint
+fn public_hint_reader(server: &Server) -> (usize, impl Read + '_) {
+    let key = server.verifying_key();
+    wire::public_hint_reader(server.params(), server.hint(), server.layout(), key.as_ref())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::pir::{Client, PublicHint};
+
+    #[test]
+    fn test_load_records() {
+        let records = load_records(&[1, 2, 3, 4, 5], 2).unwrap();
+        assert_eq!(records, vec![vec![1, 2], vec![3, 4], vec![5, 0]]);
+        assert_eq!(load_records(&[1, 2], 0), Err(SimplePirError::Empty("record")));
+    }
+
+    #[test]
+    fn test_handle() {
+        let db: Vec<u8> = (0..200).map(|i| (i * 7) as u8).collect();
+        let server = setup_server(&db, 8, 128).unwrap();
+
+        let get = |path: &str| {
+            let response = handle(&server, "GET", path, &[]);
+            assert_eq!(response.status, 200);
+            let len = response.body.len();
+            let body = response.body.into_bytes().unwrap();
+            assert_eq!(Some(body.len()), len);
+            body
+        };
+        let params = wire::decode_params(&get("/params")).unwrap();
+        let layout = wire::decode_layout(&get("/layout")).unwrap();
+        let hint = wire::decode_hint(&get("/hint")).unwrap();
+        let mut client = Client::with_layout(params, hint, layout).unwrap();
+
+        let digest = *client.params_digest();
+        for (idx, record) in db.chunks(8).enumerate() {
+            let query = wire::encode_query_with_digest(&digest, &client.query_record(idx).unwrap());
+            assert_eq!(query.len(), max_body_len(&server));
+            let response = handle(&server, "POST", "/query", &query);
+            assert_eq!(response.status, 200);
+            let answer = wire::decode_answer(&response.body.into_bytes().unwrap()).unwrap();
+            assert_eq!(client.recover_record(&answer).unwrap(), record);
+        }
+
+        // The public hint bundles the same downloads
+        let public = PublicHint::from_bytes(&get("/public-hint")).unwrap();
+        assert_eq!(public.digest().as_slice(), get("/public-hint/digest"));
+        let mut client = Client::from_public_hint(public).unwrap();
+        let query = wire::encode_query_with_digest(&digest, &client.query_record(3).unwrap());
+        let body = handle(&server, "POST", "/query", &query).body.into_bytes().unwrap();
+        let answer = wire::decode_answer(&body).unwrap();
+        assert_eq!(client.recover_record(&answer).unwrap(), db.chunks(8).nth(3).unwrap());
+    }
+
+    #[test]
+    fn test_handle_compressed() {
+        // A database of zeros gives a hint of zeros, which compresses well
+        let server = setup_server(&[0; 4000], 8, 128).unwrap();
+        let offered = Codec::offer();
+        for path in ["/params", "/hint", "/public-hint"] {
+            let plain = handle(&server, "GET", path, &[]).body.into_bytes().unwrap();
+            let response = handle_compressed(&server, "GET", path, &[], &offered);
+            assert_eq!(response.status, 200);
+            let body = response.body.into_bytes().unwrap();
+            assert_eq!(compression::decompress(&body, plain.len()).unwrap(), plain);
+            if path == "/hint" && Codec::supported()[0] != Codec::Identity {
+                assert!(body.len() < plain.len() / 2);
+            }
+        }
+
+        let mut client = Client::from_public_hint(server.public_hint()).unwrap();
+        let query = client.query_record(7).unwrap();
+        let query = wire::encode_query_with_digest(client.params_digest(), &query);
+        let response = handle_compressed(&server, "POST", "/query", &query, "lz4, zstd");
+        let body = compression::decompress(&response.body.into_bytes().unwrap(), usize::MAX);
+        let answer = wire::decode_answer(&body.unwrap()).unwrap();
+        assert_eq!(client.recover_record(&answer).unwrap(), vec![0; 8]);
+
+        // Errors are sent as they are
+        let response = handle_compressed(&server, "GET", "/missing", &[], &offered);
+        assert_eq!(response.body.into_bytes().unwrap(), b"not found");
+    }
+
+    #[test]
+    fn test_handle_errors() {
+        assert_eq!(setup_server(&[], 1, 128).err(), Some(SimplePirError::Empty("database")));
+        assert_eq!(setup_server(&[1], 1, 192).err(), Some(SimplePirError::UnsupportedSecurity(192)));
+        assert_eq!(setup_server(&[1], 0, 128).err(), Some(SimplePirError::Empty("record")));
+        let server = setup_server(&[1, 2, 3, 4], 1, 128).unwrap();
+
+        assert_eq!(handle(&server, "GET", "/missing", &[]).status, 404);
+        assert_eq!(handle(&server, "POST", "/hint", &[]).status, 405);
+        assert_eq!(handle(&server, "GET", "/query", &[]).status, 405);
+        assert_eq!(handle(&server, "POST", "/query", &[1, 2, 3]).status, 400);
+
+        // A well-formed query of the wrong length
+        let query = crate::pir::Query(vec![crate::element::Element::from(server.params().q, 1)]);
+        let body = wire::encode_query_with_digest(server.params_digest(), &query);
+        assert_eq!(handle(&server, "POST", "/query", &body).status, 400);
+
+        // A query generated with other params
+        let query = crate::pir::Query(vec![crate::element::Element::from(server.params().q, 1)]);
+        let body = wire::encode_query_with_digest(&[0; 32], &query);
+        assert_eq!(handle(&server, "POST", "/query", &body).status, 409);
+    }
+
+    #[test]
+    fn test_read_body() {
+        let body = vec![7u8; 100];
+        assert_eq!(read_body(&body[..], 100).unwrap(), body);
+        assert_eq!(read_body(&body[..], 99).unwrap_err().status, 413);
+        assert!(read_body(&[][..], 0).unwrap().is_empty());
+
+        // No more than one byte past the limit is read
+        let mut reader = &body[..];
+        assert!(read_body(&mut reader, 10).is_err());
+        assert_eq!(reader.len(), 89);
+    }
+}
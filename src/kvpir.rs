@@ -0,0 +1,347 @@
+use core::marker::PhantomData;
+use crate::error::SimplePirError;
+use crate::hash::{derive_key, KeyedHash, SipHash};
+use crate::pir::{Answer, Client, Query, Server};
+use crate::record::RecordLayout;
+use crate::simplepir::{Hint, SimplePIRParams};
+
+// The length in bytes of the key tag stored alongside each value
+const TAG_LEN: usize = 8;
+
+// The number of seeds to try before giving up on key tags that collide within a bucket
+const MAX_SEED_ATTEMPTS: u64 = 8;
+
+// The domains of the keyed hashes which choose the bucket and the tag of a key
+const BUCKET_DOMAIN: &str = "simplepir-rs/kvpir/bucket";
+const TAG_DOMAIN: &str = "simplepir-rs/kvpir/tag";
+
+// A key and its value
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// The public description of a key-value database. Keys are hashed into buckets, and each bucket
+/// is stored as a single byte record holding up to bucket_size (tag, value) slots, so that
+/// colliding keys share a bucket and one query retrieves all of them.
+///
+/// Buckets and tags are chosen with the keyed hash H, under keys derived from the seed in their
+/// own domains. The params cannot serve as the context, since they are sized to the buckets.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound = ""))]
+pub struct KvIndex<H = SipHash> {
+    pub num_buckets: usize,
+    pub bucket_size: usize,
+    pub value_len: usize,
+    pub seed: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hash: PhantomData<H>,
+}
+
+impl<H: KeyedHash> KvIndex<H> {
+    // The hashes which choose the bucket and the tag of a key under the seed
+    fn hashes(&self) -> (H, H) {
+        let seed = self.seed.to_le_bytes();
+        (H::new(&derive_key(BUCKET_DOMAIN, &seed)), H::new(&derive_key(TAG_DOMAIN, &seed)))
+    }
+
+    /// The bucket in which key is stored.
+    pub fn bucket(&self, key: &[u8]) -> usize {
+        (self.hashes().0.hash(key) % self.num_buckets as u64) as usize
+    }
+
+    // A non-zero tag identifying key within its bucket. Zero marks an empty slot.
+    fn tag(hash: &H, key: &[u8]) -> [u8; TAG_LEN] {
+        hash.hash(key).max(1).to_le_bytes()
+    }
+
+    // Hash the entries into buckets. Returns None if two distinct keys of a bucket share a tag,
+    // in which case another seed must be used.
+    fn fill<'a>(
+        &self,
+        entries: &'a [Entry],
+    ) -> Result<Option<Vec<Vec<&'a Entry>>>, SimplePirError> {
+        let (bucket_hash, tag_hash) = self.hashes();
+        let mut buckets: Vec<Vec<&Entry>> = vec![vec![]; self.num_buckets];
+        for entry in entries {
+            if entry.1.len() != self.value_len {
+                return Err(SimplePirError::LengthMismatch {
+                    expected: self.value_len,
+                    actual: entry.1.len(),
+                });
+            }
+            let bucket = (bucket_hash.hash(&entry.0) % self.num_buckets as u64) as usize;
+            let bucket = &mut buckets[bucket];
+            let tag = Self::tag(&tag_hash, &entry.0);
+            for other in bucket.iter() {
+                if other.0 == entry.0 {
+                    return Err(SimplePirError::DuplicateKey);
+                }
+                if Self::tag(&tag_hash, &other.0) == tag {
+                    return Ok(None);
+                }
+            }
+            bucket.push(entry);
+        }
+        Ok(Some(buckets))
+    }
+
+    fn slot_len(&self) -> usize {
+        TAG_LEN + self.value_len
+    }
+
+    /// The length in bytes of each bucket record.
+    pub fn record_len(&self) -> usize {
+        self.bucket_size * self.slot_len()
+    }
+
+    // Find the value stored under key in a bucket record
+    fn lookup(&self, bucket: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+        let tag = Self::tag(&self.hashes().1, key);
+        bucket.chunks(self.slot_len())
+            .find(|slot| slot[..TAG_LEN] == tag)
+            .map(|slot| slot[TAG_LEN..].to_vec())
+    }
+}
+
+/// The server side of keyword PIR.
+pub struct KvServer<H = SipHash> {
+    index: KvIndex<H>,
+    server: Server,
+}
+
+impl KvServer {
+    /// Build the key-value database, with params of at least `bits` of security. Every value must
+    /// be value_len bytes long and keys must be distinct. Buckets are sized to fit the most
+    /// heavily loaded one. If two keys of a bucket share a tag under seed, the following seeds are
+    /// tried in turn, so the index may hold a later seed than the one given.
+    pub fn setup(
+        entries: &[Entry],
+        value_len: usize,
+        bits: u32,
+        seed: u64,
+    ) -> Result<Self, SimplePirError> {
+        Self::setup_with_hash(entries, value_len, bits, seed)
+    }
+}
+
+impl<H: KeyedHash> KvServer<H> {
+    /// Like setup(), but choosing buckets and tags with the keyed hash H rather than SipHash.
+    pub fn setup_with_hash(
+        entries: &[Entry],
+        value_len: usize,
+        bits: u32,
+        seed: u64,
+    ) -> Result<Self, SimplePirError> {
+        let mut index = KvIndex {
+            num_buckets: (entries.len() / 2).max(1),
+            bucket_size: 0,
+            value_len,
+            seed,
+            hash: PhantomData,
+        };
+
+        let mut buckets = None;
+        for attempt in 0..MAX_SEED_ATTEMPTS {
+            index.seed = seed.wrapping_add(attempt);
+            buckets = index.fill(entries)?;
+            if buckets.is_some() {
+                break;
+            }
+        }
+        let buckets = buckets.ok_or(SimplePirError::KeyTagCollision)?;
+        index.bucket_size = buckets.iter().map(|b| b.len()).max().unwrap().max(1);
+
+        let tag_hash = index.hashes().1;
+        let records: Vec<Vec<u8>> = buckets.iter().map(|bucket| {
+            let mut record = Vec::with_capacity(index.record_len());
+            for (key, value) in bucket {
+                record.extend_from_slice(&KvIndex::tag(&tag_hash, key));
+                record.extend_from_slice(value);
+            }
+            record.resize(index.record_len(), 0);
+            record
+        }).collect();
+
+        let record_bits = 8 * index.record_len();
+        let params = SimplePIRParams::for_security(bits, index.num_buckets, record_bits)?;
+        let layout = RecordLayout::new(index.num_buckets, index.record_len(), params.p)?;
+        let server = Server::setup_records(params, layout, &records)?;
+        Ok(Self { index, server })
+    }
+
+    pub fn index(&self) -> &KvIndex<H> {
+        &self.index
+    }
+
+    pub fn params(&self) -> &SimplePIRParams {
+        self.server.params()
+    }
+
+    pub fn hint(&self) -> &Hint {
+        self.server.hint()
+    }
+
+    pub fn layout(&self) -> &RecordLayout {
+        self.server.layout().unwrap()
+    }
+
+    pub fn answer(&self, query: &Query) -> Result<Answer, SimplePirError> {
+        self.server.answer(query)
+    }
+}
+
+/// The client side of keyword PIR.
+pub struct KvClient<H = SipHash> {
+    index: KvIndex<H>,
+    client: Client,
+    pending_key: Option<Vec<u8>>,
+}
+
+impl<H: KeyedHash> KvClient<H> {
+    /// Create a client from the index, params, hint and record layout published by the server.
+    pub fn new(
+        index: KvIndex<H>,
+        params: SimplePIRParams,
+        hint: Hint,
+        layout: RecordLayout,
+    ) -> Result<Self, SimplePirError> {
+        if index.num_buckets == 0 {
+            return Err(SimplePirError::Empty("index"));
+        }
+        if layout.num_records != index.num_buckets {
+            return Err(SimplePirError::LengthMismatch {
+                expected: index.num_buckets,
+                actual: layout.num_records,
+            });
+        }
+        if layout.record_len != index.record_len() {
+            return Err(SimplePirError::LengthMismatch {
+                expected: index.record_len(),
+                actual: layout.record_len,
+            });
+        }
+        let client = Client::with_layout(params, hint, layout)?;
+        Ok(Self { index, client, pending_key: None })
+    }
+
+    /// Generate a query for the bucket which would hold key.
+    pub fn query(&mut self, key: &[u8]) -> Result<Query, SimplePirError> {
+        let query = self.client.query_record(self.index.bucket(key))?;
+        self.pending_key = Some(key.to_vec());
+        Ok(query)
+    }
+
+    /// Recover the value stored under the queried key, or None if the key is not present.
+    pub fn recover(&mut self, answer: &Answer) -> Result<Option<Vec<u8>>, SimplePirError> {
+        let key = self.pending_key.take().ok_or(SimplePirError::NoPendingQuery)?;
+        let bucket = self.client.recover_record(answer)?;
+        Ok(self.index.lookup(&bucket, &key))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn gen_entries(num_entries: usize, value_len: usize) -> Vec<Entry> {
+        (0..num_entries).map(|i| {
+            let key = format!("key-{}", i).into_bytes();
+            let value = (0..value_len).map(|j| (i * 13 + j) as u8).collect();
+            (key, value)
+        }).collect()
+    }
+
+    #[test]
+    fn test_kvpir() {
+        let entries = gen_entries(30, 4);
+        let server = KvServer::setup(&entries, 4, 128, 9).unwrap();
+        let mut client = KvClient::new(
+            server.index().clone(),
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().clone(),
+        ).unwrap();
+
+        for (key, value) in entries.iter() {
+            let query = client.query(key).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer), Ok(Some(value.clone())));
+        }
+
+        for key in [b"missing".to_vec(), b"key-30".to_vec(), vec![]] {
+            let query = client.query(&key).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer), Ok(None));
+        }
+    }
+
+    // A keyed hash other than the default: the first 8 bytes of SHA-256 over the key and input
+    #[derive(Debug, PartialEq, Clone)]
+    struct Sha256Hash([u8; 32]);
+
+    impl KeyedHash for Sha256Hash {
+        fn new(key: &[u8; 32]) -> Self {
+            Self(*key)
+        }
+
+        fn hash(&self, input: &[u8]) -> u64 {
+            use sha2::{Digest as _, Sha256};
+            let digest = Sha256::new().chain_update(self.0).chain_update(input).finalize();
+            u64::from_le_bytes(digest[..8].try_into().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_kvpir_keyed_hash() {
+        let entries = gen_entries(20, 4);
+        let server = KvServer::<Sha256Hash>::setup_with_hash(&entries, 4, 128, 3).unwrap();
+        let default = KvServer::setup(&entries, 4, 128, 3).unwrap();
+        let moved = |(key, _): &Entry| server.index().bucket(key) != default.index().bucket(key);
+        assert!(entries.iter().any(moved));
+
+        let mut client = KvClient::new(
+            server.index().clone(),
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().clone(),
+        ).unwrap();
+        for (key, value) in &entries {
+            let query = client.query(key).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer), Ok(Some(value.clone())));
+        }
+    }
+
+    #[test]
+    fn test_kvpir_errors() {
+        let mut entries = gen_entries(3, 4);
+        entries.push(entries[0].clone());
+        assert_eq!(
+            KvServer::setup(&entries, 4, 128, 9).err(),
+            Some(SimplePirError::DuplicateKey),
+        );
+
+        let mut entries = gen_entries(3, 4);
+        entries[1].1.push(0);
+        assert_eq!(
+            KvServer::setup(&entries, 4, 128, 9).err(),
+            Some(SimplePirError::LengthMismatch { expected: 4, actual: 5 }),
+        );
+
+        let server = KvServer::setup(&gen_entries(3, 4), 4, 128, 9).unwrap();
+        let mut index = server.index().clone();
+        index.value_len = 5;
+        let client = KvClient::new(
+            index,
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().clone(),
+        );
+        assert_eq!(
+            client.err(),
+            Some(SimplePirError::LengthMismatch {
+                expected: 13 * server.index().bucket_size,
+                actual: server.layout().record_len,
+            }),
+        );
+    }
+}
@@ -1,6 +1,83 @@
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`. Everything
+//! needed to generate queries and recover answers remains available, with randomness supplied by
+//! the caller through the `_with_rng` functions.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 pub mod element;
+pub mod error;
 pub mod matrix;
+pub mod vector;
+pub mod modulus;
+pub mod compact;
+pub mod sparse;
+pub mod bitpacked;
+pub mod database;
+pub mod arena;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod regev;
+pub mod ntt;
+pub mod ring;
+pub mod rns;
+#[cfg(feature = "packing")]
+pub mod packing;
+pub mod ringpir;
+pub mod scaling;
+#[cfg(feature = "std")]
 pub mod toypir;
 pub mod simplepir;
+#[cfg(feature = "std")]
 pub mod doublepir;
+pub mod pir;
+#[cfg(feature = "std")]
+pub mod batchpir;
+#[cfg(feature = "std")]
+pub mod kvpir;
+#[cfg(feature = "std")]
+pub mod dedup;
+#[cfg(feature = "std")]
+pub mod publish;
+#[cfg(feature = "std")]
+pub mod store;
+#[cfg(feature = "std")]
+pub mod sharding;
+pub mod record;
+pub mod merkle;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "spir")]
+pub mod spir;
+#[cfg(feature = "signing")]
+pub mod signing;
+pub mod wire;
+#[cfg(feature = "std")]
+pub mod compression;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "std")]
+pub mod report;
+#[cfg(feature = "std")]
+pub mod tune;
+#[cfg(feature = "std")]
+pub mod observer;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "server")]
+pub mod http;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+#[cfg(feature = "std")]
+pub mod hash;
+#[cfg(feature = "simd")]
+mod simd;
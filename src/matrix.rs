@@ -1,46 +1,64 @@
-use crate::element::Element;
-use std::clone::Clone;
-use std::default::Default;
-use std::fmt::{Display, Formatter};
-use std::ops::{Add, AddAssign, Sub, SubAssign, Index, IndexMut, Mul, MulAssign};
-
-#[derive(Debug, PartialEq)]
+use crate::element::{ops, Element};
+use crate::error::{ShapeError, SimplePirError};
+use crate::modulus::{Modulus, Montgomery};
+use crate::regev::check_moduli;
+use crate::vector::Vector;
+use crate::record::{bits_per_limb, decode_record, encode_record, limbs_per_record};
+use alloc::vec;
+use alloc::vec::Vec;
+use rand::RngCore;
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, SeedableRng};
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
+use core::clone::Clone;
+use core::default::Default;
+use core::fmt::{Display, Formatter};
+use core::ops::{Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Range};
+
+/// A matrix mod q, stored column by column in one contiguous buffer so that the long loops over
+/// a column (and from one column to the next) read memory in order. The modulus is stored once
+/// for the whole matrix rather than with every value. get(), set() and col() convert entries to
+/// and from Elements, while values() and col_values() expose the raw values, each below m.q().
+/// There is no mutable access to the raw values, so that they stay below the modulus.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "MatrixRepr"))]
 pub struct Matrix {
-    pub data: Vec<Vec<Element>>,
+    // 0 if the matrix has no values
+    q: u64,
+    // Column i is data[i * num_rows..(i + 1) * num_rows]
+    data: Vec<u64>,
+    num_cols: usize,
+    num_rows: usize,
 }
 
-impl Index<usize> for Matrix {
-    type Output = Vec<Element>;
-    fn index(&self, i: usize) -> &Self::Output {
-        &self.data[i]
-    }
+// The serialized form of a Matrix, which is checked to hold num_cols * num_rows values below q
+// when deserializing
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct MatrixRepr {
+    q: u64,
+    data: Vec<u64>,
+    num_cols: usize,
+    num_rows: usize,
 }
 
-impl IndexMut<usize> for Matrix {
-    fn index_mut(&mut self, i: usize) -> &mut Vec<Element> {
-        &mut self.data[i]
-    }
-}
+#[cfg(feature = "serde")]
+impl TryFrom<MatrixRepr> for Matrix {
+    type Error = SimplePirError;
 
-impl Clone for Matrix {
-    fn clone(&self) -> Self {
-        if self.data.is_empty() {
-            return Matrix { data: vec![] };
+    fn try_from(repr: MatrixRepr) -> Result<Self, Self::Error> {
+        let expected = repr.num_cols.checked_mul(repr.num_rows)
+            .ok_or(SimplePirError::InvalidShape("matrix"))?;
+        if repr.data.len() != expected {
+            return Err(SimplePirError::LengthMismatch { expected, actual: repr.data.len() });
         }
-        let mut d: Vec<Vec<Element>> = Vec::with_capacity(self.data.len());
-        for i in 0..self.data.len() {
-            let mut e = Vec::<Element>::with_capacity(self.data[i].len());
-            for j in 0..self.data[i].len() {
-                e.push(self.data[i][j].clone());
-            }
-            d.push(e);
+        if repr.data.is_empty() {
+            return Ok(Matrix::from_values(0, repr.num_cols, repr.num_rows, repr.data));
         }
-        Matrix { data: d }
-    }
-
-    fn clone_from(&mut self, source: &Self) {
-        let c = source.clone();
-        *self = c;
+        let values = Vector::checked_from(repr.q, repr.data)?;
+        Ok(Matrix::from_vector(repr.num_cols, repr.num_rows, values))
     }
 }
 
@@ -53,92 +71,324 @@ impl Default for Matrix {
 impl Matrix {
     /// Initialise an empty matrix (0 by 0)
     pub fn new() -> Matrix {
-        Matrix { data: vec![] }
+        Matrix { q: 0, data: vec![], num_cols: 0, num_rows: 0 }
     }
 
-    /// Initialise a matrix from Vectors of Vectors of elements
+    /// Initialise a matrix from Vectors of Vectors of elements, one per column. Panics if the
+    /// columns have different lengths or the elements different moduli.
+    #[allow(clippy::ptr_arg)]
     pub fn from(data: &Vec<Vec<Element>>) -> Self {
+        Self::from_cols(data.iter().map(|col| col.as_slice()))
+    }
+
+    /// Initialise a matrix from its columns, which must all have the same length and modulus.
+    pub fn from_cols<'a>(cols: impl IntoIterator<Item = &'a [Element]>) -> Self {
         let mut matrix = Self::new();
-        matrix.data = data.to_owned();
+        for col in cols {
+            matrix.append_col(col.to_vec());
+        }
         matrix
     }
 
+    /// Initialise a matrix from its elements, column by column, which must all have the same
+    /// modulus.
+    pub fn from_flat(num_cols: usize, num_rows: usize, data: Vec<Element>) -> Self {
+        let q = data.first().map_or(0, |e| e.q);
+        Self::from_values(q, num_cols, num_rows, col_uints(&data, q))
+    }
+
+    /// Initialise a matrix mod q from its values, column by column. Panics if a value is not below
+    /// q.
+    pub fn from_values(q: u64, num_cols: usize, num_rows: usize, data: Vec<u64>) -> Self {
+        assert_eq!(data.len(), num_cols * num_rows);
+        assert!(data.iter().all(|&v| v < q), "the value is not below the modulus");
+        Self::from_reduced(q, num_cols, num_rows, data)
+    }
+
+    /// Initialise a matrix from the values of a vector, column by column.
+    pub fn from_vector(num_cols: usize, num_rows: usize, values: Vector) -> Self {
+        assert_eq!(values.len(), num_cols * num_rows);
+        Self::from_reduced(values.q(), num_cols, num_rows, values.into_values())
+    }
+
+    // Like from_values(), for values which are known to be below q
+    fn from_reduced(q: u64, num_cols: usize, num_rows: usize, data: Vec<u64>) -> Self {
+        let q = if data.is_empty() { 0 } else { q };
+        Matrix { q, data, num_cols, num_rows }
+    }
+
     pub fn from_single(elem: &Element) -> Self {
         Self::from(&vec![vec![elem.clone()]])
     }
 
-    pub fn from_col(col: &Vec<Element>) -> Self {
-        Self::from(&vec![col.to_owned()])
+    pub fn from_col(col: &[Element]) -> Self {
+        Self::from_flat(1, col.len(), col.to_vec())
     }
 
     pub fn from_val(rows: usize, cols: usize, val: Element) -> Self {
-        let row = vec![val; cols];
-        let cols = vec![row; rows];
-        Matrix::from(&cols)
+        Self::from_reduced(val.q, rows, cols, vec![val.uint; rows * cols])
+    }
+
+    /// The modulus of every value, or 0 if the matrix has no values.
+    pub fn q(&self) -> u64 {
+        self.q
+    }
+
+    /// Whether every value is mod q, which holds for any q if the matrix has no values.
+    pub fn has_modulus(&self, q: u64) -> bool {
+        self.data.is_empty() || self.q == q
+    }
+
+    /// The values, column by column.
+    pub fn values(&self) -> &[u64] {
+        &self.data
+    }
+
+    /// The entry at column i, row j.
+    pub fn get(&self, i: usize, j: usize) -> Element {
+        Element::from(self.q, self.col_values(i)[j])
+    }
+
+    /// Set the entry at column i, row j. Panics if value does not have the modulus of the matrix.
+    pub fn set(&mut self, i: usize, j: usize, value: &Element) {
+        assert_eq!(value.q, self.q);
+        self.col_values_mut(i)[j] = value.uint;
+    }
+
+    /// The values of column i.
+    pub fn col_values(&self, i: usize) -> &[u64] {
+        assert!(i < self.num_cols, "column {} is out of range", i);
+        &self.data[i * self.num_rows..(i + 1) * self.num_rows]
+    }
+
+    // The values of column i, which must be kept below q
+    fn col_values_mut(&mut self, i: usize) -> &mut [u64] {
+        assert!(i < self.num_cols, "column {} is out of range", i);
+        &mut self.data[i * self.num_rows..(i + 1) * self.num_rows]
+    }
+
+    /// Column i as elements.
+    pub fn col(&self, i: usize) -> Vec<Element> {
+        self.col_values(i).iter().map(|&v| Element::from(self.q, v)).collect()
+    }
+
+    /// An iterator over the values of each column.
+    pub fn cols(&self) -> impl ExactSizeIterator<Item = &[u64]> + Clone + '_ {
+        (0..self.num_cols).map(move |i| self.col_values(i))
+    }
+
+    /// Every entry as an element, column by column.
+    pub fn elements(&self) -> impl ExactSizeIterator<Item = Element> + '_ {
+        self.data.iter().map(move |&v| Element::from(self.q, v))
+    }
+
+    /// The matrix of the given range of columns.
+    pub fn select_cols(&self, cols: Range<usize>) -> Self {
+        assert!(cols.end <= self.num_cols, "the columns are out of range");
+        let data = self.data[cols.start * self.num_rows..cols.end * self.num_rows].to_vec();
+        Self::from_reduced(self.q, cols.len(), self.num_rows, data)
+    }
+
+    /// Move the columns of other to the end of this matrix.
+    pub fn append(&mut self, other: Matrix) {
+        if self.num_cols == 0 {
+            *self = other;
+            return;
+        }
+        if other.num_cols == 0 {
+            return;
+        }
+        assert_eq!(self.num_rows, other.num_rows);
+        if !other.data.is_empty() {
+            assert_eq!(self.q, other.q);
+        }
+        self.data.extend(other.data);
+        self.num_cols += other.num_cols;
+    }
+
+    /// The matrix of the columns of each block in turn, which must all have the same number of
+    /// rows.
+    pub fn concat(blocks: impl IntoIterator<Item = Matrix>) -> Self {
+        let mut matrix = Self::new();
+        for block in blocks {
+            matrix.append(block);
+        }
+        matrix
+    }
+
+    /// Remove and return the last column.
+    pub fn pop_col(&mut self) -> Option<Vec<Element>> {
+        if self.num_cols == 0 {
+            return None;
+        }
+        let col = self.col(self.num_cols - 1);
+        self.data.truncate((self.num_cols - 1) * self.num_rows);
+        self.num_cols -= 1;
+        if self.num_cols == 0 {
+            *self = Self::new();
+        }
+        Some(col)
+    }
+
+    /// The columns as separate vectors of elements.
+    pub fn into_cols(self) -> Vec<Vec<Element>> {
+        (0..self.num_cols).map(|i| self.col(i)).collect()
+    }
+
+    /// Pack bytes into a matrix mod p of the given (cols, rows) shape, ⌊log2(p)⌋ bits per element
+    /// (least significant first). Element k is stored at [k / rows][k % rows], so for a square
+    /// database item idx is element idx, as laid out by simplepir::position(). The remaining
+    /// elements are zero.
+    pub fn from_bytes(bytes: &[u8], p: u64, shape: (usize, usize)) -> Result<Self, SimplePirError> {
+        let (cols, rows) = shape;
+        let capacity = cols * rows * bits_per_limb(p) / 8;
+        if bytes.len() > capacity {
+            return Err(SimplePirError::TooManyBytes { len: bytes.len(), capacity });
+        }
+        let mut m = Self::from_val(cols, rows, Element::zero(p));
+        for (k, limb) in encode_record(bytes, p).into_iter().enumerate() {
+            m.set(k / rows, k % rows, &limb);
+        }
+        Ok(m)
+    }
+
+    /// Unpack the first len bytes packed by from_bytes().
+    pub fn to_bytes(&self, len: usize) -> Vec<u8> {
+        if len == 0 {
+            return vec![];
+        }
+        let limbs: Vec<Element> = self.elements().take(limbs_per_record(len, self.q)).collect();
+        decode_record(&limbs, len)
     }
 
     // TODO: change all (rows, cols) to (cols, rows) for consistency with the paper
+    #[cfg(feature = "std")]
     pub fn gen_uniform_rand(q: u64, cols: usize, rows: usize) -> Self  {
-        let mut a = Vec::with_capacity(cols);
-        for _ in 0..cols {
-            let mut row = Vec::with_capacity(rows);
-            for _ in 0..rows {
-                row.push(Element::gen_uniform_rand(q));
-            }
-            a.push(row);
-        }
-        Matrix::from(&a)
+        let mut rng = StdRng::from_entropy();
+        Self::gen_uniform_rand_with_rng(q, cols, rows, &mut rng)
+    }
+
+    pub fn gen_uniform_rand_with_rng<R: RngCore>(
+        q: u64,
+        cols: usize,
+        rows: usize,
+        rng: &mut R,
+    ) -> Self  {
+        let data = (0..cols * rows)
+            .map(|_| Element::gen_uniform_rand_with_rng(q, rng).uint)
+            .collect();
+        Matrix::from_reduced(q, cols, rows, data)
     }
 
     pub fn append_col(&mut self, col: Vec<Element>) {
+        if self.num_cols == 0 {
+            self.num_rows = col.len();
+        }
         assert_eq!(self.num_rows(), col.len());
-        self.data.push(col);
+        if self.data.is_empty() {
+            self.q = col.first().map_or(0, |e| e.q);
+        }
+        self.data.extend(col_uints(&col, self.q));
+        self.num_cols += 1;
     }
 
+    /// Reinterpret every value mod new_q, which must not be below any of them.
     pub fn change_q(&mut self, new_q: u64) {
-        for i in 0..self.num_cols() {
-            for j in 0..self.num_rows() {
-                self[i][j].q = new_q;
-            }
+        if !self.data.is_empty() {
+            self.q = new_q;
         }
     }
 
+    /// Rescale every element from Z_q to Z_new_q. See Element::mod_switch().
+    pub fn mod_switch(&self, new_q: u64) -> Self {
+        let data = self.elements().map(|val| val.mod_switch(new_q).uint).collect();
+        Matrix::from_reduced(new_q, self.num_cols, self.num_rows, data)
+    }
+
     pub fn num_vals(&self) -> usize {
-        if !self.data.is_empty() {
-            return self.data.len() * self.data[0].len();
-        }
-        0
+        self.data.len()
     }
 
-    #[allow(clippy::needless_range_loop)]
     pub fn rotated(self) -> Self {
-        let zero = Element::zero(self.data[0][0].q);
-        let new_row = vec![zero; self.num_cols()];
-        let mut rotated = vec![new_row; self.num_rows()];
-        for i in 0..self.num_cols() {
-            for j in 0..self.num_rows() {
-                rotated[j][i] = self.data[i][j].clone();
-            }
+        self.transpose()
+    }
+
+    /// The transpose of this matrix, i.e. transpose()[j][i] == self[i][j]. Unlike rotated(), this
+    /// does not consume the matrix.
+    pub fn transpose(&self) -> Self {
+        if self.num_vals() == 0 {
+            return Self::new();
         }
-        Self::from(&rotated)
+        let data = (0..self.num_rows())
+            .flat_map(|j| self.cols().map(move |col| col[j]))
+            .collect();
+        Self::from_reduced(self.q, self.num_rows(), self.num_cols(), data)
+    }
+
+    /// A view of the transpose which reads the entries of this matrix in place, for when only
+    /// some entries are needed or the matrix is too large to copy.
+    pub fn transposed(&self) -> Transposed<'_> {
+        Transposed { matrix: self }
     }
 
     pub fn mul_elem(self, rhs: &Element) -> Self {
-        let mut r = self.clone();
-        for i in 0..self.num_cols() {
-            for j in 0..self.num_rows() {
-                r[i][j] *= rhs.to_owned();
-            }
+        let mut r = self;
+        if !r.data.is_empty() {
+            assert_eq!(r.q, rhs.q);
+            ops::scale_assign(&mut r.data, rhs.uint, rhs.q);
         }
-
         r
     }
 
-    pub fn mul_vec(self, rhs: &Vec<Element>) -> Self {
-        let rhs_matrix = Self::from(&vec![rhs.to_owned()]).rotated();
+    pub fn mul_vec(self, rhs: &[Element]) -> Self {
+        #[cfg(feature = "simd")]
+        if let Some(result) = self.mul_vec_simd(rhs) {
+            return result;
+        }
+
+        let rhs_matrix = Self::from(&vec![rhs.to_vec()]).rotated();
         self.mul(rhs_matrix)
     }
 
+    /// Multiply by a vector, whose modulus the matrix must have.
+    pub fn mul_vector(&self, rhs: &Vector) -> Vector {
+        let q = rhs.q();
+        if !self.data.is_empty() {
+            assert_eq!(self.q, q);
+        }
+        let values = self.cols().map(|col| ops::dot(col, rhs.values(), q)).collect();
+        Vector::from(q, values)
+    }
+
+    /// Multiply by a vector of values mod q given as plain words, without converting either side.
+    /// The entries of the matrix must be below q.
+    pub fn mul_words(&self, rhs: &[u64], q: u64) -> Self {
+        assert_eq!(self.num_rows(), rhs.len());
+        #[cfg(feature = "simd")]
+        if q <= crate::simd::MAX_Q {
+            let data = self.cols().map(|col| crate::simd::dot_mod(col, rhs, q)).collect();
+            return Matrix::from_reduced(q, self.num_cols(), 1, data);
+        }
+        let data = self.cols().map(|col| ops::dot(col, rhs, q)).collect();
+        Matrix::from_reduced(q, self.num_cols(), 1, data)
+    }
+
+    // Compute each entry of the product with the vectorized inner product kernel. Returns None if
+    // the modulus is too large for the kernel.
+    #[cfg(feature = "simd")]
+    fn mul_vec_simd(&self, rhs: &[Element]) -> Option<Self> {
+        if rhs.is_empty() || rhs[0].q > crate::simd::MAX_Q {
+            return None;
+        }
+        assert_eq!(self.num_rows(), rhs.len());
+
+        let q = rhs[0].q;
+        assert_eq!(self.q, q);
+        let rhs = col_uints(rhs, q);
+        let data = self.cols().map(|col| crate::simd::dot_mod(col, &rhs, q)).collect();
+        Some(Matrix::from_reduced(q, self.num_cols(), 1, data))
+    }
+
     fn log_q_div_log_p_ceil(q: u64, p: u64) -> usize {
         ((q - 1) as f64).log(p as f64).ceil() as usize
     }
@@ -147,124 +397,164 @@ impl Matrix {
         let num_digits = Self::log_q_div_log_p_ceil(q, p);
         assert_eq!(self.num_cols() % num_digits, 0);
 
-        let each_col = vec![Element::from(q, 0); self.num_rows()];
-        let mut all_cols = vec![each_col.clone(); self.num_cols() / num_digits];
-
-        for i in 0..all_cols.len() {
-            for j in 0..each_col.len() {
-                let mut d = Vec::with_capacity(num_digits);
-                for k in 0..num_digits {
-                    d.push(self.data[i * num_digits + k][j].uint);
-                }
-                let d = Element::recompose(p, q, &d);
-                all_cols[i][j] = d;
+        let num_cols = self.num_cols() / num_digits;
+        let mut data = Vec::with_capacity(num_cols * self.num_rows());
+        for i in 0..num_cols {
+            for j in 0..self.num_rows() {
+                let d: Vec<u64> = (0..num_digits)
+                    .map(|k| self.col_values(i * num_digits + k)[j])
+                    .collect();
+                data.push(Element::recompose(p, q, &d).uint);
             }
         }
-        Matrix::from(&all_cols)
+        Matrix::from_reduced(q, num_cols, self.num_rows(), data)
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn decomposed(self, p: u64) -> Self {
         if self.num_cols() == 0 {
             return self;
         }
 
-        let q = self.data[0][0].q;
+        let q = self.q;
 
         let num_digits = Self::log_q_div_log_p_ceil(q, p);
 
-        let each_col = vec![Element::from(q, 0); self.num_rows()];
-        let mut all_cols = vec![each_col; num_digits * self.num_cols()];
+        let mut all_cols = Matrix::from_val(num_digits * self.num_cols(), self.num_rows(),
+            Element::zero(q));
 
         // NOTE: this is slow!
         for i in 0..self.num_cols() {
             for j in 0..self.num_rows() {
-                let d = self.data[i][j].to_owned().decomposed(p);
+                let d = self.get(i, j).decomposed(p);
                 for k in 0..num_digits {
-                    all_cols[i * num_digits + k][j].uint = d[k];
+                    all_cols.col_values_mut(i * num_digits + k)[j] = d[k];
                 }
             }
         }
-        Self::from(&all_cols)
+        all_cols
     }
 
     pub fn num_rows(&self) -> usize {
-        if self.num_cols() == 0 {
-                return 0;
-        }
-
-        self.data[0].len()
+        self.num_rows
     }
 
     pub fn num_cols(&self) -> usize {
-        self.data.len()
+        self.num_cols
     }
 
     pub fn dimensions(&self) -> (usize, usize) {
         (self.num_cols(), self.num_rows())
     }
+
+    /// Like `self + rhs`, but returning an error instead of panicking if the dimensions or the
+    /// moduli differ.
+    pub fn try_add(self, rhs: Matrix) -> Result<Self, SimplePirError> {
+        if self.dimensions() != rhs.dimensions() {
+            let (lhs, rhs) = (self.dimensions(), rhs.dimensions());
+            return Err(ShapeError { op: "add", lhs, rhs }.into());
+        }
+        check_same_mod(&self, &rhs)?;
+        Ok(self + rhs)
+    }
+
+    /// Like `self * rhs`, but returning an error instead of panicking if the inner dimensions or
+    /// the moduli differ.
+    pub fn try_mul(&self, rhs: &Matrix) -> Result<Self, SimplePirError> {
+        check_mul(self.dimensions(), rhs.dimensions())?;
+        check_same_mod(self, rhs)?;
+        Ok(self * rhs)
+    }
+
+    /// Like mul_vec(), but returning an error instead of panicking if rhs has the wrong length,
+    /// or entries which are not mod the modulus of the matrix.
+    pub fn try_mul_vec(self, rhs: &[Element]) -> Result<Self, SimplePirError> {
+        check_mul(self.dimensions(), (rhs.len(), 1))?;
+        if let Some(first) = rhs.first() {
+            let q = if self.data.is_empty() { first.q } else { self.q };
+            check_moduli(rhs, q)?;
+        }
+        Ok(self.mul_vec(rhs))
+    }
 }
 
-impl Add for Matrix {
-    type Output = Matrix;
-    #[allow(clippy::needless_range_loop)]
-    fn add(self, rhs: Matrix) -> Self::Output {
-        assert_eq!(self.num_rows(), rhs.num_rows());
-        assert_eq!(self.num_cols(), rhs.num_cols());
+// Check that two matrices which both have entries have the same modulus
+fn check_same_mod(lhs: &Matrix, rhs: &Matrix) -> Result<(), SimplePirError> {
+    if !lhs.data.is_empty() && !rhs.data.is_empty() && lhs.q != rhs.q {
+        return Err(SimplePirError::ModulusMismatch { expected: lhs.q, actual: rhs.q });
+    }
+    Ok(())
+}
 
-        let mut s = self.clone();
+// Check that a product of matrices with the given dimensions is defined
+fn check_mul(lhs: (usize, usize), rhs: (usize, usize)) -> Result<(), ShapeError> {
+    if lhs.1 != rhs.0 {
+        return Err(ShapeError { op: "multiply", lhs, rhs });
+    }
+    Ok(())
+}
 
-        for i in 0..self.num_cols() {
-            for j in 0..self.num_rows() {
-                s.data[i][j] = s.data[i][j].clone() + rhs.data[i][j].clone();
-            }
+// The values of a column, which must all be mod q
+fn col_uints(col: &[Element], q: u64) -> Vec<u64> {
+    col.iter().map(|e| {
+        assert_eq!(e.q, q);
+        e.uint
+    }).collect()
+}
+
+impl Matrix {
+    // Apply a slice operation to the values of self and the matching values of rhs
+    fn zip_with(&mut self, rhs: &Matrix, op: fn(&mut [u64], &[u64], u64)) {
+        assert_eq!(self.num_rows(), rhs.num_rows());
+        assert_eq!(self.num_cols(), rhs.num_cols());
+        if self.data.is_empty() {
+            return;
         }
+        assert_eq!(self.q, rhs.q);
+        // Both matrices are laid out alike, so the columns can be combined as one slice
+        op(&mut self.data, &rhs.data, self.q);
+    }
+}
+
+impl Add for Matrix {
+    type Output = Matrix;
+    fn add(self, rhs: Matrix) -> Self::Output {
+        let mut s = self;
+        s += rhs;
         s
     }
 }
 
 impl AddAssign for Matrix {
     fn add_assign(&mut self, rhs: Matrix) {
-        assert_eq!(self.num_rows(), rhs.num_rows());
-        assert_eq!(self.num_cols(), rhs.num_cols());
-        for i in 0..self.num_cols() {
-            for j in 0..self.num_rows() {
-                self.data[i][j] += rhs.data[i][j].clone();
-            }
-        }
+        self.zip_with(&rhs, ops::add_assign_slices);
     }
 }
 
 impl Sub for Matrix {
     type Output = Matrix;
-    #[allow(clippy::needless_range_loop)]
     fn sub(self, rhs: Matrix) -> Self::Output {
-        assert_eq!(self.num_rows(), rhs.num_rows());
-        assert_eq!(self.num_cols(), rhs.num_cols());
-
-        let mut s = self.clone();
-
-        for i in 0..self.num_cols() {
-            for j in 0..self.num_rows() {
-                s.data[i][j] = s.data[i][j].clone() - rhs.data[i][j].clone();
-            }
-        }
+        let mut s = self;
+        s -= rhs;
         s
     }
 }
 
 impl SubAssign for Matrix {
     fn sub_assign(&mut self, rhs: Matrix) {
-        assert_eq!(self.num_rows(), rhs.num_rows());
-        assert_eq!(self.num_cols(), rhs.num_cols());
-        for i in 0..self.num_cols() {
-            for j in 0..self.num_rows() {
-                self.data[i][j] = self.data[i][j].clone() - rhs.data[i][j].clone();
-            }
-        }
+        self.zip_with(&rhs, ops::sub_assign_slices);
     }
 }
 
 impl Mul for Matrix {
+    type Output = Matrix;
+    fn mul(self, rhs: Matrix) -> Self::Output {
+        &self * &rhs
+    }
+}
+
+/// Multiply without consuming either operand.
+impl Mul<&Matrix> for &Matrix {
     /*
      * [a00, a01, a02] [b00, b01] = [a00b00 + a01b10 + a02b20, a00b01 + a01b11 + a02b21]
      * [a10, a11, a12] [b10, b11]   [a10b00 + a11b10 * a12b20, a10b01 + a11b11 * a12b21]
@@ -272,30 +562,60 @@ impl Mul for Matrix {
      */
     type Output = Matrix;
     #[allow(clippy::needless_range_loop)]
-    fn mul(self, rhs: Matrix) -> Self::Output {
+    fn mul(self, rhs: &Matrix) -> Self::Output {
         // Ensure that the rhs matrix has the correct dimensions
-        assert_eq!(self.num_rows(), rhs.num_cols());
+        assert_eq!(self.num_rows(), rhs.num_cols(), "the inner dimensions of the product differ");
 
-        // Assign a result matrix of the required dimensions with 0s in each cell
-        let zero = Element::zero(self.data[0][0].q);
         let n = self.num_cols();
         let m = self.num_rows(); // = rhs.num_cols()
         let p = rhs.num_rows();
 
-        let each_result_row = vec![zero.clone(); p];
-        let mut result: Vec<Vec<Element>> = vec![each_result_row; n];
+        // The result has no entries, so there is nothing to compute
+        if n == 0 || p == 0 {
+            return Matrix::from(&vec![vec![]; n]);
+        }
+        assert!(m > 0, "cannot multiply matrices with an empty inner dimension");
+
+        let q = self.q;
+        assert_eq!(rhs.q, q);
+        let mut result = Matrix::from_val(n, p, Element::zero(q));
+
+        if (2..=1 << 32).contains(&q) && (m as u64) < 1 << 32 {
+            // Each reduced product is below 2^32, so the sum of fewer than 2^32 of them fits in a
+            // u64 and only needs reducing once
+            let modulus = Modulus::new(q);
+
+            // For odd q, convert the right hand side to Montgomery form once. A Montgomery product
+            // with one operand in Montgomery form yields the canonical product directly.
+            let mont = Montgomery::new(q);
+            let rhs_vals: Vec<Vec<u64>> = (0..m).map(|k| {
+                rhs.col_values(k).iter().map(|&v| mont.map_or(v, |mont| mont.to_mont(v))).collect()
+            }).collect();
+
+            for i in 0..n {
+                for j in 0..p {
+                    let mut sum = 0u64;
+                    for (k, &a) in self.col_values(i).iter().enumerate() {
+                        let b = rhs_vals[k][j];
+                        sum += match mont {
+                            Some(mont) => mont.mul(a, b),
+                            None => modulus.mul(a, b),
+                        };
+                    }
+                    result.col_values_mut(i)[j] = modulus.reduce(sum);
+                }
+            }
+            return result;
+        }
 
+        // Row i of the result is the combination of the rows of rhs with the entries of row i
         for i in 0..n {
-            for j in 0..p {
-                let mut sum = zero.clone();
-                for k in 0..m {
-                    sum += self.data[i][k].clone() * rhs[k][j].clone();
-                }
-                result[i][j] = sum;
+            for (k, &a) in self.col_values(i).iter().enumerate() {
+                ops::scale_accumulate(result.col_values_mut(i), rhs.col_values(k), a, q);
             }
         }
 
-        Self::from(&result)
+        result
     }
 }
 
@@ -307,9 +627,64 @@ impl MulAssign for Matrix {
     }
 }
 
+impl Add<u64> for Matrix {
+    type Output = Matrix;
+    fn add(self, rhs: u64) -> Self::Output {
+        let mut s = self;
+        let q = s.q;
+        for val in s.data.iter_mut() {
+            *val = (Element::from(q, *val) + rhs).uint;
+        }
+        s
+    }
+}
+
+impl Mul<u64> for Matrix {
+    type Output = Matrix;
+    fn mul(self, rhs: u64) -> Self::Output {
+        let mut s = self;
+        s *= rhs;
+        s
+    }
+}
+
+impl MulAssign<u64> for Matrix {
+    fn mul_assign(&mut self, rhs: u64) {
+        ops::scale_assign(&mut self.data, rhs, self.q);
+    }
+}
+
+/// A lazily transposed matrix, see Matrix::transposed().
+#[derive(Debug, Clone, Copy)]
+pub struct Transposed<'a> {
+    matrix: &'a Matrix,
+}
+
+impl<'a> Transposed<'a> {
+    /// The entry at [i][j] of the transpose, which is matrix[j][i].
+    pub fn get(&self, i: usize, j: usize) -> Element {
+        self.matrix.get(j, i)
+    }
+
+    /// The entries of line i of the transpose, i.e. entry i of every line of the matrix.
+    pub fn line(&self, i: usize) -> impl Iterator<Item = Element> + 'a {
+        let matrix = self.matrix;
+        matrix.cols().map(move |line| Element::from(matrix.q, line[i]))
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.matrix.num_rows(), self.matrix.num_cols())
+    }
+
+    /// Copy the view into a matrix.
+    pub fn to_matrix(&self) -> Matrix {
+        self.matrix.transpose()
+    }
+}
+
 impl Display for Matrix {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for row in self.data.iter() {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        for row in self.cols() {
             write!(f, "[")?;
             for (j, val) in row.iter().enumerate() {
                 write!(f, "{}", val)?;
@@ -319,7 +694,7 @@ impl Display for Matrix {
             }
             write!(f, "]")?;
         }
-        write!(f, "\n")?;
+        writeln!(f)?;
         Ok(())
     }
 }
@@ -328,9 +703,10 @@ impl Display for Matrix {
 pub mod tests {
     use super::Matrix;
     use super::Element;
+    use crate::error::{ShapeError, SimplePirError};
 
     fn gen_q() -> u64 {
-        return 101u64;
+        101u64
     }
 
     // Tests for matrix.rs
@@ -391,12 +767,48 @@ pub mod tests {
     fn test_indices() {
         let q = gen_q();
         let mut m = gen_matrix_3_2();
-        assert_eq!(m[0][0], Element::from(q, 1u64));
-        assert_eq!(m[0][1], Element::from(q, 2u64));
-        assert_eq!(m[1][1], Element::from(q, 5u64));
+        assert_eq!(m.get(0, 0), Element::from(q, 1u64));
+        assert_eq!(m.get(0, 1), Element::from(q, 2u64));
+        assert_eq!(m.get(1, 1), Element::from(q, 5u64));
+
+        m.set(1, 1, &Element::from(q, 0u64));
+        assert_eq!(m.get(1, 1), Element::from(q, 0u64));
+        assert_eq!(m.col(1), [4, 0, 6].map(|v| Element::from(q, v)));
+        assert_eq!(m.col_values(1), [4, 0, 6]);
+    }
+
+    #[test]
+    fn test_cols() {
+        let m = gen_matrix_3_2();
+        // The columns are stored one after the other
+        assert_eq!(m.values(), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(m.cols().collect::<Vec<_>>(), [m.col_values(0), m.col_values(1)]);
+        assert_eq!(Matrix::from_values(m.q(), 2, 3, m.values().to_vec()), m);
+        assert_eq!(Matrix::from_flat(2, 3, m.elements().collect()), m);
+        assert_eq!(Matrix::from(&m.clone().into_cols()), m);
+
+        let mut n = m.select_cols(1..2);
+        assert_eq!(n, Matrix::from_col(&m.col(1)));
+        n.append(m.select_cols(0..0));
+        assert_eq!(n.dimensions(), (1, 3));
+        assert_eq!(Matrix::concat([m.select_cols(0..1), n.clone()]), m);
+        assert_eq!(n.pop_col(), Some(m.col(1)));
+        assert_eq!(n, Matrix::new());
+        assert_eq!(n.pop_col(), None);
+    }
 
-        m[1][1] = Element::from(q, 0u64);
-        assert_eq!(m[1][1], Element::from(q, 0u64));
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let m = gen_matrix_3_2();
+        let json = serde_json::to_string(&m).unwrap();
+        assert_eq!(serde_json::from_str::<Matrix>(&json).unwrap(), m);
+
+        // Values must be below the modulus, and match the dimensions
+        assert!(serde_json::from_str::<Matrix>(&json.replace(",6]", ",101]")).is_err());
+        assert!(serde_json::from_str::<Matrix>(&json.replace(",6]", "]")).is_err());
+        let repr = super::MatrixRepr { q: 101, data: vec![], num_cols: usize::MAX, num_rows: 2 };
+        assert_eq!(Matrix::try_from(repr), Err(SimplePirError::InvalidShape("matrix")));
     }
 
     #[test]
@@ -406,6 +818,21 @@ pub mod tests {
         assert_eq!(m.rotated(), n);
     }
 
+    #[test]
+    fn test_transpose() {
+        let q = gen_q();
+        let m = gen_matrix_3_2();
+        assert_eq!(m.transpose(), gen_matrix_2_3());
+        assert_eq!(m.transpose().transpose(), m);
+        assert_eq!(Matrix::new().transpose(), Matrix::new());
+
+        let view = m.transposed();
+        assert_eq!(view.dimensions(), (3, 2));
+        assert_eq!(view.get(2, 1), Element::from(q, 6u64));
+        assert_eq!(view.line(1).collect::<Vec<_>>(), gen_matrix_2_3().col(1));
+        assert_eq!(view.to_matrix(), gen_matrix_2_3());
+    }
+
     #[test]
     fn test_mul() {
         // 3 rows, 2 cols
@@ -417,6 +844,89 @@ pub mod tests {
         assert_eq!(m * n, o);
     }
 
+    #[test]
+    fn test_mul_ref() {
+        let q = gen_q();
+        let m = gen_matrix_3_2();
+        let n = gen_matrix_2_3();
+        assert_eq!(&m * &n, gen_matrix_2_2());
+
+        // The operands are left intact, and can be multiplied the other way around
+        let from = |rows: Vec<Vec<u64>>, q: u64| {
+            let rows = rows.into_iter()
+                .map(|row| row.into_iter().map(|v| Element::from(q, v)).collect())
+                .collect();
+            Matrix::from(&rows)
+        };
+        assert_eq!(&n * &m, from(vec![vec![17, 22, 27], vec![22, 29, 36], vec![27, 36, 45]], q));
+
+        // Moduli above 2^32 take the generic path
+        let q = (1 << 40) - 87;
+        let a = from(vec![vec![q - 1, 2], vec![3, q - 1]], q);
+        let b = from(vec![vec![q - 1, 0], vec![0, 1]], q);
+        assert_eq!(&a * &b, from(vec![vec![1, 2], vec![q - 3, q - 1]], q));
+    }
+
+    #[test]
+    #[should_panic(expected = "inner dimensions")]
+    fn test_mul_dimension_mismatch() {
+        let _ = &gen_matrix_3_2() * &gen_matrix_3_2();
+    }
+
+    #[test]
+    fn test_try_ops() {
+        let m = gen_matrix_3_2();
+        let n = gen_matrix_2_3();
+        assert_eq!(m.try_mul(&n), Ok(gen_matrix_2_2()));
+        assert_eq!(m.clone().try_add(m.clone()), Ok(m.clone() + m.clone()));
+        assert_eq!(m.clone().try_mul_vec(&gen_vec_3()), Ok(m.clone().mul_vec(&gen_vec_3())));
+
+        assert_eq!(
+            m.try_mul(&m),
+            Err(ShapeError { op: "multiply", lhs: (2, 3), rhs: (2, 3) }.into()),
+        );
+        assert_eq!(
+            m.clone().try_add(n.clone()),
+            Err(ShapeError { op: "add", lhs: (2, 3), rhs: (3, 2) }.into()),
+        );
+        assert_eq!(
+            m.clone().try_mul_vec(&gen_vec_3()[..2]),
+            Err(ShapeError { op: "multiply", lhs: (2, 3), rhs: (2, 1) }.into()),
+        );
+        let no_rows = Matrix::from(&vec![vec![]; 2]);
+        assert_eq!(no_rows.try_mul(&Matrix::new()).map(|p| p.dimensions()), Ok((2, 0)));
+
+        // Operands with another modulus are rejected rather than panicking
+        let q = gen_q();
+        let mismatch = Err(SimplePirError::ModulusMismatch { expected: q, actual: 97 });
+        let other_q = Matrix::gen_uniform_rand(97, 3, 2);
+        assert_eq!(m.try_mul(&other_q), mismatch);
+        assert_eq!(m.clone().try_add(Matrix::gen_uniform_rand(97, 2, 3)), mismatch);
+        let mut v = gen_vec_3();
+        v[1] = Element::from(97, 1);
+        assert_eq!(m.clone().try_mul_vec(&v), mismatch);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let bytes: Vec<u8> = (0..100).map(|i| (i * 37) as u8).collect();
+        for p in [2, 256, 991] {
+            let m = Matrix::from_bytes(&bytes, p, (30, 40)).unwrap();
+            assert_eq!(m.dimensions(), (30, 40));
+            assert_eq!(m.to_bytes(bytes.len()), bytes);
+        }
+
+        // Bytes are packed in item order, one byte per element for p = 256
+        let m = Matrix::from_bytes(&[1, 2, 3], 256, (2, 2)).unwrap();
+        assert_eq!((m.col_values(0)[1], m.col_values(1)[0], m.col_values(1)[1]), (2, 3, 0));
+        assert_eq!(m.to_bytes(0), Vec::<u8>::new());
+
+        assert_eq!(
+            Matrix::from_bytes(&bytes, 2, (10, 10)),
+            Err(SimplePirError::TooManyBytes { len: 100, capacity: 12 }),
+        );
+    }
+
     #[test]
     fn test_mul_assign() {
         let mut m = gen_matrix_3_2();
@@ -434,15 +944,31 @@ pub mod tests {
         assert_eq!(m.mul_vec(&v), r);
     }
 
+    #[test]
+    fn test_mul_vec_moduli() {
+        // Odd and even moduli on the 32-bit paths, and moduli which need 128-bit products
+        for q in [3329, 65536, 4294967291, 1 << 32, (1 << 40) - 87, u64::MAX - 58] {
+            let m = Matrix::gen_uniform_rand(q, 5, 40);
+            let v = Matrix::gen_uniform_rand(q, 1, 40).col(0);
+            let r = m.clone().mul_vec(&v);
+            for i in 0..5 {
+                let expected = m.col_values(i).iter().zip(&v).fold(0u128, |acc, (&x, y)| {
+                    (acc + x as u128 * y.uint as u128) % q as u128
+                }) as u64;
+                assert_eq!(r.get(i, 0), Element::from(q, expected));
+            }
+        }
+    }
+
     #[test]
     fn test_add() {
         let m = gen_matrix_3_2();
         let n = gen_matrix_3_2();
         let o = m.clone() + n.clone();
         
-        for (i, row) in o.data.iter().enumerate() {
-            for (j, val) in row.iter().enumerate() {
-                assert_eq!(*val, m[i][j].clone() + n[i][j].clone());
+        for i in 0..o.num_cols() {
+            for j in 0..o.num_rows() {
+                assert_eq!(o.get(i, j), m.get(i, j) + n.get(i, j));
             }
         }
     }
@@ -454,9 +980,56 @@ pub mod tests {
         let o = m.clone();
         m += n.clone();
         
-        for (i, row) in m.data.iter().enumerate() {
-            for (j, val) in row.iter().enumerate() {
-                assert_eq!(*val, o[i][j].clone() + n[i][j].clone());
+        for i in 0..m.num_cols() {
+            for j in 0..m.num_rows() {
+                assert_eq!(m.get(i, j), o.get(i, j) + n.get(i, j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_scalar_ops() {
+        let m = gen_matrix_3_2();
+        let scaled = m.clone() * 20u64;
+        let shifted = m.clone() + 100u64;
+        for i in 0..m.num_cols() {
+            for j in 0..m.num_rows() {
+                assert_eq!(scaled.get(i, j), m.get(i, j) * 20u64);
+                assert_eq!(shifted.get(i, j), m.get(i, j) + 100u64);
+            }
+        }
+
+        let mut n = m.clone();
+        n *= 20u64;
+        assert_eq!(n, scaled);
+    }
+
+    #[test]
+    fn test_empty() {
+        let q = gen_q();
+        let empty = Matrix::new();
+        assert_eq!(empty.clone().rotated(), empty);
+        assert_eq!(empty.clone() * empty.clone(), empty);
+        assert_eq!(empty.clone().decomposed(2), empty);
+
+        // 2 columns with no rows
+        let no_rows = Matrix::from(&vec![vec![], vec![]]);
+        assert_eq!(no_rows.clone().rotated(), empty);
+        assert_eq!((no_rows.clone() * empty.clone()).dimensions(), (2, 0));
+
+        let single = Matrix::from_single(&Element::from(q, 7u64));
+        assert_eq!(single.clone().rotated(), single);
+        assert_eq!(single.clone() * single, Matrix::from_single(&Element::from(q, 49u64)));
+    }
+
+    #[test]
+    fn test_mod_switch() {
+        let m = gen_matrix_3_2();
+        let switched = m.mod_switch(10);
+        assert_eq!(switched.dimensions(), m.dimensions());
+        for i in 0..m.num_cols() {
+            for j in 0..m.num_rows() {
+                assert_eq!(switched.get(i, j), m.get(i, j).mod_switch(10));
             }
         }
     }
@@ -483,9 +1056,9 @@ pub mod tests {
             vec![0, 0, 0],
             vec![0, 0, 0],
         ];
-        for (i, col) in decomposed.data.iter().enumerate() {
+        for (i, col) in decomposed.cols().enumerate() {
             for j in 0..col.len() {
-                assert_eq!(col[j].uint, expected[i][j] as u64);
+                assert_eq!(col[j], expected[i][j] as u64);
             }
         }
     }
@@ -494,7 +1067,7 @@ pub mod tests {
     fn test_matrix_recomposition() {
         let m = gen_matrix_3_2();
         let decomposed = m.to_owned().decomposed(2);
-        let r = decomposed.recompose(2, m[0][0].q);
+        let r = decomposed.recompose(2, m.q());
         assert_eq!(m, r);
     }
 }
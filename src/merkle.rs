@@ -0,0 +1,176 @@
+// Merkle commitments to byte records, so that clients can check that a retrieved record is the
+// one the server committed to.
+//
+// The leaves are the hashes of the records together with their indices, padded with zeros to a
+// power of two, and the server publishes the root. The authentication path of a record (the
+// sibling of each node on the way from its leaf to the root) depends on which record it is, so
+// the server cannot send it along with the answer without learning the index. Instead each
+// record is stored with its path appended, and PIR retrieves both at once: the client recomputes
+// the root from the record and its path and rejects the record unless it matches. Leaves and
+// inner nodes are hashed with different prefixes, so that one cannot be passed off as the other.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use sha2::{Digest as _, Sha256};
+use crate::error::SimplePirError;
+
+/// A SHA-256 hash.
+pub type Digest = [u8; 32];
+
+// Prefixes which separate the hashes of leaves and of inner nodes
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// The leaf of record idx.
+pub fn leaf_hash(idx: usize, record: &[u8]) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update((idx as u64).to_le_bytes());
+    hasher.update(record);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Digest, right: &Digest) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The number of hashes in the authentication path of each of num_records records.
+pub fn depth(num_records: usize) -> usize {
+    num_records.next_power_of_two().trailing_zeros() as usize
+}
+
+/// The length of a record of record_len bytes with its authentication path appended, which is
+/// the record length to lay out an authenticated database with.
+pub fn authenticated_len(num_records: usize, record_len: usize) -> usize {
+    record_len + depth(num_records) * 32
+}
+
+/// A Merkle tree over the leaves of a list of records.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MerkleTree {
+    // The leaves padded to a power of two, then each level up to the root
+    levels: Vec<Vec<Digest>>,
+}
+
+impl MerkleTree {
+    pub fn new(records: &[Vec<u8>]) -> Self {
+        assert!(!records.is_empty());
+        let mut leaves: Vec<Digest> =
+            records.iter().enumerate().map(|(idx, record)| leaf_hash(idx, record)).collect();
+        leaves.resize(records.len().next_power_of_two(), [0; 32]);
+
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let level = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], &pair[1]))
+                .collect();
+            levels.push(level);
+        }
+        Self { levels }
+    }
+
+    pub fn root(&self) -> Digest {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    /// The authentication path of leaf idx, from the bottom up.
+    pub fn path(&self, idx: usize) -> Vec<Digest> {
+        assert!(idx < self.levels[0].len());
+        let levels = &self.levels[..self.levels.len() - 1];
+        levels.iter().enumerate().map(|(i, level)| level[(idx >> i) ^ 1]).collect()
+    }
+}
+
+/// Whether the leaf of record idx and its authentication path hash to the root.
+pub fn verify(root: &Digest, idx: usize, leaf: &Digest, path: &[Digest]) -> bool {
+    let computed = path.iter().enumerate().fold(*leaf, |node, (i, sibling)| {
+        if (idx >> i) & 1 == 0 { node_hash(&node, sibling) } else { node_hash(sibling, &node) }
+    });
+    // An index past the leaves of the tree could otherwise alias a smaller one
+    idx >> path.len() == 0 && computed == *root
+}
+
+/// Commit to the records, returning the tree and the records with their authentication paths
+/// appended, which are to be stored in the database in their place.
+pub fn authenticate_records(records: &[Vec<u8>]) -> (MerkleTree, Vec<Vec<u8>>) {
+    let tree = MerkleTree::new(records);
+    let authenticated = records.iter().enumerate().map(|(idx, record)| {
+        let mut authenticated = record.clone();
+        tree.path(idx).iter().for_each(|node| authenticated.extend_from_slice(node));
+        authenticated
+    }).collect();
+    (tree, authenticated)
+}
+
+/// Split a record retrieved from a database of num_records authenticated records into the record
+/// and its path, and return the record if it hashes to the root.
+pub fn verify_record(
+    root: &Digest,
+    num_records: usize,
+    idx: usize,
+    authenticated: &[u8],
+) -> Result<Vec<u8>, SimplePirError> {
+    let path_len = depth(num_records) * 32;
+    if authenticated.len() < path_len {
+        let actual = authenticated.len();
+        return Err(SimplePirError::LengthMismatch { expected: path_len, actual });
+    }
+    let (record, path) = authenticated.split_at(authenticated.len() - path_len);
+    let path: Vec<Digest> = path.chunks(32).map(|node| node.try_into().unwrap()).collect();
+    if !verify(root, idx, &leaf_hash(idx, record), &path) {
+        return Err(SimplePirError::Unauthenticated);
+    }
+    Ok(record.to_vec())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn gen_records(num_records: usize) -> Vec<Vec<u8>> {
+        (0..num_records).map(|i| vec![i as u8; 1 + i % 3]).collect()
+    }
+
+    #[test]
+    fn test_merkle_tree() {
+        for num_records in [1, 2, 5, 8] {
+            let records = gen_records(num_records);
+            let tree = MerkleTree::new(&records);
+            assert_eq!(tree.path(0).len(), depth(num_records));
+            for (idx, record) in records.iter().enumerate() {
+                let path = tree.path(idx);
+                assert!(verify(&tree.root(), idx, &leaf_hash(idx, record), &path));
+                // The leaf commits to the index as well as the record
+                assert!(!verify(&tree.root(), idx ^ 1, &leaf_hash(idx, record), &path));
+                assert!(!verify(&tree.root(), idx, &leaf_hash(idx, b"forged"), &path));
+            }
+        }
+        let tree = MerkleTree::new(&gen_records(4));
+        assert!(!verify(&tree.root(), 4, &leaf_hash(0, &[0]), &tree.path(0)));
+    }
+
+    #[test]
+    fn test_authenticated_records() {
+        let records = gen_records(6);
+        let (tree, authenticated) = authenticate_records(&records);
+        let root = tree.root();
+        for (idx, record) in records.iter().enumerate() {
+            assert_eq!(authenticated[idx].len(), authenticated_len(6, record.len()));
+            assert_eq!(verify_record(&root, 6, idx, &authenticated[idx]), Ok(record.clone()));
+        }
+
+        let mut modified = authenticated[2].clone();
+        modified[0] ^= 1;
+        assert_eq!(verify_record(&root, 6, 2, &modified), Err(SimplePirError::Unauthenticated));
+        assert_eq!(
+            verify_record(&root, 6, 3, &authenticated[2]),
+            Err(SimplePirError::Unauthenticated),
+        );
+        assert!(verify_record(&root, 6, 0, &[0; 10]).is_err());
+    }
+}
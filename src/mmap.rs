@@ -0,0 +1,252 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use memmap2::Mmap;
+use crate::database::{check_range, Database};
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::matrix::Matrix;
+use crate::record::{encode_record, RecordLayout};
+
+// Identifies a database file, and the version of its layout
+const MAGIC: &[u8; 8] = b"SPIRDB01";
+
+// The magic followed by p, the number of columns, the number of rows and the bytes per item
+const HEADER_LEN: usize = 40;
+
+/// A database served from a memory-mapped file, so that databases larger than RAM can be
+/// answered, with the OS paging columns in as they are scanned.
+///
+/// The file starts with a header followed by the items column by column, each packed into the
+/// fewest bytes (1, 2, 4 or 8) which hold values below p, little-endian. Files are written with
+/// write() from a database matrix, or with write_records() straight from byte records.
+pub struct MmapDatabase {
+    mmap: Mmap,
+    p: u64,
+    num_cols: usize,
+    num_rows: usize,
+    width: usize,
+}
+
+// The number of bytes each item mod p is packed into
+fn item_width(p: u64) -> usize {
+    match p - 1 {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x10000..=0xffff_ffff => 4,
+        _ => 8,
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Write the header of a database of num_cols x num_rows items mod p
+fn write_header<W: Write>(out: &mut W, p: u64, num_cols: usize, num_rows: usize) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    for field in [p, num_cols as u64, num_rows as u64, item_width(p) as u64] {
+        out.write_all(&field.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+// Write one item, packed into width bytes
+fn write_item<W: Write>(out: &mut W, item: u64, width: usize) -> io::Result<()> {
+    out.write_all(&item.to_le_bytes()[..width])
+}
+
+impl MmapDatabase {
+    /// Write a database mod p in the layout read by open().
+    pub fn write<P: AsRef<Path>>(path: P, db: &Matrix, p: u64) -> io::Result<()> {
+        let width = item_width(p);
+        let mut out = BufWriter::new(File::create(path)?);
+        write_header(&mut out, p, db.num_cols(), db.num_rows())?;
+        assert!(db.has_modulus(p));
+        for &item in db.values() {
+            write_item(&mut out, item, width)?;
+        }
+        out.flush()
+    }
+
+    /// Encode byte records according to the layout and write them in the layout read by open(),
+    /// producing the same database as RecordLayout::encode_db(). Only one row's worth of records
+    /// is held in memory at a time, so databases larger than RAM can be converted.
+    pub fn write_records<P, I>(path: P, layout: &RecordLayout, records: I, p: u64) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let width = item_width(p);
+        let mut out = BufWriter::new(File::create(path)?);
+        write_header(&mut out, p, layout.num_cols(), layout.rows)?;
+
+        // Records idx of each block of layout.rows records share the same `limbs` columns
+        let mut records = records.into_iter();
+        let mut written = 0;
+        for _ in 0..layout.records_per_row() {
+            let block: Vec<Vec<Element>> = records.by_ref()
+                .take(layout.rows)
+                .map(|record| {
+                    assert_eq!(record.as_ref().len(), layout.record_len);
+                    encode_record(record.as_ref(), p)
+                })
+                .collect();
+            written += block.len();
+            for i in 0..layout.limbs {
+                for row in 0..layout.rows {
+                    let limb = block.get(row).map_or(Element::zero(p), |limbs| limbs[i].clone());
+                    write_item(&mut out, limb.uint, width)?;
+                }
+            }
+        }
+        assert!(written == layout.num_records && records.next().is_none());
+        out.flush()
+    }
+
+    /// Map a database file written by write() or write_records().
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the file must not be modified while it is mapped, as for any mapped database
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Err(invalid_data("not a database file"));
+        }
+        let field = |i: usize| u64::from_le_bytes(mmap[8 * i..8 * (i + 1)].try_into().unwrap());
+        let (p, num_cols, num_rows, width) = (field(1), field(2), field(3), field(4));
+        if p < 2 || width as usize != item_width(p) {
+            return Err(invalid_data("invalid database header"));
+        }
+        // Checked in u64, as dimensions truncated to a 32-bit usize could pass for the file's
+        let len = num_cols.checked_mul(num_rows).and_then(|n| n.checked_mul(width));
+        if len != Some((mmap.len() - HEADER_LEN) as u64) {
+            return Err(invalid_data("the database file is truncated"));
+        }
+        if num_cols == 0 || num_rows == 0 {
+            return Err(invalid_data("the database is empty"));
+        }
+        // Both divide the mapped length, so they fit in a usize
+        let (num_cols, num_rows, width) = (num_cols as usize, num_rows as usize, width as usize);
+        Ok(Self { mmap, p, num_cols, num_rows, width })
+    }
+
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+}
+
+impl Database for MmapDatabase {
+    fn len(&self) -> usize {
+        self.num_cols
+    }
+
+    fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    fn chunk(&self, range: Range<usize>) -> Result<Matrix, SimplePirError> {
+        check_range(&range, self.num_cols)?;
+        let col_len = self.num_rows * self.width;
+        let start = HEADER_LEN + range.start * col_len;
+        let bytes = &self.mmap[start..start + range.len() * col_len];
+
+        let data = bytes.chunks(col_len).map(|col| {
+            col.chunks(self.width).map(|b| {
+                let mut item = [0u8; 8];
+                item[..self.width].copy_from_slice(b);
+                Element::checked_from(self.p, u64::from_le_bytes(item))
+            }).collect()
+        }).collect::<Result<_, _>>()?;
+        Ok(Matrix::from(&data))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::pir::{Client, Server};
+    use crate::simplepir;
+
+    // A path in the temp directory which is unique to the test
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("simplepir-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_mmap_database() {
+        let path = temp_path("mmap");
+        for p in [2, 991, 65537, 4294967291] {
+            let db = Matrix::gen_uniform_rand(p, 9, 5);
+            MmapDatabase::write(&path, &db, p).unwrap();
+
+            let mmap_db = MmapDatabase::open(&path).unwrap();
+            assert_eq!((mmap_db.p(), mmap_db.len(), mmap_db.num_rows()), (p, 9, 5));
+            assert_eq!(mmap_db.chunk(0..9).unwrap(), db);
+            assert_eq!(mmap_db.chunk(3..7).unwrap(), db.select_cols(3..7));
+            assert_eq!(mmap_db.record(8).unwrap(), db.col(8));
+            assert!(mmap_db.chunk(8..10).is_err());
+            #[allow(clippy::reversed_empty_ranges)]
+            let reversed = mmap_db.chunk(7..3);
+            assert_eq!(reversed, Err(SimplePirError::InvalidRange { start: 7, end: 3 }));
+        }
+
+        // Truncated and foreign files are rejected
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MmapDatabase::open(&path).is_err());
+        std::fs::write(&path, [0u8; 64]).unwrap();
+        assert!(MmapDatabase::open(&path).is_err());
+        MmapDatabase::write(&path, &Matrix::gen_uniform_rand(991, 0, 5), 991).unwrap();
+        assert!(MmapDatabase::open(&path).is_err());
+        MmapDatabase::write(&path, &Matrix::gen_uniform_rand(991, 9, 0), 991).unwrap();
+        assert!(MmapDatabase::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mmap_records() {
+        let path = temp_path("mmap-records");
+        let records: Vec<Vec<u8>> = (0..23).map(|i| vec![i as u8; 5]).collect();
+        let layout = RecordLayout::new(records.len(), 5, 991).unwrap();
+        MmapDatabase::write_records(&path, &layout, &records, 991).unwrap();
+        let mmap_db = MmapDatabase::open(&path).unwrap();
+        let db = layout.encode_db(&records, 991).unwrap();
+        assert_eq!(mmap_db.chunk(0..mmap_db.len()).unwrap(), db);
+
+        // Answers over the mapped file match answers over the database in memory
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 991);
+        let s = crate::regev::gen_secret(params.q, params.n);
+        let (query, _) = simplepir::query(&params, 3, s).unwrap();
+        let expected = simplepir::answer(&query, &db).unwrap();
+        assert_eq!(simplepir::answer_db(&query, &mmap_db, 4).unwrap(), expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mmap_server() {
+        let path = temp_path("mmap-server");
+        let db_size = 300;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 991);
+        let items: Vec<Element> = (0..db_size).map(|_| Element::gen_uniform_rand(991)).collect();
+        let (db, hint) = simplepir::setup(&params, &items).unwrap();
+        MmapDatabase::write(&path, &db, params.p).unwrap();
+
+        let mmap_db = MmapDatabase::open(&path).unwrap();
+        assert_eq!(simplepir::gen_hint_db(&params, &mmap_db, 7).unwrap(), hint);
+        let server = Server::setup_database(params.clone(), mmap_db).unwrap();
+        assert_eq!(server.hint(), &hint);
+        let mut client = Client::from_public_hint(server.public_hint()).unwrap();
+        for idx in [0, 150, 299] {
+            let query = client.query(idx).unwrap();
+            assert_eq!(client.recover(&server.answer(&query).unwrap()).unwrap(), items[idx]);
+        }
+
+        // A database with the wrong number of rows for the params is refused
+        MmapDatabase::write(&path, &db.select_cols(0..3).transpose(), params.p).unwrap();
+        let result = Server::setup_database(params, MmapDatabase::open(&path).unwrap());
+        assert!(matches!(result, Err(SimplePirError::LengthMismatch { .. })));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
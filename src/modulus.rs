@@ -0,0 +1,152 @@
+/// A modulus q ≤ 2^32 together with its precomputed Barrett constant ⌊2^64 / q⌋, so that values
+/// can be reduced with a multiplication and a shift instead of a hardware division. Computing the
+/// constant costs a division, so it pays off in loops which reduce many values mod the same q.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Modulus {
+    q: u64,
+    barrett: u64,
+}
+
+impl Modulus {
+    pub fn new(q: u64) -> Self {
+        assert!(q >= 2);
+        assert!(q <= 1 << 32, "the modulus does not fit in 32 bits");
+        let barrett = ((1u128 << 64) / q as u128) as u64;
+        Self { q, barrett }
+    }
+
+    pub fn q(&self) -> u64 {
+        self.q
+    }
+
+    /// Compute x mod q.
+    pub fn reduce(&self, x: u64) -> u64 {
+        // The estimate of ⌊x / q⌋ is low by at most 1, so one correction suffices
+        let estimate = ((x as u128 * self.barrett as u128) >> 64) as u64;
+        let r = x - estimate * self.q;
+        if r >= self.q {
+            r - self.q
+        } else {
+            r
+        }
+    }
+
+    /// Compute (a + b) mod q for a, b < q.
+    pub fn add(&self, a: u64, b: u64) -> u64 {
+        let s = a + b;
+        if s >= self.q {
+            s - self.q
+        } else {
+            s
+        }
+    }
+
+    /// Compute (a * b) mod q for a, b < q.
+    pub fn mul(&self, a: u64, b: u64) -> u64 {
+        self.reduce(a * b)
+    }
+}
+
+/// Montgomery arithmetic mod an odd q < 2^32 with R = 2^32. Values in Montgomery form are stored
+/// as xR mod q, which lets products be reduced with multiplications and shifts only. This is an
+/// internal representation: values are converted back before they leave the crate.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct Montgomery {
+    q: u64,
+    // -q^-1 mod R
+    q_inv_neg: u32,
+    // R^2 mod q
+    r2: u64,
+}
+
+impl Montgomery {
+    /// Returns None if q is even or does not fit in 32 bits.
+    pub(crate) fn new(q: u64) -> Option<Self> {
+        if q.is_multiple_of(2) || q >= 1 << 32 {
+            return None;
+        }
+
+        // Newton's iteration doubles the number of correct low bits of q^-1 each step
+        let q32 = q as u32;
+        let mut inv = q32;
+        for _ in 0..5 {
+            inv = inv.wrapping_mul(2u32.wrapping_sub(q32.wrapping_mul(inv)));
+        }
+        let r2 = ((1u128 << 64) % q as u128) as u64;
+        Some(Self { q, q_inv_neg: inv.wrapping_neg(), r2 })
+    }
+
+    // Compute tR^-1 mod q for t < qR
+    fn redc(&self, t: u64) -> u64 {
+        let m = (t as u32).wrapping_mul(self.q_inv_neg) as u64;
+        // The low halves of t and mq sum to either 0 or R, so only the carry is needed
+        let carry = (t as u32 != 0) as u64;
+        let u = (t >> 32) + ((m * self.q) >> 32) + carry;
+        if u >= self.q {
+            u - self.q
+        } else {
+            u
+        }
+    }
+
+    pub(crate) fn to_mont(self, x: u64) -> u64 {
+        self.redc(x * self.r2)
+    }
+
+    #[allow(dead_code, clippy::wrong_self_convention)]
+    pub(crate) fn from_mont(self, x: u64) -> u64 {
+        self.redc(x)
+    }
+
+    /// Compute abR^-1 mod q for a, b < q. If b is in Montgomery form and a is not, this is the
+    /// canonical product ab mod q.
+    pub(crate) fn mul(&self, a: u64, b: u64) -> u64 {
+        self.redc(a * b)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_reduce() {
+        let mut rng = ChaCha20Rng::from_seed([2u8; 32]);
+        for q in [2, 3, 3329, 65536, 4294967291, 1 << 32] {
+            let modulus = Modulus::new(q);
+            for x in [0, 1, q - 1, q, q + 1, 2 * q - 1, u64::MAX, u64::MAX - 1] {
+                assert_eq!(modulus.reduce(x), x % q);
+            }
+            for _ in 0..1000 {
+                let a = rng.gen_range(0..q);
+                let b = rng.gen_range(0..q);
+                assert_eq!(modulus.mul(a, b), ((a as u128 * b as u128) % q as u128) as u64);
+                assert_eq!(modulus.add(a, b), (a + b) % q);
+                let x: u64 = rng.gen();
+                assert_eq!(modulus.reduce(x), x % q);
+            }
+        }
+    }
+
+    #[test]
+    fn test_montgomery() {
+        assert_eq!(Montgomery::new(3328), None);
+        assert_eq!(Montgomery::new(1 << 32), None);
+
+        let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+        for q in [3, 3329, 65537, 4294967291] {
+            let mont = Montgomery::new(q).unwrap();
+            for _ in 0..1000 {
+                let a = rng.gen_range(0..q);
+                let b = rng.gen_range(0..q);
+                let expected = ((a as u128 * b as u128) % q as u128) as u64;
+                assert_eq!(mont.from_mont(mont.to_mont(a)), a);
+                assert_eq!(mont.mul(a, mont.to_mont(b)), expected);
+                assert_eq!(mont.from_mont(mont.mul(mont.to_mont(a), mont.to_mont(b))), expected);
+            }
+            assert_eq!(mont.mul(q - 1, mont.to_mont(q - 1)), 1);
+        }
+    }
+}
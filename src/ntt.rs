@@ -0,0 +1,221 @@
+// The negacyclic number-theoretic transform over Z_q[X]/(X^d + 1).
+//
+// For a prime q ≡ 1 mod 2d there is a primitive 2d-th root of unity psi mod q, and evaluating a
+// polynomial at the odd powers psi, psi^3, ..., psi^(2d - 1) (the roots of X^d + 1) turns the
+// negacyclic convolution into a pointwise product. The forward transform is the Cooley-Tukey
+// butterfly and the inverse the Gentleman-Sande butterfly, both in place with the powers of psi
+// precomputed in bit-reversed order (Longa and Naehrig, "Speeding up the Number Theoretic
+// Transform for Faster Ideal Lattice-Based Cryptography", 2016), so multiplying costs
+// O(d log d) instead of O(d^2).
+
+use alloc::vec::Vec;
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::ring::Poly;
+
+/// 2^32 - 2^20 + 1, a prime just below 2^32 which is NTT-friendly for ring dimensions up to 2^19.
+pub const NTT_Q32: u64 = 4293918721;
+
+/// The precomputed twiddle factors of the NTT for one modulus q and ring dimension d.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NttTables {
+    q: u64,
+    d: usize,
+    // psi^bitrev(i), for the primitive 2d-th root of unity psi
+    roots: Vec<u64>,
+    // psi^-bitrev(i)
+    inv_roots: Vec<u64>,
+    // d^-1 mod q
+    d_inv: u64,
+}
+
+fn mul_mod(a: u64, b: u64, q: u64) -> u64 {
+    ((a as u128 * b as u128) % q as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, q: u64) -> u64 {
+    let mut result = 1 % q;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, q);
+        }
+        base = mul_mod(base, base, q);
+        exp >>= 1;
+    }
+    result
+}
+
+// Miller-Rabin with the first 12 primes as bases, which is deterministic below 2^64
+fn is_prime(q: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if q < 2 {
+        return false;
+    }
+    if let Some(&base) = BASES.iter().find(|&&b| q.is_multiple_of(b)) {
+        return q == base;
+    }
+    let (mut d, mut r) = (q - 1, 0);
+    while d.is_multiple_of(2) {
+        d /= 2;
+        r += 1;
+    }
+    BASES.iter().all(|&a| {
+        let mut x = pow_mod(a, d, q);
+        if x == 1 || x == q - 1 {
+            return true;
+        }
+        for _ in 1..r {
+            x = mul_mod(x, x, q);
+            if x == q - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Whether the NTT supports q and d: d is a power of two and q is a prime with q ≡ 1 mod 2d.
+/// Sums of two values mod q must not overflow, so q must also be below 2^63.
+pub fn is_ntt_friendly(q: u64, d: usize) -> bool {
+    (2..1 << 63).contains(&q)
+        && d.is_power_of_two()
+        && (q - 1).is_multiple_of(2 * d as u64)
+        && is_prime(q)
+}
+
+fn bit_reverse(i: usize, bits: u32) -> usize {
+    if bits == 0 { 0 } else { i.reverse_bits() >> (usize::BITS - bits) }
+}
+
+impl NttTables {
+    /// Precompute the tables for q and d, which must satisfy is_ntt_friendly().
+    pub fn new(q: u64, d: usize) -> Result<Self, SimplePirError> {
+        if !is_ntt_friendly(q, d) {
+            return Err(SimplePirError::UnsupportedRing { q, d });
+        }
+        // x^((q - 1) / 2d) has order dividing 2d, and exactly 2d when its d-th power is -1
+        let psi = (2..q)
+            .map(|x| pow_mod(x, (q - 1) / (2 * d as u64), q))
+            .find(|&psi| pow_mod(psi, d as u64, q) == q - 1)
+            .unwrap();
+        let psi_inv = pow_mod(psi, q - 2, q);
+
+        let bits = d.trailing_zeros();
+        let powers = |root: u64| -> Vec<u64> {
+            let mut powers = Vec::with_capacity(d);
+            let mut x = 1;
+            for _ in 0..d {
+                powers.push(x);
+                x = mul_mod(x, root, q);
+            }
+            (0..d).map(|i| powers[bit_reverse(i, bits)]).collect()
+        };
+        let roots = powers(psi);
+        let inv_roots = powers(psi_inv);
+        Ok(Self { q, d, roots, inv_roots, d_inv: pow_mod(d as u64, q - 2, q) })
+    }
+
+    pub fn q(&self) -> u64 {
+        self.q
+    }
+
+    pub fn d(&self) -> usize {
+        self.d
+    }
+
+    /// Transform d coefficients mod q in place into the evaluations at the roots of X^d + 1, in
+    /// bit-reversed order.
+    pub fn forward(&self, a: &mut [u64]) {
+        assert_eq!(a.len(), self.d);
+        let q = self.q;
+        let (mut t, mut m) = (self.d, 1);
+        while m < self.d {
+            t /= 2;
+            for i in 0..m {
+                let s = self.roots[m + i];
+                let start = 2 * i * t;
+                for j in start..start + t {
+                    let u = a[j];
+                    let v = mul_mod(a[j + t], s, q);
+                    a[j] = (u + v) % q;
+                    a[j + t] = (u + q - v) % q;
+                }
+            }
+            m *= 2;
+        }
+    }
+
+    /// The inverse of forward().
+    pub fn inverse(&self, a: &mut [u64]) {
+        assert_eq!(a.len(), self.d);
+        let q = self.q;
+        let (mut t, mut m) = (1, self.d);
+        while m > 1 {
+            let h = m / 2;
+            for i in 0..h {
+                let s = self.inv_roots[h + i];
+                let start = 2 * i * t;
+                for j in start..start + t {
+                    let (u, v) = (a[j], a[j + t]);
+                    a[j] = (u + v) % q;
+                    a[j + t] = mul_mod((u + q - v) % q, s, q);
+                }
+            }
+            t *= 2;
+            m = h;
+        }
+        a.iter_mut().for_each(|x| *x = mul_mod(*x, self.d_inv, q));
+    }
+
+    /// The negacyclic product of two polynomials mod q of dimension d.
+    pub fn mul(&self, lhs: &Poly, rhs: &Poly) -> Poly {
+        assert!(lhs.d() == self.d && rhs.d() == self.d);
+        assert!(lhs.q() == self.q && rhs.q() == self.q);
+        let mut a: Vec<u64> = lhs.coeffs.iter().map(|c| c.uint).collect();
+        let mut b: Vec<u64> = rhs.coeffs.iter().map(|c| c.uint).collect();
+        self.forward(&mut a);
+        self.forward(&mut b);
+        a.iter_mut().zip(&b).for_each(|(x, y)| *x = mul_mod(*x, *y, self.q));
+        self.inverse(&mut a);
+        Poly::from_coeffs(a.into_iter().map(|x| Element::from(self.q, x)).collect())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_ntt_params() {
+        assert!(is_prime(NTT_Q32) && is_prime(3329) && is_prime(4294967291));
+        assert!(!is_prime(1 << 32) && !is_prime(3329 * 3331) && !is_prime(1));
+
+        assert!(is_ntt_friendly(NTT_Q32, 1 << 19));
+        assert!(!is_ntt_friendly(NTT_Q32, 1 << 20));
+        // 3328 = 2^8 * 13, so 3329 supports d up to 128
+        assert!(is_ntt_friendly(3329, 128));
+        assert!(!is_ntt_friendly(3329, 256));
+        assert!(!is_ntt_friendly(4294967291, 4));
+        assert!(!is_ntt_friendly(97, 12));
+        let err = SimplePirError::UnsupportedRing { q: 1 << 32, d: 16 };
+        assert_eq!(NttTables::new(1 << 32, 16), Err(err));
+    }
+
+    #[test]
+    fn test_ntt_roundtrip_and_mul() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        for (q, d) in [(3329, 128), (NTT_Q32, 1024), (97, 16), (17, 1)] {
+            let tables = NttTables::new(q, d).unwrap();
+            let a = Poly::gen_uniform_rand_with_rng(q, d, &mut rng);
+            let mut values: Vec<u64> = a.coeffs.iter().map(|c| c.uint).collect();
+            tables.forward(&mut values);
+            tables.inverse(&mut values);
+            assert!(values.iter().zip(&a.coeffs).all(|(x, c)| *x == c.uint));
+
+            let b = Poly::gen_uniform_rand_with_rng(q, d, &mut rng);
+            assert_eq!(tables.mul(&a, &b), crate::ring::mul_schoolbook(&a, &b));
+        }
+    }
+}
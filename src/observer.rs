@@ -0,0 +1,185 @@
+//! Hooks for auditing every step of the protocol: a Server calls its observers when it publishes
+//! its public hint, receives a query and emits an answer, and a Client calls its observers when
+//! it recovers an answer.
+//!
+//! Observers only ever see what the server or an eavesdropper learns anyway: the sizes of the
+//! messages, the epoch of the database and the time each step took. Queried indices, secrets and
+//! recovered items are never passed on, so observers can forward what they receive to an audit
+//! or compliance system as it is.
+
+use std::time::Duration;
+
+/// The public hint was handed out by Server::public_hint().
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct HintPublished {
+    pub epoch: u64,
+    pub hint_bytes: usize,
+}
+
+/// A query was received by the server, before it was checked, so rejected queries are seen too.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct QueryReceived {
+    pub epoch: u64,
+    pub query_bytes: usize,
+}
+
+/// An answer was computed by the server. Answers of a batch share the time of the batch evenly.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AnswerEmitted {
+    pub epoch: u64,
+    pub answer_bytes: usize,
+    pub time: Duration,
+}
+
+/// An answer was recovered by the client. Answers of a batch share the time of the batch evenly.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Recovered {
+    pub answer_bytes: usize,
+    pub time: Duration,
+}
+
+/// Callbacks on the steps of the protocol, registered with Server::add_observer() or
+/// Client::add_observer(). Each does nothing unless overridden. Answers take &self and may run
+/// concurrently, so the callbacks may be called from several threads at once.
+pub trait ProtocolObserver: Send + Sync {
+    fn on_hint_published(&self, _event: &HintPublished) {}
+
+    fn on_query_received(&self, _event: &QueryReceived) {}
+
+    fn on_answer_emitted(&self, _event: &AnswerEmitted) {}
+
+    fn on_recovered(&self, _event: &Recovered) {}
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::element::Element;
+    #[cfg(feature = "packing")]
+    use crate::packing::PackingParams;
+    use crate::pir::{Client, Server};
+    use crate::record::RecordLayout;
+    use crate::simplepir;
+
+    fn gen_items(db_size: usize, p: u64) -> Vec<Element> {
+        (0..db_size).map(|_| Element::gen_uniform_rand(p)).collect()
+    }
+
+    // Every event observed, in order
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ProtocolObserver for Recorder {
+        fn on_hint_published(&self, event: &HintPublished) {
+            self.0.lock().unwrap().push(format!("hint {} {}", event.epoch, event.hint_bytes));
+        }
+
+        fn on_query_received(&self, event: &QueryReceived) {
+            self.0.lock().unwrap().push(format!("query {} {}", event.epoch, event.query_bytes));
+        }
+
+        fn on_answer_emitted(&self, event: &AnswerEmitted) {
+            self.0.lock().unwrap().push(format!("answer {} {}", event.epoch, event.answer_bytes));
+        }
+
+        fn on_recovered(&self, event: &Recovered) {
+            self.0.lock().unwrap().push(format!("recovered {}", event.answer_bytes));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let params = simplepir::gen_insecure_test_params_for_size(100, 991);
+        let items = gen_items(100, 991);
+        let mut server = Server::setup(params, &items).unwrap();
+        let recorder = Arc::new(Recorder::default());
+        server.add_observer(recorder.clone());
+
+        let mut client = Client::from_public_hint(server.public_hint()).unwrap();
+        client.add_observer(recorder.clone());
+        let query = client.query(42).unwrap();
+        let answer = server.answer(&query).unwrap();
+        assert_eq!(client.recover(&answer).unwrap(), items[42]);
+
+        // A malformed query is seen before it is rejected
+        let mut bad = query.clone();
+        bad.0.pop();
+        server.update_item(0, items[1].clone()).unwrap();
+        assert!(server.answer(&bad).is_err());
+
+        let (hint, query, answer) =
+            (server.hint().wire_size(), query.wire_size(), answer.wire_size());
+        let events = recorder.0.lock().unwrap().clone();
+        assert_eq!(events, vec![
+            format!("hint 0 {}", hint),
+            format!("query 0 {}", query),
+            format!("answer 0 {}", answer),
+            format!("recovered {}", answer),
+            format!("query 1 {}", bad.wire_size()),
+        ]);
+    }
+
+    #[test]
+    fn test_observer_range() {
+        let records: Vec<Vec<u8>> = (0..40).map(|i| vec![i as u8; 64]).collect();
+        let layout = RecordLayout::new(40, 64, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let mut server = Server::setup_records(params, layout.clone(), &records).unwrap();
+        server.enable_stats();
+        let recorder = Arc::new(Recorder::default());
+        server.add_observer(recorder.clone());
+
+        let mut client = Client::with_layout(
+            server.params().clone(),
+            server.hint().clone(),
+            layout,
+        ).unwrap();
+        client.add_observer(recorder.clone());
+        let query = client.query_range(7, 10..20).unwrap();
+        let answer = server.answer_range(&query).unwrap();
+        assert_eq!(client.recover_range(&answer).unwrap(), records[7][10..20]);
+
+        let (query_bytes, answer_bytes) = (query.query.wire_size(), answer.wire_size());
+        let events = recorder.0.lock().unwrap().clone();
+        assert_eq!(events, vec![
+            format!("query 0 {}", query_bytes),
+            format!("answer 0 {}", answer_bytes),
+            format!("recovered {}", answer_bytes),
+        ]);
+        let stats = server.stats().unwrap();
+        assert_eq!(stats.num_answers(), 1);
+        assert_eq!(stats.bytes_downloaded, answer_bytes as u64);
+        let scanned = answer.0.num_cols() * server.params().m;
+        assert_eq!(stats.items_scanned, scanned as u64);
+    }
+
+    #[cfg(feature = "packing")]
+    #[test]
+    fn test_observer_packed() {
+        let params = simplepir::gen_insecure_test_params_for_size(30, 16);
+        let items = gen_items(30, 16);
+        let mut server = Server::setup(params, &items).unwrap();
+        server.enable_stats();
+        let recorder = Arc::new(Recorder::default());
+        server.add_observer(recorder.clone());
+
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        client.add_observer(recorder.clone());
+        let packing = PackingParams::new(4, 16, 1.0);
+        let (query, key) = client.query_packed(13, &packing).unwrap();
+        let packed = server.answer_packed(&query, &key).unwrap();
+        assert_eq!(client.recover_packed(&packed).unwrap(), items[13]);
+
+        let events = recorder.0.lock().unwrap().clone();
+        assert_eq!(events, vec![
+            format!("query 0 {}", query.wire_size()),
+            format!("answer 0 {}", packed.wire_size()),
+            format!("recovered {}", packed.wire_size()),
+        ]);
+        let stats = server.stats().unwrap();
+        assert_eq!(stats.num_answers(), 1);
+        assert_eq!(stats.bytes_downloaded, packed.wire_size() as u64);
+    }
+}
@@ -0,0 +1,310 @@
+// Packing of SimplePIR answers into ring ciphertexts.
+//
+// Answer entry i is an LWE ciphertext ans_i = <hint_i, s> + e_i + Δx_i whose public part hint_i is
+// a row of the hint. With a packing key, which encrypts each coordinate s_j of the query's secret
+// (times each power of the gadget base) under a ring secret z, the server switches d entries at a
+// time from s to z: writing H_j for the polynomial whose coefficient i is hint_i[j], it subtracts
+// sum_j s_j H_j homomorphically from the polynomial of the answer entries, decomposing each H_j
+// into small digits so that the key errors stay small. What remains is a ring ciphertext of the
+// d entries under z alone, so the client recovers them without the hint.
+//
+// The packing key encrypts the query's secret, so like the secret it is fresh for every query:
+// a key reused across queries would be a key for a reused secret. It takes n * num_digits(q)
+// ring ciphertexts, e.g. 134 MB for n = 1024 and q = 2^32 with d = 2048 and base 16, against a
+// hint of 4n bytes per database column. Packing therefore only saves bandwidth for databases of
+// more columns than that, or for clients which cannot hold the hint, and is behind the `packing`
+// feature. Reusable keys need a long-term ring secret which the query secret is derived from,
+// as in the HintlessPIR construction, which this module does not implement.
+
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, SeedableRng};
+use zeroize::Zeroizing;
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::matrix::Matrix;
+use crate::regev::{check_matrix_mod, check_vector_mod, error_bound, NoiseBudget, SecretKey};
+use crate::ring::{self, Ciphertext, Poly, Ring};
+use crate::simplepir::{Hint, SimplePIRParams};
+use crate::wire;
+
+/// The ring and the gadget which answers are packed with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PackingParams {
+    // The ring dimension, a power of two. Each ring ciphertext packs d answer entries
+    pub d: usize,
+    // The base in which the hint is decomposed. Smaller bases add less noise but need more keys
+    pub base: u64,
+    // The standard deviation of the packing key errors
+    pub std_dev: f64,
+}
+
+/// The key which lets the server pack the answer to one query. It holds one ring ciphertext of
+/// s_j * base^k for each coordinate j of the query's secret and each digit k, so it takes
+/// n * num_digits(q) ring ciphertexts, or PackingParams::key_len() bytes. Like the secret, it
+/// must not be reused across queries.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PackingKey {
+    // The gadget base the key was generated for
+    pub base: u64,
+    // The ciphertext of s_j * base^k at index j * num_digits + k
+    pub keys: Vec<Ciphertext>,
+}
+
+/// An answer packed into ⌈num_cols / d⌉ ring ciphertexts, each holding d entries from its
+/// constant term up.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PackedAnswer(pub Vec<Ciphertext>);
+
+impl PackedAnswer {
+    /// The number of bytes the packed answer takes, with each coefficient packed into ⌈log2(q)⌉
+    /// bits as for PackingParams::key_len().
+    pub fn wire_size(&self) -> usize {
+        let coeffs: usize = self.0.iter().map(|ct| ct.a.coeffs.len() + ct.b.coeffs.len()).sum();
+        let q = self.0.first().and_then(|ct| ct.a.coeffs.first()).map_or(2, |e| e.q);
+        (coeffs * wire::bits_per_coeff(q)).div_ceil(8)
+    }
+}
+
+impl PackingParams {
+    pub fn new(d: usize, base: u64, std_dev: f64) -> Self {
+        assert!(d.is_power_of_two(), "the ring dimension must be a power of two");
+        assert!(base >= 2);
+        Self { d, base, std_dev }
+    }
+
+    /// The number of digits in base `base` of values mod q.
+    pub fn num_digits(&self, q: u64) -> usize {
+        num_digits(q, self.base)
+    }
+
+    /// The number of bytes a packing key takes, with each coefficient packed into ⌈log2(q)⌉
+    /// bits, which a client sends with every packed query.
+    pub fn key_len(&self, params: &SimplePIRParams) -> usize {
+        let coeffs = params.n * self.num_digits(params.q) * 2 * self.d;
+        (coeffs * wire::bits_per_coeff(params.q)).div_ceil(8)
+    }
+
+    /// The noise in each packed entry of an answer over db_size items. Packing adds, to the noise
+    /// of the answer, one key error times a digit below base for each of the d coefficients of
+    /// each of the n * num_digits(q) products.
+    pub fn noise_budget(&self, params: &SimplePIRParams, db_size: usize) -> NoiseBudget {
+        let budget = params.noise_budget(db_size);
+        let terms = (params.n * self.num_digits(params.q) * self.d) as f64;
+        let b = self.base as f64;
+        let digit_square = (b - 1.0) * (2.0 * b - 1.0) / 6.0;
+        let variance = terms * digit_square * self.std_dev * self.std_dev;
+        NoiseBudget {
+            std_dev: (budget.std_dev * budget.std_dev + variance).sqrt(),
+            max: budget.max + terms * (b - 1.0) * error_bound(self.std_dev) as f64,
+            threshold: budget.threshold,
+        }
+    }
+}
+
+// The number of digits in base `base` of values mod q, as produced by Element::decomposed()
+fn num_digits(q: u64, base: u64) -> usize {
+    ((q - 1) as f64).log(base as f64).ceil() as usize
+}
+
+/// Generate a fresh ring secret z and the key which packs answers to a query with secret s
+/// into ring ciphertexts under z.
+#[cfg(feature = "std")]
+pub fn gen_packing_key(
+    params: &SimplePIRParams,
+    packing: &PackingParams,
+    s: &SecretKey,
+) -> Result<(PackingKey, Zeroizing<Poly>), SimplePirError> {
+    gen_packing_key_with_rng(params, packing, s, &mut StdRng::from_entropy())
+}
+
+/// Like gen_packing_key(), but sampling the ring secret and errors with the given RNG.
+pub fn gen_packing_key_with_rng<R: CryptoRng + RngCore>(
+    params: &SimplePIRParams,
+    packing: &PackingParams,
+    s: &SecretKey,
+    rng: &mut R,
+) -> Result<(PackingKey, Zeroizing<Poly>), SimplePirError> {
+    if s.len() != params.n {
+        return Err(SimplePirError::LengthMismatch { expected: params.n, actual: s.len() });
+    }
+    check_vector_mod(s, params.q)?;
+
+    let (q, d) = (params.q, packing.d);
+    let ring = Ring::new(q, d);
+    let z = Zeroizing::new(Poly::gen_ternary_with_rng(q, d, rng));
+    let mut keys = Vec::with_capacity(params.n * packing.num_digits(q));
+    for s_j in s.elements().iter() {
+        let mut power = Element::from(q, 1);
+        for _ in 0..packing.num_digits(q) {
+            // b = a * z + e + s_j * base^k
+            let a = Poly::gen_uniform_rand_with_rng(q, d, rng);
+            let mut b = ring.mul(&a, &z) + Poly::gen_error_with_rng(q, d, packing.std_dev, rng);
+            b.coeffs[0] += s_j.clone() * power.clone();
+            keys.push(Ciphertext { a, b });
+            power = power * packing.base;
+        }
+    }
+    Ok((PackingKey { base: packing.base, keys }, z))
+}
+
+// Check that a packing key was generated for the params and a ring of dimension d, returning d
+fn check_key(params: &SimplePIRParams, key: &PackingKey) -> Result<usize, SimplePirError> {
+    if key.base < 2 {
+        return Err(SimplePirError::InvalidModulus(key.base));
+    }
+    let expected = params.n * num_digits(params.q, key.base);
+    if key.keys.len() != expected {
+        return Err(SimplePirError::LengthMismatch { expected, actual: key.keys.len() });
+    }
+    let d = key.keys[0].a.d();
+    if !d.is_power_of_two() {
+        return Err(SimplePirError::LengthMismatch { expected: d.next_power_of_two(), actual: d });
+    }
+    for ct in &key.keys {
+        ring::check_ciphertext(params.q, d, ct)?;
+    }
+    Ok(d)
+}
+
+// The polynomial with the given coefficients, padded with zeros to d coefficients
+fn padded_poly<I: IntoIterator<Item = Element>>(q: u64, d: usize, coeffs: I) -> Poly {
+    let mut coeffs: Vec<Element> = coeffs.into_iter().collect();
+    coeffs.resize(d, Element::zero(q));
+    Poly::from_coeffs(coeffs)
+}
+
+/// Pack an answer mod q into ring ciphertexts under the ring secret of the packing key, d entries
+/// (i.e. database columns) per ciphertext.
+pub fn pack(
+    params: &SimplePIRParams,
+    hint: &Hint,
+    key: &PackingKey,
+    answer: &Matrix,
+) -> Result<PackedAnswer, SimplePirError> {
+    let d = check_key(params, key)?;
+    let num_cols = hint.0.num_cols();
+    if answer.num_cols() != num_cols {
+        let actual = answer.num_cols();
+        return Err(SimplePirError::LengthMismatch { expected: num_cols, actual });
+    }
+    if num_cols > 0 && answer.num_rows() != 1 {
+        return Err(SimplePirError::LengthMismatch { expected: 1, actual: answer.num_rows() });
+    }
+    check_matrix_mod(answer, params.q)?;
+
+    let q = params.q;
+    let digits = num_digits(q, key.base);
+    let ring = Ring::new(q, d);
+    let packed = (0..num_cols).step_by(d).map(|start| {
+        let cols = start..num_cols.min(start + d);
+
+        let mut a = Poly::zero(q, d);
+        let mut b = padded_poly(q, d, cols.clone().map(|i| answer.get(i, 0)));
+        for j in 0..params.n {
+            let h_j = padded_poly(q, d, cols.clone().map(|i| hint.0.get(i, j)));
+            for (k, digit) in h_j.decomposed(key.base).iter().enumerate() {
+                let ct = &key.keys[j * digits + k];
+                a += &ring.mul(digit, &ct.a);
+                b -= &ring.mul(digit, &ct.b);
+            }
+        }
+        // b + a * z = b0 - sum_j s_j H_j + noise, so (-a, b) decrypts under z
+        Ciphertext { a: Poly::zero(q, d) - a, b }
+    }).collect();
+    Ok(PackedAnswer(packed))
+}
+
+/// Recover the row selected by the query from its packed answer with the ring secret, one item
+/// per database column as recover_row() does.
+pub fn unpack_row(
+    params: &SimplePIRParams,
+    z: &Poly,
+    num_cols: usize,
+    packed: &PackedAnswer,
+) -> Result<Vec<Element>, SimplePirError> {
+    let d = z.d();
+    let expected = num_cols.div_ceil(d);
+    if packed.0.len() != expected {
+        return Err(SimplePirError::LengthMismatch { expected, actual: packed.0.len() });
+    }
+    if z.q() != params.q {
+        return Err(SimplePirError::ModulusMismatch { expected: params.q, actual: z.q() });
+    }
+
+    let ring = Ring::new(params.q, d);
+    let mut row = Vec::with_capacity(expected * d);
+    for ct in &packed.0 {
+        row.extend(ring::decrypt(&ring, z, ct, params.p)?.coeffs);
+    }
+    row.truncate(num_cols);
+    Ok(row)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::simplepir;
+    use rand_chacha::ChaCha20Rng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_pack_answer() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let params = simplepir::gen_insecure_test_params_for_width(20, 256);
+        let packing = PackingParams::new(8, 16, 1.0);
+        assert!(packing.noise_budget(&params, 400).is_guaranteed());
+
+        // 20 columns take 3 ciphertexts, the last of which is partly padding
+        let db = Matrix::gen_uniform_rand_with_rng(params.p, 20, 20, &mut rng);
+        let hint = simplepir::gen_hint(&params, &db);
+        let rows = db.transpose();
+        for row in [0, 7, 19] {
+            let prepared = simplepir::prepare_query_with_rng(&params, &hint, &mut rng).unwrap();
+            let (query, state) = prepared.finish(&params, row).unwrap();
+            let s = state.secret();
+            let (key, z) = gen_packing_key_with_rng(&params, &packing, s, &mut rng).unwrap();
+            assert_eq!(key.keys.len(), params.n * packing.num_digits(params.q));
+            let coeffs = key.keys.len() * 2 * packing.d;
+            assert_eq!(packing.key_len(&params), coeffs * wire::bits_per_coeff(params.q) / 8);
+
+            let answer = simplepir::answer(&query, &db).unwrap();
+            let packed = pack(&params, &hint, &key, &answer).unwrap();
+            assert_eq!(packed.0.len(), 3);
+            assert_eq!(unpack_row(&params, &z, 20, &packed).unwrap(), rows.col(row));
+        }
+    }
+
+    #[test]
+    fn test_pack_rejects_malformed() {
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let params = simplepir::gen_insecure_test_params_for_width(4, 2);
+        let packing = PackingParams::new(4, 256, 1.0);
+        let db = Matrix::gen_uniform_rand_with_rng(params.p, 4, 4, &mut rng);
+        let hint = simplepir::gen_hint(&params, &db);
+        let s = crate::regev::gen_secret_with_rng(params.q, params.n, &mut rng);
+        let (query, s) = simplepir::query_with_rng(&params, 0, s, &mut rng).unwrap();
+        let (mut key, z) =
+            gen_packing_key_with_rng(&params, &packing, s.secret(), &mut rng).unwrap();
+        let answer = simplepir::answer(&query, &db).unwrap();
+
+        // Answers must be uncompressed, and packed answers must have one ciphertext per d entries
+        let compressed = answer.mod_switch(256);
+        assert!(matches!(
+            pack(&params, &hint, &key, &compressed),
+            Err(SimplePirError::ModulusMismatch { .. })
+        ));
+        let mut packed = pack(&params, &hint, &key, &answer).unwrap();
+        packed.0.push(packed.0[0].clone());
+        assert!(unpack_row(&params, &z, 4, &packed).is_err());
+
+        key.keys.pop();
+        assert!(matches!(
+            pack(&params, &hint, &key, &answer),
+            Err(SimplePirError::LengthMismatch { .. })
+        ));
+    }
+}
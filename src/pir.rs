@@ -0,0 +1,3333 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, SeedableRng};
+use rand::{CryptoRng, Rng, RngCore};
+#[cfg(feature = "std")]
+use rand_chacha::ChaCha20Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "packing")]
+use zeroize::Zeroizing;
+use crate::arena::Arena;
+use crate::bitpacked::{self, BitPackedMatrix};
+use crate::database::Database;
+use crate::element::Element;
+#[cfg(feature = "encryption")]
+use crate::encryption::{Expected, Keyring};
+use crate::error::SimplePirError;
+#[cfg(feature = "gpu")]
+use crate::gpu::{self, GpuDatabase};
+use crate::matrix::Matrix;
+use crate::merkle::{self, Digest};
+#[cfg(feature = "packing")]
+use crate::packing::{self, PackedAnswer, PackingKey, PackingParams};
+use crate::record::{self, encode_record, RecordLayout, VariableLayout};
+#[cfg(feature = "packing")]
+use crate::ring::Poly;
+#[cfg(feature = "signing")]
+use crate::signing::{self, SignedAnswer, SignedManifest};
+use crate::simplepir::{self, Hint, HintDelta, PreparedQuery, QueryState, SimplePIRParams};
+use crate::sparse::SparseMatrix;
+use sha2::{Digest as _, Sha256};
+#[cfg(feature = "signing")]
+use ed25519_dalek::SigningKey;
+#[cfg(feature = "spir")]
+use crate::spir::{self, OprfKey, OprfRequest, OprfResponse, OprfState};
+#[cfg(feature = "std")]
+use crate::stats::Stats;
+#[cfg(feature = "std")]
+use crate::audit::{NoisyCount, QueryLog};
+#[cfg(feature = "std")]
+use crate::tune::{self, MachineProfile};
+#[cfg(feature = "std")]
+use crate::observer::{AnswerEmitted, HintPublished, ProtocolObserver, QueryReceived, Recovered};
+use crate::wire;
+#[cfg(feature = "std")]
+use core::slice;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A query for one database item, to be sent to the server.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Query(pub Vec<Element>);
+
+impl Query {
+    /// The SHA-256 digest of the query, which identifies it: a re-sent query has the same id.
+    pub fn id(&self) -> Digest {
+        simplepir::query_digest(&self.0)
+    }
+
+    /// The bytes the query takes on the wire, see wire::encode_query().
+    pub fn wire_size(&self) -> usize {
+        wire::encoded_query_len(self)
+    }
+}
+
+/// Everything a client downloads before querying: the params, the hint and the record layout, if
+/// any. None of it depends on a client, so one copy can be published (e.g. on a CDN) for all
+/// clients, which check the copy they download against the digest obtained from the server.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PublicHint {
+    pub params: SimplePIRParams,
+    pub hint: Hint,
+    pub layout: Option<RecordLayout>,
+    /// The Ed25519 key which the server's signed answers verify under, if it signs them.
+    pub verifying_key: Option<[u8; 32]>,
+}
+
+impl PublicHint {
+    /// Encode the public hint, see wire::encode_public_hint().
+    pub fn to_bytes(&self) -> Vec<u8> {
+        wire::encode_public_hint(self)
+    }
+
+    /// Decode a public hint encoded by to_bytes().
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, wire::WireError> {
+        wire::decode_public_hint(bytes)
+    }
+
+    /// The SHA-256 digest of to_bytes().
+    pub fn digest(&self) -> Digest {
+        Sha256::digest(self.to_bytes()).into()
+    }
+
+    /// Check that the params are valid and that the hint and layout belong to them, as a client
+    /// must before querying with a public hint from the server.
+    pub fn validate(&self) -> Result<(), SimplePirError> {
+        check_public(&self.params, &self.hint, self.layout.as_ref())
+    }
+}
+
+// Check that the params are valid and that the hint and layout belong to them
+fn check_public(
+    params: &SimplePIRParams,
+    hint: &Hint,
+    layout: Option<&RecordLayout>,
+) -> Result<(), SimplePirError> {
+    params.validate()?;
+    simplepir::check_hint(params, hint)?;
+    if let Some(layout) = layout {
+        let cols = layout.records_per_row().checked_mul(layout.limbs);
+        if layout.rows != params.m || cols != Some(hint.0.num_cols()) {
+            return Err(SimplePirError::InvalidShape("record layout"));
+        }
+    }
+    Ok(())
+}
+
+/// The server's answer to a query.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Answer(pub Matrix);
+
+impl Answer {
+    /// The bytes the answer takes on the wire, see wire::encode_answer().
+    pub fn wire_size(&self) -> usize {
+        wire::encoded_answer_len(self)
+    }
+}
+
+/// The first 16 bytes of the SHA-256 digest of a query id and the epoch of the database which
+/// answered it, which binds an answer to both.
+pub type AnswerDigest = [u8; 16];
+
+/// An answer tagged with the epoch of the database it was computed from and the digest of its
+/// query id and that epoch, so that a client can drop answers to queries other than its own, such
+/// as duplicates or answers routed to the wrong client by a lossy transport.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaggedAnswer {
+    pub epoch: u64,
+    pub digest: AnswerDigest,
+    pub answer: Answer,
+}
+
+// The current time in seconds since the Unix epoch
+#[cfg(feature = "std")]
+fn unix_now() -> u64 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+    now.map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// Without a clock, databases count as built at the Unix epoch
+#[cfg(not(feature = "std"))]
+fn unix_now() -> u64 {
+    0
+}
+
+/// The digest which tags the answer to the query with the given id in the given epoch.
+pub fn answer_digest(query_id: &Digest, epoch: u64) -> AnswerDigest {
+    let mut hasher = Sha256::new();
+    hasher.update(query_id);
+    hasher.update(epoch.to_le_bytes());
+    hasher.finalize()[..16].try_into().unwrap()
+}
+
+/// A query for a byte range of a record: a record query, along with the limbs which hold the
+/// range. The server answers only the columns which hold those limbs.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RangeQuery {
+    pub query: Query,
+    pub limbs: Range<usize>,
+}
+
+// The seed of the retrievals run by Server::self_test()
+#[cfg(feature = "std")]
+const SELF_TEST_SEED: u64 = 0x5e1f7e57;
+
+// Identifies a server state file
+#[cfg(feature = "std")]
+const STATE_MAGIC: &[u8; 8] = b"SPIRSTAT";
+
+// The version of the state file format, to be bumped whenever it changes
+#[cfg(feature = "std")]
+const STATE_VERSION: u32 = 5;
+
+// Databases with a smaller fraction of non-zero items are stored as sparse matrices
+const SPARSE_DENSITY: f64 = 0.25;
+
+// Databases whose items fit in this many bits are stored bit-packed
+const BITPACKED_MAX_BITS: usize = 16;
+
+/// The number of columns read from a database backend at a time, unless set otherwise with
+/// Server::set_chunk_cols() or Server::autotune().
+pub const BACKEND_CHUNK_COLS: usize = 256;
+
+/// The default size of the scratch arenas which answers take their temporaries from, enough for
+/// queries of up to 2^17 entries without touching the heap.
+pub const DEFAULT_ARENA_BYTES: usize = 1 << 20;
+
+// The encoded database, stored densely, sparsely when it is mostly zeros, or bit-packed when p
+// is small, or read from a backend supplied by the caller
+enum Storage {
+    Dense(Matrix),
+    Sparse(SparseMatrix),
+    BitPacked(BitPackedMatrix),
+    Backend(Box<dyn Database + Send + Sync>),
+}
+
+/// The server side of SimplePIR. It holds the encoded database and the hint, both of which are
+/// computed once during setup. Databases which are mostly zeros are stored sparsely, so that
+/// answering only touches their non-zero items, and other databases mod a small p are stored
+/// bit-packed, so that they take a fraction of the memory.
+pub struct Server {
+    params: SimplePIRParams,
+    // The digest of the params, computed once since it hashes all of A
+    params_digest: Digest,
+    db: Storage,
+    hint: Hint,
+    layout: Option<RecordLayout>,
+    // The Merkle root of the records, if they are stored with their authentication paths
+    root: Option<Digest>,
+    // The version of the database, advanced by every update
+    epoch: u64,
+    // When the database of the epoch was built, in seconds since the Unix epoch
+    built_at: u64,
+    // The key of the pads which the records are masked with, in symmetric mode
+    #[cfg(feature = "spir")]
+    oprf_key: Option<OprfKey>,
+    // The key which answers and manifests are signed with
+    #[cfg(feature = "signing")]
+    signing_key: Option<SigningKey>,
+    #[cfg(feature = "parallel")]
+    pool: Option<rayon::ThreadPool>,
+    // A copy of the dense database on the GPU, which answers queries when present
+    #[cfg(feature = "gpu")]
+    gpu: Option<GpuDatabase>,
+    #[cfg(feature = "std")]
+    setup_time: Duration,
+    // The capacity in words of the scratch arenas, and the arenas not in use by an answer.
+    // Answers take &self and may run concurrently, hence the lock around the pool.
+    arena_capacity: usize,
+    #[cfg(feature = "std")]
+    arenas: Mutex<Vec<Arena>>,
+    // The number of columns of a backend database scanned at a time
+    chunk_cols: usize,
+    // Collected once enabled. Answers take &self, hence the lock.
+    #[cfg(feature = "std")]
+    stats: Option<Mutex<Stats>>,
+    // The counts of queries answered per epoch, once enabled
+    #[cfg(feature = "std")]
+    query_log: Option<Mutex<QueryLog>>,
+    #[cfg(feature = "std")]
+    observers: Vec<Arc<dyn ProtocolObserver>>,
+}
+
+impl Server {
+    /// Lay out the items (each mod p) as a square database and compute the hint. Items which do
+    /// not fit the params are rejected as by simplepir::encode_db().
+    pub fn setup(params: SimplePIRParams, items: &[Element]) -> Result<Self, SimplePirError> {
+        Self::new(params, None, |params| simplepir::setup(params, items))
+    }
+
+    /// Like setup(), but generating the hint with num_threads threads. This is independent of the
+    /// threads which answer queries, which are set with set_threads().
+    #[cfg(feature = "parallel")]
+    pub fn setup_with_threads(
+        params: SimplePIRParams,
+        items: &[Element],
+        num_threads: usize,
+    ) -> Result<Self, SimplePirError> {
+        let pool = build_pool(num_threads);
+        Self::new(params, None, |params| {
+            let db = simplepir::encode_db(params, items)?;
+            let hint = simplepir::gen_hint_parallel(params, &db, &pool);
+            Ok((db, hint))
+        })
+    }
+
+    /// Encode fixed-length byte records according to the layout and compute the hint. The params
+    /// must have been generated for layout.rows rows, and the records must match the layout, or
+    /// they are rejected with LengthMismatch.
+    pub fn setup_records(
+        params: SimplePIRParams,
+        layout: RecordLayout,
+        records: &[Vec<u8>],
+    ) -> Result<Self, SimplePirError> {
+        Self::setup_records_with(params, layout, records, simplepir::gen_hint)
+    }
+
+    /// Like setup_records(), but generating the hint with num_threads threads. This is
+    /// independent of the threads which answer queries, which are set with set_threads().
+    #[cfg(feature = "parallel")]
+    pub fn setup_records_with_threads(
+        params: SimplePIRParams,
+        layout: RecordLayout,
+        records: &[Vec<u8>],
+        num_threads: usize,
+    ) -> Result<Self, SimplePirError> {
+        let pool = build_pool(num_threads);
+        Self::setup_records_with(params, layout, records, |params, db| {
+            simplepir::gen_hint_parallel(params, db, &pool)
+        })
+    }
+
+    /// Commit to the records with a Merkle tree and store each record with its authentication
+    /// path appended, so that clients created with Client::with_merkle_root() can check every
+    /// record they retrieve against merkle_root(). The layout must be for records of
+    /// merkle::authenticated_len() bytes, and the params generated for layout.rows rows, or they
+    /// are rejected with LengthMismatch.
+    pub fn setup_authenticated_records(
+        params: SimplePIRParams,
+        layout: RecordLayout,
+        records: &[Vec<u8>],
+    ) -> Result<Self, SimplePirError> {
+        let record_len = records.first().map_or(0, |record| record.len());
+        let expected = merkle::authenticated_len(records.len(), record_len);
+        if layout.record_len != expected {
+            return Err(SimplePirError::LengthMismatch { expected, actual: layout.record_len });
+        }
+        let (tree, authenticated) = merkle::authenticate_records(records);
+        let server = Self::setup_records(params, layout, &authenticated)?;
+        Ok(Self { root: Some(tree.root()), ..server })
+    }
+
+    /// Set up symmetric PIR: mask each record with a pad which only a client that has the server
+    /// evaluate the OPRF on the record's index can derive, so that clients learn nothing about
+    /// the records they did not query. Clients query with Client::query_record_symmetric() and the
+    /// server answers each query together with the OPRF request sent along with it with
+    /// answer_symmetric().
+    #[cfg(all(feature = "spir", feature = "std"))]
+    pub fn setup_symmetric_records(
+        params: SimplePIRParams,
+        layout: RecordLayout,
+        records: &[Vec<u8>],
+    ) -> Result<Self, SimplePirError> {
+        Self::setup_symmetric_records_with_rng(params, layout, records, &mut StdRng::from_entropy())
+    }
+
+    /// Like setup_symmetric_records(), but sampling the OPRF key with the given RNG.
+    #[cfg(feature = "spir")]
+    pub fn setup_symmetric_records_with_rng<R: CryptoRng + RngCore>(
+        params: SimplePIRParams,
+        layout: RecordLayout,
+        records: &[Vec<u8>],
+        rng: &mut R,
+    ) -> Result<Self, SimplePirError> {
+        let key = spir::gen_oprf_key_with_rng(rng);
+        let server = Self::setup_records(params, layout, &spir::mask_records(&key, records))?;
+        Ok(Self { oprf_key: Some(key), ..server })
+    }
+
+    // Encode the records and generate the hint with gen_hint
+    fn setup_records_with<F>(
+        params: SimplePIRParams,
+        layout: RecordLayout,
+        records: &[Vec<u8>],
+        gen_hint: F,
+    ) -> Result<Self, SimplePirError>
+    where
+        F: FnOnce(&SimplePIRParams, &Matrix) -> Hint,
+    {
+        if params.m != layout.rows {
+            return Err(SimplePirError::LengthMismatch { expected: layout.rows, actual: params.m });
+        }
+        Self::new(params, Some(layout.clone()), |params| {
+            let db = layout.encode_db(records, params.p)?;
+            let hint = gen_hint(params, &db);
+            Ok((db, hint))
+        })
+    }
+
+    /// Serve a database read from any backend, such as a FileDatabase or an MmapDatabase, which
+    /// is scanned chunk_cols() columns at a time rather than held in memory. The hint must be that
+    /// of the database, or it is rejected with InvalidShape. Such servers cannot update items.
+    pub fn from_database<D: Database + Send + Sync + 'static>(
+        params: SimplePIRParams,
+        db: D,
+        hint: Hint,
+    ) -> Result<Self, SimplePirError> {
+        if db.num_rows() != params.m {
+            return Err(SimplePirError::InvalidShape("database"));
+        }
+        if hint.0.dimensions() != (db.len(), params.n) {
+            return Err(SimplePirError::InvalidShape("hint"));
+        }
+        let server = Self::new(params, None, |_| Ok((Matrix::new(), hint)))?;
+        Ok(Self { db: Storage::Backend(Box::new(db)), ..server })
+    }
+
+    /// Serve a database read from any backend, as from_database() does, computing its hint with
+    /// a scan of the database BACKEND_CHUNK_COLS columns at a time.
+    pub fn setup_database<D: Database + Send + Sync + 'static>(
+        params: SimplePIRParams,
+        db: D,
+    ) -> Result<Self, SimplePirError> {
+        let hint = simplepir::gen_hint_db(&params, &db, BACKEND_CHUNK_COLS)?;
+        Self::from_database(params, db, hint)
+    }
+
+    // Set up a server with the database and hint returned by setup
+    fn new<F>(
+        params: SimplePIRParams,
+        layout: Option<RecordLayout>,
+        setup: F,
+    ) -> Result<Self, SimplePirError>
+    where
+        F: FnOnce(&SimplePIRParams) -> Result<(Matrix, Hint), SimplePirError>,
+    {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let (db, hint) = setup(&params)?;
+        let nonzero = db.values().iter().filter(|&&v| v != 0).count();
+        let db = if (nonzero as f64) < SPARSE_DENSITY * (db.num_cols() * db.num_rows()) as f64 {
+            Storage::Sparse(SparseMatrix::from_matrix(&db, params.p))
+        } else if bitpacked::bits_per_value(params.p) <= BITPACKED_MAX_BITS {
+            Storage::BitPacked(BitPackedMatrix::from_matrix(&db, params.p))
+        } else {
+            Storage::Dense(db)
+        };
+        Ok(Self {
+            params_digest: params.digest(),
+            params,
+            db,
+            hint,
+            layout,
+            root: None,
+            epoch: 0,
+            built_at: unix_now(),
+            #[cfg(feature = "spir")]
+            oprf_key: None,
+            #[cfg(feature = "signing")]
+            signing_key: None,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            #[cfg(feature = "gpu")]
+            gpu: None,
+            #[cfg(feature = "std")]
+            setup_time: start.elapsed(),
+            arena_capacity: DEFAULT_ARENA_BYTES / 8,
+            #[cfg(feature = "std")]
+            arenas: Mutex::new(vec![]),
+            chunk_cols: BACKEND_CHUNK_COLS,
+            #[cfg(feature = "std")]
+            stats: None,
+            #[cfg(feature = "std")]
+            query_log: None,
+            #[cfg(feature = "std")]
+            observers: vec![],
+        })
+    }
+
+    /// Split the database scan of each answer across num_threads threads. With one thread the
+    /// scan runs on the calling thread.
+    #[cfg(feature = "parallel")]
+    pub fn set_threads(&mut self, num_threads: usize) {
+        assert!(num_threads > 0);
+        self.pool = if num_threads == 1 { None } else { Some(build_pool(num_threads)) };
+    }
+
+    /// Set the size in bytes of the scratch arenas which answers take their temporaries from,
+    /// such as the query converted for the database scan. Each arena keeps this much memory
+    /// between answers, and an answer needing more allocates the rest for its own duration.
+    pub fn set_arena_size(&mut self, bytes: usize) {
+        self.arena_capacity = bytes / 8;
+        #[cfg(feature = "std")]
+        self.arenas.get_mut().unwrap().clear();
+    }
+
+    /// The size in bytes of the scratch arenas.
+    pub fn arena_size(&self) -> usize {
+        8 * self.arena_capacity
+    }
+
+    /// Set the number of columns which a database read from a backend is scanned in at a time.
+    pub fn set_chunk_cols(&mut self, cols: usize) {
+        assert!(cols > 0);
+        self.chunk_cols = cols;
+    }
+
+    /// The number of columns which a database read from a backend is scanned in at a time.
+    pub fn chunk_cols(&self) -> usize {
+        self.chunk_cols
+    }
+
+    /// Probe the cache size and memory bandwidth of the machine and size the chunks of backend
+    /// scans to suit them, see the tune module. Returns what was measured.
+    #[cfg(feature = "std")]
+    pub fn autotune(&mut self) -> MachineProfile {
+        let profile = tune::probe();
+        self.set_chunk_cols(profile.chunk_cols(self.params.m));
+        profile
+    }
+
+    // Take a scratch arena from the pool, or create one if all are in use
+    fn take_arena(&self) -> Arena {
+        #[cfg(feature = "std")]
+        if let Some(arena) = self.arenas.lock().unwrap().pop() {
+            return arena;
+        }
+        Arena::with_capacity(self.arena_capacity)
+    }
+
+    // Return a scratch arena to the pool once an answer is done with it
+    fn put_arena(&self, mut arena: Arena) {
+        arena.reset();
+        #[cfg(feature = "std")]
+        if arena.capacity() == self.arena_capacity {
+            self.arenas.lock().unwrap().push(arena);
+        }
+    }
+
+    /// Upload the database to the GPU and answer queries there. Returns false, leaving answers on
+    /// the CPU, if no GPU is available, the database is stored sparsely or q exceeds 2^32.
+    #[cfg(feature = "gpu")]
+    pub fn enable_gpu(&mut self) -> bool {
+        self.gpu = match &self.db {
+            Storage::Dense(db) if self.params.q <= gpu::MAX_Q => GpuDatabase::new(db),
+            Storage::BitPacked(db) if self.params.q <= gpu::MAX_Q => {
+                GpuDatabase::new(&db.to_matrix())
+            }
+            _ => None,
+        };
+        self.gpu.is_some()
+    }
+
+    /// Save the params, hint, record layout, Merkle root, epoch, build time and encoded database,
+    /// so that a restarted server can be loaded with load_state() instead of running setup again.
+    /// Thread, GPU, stats and query log settings are not saved.
+    ///
+    /// The file holds a magic and a format version (u32), followed by the params, hint, layout,
+    /// root, epoch (u64), build time (u64) and database in their wire encodings, each prefixed
+    /// with its length (u64). The layout and root are empty for a server without them. Integers are
+    /// little-endian.
+    ///
+    /// The OPRF key of a server set up with symmetric records is not saved, and such servers
+    /// return an Unsupported error.
+    #[cfg(feature = "std")]
+    pub fn save_state<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        #[cfg(feature = "spir")]
+        if self.oprf_key.is_some() {
+            let msg = "the OPRF key of symmetric records is not saved";
+            return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+        }
+        let db = match &self.db {
+            Storage::Dense(db) => wire::encode_matrix(db),
+            Storage::Sparse(db) => wire::encode_matrix(&db.to_matrix()),
+            Storage::BitPacked(db) => wire::encode_matrix(&db.to_matrix()),
+            Storage::Backend(_) => {
+                let msg = "a database backend is not saved, but reopened";
+                return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+            }
+        };
+        let layout = self.layout.as_ref().map_or(vec![], wire::encode_layout);
+        let params = wire::encode_params(&self.params);
+        let root = self.root.map_or(vec![], |root| root.to_vec());
+        let epoch = self.epoch.to_le_bytes().to_vec();
+        let built_at = self.built_at.to_le_bytes().to_vec();
+        let sections = [params, wire::encode_hint(&self.hint), layout, root, epoch, built_at, db];
+
+        let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+        out.write_all(STATE_MAGIC)?;
+        out.write_all(&STATE_VERSION.to_le_bytes())?;
+        for section in sections {
+            out.write_all(&(section.len() as u64).to_le_bytes())?;
+            out.write_all(&section)?;
+        }
+        out.flush()
+    }
+
+    /// Load a server saved by save_state(). Files which are not state files, or which were written
+    /// by a different version of the format, are rejected with an InvalidData error.
+    #[cfg(feature = "std")]
+    pub fn load_state<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut file = io::BufReader::new(std::fs::File::open(path)?);
+        let mut header = [0u8; 12];
+        file.read_exact(&mut header)?;
+        if &header[..8] != STATE_MAGIC {
+            return Err(invalid("not a server state file".into()));
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if version != STATE_VERSION {
+            return Err(invalid(format!(
+                "the state file has version {}, but only version {} is supported",
+                version, STATE_VERSION,
+            )));
+        }
+
+        let mut read_section = || -> io::Result<Vec<u8>> {
+            let mut len = [0u8; 8];
+            file.read_exact(&mut len)?;
+            let mut section = vec![];
+            (&mut file).take(u64::from_le_bytes(len)).read_to_end(&mut section)?;
+            if section.len() as u64 != u64::from_le_bytes(len) {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Ok(section)
+        };
+        let wire_err = |err: wire::WireError| invalid(err.to_string());
+        let params = wire::decode_params(&read_section()?).map_err(wire_err)?;
+        let hint = wire::decode_hint(&read_section()?).map_err(wire_err)?;
+        let layout = match read_section()? {
+            layout if layout.is_empty() => None,
+            layout => Some(wire::decode_layout(&layout).map_err(wire_err)?),
+        };
+        let root = match read_section()? {
+            root if root.is_empty() => None,
+            root => {
+                let root = Digest::try_from(root.as_slice());
+                Some(root.map_err(|_| invalid("the Merkle root is not 32 bytes".into()))?)
+            }
+        };
+        let epoch = read_section()?.try_into().map(u64::from_le_bytes);
+        let epoch = epoch.map_err(|_| invalid("the epoch is not 8 bytes".into()))?;
+        let built_at = read_section()?.try_into().map(u64::from_le_bytes);
+        let built_at = built_at.map_err(|_| invalid("the build time is not 8 bytes".into()))?;
+        let db = wire::decode_matrix(&read_section()?).map_err(wire_err)?;
+        if file.read(&mut [0u8])? != 0 {
+            return Err(invalid("the state file has trailing bytes".into()));
+        }
+
+        if db.dimensions() != (hint.0.num_cols(), params.m) || hint.0.num_rows() != params.n {
+            return Err(invalid("the database does not match the params and hint".into()));
+        }
+        if !db.has_modulus(params.p) || !hint.0.has_modulus(params.q) {
+            return Err(invalid("the database or hint has the wrong modulus".into()));
+        }
+        if layout.as_ref().is_some_and(|layout| layout.rows != params.m) {
+            return Err(invalid("the record layout does not match the params".into()));
+        }
+        let server = Self::new(params, layout, |_| Ok((db, hint)))
+            .map_err(|err| invalid(err.to_string()))?;
+        Ok(Self { root, epoch, built_at, ..server })
+    }
+
+    /// Start collecting stats, discarding any collected so far.
+    #[cfg(feature = "std")]
+    pub fn enable_stats(&mut self) {
+        let stats = Stats { setup_time: self.setup_time, ..Default::default() };
+        self.stats = Some(Mutex::new(stats));
+    }
+
+    /// A snapshot of the stats, if they are enabled.
+    #[cfg(feature = "std")]
+    pub fn stats(&self) -> Option<Stats> {
+        self.stats.as_ref().map(|stats| stats.lock().unwrap().clone())
+    }
+
+    /// Start counting the queries answered in each epoch from the current one on, for release
+    /// with differential privacy budget epsilon per epoch by release_query_counts(). Only the
+    /// counts are kept, never the queries. Counts collected so far are discarded.
+    #[cfg(feature = "std")]
+    pub fn enable_query_log(&mut self, epsilon: f64) -> Result<(), SimplePirError> {
+        self.query_log = Some(Mutex::new(QueryLog::new(epsilon, self.epoch)?));
+        Ok(())
+    }
+
+    /// The noised query counts of the epochs which ended since the last call, or since the log
+    /// was enabled. Each epoch is released once; see the audit module.
+    #[cfg(feature = "std")]
+    pub fn release_query_counts(&self) -> Vec<NoisyCount> {
+        match &self.query_log {
+            Some(log) => log.lock().unwrap().release(self.epoch),
+            None => vec![],
+        }
+    }
+
+    /// Call the observer on every hint published, query received and answer emitted from now on,
+    /// see the observer module.
+    #[cfg(feature = "std")]
+    pub fn add_observer(&mut self, observer: Arc<dyn ProtocolObserver>) {
+        self.observers.push(observer);
+    }
+
+    // Tell the observers of queries as they are received
+    #[cfg(feature = "std")]
+    fn observe_queries(&self, queries: &[Query]) {
+        for observer in &self.observers {
+            for query in queries {
+                let event = QueryReceived { epoch: self.epoch, query_bytes: query.wire_size() };
+                observer.on_query_received(&event);
+            }
+        }
+    }
+
+    // Record answers which took `time` in total and read the database `scans` times
+    #[cfg(feature = "std")]
+    fn record_stats(&self, time: Duration, queries: &[Query], answers: &[Answer], scans: usize) {
+        let answer_sizes: Vec<usize> = answers.iter().map(Answer::wire_size).collect();
+        self.record_answers(time, queries, &answer_sizes, self.scan_len() as u64 * scans as u64);
+    }
+
+    // Record answers of the given sizes, which took `time` in total and read `items_scanned`
+    // items of the database
+    #[cfg(feature = "std")]
+    fn record_answers(
+        &self,
+        time: Duration,
+        queries: &[Query],
+        answer_sizes: &[usize],
+        items_scanned: u64,
+    ) {
+        if let Some(log) = &self.query_log {
+            log.lock().unwrap().record(self.epoch, queries.len() as u64);
+        }
+        let time = time / queries.len().max(1) as u32;
+        for observer in &self.observers {
+            for &answer_bytes in answer_sizes {
+                let event = AnswerEmitted { epoch: self.epoch, answer_bytes, time };
+                observer.on_answer_emitted(&event);
+            }
+        }
+        let Some(stats) = &self.stats else { return };
+        let mut stats = stats.lock().unwrap();
+        for (query, &answer_bytes) in queries.iter().zip(answer_sizes) {
+            stats.record_answer(time, query.wire_size(), answer_bytes);
+        }
+        stats.items_scanned += items_scanned;
+    }
+
+    // The number of items one pass over the database reads
+    #[cfg(feature = "std")]
+    fn scan_len(&self) -> usize {
+        match &self.db {
+            Storage::Dense(db) => db.num_cols() * db.num_rows(),
+            Storage::Sparse(db) => db.num_nonzero(),
+            Storage::BitPacked(db) => db.num_cols() * db.num_rows(),
+            Storage::Backend(db) => db.len() * db.num_rows(),
+        }
+    }
+
+    /// The parameters which clients must use.
+    pub fn params(&self) -> &SimplePIRParams {
+        &self.params
+    }
+
+    /// The digest of the params, which clients compare with that of their own params.
+    pub fn params_digest(&self) -> &Digest {
+        &self.params_digest
+    }
+
+    /// Refuse to serve a client whose params have a different digest, since its queries would
+    /// only get garbage answers.
+    pub fn check_params(&self, digest: &Digest) -> Result<(), SimplePirError> {
+        if *digest != self.params_digest {
+            return Err(SimplePirError::ParamsMismatch);
+        }
+        Ok(())
+    }
+
+    /// The hint which clients must download before querying.
+    pub fn hint(&self) -> &Hint {
+        &self.hint
+    }
+
+    /// The params, hint and layout as a single artifact, which can be served to every client.
+    /// It changes only when the hint is updated. Observers are told of it as published.
+    pub fn public_hint(&self) -> PublicHint {
+        #[cfg(feature = "std")]
+        for observer in &self.observers {
+            let hint_bytes = self.hint.wire_size();
+            observer.on_hint_published(&HintPublished { epoch: self.epoch, hint_bytes });
+        }
+        self.build_public_hint()
+    }
+
+    // The public hint, without telling the observers
+    fn build_public_hint(&self) -> PublicHint {
+        PublicHint {
+            params: self.params.clone(),
+            hint: self.hint.clone(),
+            layout: self.layout.clone(),
+            verifying_key: self.verifying_key(),
+        }
+    }
+
+    /// Sign answers and manifests with the Ed25519 key of the given secret. The verifying key is
+    /// published in the public hint from then on.
+    #[cfg(feature = "signing")]
+    pub fn set_signing_key(&mut self, secret: &[u8; 32]) {
+        self.signing_key = Some(SigningKey::from_bytes(secret));
+    }
+
+    /// The key which signed answers verify under, if the server signs them.
+    pub fn verifying_key(&self) -> Option<[u8; 32]> {
+        #[cfg(feature = "signing")]
+        if let Some(key) = &self.signing_key {
+            return Some(key.verifying_key().to_bytes());
+        }
+        None
+    }
+
+    /// Answer a query as answer_tagged() does, and sign the tagged answer along with the params
+    /// digest and the build time of the database. Fails with NoSigningKey unless a signing key is
+    /// set.
+    #[cfg(feature = "signing")]
+    pub fn answer_signed(&self, query: &Query) -> Result<SignedAnswer, SimplePirError> {
+        let key = self.signing_key.as_ref().ok_or(SimplePirError::NoSigningKey)?;
+        let tagged = self.answer_tagged(query)?;
+        Ok(signing::sign_answer(key, &self.params_digest, self.built_at, tagged))
+    }
+
+    /// Sign the digest of the public hint in the current epoch and the time its database was built,
+    /// for clients which download the public hint from a CDN. Fails with NoSigningKey unless a
+    /// signing key is set.
+    #[cfg(feature = "signing")]
+    pub fn manifest(&self) -> Result<SignedManifest, SimplePirError> {
+        let key = self.signing_key.as_ref().ok_or(SimplePirError::NoSigningKey)?;
+        let digest = self.build_public_hint().digest();
+        Ok(signing::sign_manifest(key, self.epoch, self.built_at, digest))
+    }
+
+    /// The Merkle root which clients check records against, if the server was set up with
+    /// setup_authenticated_records().
+    pub fn merkle_root(&self) -> Option<&Digest> {
+        self.root.as_ref()
+    }
+
+    /// Whether the database is stored sparsely.
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.db, Storage::Sparse(_))
+    }
+
+    /// The record layout, if the server was set up with byte records.
+    pub fn layout(&self) -> Option<&RecordLayout> {
+        self.layout.as_ref()
+    }
+
+    /// The number of items of the database, i.e. its m rows times its columns.
+    pub fn num_items(&self) -> usize {
+        self.hint.0.num_cols() * self.params.m
+    }
+
+    /// The version of the database, which starts at 0 and is advanced by every update.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// When the database of the current epoch was built, in seconds since the Unix epoch. Setup
+    /// and every update set it to the current time (0 without the `std` feature), and signed
+    /// answers and manifests attest to it.
+    pub fn built_at(&self) -> u64 {
+        self.built_at
+    }
+
+    /// Set the build time of the current epoch's database, e.g. to the time of the snapshot it
+    /// was built from rather than the time the server set it up.
+    pub fn set_built_at(&mut self, secs: u64) {
+        self.built_at = secs;
+    }
+
+    /// Replace item idx, patching the database and hint in place. The returned delta brings a
+    /// client's hint up to date without downloading it again.
+    pub fn update_item(&mut self, idx: usize, value: Element) -> Result<HintDelta, SimplePirError> {
+        if self.layout.is_some() {
+            return Err(SimplePirError::HasRecordLayout);
+        }
+        self.check_writable()?;
+        if value.q != self.params.p {
+            return Err(SimplePirError::ModulusMismatch {
+                expected: self.params.p,
+                actual: value.q,
+            });
+        }
+        let len = self.num_items();
+        if idx >= len {
+            return Err(SimplePirError::IndexOutOfRange { idx, len });
+        }
+        let (col, row) = (idx / self.params.m, idx % self.params.m);
+        self.set(col, row, value);
+        Ok(self.apply_update(&[col]))
+    }
+
+    /// Replace byte record idx, patching the database and hint in place. The returned delta
+    /// brings a client's hint up to date without downloading it again.
+    ///
+    /// Authenticated records cannot be updated, since a new record changes the authentication
+    /// path of every other record; such a server has to be set up again.
+    pub fn update_record(
+        &mut self,
+        idx: usize,
+        record: &[u8],
+    ) -> Result<HintDelta, SimplePirError> {
+        if self.root.is_some() {
+            return Err(SimplePirError::ReadOnly("authenticated records"));
+        }
+        self.check_writable()?;
+        let layout = self.layout.as_ref().ok_or(SimplePirError::NoRecordLayout)?;
+        if record.len() != layout.record_len {
+            return Err(SimplePirError::LengthMismatch {
+                expected: layout.record_len,
+                actual: record.len(),
+            });
+        }
+        if idx >= layout.num_records {
+            return Err(SimplePirError::IndexOutOfRange { idx, len: layout.num_records });
+        }
+        let (col, row) = layout.position(idx);
+        let limbs = encode_record(record, self.params.p);
+        let cols: Vec<usize> = (col..col + limbs.len()).collect();
+        for (i, limb) in limbs.into_iter().enumerate() {
+            self.set(col + i, row, limb);
+        }
+        Ok(self.apply_update(&cols))
+    }
+
+    // Check that the database is held in memory, and so can be updated in place
+    fn check_writable(&self) -> Result<(), SimplePirError> {
+        match self.db {
+            Storage::Backend(_) => Err(SimplePirError::ReadOnly("database backend")),
+            _ => Ok(()),
+        }
+    }
+
+    fn set(&mut self, col: usize, row: usize, value: Element) {
+        #[cfg(feature = "gpu")]
+        if let Some(gpu) = &self.gpu {
+            gpu.set(col, row, &value);
+        }
+        match &mut self.db {
+            Storage::Dense(db) => db.set(col, row, &value),
+            Storage::Sparse(db) => db.set(col, row, &value),
+            Storage::BitPacked(db) => db.set(col, row, &value),
+            Storage::Backend(_) => unreachable!("the database backend is read-only"),
+        }
+    }
+
+    fn apply_update(&mut self, cols: &[usize]) -> HintDelta {
+        let delta = match &self.db {
+            Storage::Dense(db) => simplepir::gen_hint_rows(&self.params, db, cols),
+            Storage::Sparse(db) => HintDelta::Rows(cols.iter()
+                .map(|&i| (i, simplepir::gen_hint_row(&self.params, &db.column(i))))
+                .collect()),
+            Storage::BitPacked(db) => HintDelta::Rows(cols.iter()
+                .map(|&i| (i, simplepir::gen_hint_row(&self.params, &db.column(i))))
+                .collect()),
+            Storage::Backend(_) => unreachable!(),
+        };
+        simplepir::apply_hint_delta(&mut self.hint, &delta)
+            .expect("the delta was generated from the database");
+        self.epoch += 1;
+        self.built_at = unix_now();
+        delta
+    }
+
+    // Check that a query is mod q. Its shape is checked by the simplepir answer functions.
+    fn check_query(&self, query: &Query) -> Result<(), SimplePirError> {
+        match query.0.first() {
+            Some(first) if first.q != self.params.q => Err(SimplePirError::ModulusMismatch {
+                expected: self.params.q,
+                actual: first.q,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Answer a query. Queries which do not match the params are rejected, so queries can be
+    /// answered as received from untrusted clients.
+    pub fn answer(&self, query: &Query) -> Result<Answer, SimplePirError> {
+        #[cfg(feature = "std")]
+        self.observe_queries(slice::from_ref(query));
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let answer = self.compute_answer(query)?;
+        #[cfg(feature = "std")]
+        self.record_stats(start.elapsed(), slice::from_ref(query), slice::from_ref(&answer), 1);
+        Ok(answer)
+    }
+
+    /// Answer a query from a client whose params have the given digest, which is sent along with
+    /// the query. Clients with other params are refused, since they would only get garbage
+    /// answers, so servers reached over a network answer with this rather than answer().
+    pub fn answer_checked(
+        &self,
+        params_digest: &Digest,
+        query: &Query,
+    ) -> Result<Answer, SimplePirError> {
+        self.check_params(params_digest)?;
+        self.answer(query)
+    }
+
+    /// Check the server end to end before it takes traffic: retrieve `rounds` items, chosen with
+    /// a fixed seed, with a throwaway client, and compare each with the database. A retrieval
+    /// which recovers another item fails with MalformedAnswer, and answers which take longer than
+    /// max_answer_time on average fail with SlowSelfTest. Returns the mean answer time. The
+    /// retrievals are not recorded in the stats.
+    #[cfg(feature = "std")]
+    pub fn self_test(
+        &self,
+        rounds: usize,
+        max_answer_time: Duration,
+    ) -> Result<Duration, SimplePirError> {
+        let mut client = Client::new(self.params.clone(), self.hint.clone())?;
+        let mut rng = ChaCha20Rng::seed_from_u64(SELF_TEST_SEED);
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..rounds {
+            let idx = rng.gen_range(0..self.num_items());
+            let query = client.query_with_rng(idx, &mut rng)?;
+            let start = Instant::now();
+            let answer = self.compute_answer(&query)?;
+            elapsed += start.elapsed();
+            if client.recover(&answer)? != self.item(idx / self.params.m, idx % self.params.m)? {
+                return Err(SimplePirError::MalformedAnswer);
+            }
+        }
+        let mean = elapsed / rounds.max(1) as u32;
+        if mean > max_answer_time {
+            return Err(SimplePirError::SlowSelfTest { elapsed: mean, limit: max_answer_time });
+        }
+        Ok(mean)
+    }
+
+    // The item at the given column and row of the database
+    #[cfg(feature = "std")]
+    fn item(&self, col: usize, row: usize) -> Result<Element, SimplePirError> {
+        Ok(match &self.db {
+            Storage::Dense(db) => db.get(col, row),
+            Storage::Sparse(db) => db.column(col).swap_remove(row),
+            Storage::BitPacked(db) => db.column(col).swap_remove(row),
+            Storage::Backend(db) => db.record(col)?.swap_remove(row),
+        })
+    }
+
+    /// Answer a query as answer() does, tagging the answer with the epoch and the digest of the
+    /// query id and epoch. An answer is a function of the query and the database alone, so a query
+    /// which is sent again within an epoch (e.g. after its answer was lost) gets the same answer.
+    pub fn answer_tagged(&self, query: &Query) -> Result<TaggedAnswer, SimplePirError> {
+        let answer = self.answer(query)?;
+        let digest = answer_digest(&query.id(), self.epoch);
+        Ok(TaggedAnswer { epoch: self.epoch, digest, answer })
+    }
+
+    fn compute_answer(&self, query: &Query) -> Result<Answer, SimplePirError> {
+        let mut arena = self.take_arena();
+        let answer = self.compute_answer_in(query, &mut arena);
+        self.put_arena(arena);
+        answer
+    }
+
+    fn compute_answer_in(
+        &self,
+        query: &Query,
+        arena: &mut Arena,
+    ) -> Result<Answer, SimplePirError> {
+        self.check_query(query)?;
+        match &self.db {
+            Storage::Sparse(db) => return simplepir::answer_sparse(&query.0, db).map(Answer),
+            Storage::Backend(db) => {
+                let db = db.as_ref();
+                return simplepir::answer_db_in(&query.0, db, self.chunk_cols, arena).map(Answer);
+            }
+            _ => {}
+        }
+        #[cfg(feature = "gpu")]
+        if let Some(gpu) = &self.gpu {
+            return simplepir::answer_gpu(&query.0, gpu).map(Answer);
+        }
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = &self.pool {
+            return match &self.db {
+                Storage::BitPacked(db) => simplepir::answer_bitpacked_parallel(&query.0, db, pool),
+                Storage::Dense(db) => simplepir::answer_parallel(&query.0, db, pool),
+                Storage::Sparse(_) | Storage::Backend(_) => unreachable!(),
+            }.map(Answer);
+        }
+        match &self.db {
+            Storage::Dense(db) => simplepir::answer_in(&query.0, db, arena).map(Answer),
+            Storage::BitPacked(db) => {
+                simplepir::answer_bitpacked_in(&query.0, db, arena).map(Answer)
+            }
+            Storage::Sparse(_) | Storage::Backend(_) => unreachable!(),
+        }
+    }
+
+    /// Answer a batch of queries with a single pass over the database.
+    pub fn answer_batch(&self, queries: &[Query]) -> Result<Vec<Answer>, SimplePirError> {
+        #[cfg(feature = "std")]
+        self.observe_queries(queries);
+        for query in queries {
+            self.check_query(query)?;
+        }
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let (answers, scans) = match &self.db {
+            Storage::Dense(db) => {
+                let batch: Vec<Vec<Element>> = queries.iter().map(|q| q.0.clone()).collect();
+                (simplepir::answer_batch(&batch, db)?.into_iter().map(Answer).collect(), 1)
+            }
+            Storage::BitPacked(db) => {
+                let batch: Vec<Vec<Element>> = queries.iter().map(|q| q.0.clone()).collect();
+                let answers = simplepir::answer_batch_bitpacked(&batch, db)?;
+                (answers.into_iter().map(Answer).collect(), 1)
+            }
+            // Without a dense matrix to multiply, each query is its own scan
+            Storage::Sparse(_) | Storage::Backend(_) => {
+                let answers = queries.iter().map(|query| self.compute_answer(query));
+                (answers.collect::<Result<Vec<_>, _>>()?, queries.len())
+            }
+        };
+        #[cfg(feature = "std")]
+        self.record_stats(start.elapsed(), queries, &answers, scans);
+        #[cfg(not(feature = "std"))]
+        let _ = scans;
+        Ok(answers)
+    }
+
+    /// Answer a query, mod-switching the answer down to q_prime to shrink the download. Clients
+    /// recover compressed answers with the usual recover functions.
+    pub fn answer_compressed(&self, query: &Query, q_prime: u64) -> Result<Answer, SimplePirError> {
+        #[cfg(feature = "std")]
+        self.observe_queries(slice::from_ref(query));
+        self.check_query(query)?;
+        if q_prime > self.params.q || q_prime <= self.params.p {
+            return Err(SimplePirError::InvalidModulus(q_prime));
+        }
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let answer = match &self.db {
+            Storage::Dense(db) => Answer(simplepir::answer_compressed(&query.0, db, q_prime)?),
+            Storage::Sparse(db) => {
+                let answer = simplepir::answer_sparse(&query.0, db)?;
+                Answer(answer.mod_switch(q_prime))
+            }
+            Storage::BitPacked(db) => {
+                let answer = simplepir::answer_bitpacked(&query.0, db)?;
+                Answer(answer.mod_switch(q_prime))
+            }
+            Storage::Backend(db) => {
+                let answer = simplepir::answer_db(&query.0, db.as_ref(), self.chunk_cols)?;
+                Answer(answer.mod_switch(q_prime))
+            }
+        };
+        #[cfg(feature = "std")]
+        self.record_stats(start.elapsed(), slice::from_ref(query), slice::from_ref(&answer), 1);
+        Ok(answer)
+    }
+
+    /// Answer a query for a byte range of a record, with only the columns which hold the
+    /// requested limbs of each record in the queried row. Limbs outside the layout are rejected,
+    /// and servers without a record layout refuse with NoRecordLayout.
+    pub fn answer_range(&self, query: &RangeQuery) -> Result<Answer, SimplePirError> {
+        #[cfg(feature = "std")]
+        self.observe_queries(slice::from_ref(&query.query));
+        let layout = self.layout.as_ref().ok_or(SimplePirError::NoRecordLayout)?;
+        self.check_query(&query.query)?;
+        if query.limbs.is_empty() {
+            return Err(SimplePirError::Empty("limb range"));
+        }
+        if query.limbs.end > layout.limbs {
+            return Err(SimplePirError::IndexOutOfRange { idx: query.limbs.end, len: layout.limbs });
+        }
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let cols = layout.range_columns(query.limbs.clone());
+        let answer = match &self.db {
+            Storage::Dense(db) => Answer(simplepir::answer_cols(&query.query.0, db, &cols)?),
+            Storage::Sparse(db) => {
+                let sub_db: Vec<Vec<Element>> = cols.iter().map(|&col| db.column(col)).collect();
+                Answer(simplepir::answer(&query.query.0, &Matrix::from(&sub_db))?)
+            }
+            Storage::BitPacked(db) => {
+                let sub_db: Vec<Vec<Element>> = cols.iter().map(|&col| db.column(col)).collect();
+                Answer(simplepir::answer(&query.query.0, &Matrix::from(&sub_db))?)
+            }
+            Storage::Backend(db) => {
+                let sub_db = cols.iter().map(|&col| db.record(col)).collect::<Result<_, _>>()?;
+                Answer(simplepir::answer(&query.query.0, &Matrix::from(&sub_db))?)
+            }
+        };
+        #[cfg(feature = "std")]
+        self.record_answers(
+            start.elapsed(),
+            slice::from_ref(&query.query),
+            &[answer.wire_size()],
+            (cols.len() * self.params.m) as u64,
+        );
+        Ok(answer)
+    }
+
+    /// Answer a symmetric query and evaluate the OPRF on the request sent along with it, for the
+    /// client to unmask the record it retrieves. Clients which obtained more than one evaluation
+    /// per query could unmask more than one record, so the OPRF is only evaluated together with
+    /// an answer. Servers which were not set up with symmetric records refuse with
+    /// NoSymmetricRecords.
+    #[cfg(feature = "spir")]
+    pub fn answer_symmetric(
+        &self,
+        query: &Query,
+        request: &OprfRequest,
+    ) -> Result<(Answer, OprfResponse), SimplePirError> {
+        let key = self.oprf_key.as_ref().ok_or(SimplePirError::NoSymmetricRecords)?;
+        let answer = self.answer(query)?;
+        Ok((answer, spir::evaluate(key, request)?))
+    }
+
+    /// Answer a query generated by Client::query_packed() and pack the answer into ring
+    /// ciphertexts with the packing key sent along with it, so that the client needs no hint.
+    #[cfg(feature = "packing")]
+    pub fn answer_packed(
+        &self,
+        query: &Query,
+        key: &PackingKey,
+    ) -> Result<PackedAnswer, SimplePirError> {
+        #[cfg(feature = "std")]
+        self.observe_queries(slice::from_ref(query));
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let answer = self.compute_answer(query)?;
+        let packed = packing::pack(&self.params, &self.hint, key, &answer.0)?;
+        #[cfg(feature = "std")]
+        self.record_answers(
+            start.elapsed(),
+            slice::from_ref(query),
+            &[packed.wire_size()],
+            self.scan_len() as u64,
+        );
+        Ok(packed)
+    }
+
+    /// Answer a query which the client mod-switched down to a modulus q' < q with
+    /// Client::query_compressed(). The answer is computed mod q', so it is as small as a
+    /// compressed answer. Queries mod q are answered as by answer().
+    pub fn answer_compressed_query(&self, query: &Query) -> Result<Answer, SimplePirError> {
+        #[cfg(feature = "std")]
+        self.observe_queries(slice::from_ref(query));
+        let q = match query.0.first() {
+            Some(first) => first.q,
+            None => return Err(SimplePirError::Empty("query")),
+        };
+        if q > self.params.q {
+            return Err(SimplePirError::ModulusMismatch { expected: self.params.q, actual: q });
+        }
+        if q <= self.params.p {
+            return Err(SimplePirError::InvalidModulus(q));
+        }
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let answer = match &self.db {
+            Storage::Dense(db) => Answer(simplepir::answer(&query.0, db)?),
+            Storage::Sparse(db) => Answer(simplepir::answer_sparse(&query.0, db)?),
+            Storage::BitPacked(db) => Answer(simplepir::answer_bitpacked(&query.0, db)?),
+            Storage::Backend(db) => {
+                Answer(simplepir::answer_db(&query.0, db.as_ref(), self.chunk_cols)?)
+            }
+        };
+        #[cfg(feature = "std")]
+        self.record_stats(start.elapsed(), slice::from_ref(query), slice::from_ref(&answer), 1);
+        Ok(answer)
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn build_pool(num_threads: usize) -> rayon::ThreadPool {
+    assert!(num_threads > 0);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build();
+    pool.expect("failed to create the thread pool")
+}
+
+// The client state needed to recover the answer to the last query
+struct PendingQuery {
+    idx: usize,
+    state: QueryState,
+    // The id of the query as sent, which tagged answers are checked against
+    query_id: Digest,
+}
+
+impl PendingQuery {
+    fn new(idx: usize, state: QueryState, query: &Query) -> Self {
+        Self { idx, state, query_id: query.id() }
+    }
+}
+
+/// The client side of SimplePIR. It holds the hint downloaded from the server and the state of
+/// the outstanding query. A fresh secret is sampled for every query.
+///
+/// The expensive client work of a query (A * s + e and hint * s) does not depend on the queried
+/// index, and can be done offline with prepare(). Queries then consume prepared work, so that
+/// generating a query and recovering its answer are cheap; without prepared work they prepare it
+/// on the spot.
+pub struct Client {
+    params: SimplePIRParams,
+    // The digest of the params, computed once since it hashes all of A
+    params_digest: Digest,
+    hint: Hint,
+    layout: Option<RecordLayout>,
+    // The Merkle root which retrieved records are checked against
+    root: Option<Digest>,
+    // The number of check bytes at the end of each record
+    check_len: usize,
+    // The keys which sealed records are opened with
+    #[cfg(feature = "encryption")]
+    keyring: Option<Keyring>,
+    // The key which signed answers are checked against
+    verifying_key: Option<[u8; 32]>,
+    // The oldest a signed answer's database may be
+    #[cfg(all(feature = "std", feature = "signing"))]
+    max_age: Option<Duration>,
+    prepared: Vec<PreparedQuery>,
+    pending: Option<PendingQuery>,
+    pending_batch: Vec<PendingQuery>,
+    // The outstanding range query and the byte range it requested
+    pending_range: Option<(PendingQuery, Range<usize>)>,
+    // The item and ring secret of the outstanding packed query
+    #[cfg(feature = "packing")]
+    pending_packed: Option<(usize, Zeroizing<Poly>)>,
+    // The blinding of the outstanding symmetric query's OPRF request
+    #[cfg(feature = "spir")]
+    pending_oprf: Option<OprfState>,
+    #[cfg(feature = "std")]
+    stats: Option<Stats>,
+    #[cfg(feature = "std")]
+    observers: Vec<Arc<dyn ProtocolObserver>>,
+}
+
+impl Client {
+    /// Create a client for a server which was set up with items. The params and hint usually come
+    /// from the server, so they are checked to belong together.
+    pub fn new(params: SimplePIRParams, hint: Hint) -> Result<Self, SimplePirError> {
+        check_public(&params, &hint, None)?;
+        Ok(Self::unchecked(params, hint, None))
+    }
+
+    // A client with the given params, hint and layout, which were checked by the caller
+    fn unchecked(params: SimplePIRParams, hint: Hint, layout: Option<RecordLayout>) -> Self {
+        Self {
+            params_digest: params.digest(),
+            params,
+            hint,
+            layout,
+            root: None,
+            check_len: 0,
+            #[cfg(feature = "encryption")]
+            keyring: None,
+            verifying_key: None,
+            #[cfg(all(feature = "std", feature = "signing"))]
+            max_age: None,
+            prepared: vec![],
+            pending: None,
+            pending_batch: vec![],
+            pending_range: None,
+            #[cfg(feature = "packing")]
+            pending_packed: None,
+            #[cfg(feature = "spir")]
+            pending_oprf: None,
+            #[cfg(feature = "std")]
+            stats: None,
+            #[cfg(feature = "std")]
+            observers: vec![],
+        }
+    }
+
+    /// Create a client from a public hint, which should first be checked against the digest
+    /// published by the server.
+    pub fn from_public_hint(public: PublicHint) -> Result<Self, SimplePirError> {
+        public.validate()?;
+        let mut client = Self::unchecked(public.params, public.hint, public.layout);
+        client.verifying_key = public.verifying_key;
+        Ok(client)
+    }
+
+    /// Check signed answers against the given Ed25519 verifying key, for clients which did not
+    /// get it in a public hint.
+    pub fn set_verifying_key(&mut self, key: [u8; 32]) {
+        self.verifying_key = Some(key);
+    }
+
+    /// Reject signed answers from a database built longer than max_age ago with Stale, going by
+    /// the build time the server signed and the local clock.
+    #[cfg(all(feature = "std", feature = "signing"))]
+    pub fn set_max_age(&mut self, max_age: Duration) {
+        self.max_age = Some(max_age);
+    }
+
+    /// Create a client for a server which was set up with byte records.
+    pub fn with_layout(
+        params: SimplePIRParams,
+        hint: Hint,
+        layout: RecordLayout,
+    ) -> Result<Self, SimplePirError> {
+        check_public(&params, &hint, Some(&layout))?;
+        Ok(Self::unchecked(params, hint, Some(layout)))
+    }
+
+    /// Create a client for a server which was set up with authenticated records. The root must
+    /// be obtained from a source the client trusts (e.g. published by the database owner), since
+    /// recover_record() then rejects every record which does not hash to it.
+    pub fn with_merkle_root(
+        params: SimplePIRParams,
+        hint: Hint,
+        layout: RecordLayout,
+        root: Digest,
+    ) -> Result<Self, SimplePirError> {
+        Ok(Self { root: Some(root), ..Self::with_layout(params, hint, layout)? })
+    }
+
+    /// The digest of the params, which the server compares with that of its own params.
+    pub fn params_digest(&self) -> &Digest {
+        &self.params_digest
+    }
+
+    /// Refuse to query a server whose params have a different digest, since its answers would
+    /// only decode to garbage.
+    pub fn check_params(&self, digest: &Digest) -> Result<(), SimplePirError> {
+        if *digest != self.params_digest {
+            return Err(SimplePirError::ParamsMismatch);
+        }
+        Ok(())
+    }
+
+    /// Check and strip check_len bytes of redundancy from every record recovered with
+    /// recover_record(), for a server whose records were extended with record::add_check_bytes().
+    /// With a Merkle root as well, the check bytes are taken to be part of the authenticated
+    /// record.
+    pub fn set_check_bytes(&mut self, check_len: usize) {
+        assert!(check_len <= 32);
+        self.check_len = check_len;
+    }
+
+    /// Open every record recovered with recover_record() with the keyring, for a server whose
+    /// records were sealed with encryption::seal(). Sealing is the innermost layer, so the records
+    /// were sealed before any check bytes or authentication paths were added.
+    #[cfg(feature = "encryption")]
+    pub fn set_keyring(&mut self, keyring: Keyring) {
+        self.keyring = Some(keyring);
+    }
+
+    /// Start collecting stats, discarding any collected so far. The hint the client holds is
+    /// counted as downloaded.
+    #[cfg(feature = "std")]
+    pub fn enable_stats(&mut self) {
+        self.stats = Some(Stats { bytes_hint: self.hint.wire_size() as u64, ..Default::default() });
+    }
+
+    /// A snapshot of the stats, if they are enabled.
+    #[cfg(feature = "std")]
+    pub fn stats(&self) -> Option<Stats> {
+        self.stats.clone()
+    }
+
+    // Record the queries sent to the server
+    #[cfg(feature = "std")]
+    fn record_queries(&mut self, queries: &[Query]) {
+        if let Some(stats) = &mut self.stats {
+            let len: usize = queries.iter().map(Query::wire_size).sum();
+            stats.bytes_uploaded += len as u64;
+        }
+    }
+
+    /// Call the observer on every answer recovered from now on, see the observer module.
+    #[cfg(feature = "std")]
+    pub fn add_observer(&mut self, observer: Arc<dyn ProtocolObserver>) {
+        self.observers.push(observer);
+    }
+
+    // Record answers which took `time` in total to recover
+    #[cfg(feature = "std")]
+    fn record_answers(&mut self, time: Duration, answers: &[Answer]) {
+        let answer_sizes: Vec<usize> = answers.iter().map(Answer::wire_size).collect();
+        self.record_recovered(time, &answer_sizes);
+    }
+
+    // Record answers of the given sizes, which took `time` in total to recover
+    #[cfg(feature = "std")]
+    fn record_recovered(&mut self, time: Duration, answer_sizes: &[usize]) {
+        let time = time / answer_sizes.len().max(1) as u32;
+        for observer in &self.observers {
+            for &answer_bytes in answer_sizes {
+                observer.on_recovered(&Recovered { answer_bytes, time });
+            }
+        }
+        if let Some(stats) = &mut self.stats {
+            for &answer_bytes in answer_sizes {
+                stats.record_answer(time, 0, answer_bytes);
+            }
+        }
+    }
+
+    /// Do the offline work of count queries ahead of time (e.g. while the client is idle).
+    #[cfg(feature = "std")]
+    pub fn prepare(&mut self, count: usize) -> Result<(), SimplePirError> {
+        self.prepare_with_rng(count, &mut StdRng::from_entropy())
+    }
+
+    /// Like prepare(), but sampling the secrets and errors with the given RNG.
+    pub fn prepare_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        count: usize,
+        rng: &mut R,
+    ) -> Result<(), SimplePirError> {
+        for _ in 0..count {
+            let prepared = simplepir::prepare_query_with_rng(&self.params, &self.hint, rng)?;
+            self.prepared.push(prepared);
+        }
+        Ok(())
+    }
+
+    /// Like prepare(), but spreading the queries over num_threads threads, e.g. to refill the
+    /// prepared queries in the background of an interactive client. Each query samples its own
+    /// secret and error from a fresh RNG.
+    #[cfg(feature = "parallel")]
+    pub fn prepare_with_threads(
+        &mut self,
+        count: usize,
+        num_threads: usize,
+    ) -> Result<(), SimplePirError> {
+        let pool = build_pool(num_threads);
+        let (params, hint) = (&self.params, &self.hint);
+        let prepared: Vec<PreparedQuery> = pool.install(|| {
+            (0..count).into_par_iter()
+                .map(|_| {
+                    simplepir::prepare_query_with_rng(params, hint, &mut StdRng::from_entropy())
+                })
+                .collect::<Result<_, _>>()
+        })?;
+        self.prepared.extend(prepared);
+        Ok(())
+    }
+
+    /// The number of prepared queries which have not been used yet.
+    pub fn num_prepared(&self) -> usize {
+        self.prepared.len()
+    }
+
+    // Finish a prepared query for the given row, preparing one now if none is left
+    fn finish_query<R: CryptoRng + RngCore>(
+        &mut self,
+        row: usize,
+        rng: &mut R,
+    ) -> Result<(Query, QueryState), SimplePirError> {
+        let prepared = match self.prepared.pop() {
+            Some(prepared) => prepared,
+            None => simplepir::prepare_query_with_rng(&self.params, &self.hint, rng)?,
+        };
+        let (query, state) = prepared.finish(&self.params, row)?;
+        Ok((Query(query), state))
+    }
+
+    /// Bring the hint up to date after the server updated some items, along with prepared and
+    /// outstanding queries. Returns false if the delta requires the full hint to be downloaded
+    /// again. A delta which does not fit the hint is rejected before anything is updated.
+    pub fn apply_hint_delta(&mut self, delta: &HintDelta) -> Result<bool, SimplePirError> {
+        if !simplepir::apply_hint_delta(&mut self.hint, delta)? {
+            return Ok(false);
+        }
+        // The states were prepared from this hint, so the delta fits them too
+        for prepared in &mut self.prepared {
+            prepared.apply_hint_delta(delta)?;
+        }
+        let pending_range = self.pending_range.iter_mut().map(|(pending, _)| pending);
+        for pending in self.pending.iter_mut().chain(&mut self.pending_batch).chain(pending_range) {
+            pending.state.apply_hint_delta(delta)?;
+        }
+        Ok(true)
+    }
+
+    /// The number of items of the database, i.e. its m rows times the columns of the hint.
+    pub fn num_items(&self) -> usize {
+        self.hint.0.num_cols() * self.params.m
+    }
+
+    // The (column, row) position of item idx
+    fn position(&self, idx: usize) -> Result<(usize, usize), SimplePirError> {
+        let len = self.num_items();
+        if idx >= len {
+            return Err(SimplePirError::IndexOutOfRange { idx, len });
+        }
+        Ok((idx / self.params.m, idx % self.params.m))
+    }
+
+    /// Generate a query for item idx. Any previous query which has not been recovered is
+    /// discarded.
+    #[cfg(feature = "std")]
+    pub fn query(&mut self, idx: usize) -> Result<Query, SimplePirError> {
+        self.query_with_rng(idx, &mut StdRng::from_entropy())
+    }
+
+    /// Like query(), but sampling the secret and error with the given RNG.
+    pub fn query_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idx: usize,
+        rng: &mut R,
+    ) -> Result<Query, SimplePirError> {
+        let (_, row) = self.position(idx)?;
+        let (query, state) = self.finish_query(row, rng)?;
+        self.pending = Some(PendingQuery::new(idx, state, &query));
+        #[cfg(feature = "std")]
+        self.record_queries(slice::from_ref(&query));
+        Ok(query)
+    }
+
+    /// Like query(), but mod-switching the query down to the smaller modulus q_prime before it is
+    /// uploaded, for the server to answer with Server::answer_compressed_query(). This shrinks the
+    /// query from log2(q) to log2(q_prime) bits per entry at the cost of extra noise, see
+    /// SimplePIRParams::noise_budget_switched(). The answer is recovered with recover().
+    #[cfg(feature = "std")]
+    pub fn query_compressed(&mut self, idx: usize, q_prime: u64) -> Result<Query, SimplePirError> {
+        self.query_compressed_with_rng(idx, q_prime, &mut StdRng::from_entropy())
+    }
+
+    /// Like query_compressed(), but sampling the secret and error with the given RNG.
+    pub fn query_compressed_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idx: usize,
+        q_prime: u64,
+        rng: &mut R,
+    ) -> Result<Query, SimplePirError> {
+        if q_prime > self.params.q || q_prime <= self.params.p {
+            return Err(SimplePirError::InvalidModulus(q_prime));
+        }
+        let (_, row) = self.position(idx)?;
+        let (query, state) = self.finish_query(row, rng)?;
+        let query = Query(simplepir::compress_query(&query.0, q_prime)?);
+        self.pending = Some(PendingQuery::new(idx, state, &query));
+        #[cfg(feature = "std")]
+        self.record_queries(slice::from_ref(&query));
+        Ok(query)
+    }
+
+    /// Like query(), but also generating the packing key for the server to answer with
+    /// Server::answer_packed(). The key is as large as n * num_digits(q) ring ciphertexts and is
+    /// sent with every query (see PackingParams::key_len()), which only pays off when it is
+    /// smaller than the hint the client would otherwise download. The answer is recovered with
+    /// recover_packed().
+    #[cfg(all(feature = "std", feature = "packing"))]
+    pub fn query_packed(
+        &mut self,
+        idx: usize,
+        packing: &PackingParams,
+    ) -> Result<(Query, PackingKey), SimplePirError> {
+        self.query_packed_with_rng(idx, packing, &mut StdRng::from_entropy())
+    }
+
+    /// Like query_packed(), but sampling the secrets and errors with the given RNG.
+    #[cfg(feature = "packing")]
+    pub fn query_packed_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idx: usize,
+        packing: &PackingParams,
+        rng: &mut R,
+    ) -> Result<(Query, PackingKey), SimplePirError> {
+        let (_, row) = self.position(idx)?;
+        let (query, state) = self.finish_query(row, rng)?;
+        let s = state.secret();
+        let (key, z) = packing::gen_packing_key_with_rng(&self.params, packing, s, rng)?;
+        self.pending_packed = Some((idx, z));
+        #[cfg(feature = "std")]
+        self.record_queries(slice::from_ref(&query));
+        Ok((query, key))
+    }
+
+    /// Recover the item requested by the outstanding packed query from the server's answer.
+    #[cfg(feature = "packing")]
+    pub fn recover_packed(&mut self, packed: &PackedAnswer) -> Result<Element, SimplePirError> {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let (idx, z) = self.pending_packed.take().ok_or(SimplePirError::NoPendingQuery)?;
+        let (col, _) = self.position(idx)?;
+        let num_cols = self.hint.0.num_cols();
+        let item = packing::unpack_row(&self.params, &z, num_cols, packed)?.swap_remove(col);
+        #[cfg(feature = "std")]
+        self.record_recovered(start.elapsed(), &[packed.wire_size()]);
+        Ok(item)
+    }
+
+    /// Recover the item requested by the outstanding query from the server's answer.
+    pub fn recover(&mut self, answer: &Answer) -> Result<Element, SimplePirError> {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let pending = self.pending.take().ok_or(SimplePirError::NoPendingQuery)?;
+        let (col, _) = self.position(pending.idx)?;
+        let item = simplepir::recover_prepared_row(&self.params, pending.state, &answer.0)?
+            .swap_remove(col);
+        #[cfg(feature = "std")]
+        self.record_answers(start.elapsed(), slice::from_ref(answer));
+        Ok(item)
+    }
+
+    /// Like recover(), but first checking that the answer is tagged for the outstanding query. An
+    /// answer tagged for another query, such as a duplicate of an earlier answer or the answer to
+    /// another client, is rejected with AnswerMismatch, and the outstanding query is kept so that
+    /// its own answer can still be recovered.
+    pub fn recover_tagged(&mut self, tagged: &TaggedAnswer) -> Result<Element, SimplePirError> {
+        self.check_tag(tagged)?;
+        self.recover(&tagged.answer)
+    }
+
+    /// Like recover_tagged(), but first checking the server's signature over the answer. Fails
+    /// with NoSigningKey if the client has no verifying key, with InvalidSignature if the answer,
+    /// its tag, its build time or the params it was computed under were altered, and with Stale if
+    /// it is older than set_max_age() allows.
+    #[cfg(feature = "signing")]
+    pub fn recover_signed(&mut self, signed: &SignedAnswer) -> Result<Element, SimplePirError> {
+        self.check_signature(signed)?;
+        self.recover_tagged(&signed.tagged)
+    }
+
+    // Check the server's signature over an answer
+    #[cfg(feature = "signing")]
+    fn check_signature(&self, signed: &SignedAnswer) -> Result<(), SimplePirError> {
+        let key = self.verifying_key.as_ref().ok_or(SimplePirError::NoSigningKey)?;
+        signing::verify_answer(key, &self.params_digest, signed)?;
+        #[cfg(feature = "std")]
+        if let Some(max_age) = self.max_age {
+            signing::check_freshness(signed.built_at, unix_now(), max_age)?;
+        }
+        Ok(())
+    }
+
+    // Check that a tagged answer is for the outstanding query
+    fn check_tag(&self, tagged: &TaggedAnswer) -> Result<(), SimplePirError> {
+        let pending = self.pending.as_ref().ok_or(SimplePirError::NoPendingQuery)?;
+        if tagged.digest != answer_digest(&pending.query_id, tagged.epoch) {
+            return Err(SimplePirError::AnswerMismatch);
+        }
+        Ok(())
+    }
+
+    /// Generate one query per index, to be answered together by Server::answer_batch(). Each
+    /// query uses its own secret. Any previous batch which has not been recovered is discarded.
+    #[cfg(feature = "std")]
+    pub fn query_batch(&mut self, idxs: &[usize]) -> Result<Vec<Query>, SimplePirError> {
+        self.query_batch_with_rng(idxs, &mut StdRng::from_entropy())
+    }
+
+    /// Like query_batch(), but sampling the secrets and errors with the given RNG.
+    pub fn query_batch_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idxs: &[usize],
+        rng: &mut R,
+    ) -> Result<Vec<Query>, SimplePirError> {
+        self.pending_batch.clear();
+        let mut pending_batch = Vec::with_capacity(idxs.len());
+        let queries = idxs.iter().map(|&idx| {
+            let (_, row) = self.position(idx)?;
+            let (query, state) = self.finish_query(row, rng)?;
+            pending_batch.push(PendingQuery::new(idx, state, &query));
+            Ok(query)
+        }).collect::<Result<Vec<_>, SimplePirError>>()?;
+        self.pending_batch = pending_batch;
+        #[cfg(feature = "std")]
+        self.record_queries(&queries);
+        Ok(queries)
+    }
+
+    /// Recover the items requested by the outstanding batch, in the order they were queried.
+    pub fn recover_batch(&mut self, answers: &[Answer]) -> Result<Vec<Element>, SimplePirError> {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let pending = core::mem::take(&mut self.pending_batch);
+        if pending.is_empty() {
+            return Err(SimplePirError::NoPendingQuery);
+        }
+        if answers.len() != pending.len() {
+            return Err(SimplePirError::LengthMismatch {
+                expected: pending.len(),
+                actual: answers.len(),
+            });
+        }
+
+        let items = pending.into_iter().zip(answers).map(|(pending, answer)| {
+            let (col, _) = self.position(pending.idx)?;
+            let row = simplepir::recover_prepared_row(&self.params, pending.state, &answer.0)?;
+            Ok(row[col].clone())
+        }).collect::<Result<_, SimplePirError>>()?;
+        #[cfg(feature = "std")]
+        self.record_answers(start.elapsed(), answers);
+        Ok(items)
+    }
+
+    /// Generate a query for byte record idx. All of the record's limbs are returned in a single
+    /// answer. Clients without a record layout are refused with NoRecordLayout.
+    #[cfg(feature = "std")]
+    pub fn query_record(&mut self, idx: usize) -> Result<Query, SimplePirError> {
+        self.query_record_with_rng(idx, &mut StdRng::from_entropy())
+    }
+
+    /// Like query_record(), but sampling the secret and error with the given RNG.
+    pub fn query_record_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idx: usize,
+        rng: &mut R,
+    ) -> Result<Query, SimplePirError> {
+        let layout = self.layout.as_ref().ok_or(SimplePirError::NoRecordLayout)?;
+        if idx >= layout.num_records {
+            return Err(SimplePirError::IndexOutOfRange { idx, len: layout.num_records });
+        }
+        let (_, row) = layout.position(idx);
+        let (query, state) = self.finish_query(row, rng)?;
+        self.pending = Some(PendingQuery::new(idx, state, &query));
+        #[cfg(feature = "std")]
+        self.record_queries(slice::from_ref(&query));
+        Ok(query)
+    }
+
+    /// Recover the byte record requested by the outstanding query from the server's answer. With
+    /// a Merkle root, the authentication path is checked and stripped, and records which do not
+    /// match the root are rejected with Unauthenticated. Then any check bytes are checked and
+    /// stripped, and records which fail them are rejected with MalformedAnswer. Finally, with a
+    /// keyring, the record is opened as with recover_sealed_record() expecting a sealed record.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub fn recover_record(&mut self, answer: &Answer) -> Result<Vec<u8>, SimplePirError> {
+        let (idx, record) = self.recover_stored_record(answer)?;
+        #[cfg(feature = "encryption")]
+        if let Some(keyring) = &self.keyring {
+            return keyring.open(idx, &record, Expected::Sealed);
+        }
+        Ok(record)
+    }
+
+    /// Like recover_record(), but first checking the tag of the answer as recover_tagged() does.
+    pub fn recover_record_tagged(
+        &mut self,
+        tagged: &TaggedAnswer,
+    ) -> Result<Vec<u8>, SimplePirError> {
+        self.check_tag(tagged)?;
+        self.recover_record(&tagged.answer)
+    }
+
+    /// Like recover_record(), but first checking the signature and the tag of the answer as
+    /// recover_signed() does.
+    #[cfg(feature = "signing")]
+    pub fn recover_record_signed(
+        &mut self,
+        signed: &SignedAnswer,
+    ) -> Result<Vec<u8>, SimplePirError> {
+        self.check_signature(signed)?;
+        self.recover_record_tagged(&signed.tagged)
+    }
+
+    /// Like recover_record(), but opening the record with the keyring only if it is under the
+    /// expected key, such as the one the record was published under. Without a keyring, only
+    /// public records open.
+    #[cfg(feature = "encryption")]
+    pub fn recover_sealed_record(
+        &mut self,
+        answer: &Answer,
+        expected: Expected,
+    ) -> Result<Vec<u8>, SimplePirError> {
+        let (idx, record) = self.recover_stored_record(answer)?;
+        let empty = Keyring::new();
+        self.keyring.as_ref().unwrap_or(&empty).open(idx, &record, expected)
+    }
+
+    // Recover the outstanding record as stored, which may be sealed, along with its index
+    fn recover_stored_record(
+        &mut self,
+        answer: &Answer,
+    ) -> Result<(usize, Vec<u8>), SimplePirError> {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let layout = self.layout.as_ref().ok_or(SimplePirError::NoRecordLayout)?;
+        let pending = self.pending.take().ok_or(SimplePirError::NoPendingQuery)?;
+        let row = simplepir::recover_prepared_row(&self.params, pending.state, &answer.0)?;
+        let mut record = layout.decode_from_row(&row, pending.idx);
+        if let Some(root) = &self.root {
+            record = merkle::verify_record(root, layout.num_records, pending.idx, &record)?;
+        }
+        if self.check_len > 0 {
+            record = record::strip_check_bytes(&record, self.check_len)?;
+        }
+        #[cfg(feature = "std")]
+        self.record_answers(start.elapsed(), slice::from_ref(answer));
+        Ok((pending.idx, record))
+    }
+
+    /// Generate a query for the given byte range of record idx, for the server to answer with
+    /// Server::answer_range(). The answer holds only the limbs covering the range, for each record
+    /// in the queried row, so fetching a small field of a large record downloads a fraction of
+    /// the row. The server learns which bytes were requested, though not of which record.
+    ///
+    /// Ranges are of the records as stored, so they cannot be checked against a Merkle root or
+    /// check bytes, nor opened with a keyring. Clients which do any of these are refused with
+    /// UncheckableRange.
+    #[cfg(feature = "std")]
+    pub fn query_range(
+        &mut self,
+        idx: usize,
+        bytes: Range<usize>,
+    ) -> Result<RangeQuery, SimplePirError> {
+        self.query_range_with_rng(idx, bytes, &mut StdRng::from_entropy())
+    }
+
+    /// Like query_range(), but sampling the secret and error with the given RNG.
+    pub fn query_range_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idx: usize,
+        bytes: Range<usize>,
+        rng: &mut R,
+    ) -> Result<RangeQuery, SimplePirError> {
+        let layout = self.layout.as_ref().ok_or(SimplePirError::NoRecordLayout)?;
+        if self.root.is_some() || self.check_len > 0 {
+            return Err(SimplePirError::UncheckableRange);
+        }
+        #[cfg(feature = "encryption")]
+        if self.keyring.is_some() {
+            return Err(SimplePirError::UncheckableRange);
+        }
+        if idx >= layout.num_records {
+            return Err(SimplePirError::IndexOutOfRange { idx, len: layout.num_records });
+        }
+        if bytes.is_empty() {
+            return Err(SimplePirError::Empty("byte range"));
+        }
+        if bytes.end > layout.record_len {
+            return Err(SimplePirError::IndexOutOfRange { idx: bytes.end, len: layout.record_len });
+        }
+        let limbs = record::limb_range(bytes.clone(), self.params.p);
+        let (_, row) = layout.position(idx);
+        let (query, state) = self.finish_query(row, rng)?;
+        self.pending_range = Some((PendingQuery::new(idx, state, &query), bytes));
+        #[cfg(feature = "std")]
+        self.record_queries(slice::from_ref(&query));
+        Ok(RangeQuery { query, limbs })
+    }
+
+    /// Recover the byte range requested by the outstanding range query from the server's answer.
+    pub fn recover_range(&mut self, answer: &Answer) -> Result<Vec<u8>, SimplePirError> {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+        let layout = self.layout.as_ref().ok_or(SimplePirError::NoRecordLayout)?;
+        let (pending, bytes) = self.pending_range.take().ok_or(SimplePirError::NoPendingQuery)?;
+        let limbs = record::limb_range(bytes.clone(), self.params.p);
+        let cols = layout.range_columns(limbs.clone());
+        let entries =
+            simplepir::recover_prepared_cols(&self.params, pending.state, &cols, &answer.0)?;
+
+        // The entries hold the limbs of each record in the row in turn
+        let (col, _) = layout.position(pending.idx);
+        let first = col / layout.limbs * limbs.len();
+        let range = record::decode_range(&entries[first..first + limbs.len()], bytes);
+        #[cfg(feature = "std")]
+        self.record_answers(start.elapsed(), slice::from_ref(answer));
+        Ok(range)
+    }
+
+    /// Generate a query for byte record idx on a server set up with symmetric records, along with
+    /// the OPRF request for the server to answer with Server::answer_symmetric().
+    #[cfg(all(feature = "spir", feature = "std"))]
+    pub fn query_record_symmetric(
+        &mut self,
+        idx: usize,
+    ) -> Result<(Query, OprfRequest), SimplePirError> {
+        self.query_record_symmetric_with_rng(idx, &mut StdRng::from_entropy())
+    }
+
+    /// Like query_record_symmetric(), but sampling the secrets and errors with the given RNG.
+    #[cfg(feature = "spir")]
+    pub fn query_record_symmetric_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idx: usize,
+        rng: &mut R,
+    ) -> Result<(Query, OprfRequest), SimplePirError> {
+        let query = self.query_record_with_rng(idx, rng)?;
+        let (request, state) = spir::blind_with_rng(idx, rng);
+        self.pending_oprf = Some(state);
+        Ok((query, request))
+    }
+
+    /// Recover and unmask the byte record requested by the outstanding symmetric query.
+    #[cfg(feature = "spir")]
+    pub fn recover_record_symmetric(
+        &mut self,
+        answer: &Answer,
+        response: &OprfResponse,
+    ) -> Result<Vec<u8>, SimplePirError> {
+        let state = self.pending_oprf.take().ok_or(SimplePirError::NoPendingQuery)?;
+        let pad = spir::finalize(&state, response)?;
+        Ok(spir::apply_pad(&pad, &self.recover_record(answer)?))
+    }
+}
+
+/// A server for variable-length records, which are bucketed by size class as laid out by a
+/// VariableLayout, with one Server per bucket.
+pub struct BucketedServer {
+    layout: VariableLayout,
+    servers: Vec<Server>,
+}
+
+impl BucketedServer {
+    /// Pad the records into their buckets and set up the server of each. There must be one set
+    /// of params per bucket, each generated for the bucket's layout.rows rows, or they are rejected
+    /// with LengthMismatch. Records which do not match the layout are rejected as by
+    /// VariableLayout::pad_records().
+    pub fn setup(
+        params: Vec<SimplePIRParams>,
+        layout: VariableLayout,
+        records: &[Vec<u8>],
+    ) -> Result<Self, SimplePirError> {
+        if params.len() != layout.buckets.len() {
+            let expected = layout.buckets.len();
+            return Err(SimplePirError::LengthMismatch { expected, actual: params.len() });
+        }
+        let padded = layout.pad_records(records)?;
+        let servers = params.into_iter().zip(&layout.buckets).zip(&padded)
+            .map(|((params, bucket), records)| {
+                Server::setup_records(params, bucket.layout.clone(), records)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { layout, servers })
+    }
+
+    pub fn layout(&self) -> &VariableLayout {
+        &self.layout
+    }
+
+    /// The server of each bucket, whose params and hint clients need.
+    pub fn buckets(&self) -> &[Server] {
+        &self.servers
+    }
+
+    /// Answer the queries of a retrieval, one per bucket in the order of the buckets.
+    pub fn answer(&self, queries: &[Query]) -> Result<Vec<Answer>, SimplePirError> {
+        if queries.len() != self.servers.len() {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.servers.len(),
+                actual: queries.len(),
+            });
+        }
+        self.servers.iter().zip(queries).map(|(server, query)| server.answer(query)).collect()
+    }
+}
+
+/// The client side of a BucketedServer, with one Client per bucket. Every retrieval sends a query
+/// to every bucket: the one for the bucket of the queried record, and one for a random record in
+/// each other bucket, whose answer is dropped. The queries of a bucket are indistinguishable
+/// whichever record they are for, so the server does not learn the size class of the record.
+pub struct BucketedClient {
+    layout: VariableLayout,
+    clients: Vec<Client>,
+    // The bucket of the outstanding query
+    pending_bucket: Option<usize>,
+}
+
+impl BucketedClient {
+    /// Create a client from the layout and the params and hint of each bucket's server.
+    pub fn new(
+        layout: VariableLayout,
+        buckets: Vec<(SimplePIRParams, Hint)>,
+    ) -> Result<Self, SimplePirError> {
+        if buckets.len() != layout.buckets.len() {
+            return Err(SimplePirError::LengthMismatch {
+                expected: layout.buckets.len(),
+                actual: buckets.len(),
+            });
+        }
+        let clients = buckets.into_iter().zip(&layout.buckets)
+            .map(|((params, hint), bucket)| {
+                Client::with_layout(params, hint, bucket.layout.clone())
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { layout, clients, pending_bucket: None })
+    }
+
+    /// Generate the queries for record idx, one per bucket in the order of the buckets.
+    #[cfg(feature = "std")]
+    pub fn query_record(&mut self, idx: usize) -> Result<Vec<Query>, SimplePirError> {
+        self.query_record_with_rng(idx, &mut StdRng::from_entropy())
+    }
+
+    /// Like query_record(), but sampling the secrets, errors and dummy records with the given RNG.
+    pub fn query_record_with_rng<R: CryptoRng + RngCore>(
+        &mut self,
+        idx: usize,
+        rng: &mut R,
+    ) -> Result<Vec<Query>, SimplePirError> {
+        let (bucket, slot) = self.layout.locate(idx)?;
+        let queries = self.clients.iter_mut().zip(&self.layout.buckets).enumerate()
+            .map(|(i, (client, other))| {
+                let slot = if i == bucket {
+                    slot
+                } else {
+                    // A dummy query, for a random record of the bucket
+                    rng.gen_range(0..other.layout.num_records)
+                };
+                client.query_record_with_rng(slot, rng)
+            })
+            .collect::<Result<_, _>>()?;
+        self.pending_bucket = Some(bucket);
+        Ok(queries)
+    }
+
+    /// Recover the record requested by the outstanding query from the answers of every bucket,
+    /// exactly as it was before padding.
+    pub fn recover_record(&mut self, answers: &[Answer]) -> Result<Vec<u8>, SimplePirError> {
+        if answers.len() != self.clients.len() {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.clients.len(),
+                actual: answers.len(),
+            });
+        }
+        let bucket = self.pending_bucket.take().ok_or(SimplePirError::NoPendingQuery)?;
+        record::unpad_record(&self.clients[bucket].recover_record(&answers[bucket])?)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::record::PaddingPolicy;
+    use crate::regev::SecretDistribution;
+    use rand_chacha::ChaCha20Rng;
+
+    fn gen_items(db_size: usize, p: u64) -> Vec<Element> {
+        (0..db_size).map(|_| Element::gen_uniform_rand(p)).collect()
+    }
+
+    fn db_matrix(server: &Server) -> Matrix {
+        match &server.db {
+            Storage::Dense(db) => db.clone(),
+            Storage::Sparse(db) => db.to_matrix(),
+            Storage::BitPacked(db) => db.to_matrix(),
+            Storage::Backend(db) => db.chunk(0..db.len()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_client_server() {
+        let db_size = 50;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+
+        // A database of items has no records
+        assert_eq!(client.query_record(0), Err(SimplePirError::NoRecordLayout));
+        let answer = server.answer(&client.query(0).unwrap()).unwrap();
+        assert_eq!(client.recover_record(&answer), Err(SimplePirError::NoRecordLayout));
+        assert_eq!(client.query_range(0, 0..1), Err(SimplePirError::NoRecordLayout));
+        assert_eq!(client.recover_range(&answer), Err(SimplePirError::NoRecordLayout));
+        let query = RangeQuery { query: client.query(0).unwrap(), limbs: 0..1 };
+        assert_eq!(server.answer_range(&query), Err(SimplePirError::NoRecordLayout));
+    }
+
+    #[test]
+    fn test_params_mismatch() {
+        let params = SimplePIRParams::from_seed([1; 32], 3329, 2, 16, 8, 6.4);
+        let other = SimplePIRParams::from_seed([2; 32], 3329, 2, 16, 8, 6.4);
+        let server = Server::setup(params.clone(), &gen_items(64, 2)).unwrap();
+        let client = Client::new(params.clone(), server.hint().clone()).unwrap();
+        assert_eq!(client.check_params(server.params_digest()), Ok(()));
+        assert_eq!(server.check_params(client.params_digest()), Ok(()));
+
+        // A decoded without its seed has the same digest
+        let decoded = SimplePIRParams { seed: None, ..params.clone() };
+        assert_eq!(decoded.digest(), *server.params_digest());
+
+        let client = Client::new(other, server.hint().clone()).unwrap();
+        let mismatch = Err(SimplePirError::ParamsMismatch);
+        assert_eq!(client.check_params(server.params_digest()), mismatch);
+        assert_eq!(server.check_params(client.params_digest()), mismatch);
+        let params = SimplePIRParams { p: 4, ..params };
+        assert_ne!(params.digest(), *server.params_digest());
+    }
+
+    #[test]
+    fn test_public_hint() {
+        let db_size = 50;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+        let mut server = Server::setup(params, &items).unwrap();
+
+        let public = server.public_hint();
+        let digest = public.digest();
+        let bytes = public.to_bytes();
+        assert_eq!(PublicHint::from_bytes(&bytes), Ok(public.clone()));
+        assert_eq!(digest, server.public_hint().digest());
+        for len in 0..bytes.len() {
+            assert!(PublicHint::from_bytes(&bytes[..len]).is_err());
+        }
+
+        // Every client can start from the same copy
+        for idx in [0, 17, 49] {
+            let public = PublicHint::from_bytes(&bytes).unwrap();
+            let mut client = Client::from_public_hint(public).unwrap();
+            let answer = server.answer(&client.query(idx).unwrap()).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), items[idx]);
+        }
+
+        // The hint of another database is rejected
+        let hint = Hint(Matrix::gen_uniform_rand(public.params.q, 8, 3));
+        let other = PublicHint { hint, ..public.clone() };
+        assert_eq!(PublicHint::from_bytes(&other.to_bytes()), Err(wire::WireError::InvalidShape));
+
+        // So is a mismatched hint, layout or A handed to a client directly
+        let err = Some(SimplePirError::InvalidShape("hint"));
+        assert_eq!(Client::new(other.params.clone(), other.hint).err(), err);
+        let empty = PublicHint { hint: Hint(Matrix::new()), ..public.clone() };
+        assert_eq!(Client::from_public_hint(empty).err(), err);
+        let layout = RecordLayout::new(db_size, 1, 2).unwrap();
+        let client = Client::with_layout(public.params.clone(), public.hint.clone(), layout);
+        assert_eq!(client.err(), Some(SimplePirError::InvalidShape("record layout")));
+        let params = SimplePIRParams { a: Matrix::new(), ..public.params };
+        let err = Some(SimplePirError::InvalidShape("A matrix"));
+        assert_eq!(Client::new(params, public.hint).err(), err);
+
+        // Updates change the hint and so the digest
+        server.update_item(3, Element::from(2, 1 - items[3].uint)).unwrap();
+        assert_ne!(server.public_hint().digest(), digest);
+    }
+
+    #[test]
+    fn test_query_with_rng() {
+        let db_size = 20;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        // The same seed gives the same query
+        let query = client.query_with_rng(3, &mut ChaCha20Rng::seed_from_u64(1)).unwrap();
+        assert_eq!(client.query_with_rng(3, &mut ChaCha20Rng::seed_from_u64(1)).unwrap(), query);
+
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query_with_rng(idx, &mut rng).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+    }
+
+    #[test]
+    fn test_client_server_small_secrets() {
+        let db_size = 20;
+        let mut params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        params.secret_dist = SecretDistribution::Ternary;
+        let items = gen_items(db_size, params.p);
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_client_server_serde() {
+        let db_size = 20;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+        let server = Server::setup(params, &items).unwrap();
+
+        // Everything crossing the network goes through its serialized form
+        let params = serde_json::to_string(server.params()).unwrap();
+        let hint = serde_json::to_string(server.hint()).unwrap();
+        let mut client = Client::new(
+            serde_json::from_str(&params).unwrap(),
+            serde_json::from_str(&hint).unwrap(),
+        ).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = serde_json::to_string(&client.query(idx).unwrap()).unwrap();
+            let answer = server.answer(&serde_json::from_str(&query).unwrap()).unwrap();
+            let answer = serde_json::to_string(&answer).unwrap();
+            assert_eq!(client.recover(&serde_json::from_str(&answer).unwrap()).unwrap(), *item);
+        }
+    }
+
+    #[test]
+    fn test_client_server_compressed() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer_compressed(&query, 256).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+
+        // The answer must stay mod a modulus between p and q
+        let query = client.query(0).unwrap();
+        for q_prime in [server.params().q + 1, server.params().p] {
+            let err = Err(SimplePirError::InvalidModulus(q_prime));
+            assert_eq!(server.answer_compressed(&query, q_prime), err);
+        }
+    }
+
+    #[test]
+    fn test_client_server_compressed_query() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+        assert!(params.noise_budget_switched(db_size, 256).unwrap().is_guaranteed());
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query_compressed(idx, 256).unwrap();
+            let answer = server.answer_compressed_query(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+
+        // The query shrinks to 8 bits per entry, and moduli the server cannot use are rejected
+        let full = client.query(0).unwrap();
+        let compressed = client.query_compressed(0, 256).unwrap();
+        assert!(wire::encoded_query_len(&compressed) < wire::encoded_query_len(&full));
+        assert_eq!(client.query_compressed(0, 2), Err(SimplePirError::InvalidModulus(2)));
+        let lifted = Query(compressed.0.iter().map(|e| e.mod_switch(1 << 40)).collect());
+        assert!(matches!(
+            server.answer_compressed_query(&lifted),
+            Err(SimplePirError::ModulusMismatch { .. })
+        ));
+    }
+
+    #[cfg(feature = "packing")]
+    #[test]
+    fn test_client_server_packed() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 16);
+        let items = gen_items(db_size, params.p);
+        let packing = PackingParams::new(4, 16, 1.0);
+        assert!(packing.noise_budget(&params, db_size).is_guaranteed());
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        for idx in [0, 13, 29] {
+            let (query, key) = client.query_packed(idx, &packing).unwrap();
+            let packed = server.answer_packed(&query, &key).unwrap();
+            assert_eq!(packed.0.len(), 2);
+            assert_eq!(client.recover_packed(&packed).unwrap(), items[idx]);
+        }
+        assert!(client.recover_packed(&PackedAnswer(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_client_server_batch() {
+        let db_size = 40;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        let idxs = [0, 5, 17, 17, 39];
+        let queries = client.query_batch(&idxs).unwrap();
+        let answers = server.answer_batch(&queries).unwrap();
+        let recovered = client.recover_batch(&answers).unwrap();
+        for (i, idx) in idxs.iter().enumerate() {
+            assert_eq!(recovered[i], items[*idx]);
+        }
+    }
+
+    #[test]
+    fn test_client_server_updates() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let mut items = gen_items(db_size, params.p);
+
+        let mut server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        for idx in [0, 7, 29] {
+            items[idx] = Element::from(256, (items[idx].uint + 1) % 256);
+            let delta = server.update_item(idx, items[idx].clone()).unwrap();
+            assert_eq!(client.apply_hint_delta(&delta), Ok(true));
+        }
+        assert_eq!(server.hint(), &simplepir::gen_hint(server.params(), &db_matrix(&server)));
+
+        // Updates which do not fit the database are rejected
+        let len = server.num_items();
+        assert_eq!(
+            server.update_item(len, items[0].clone()),
+            Err(SimplePirError::IndexOutOfRange { idx: len, len }),
+        );
+        assert_eq!(
+            server.update_item(0, Element::zero(2)),
+            Err(SimplePirError::ModulusMismatch { expected: 256, actual: 2 }),
+        );
+        assert_eq!(server.update_record(0, &[0]), Err(SimplePirError::NoRecordLayout));
+
+        // So are deltas which do not fit the client's hint
+        let delta = HintDelta::Rows(vec![(len, vec![])]);
+        let num_cols = server.hint().0.num_cols();
+        assert_eq!(
+            client.apply_hint_delta(&delta),
+            Err(SimplePirError::IndexOutOfRange { idx: len, len: num_cols }),
+        );
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_client_server_database() {
+        use crate::database::FileDatabase;
+
+        let db_size = 300;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 991);
+        let items = gen_items(db_size, params.p);
+        let (db, hint) = simplepir::setup(&params, &items).unwrap();
+        let path = std::env::temp_dir().join(format!("simplepir-server-{}", std::process::id()));
+        FileDatabase::write(&path, &db).unwrap();
+
+        let file_db = FileDatabase::open(&path, params.p, params.m).unwrap();
+        let server = Server::from_database(params, file_db, hint).unwrap();
+        let mut client = Client::from_public_hint(server.public_hint()).unwrap();
+        for idx in [0, 150, 299] {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(answer.0, simplepir::answer(&query.0, &db).unwrap());
+            assert_eq!(client.recover(&answer).unwrap(), items[idx]);
+        }
+        let queries = client.query_batch(&[3, 4]).unwrap();
+        let recovered = client.recover_batch(&server.answer_batch(&queries).unwrap()).unwrap();
+        assert_eq!(recovered, vec![items[3].clone(), items[4].clone()]);
+
+        // Chunks of any size give the same answers
+        let mut server = server;
+        for cols in [1, 7, 1000] {
+            server.set_chunk_cols(cols);
+            let query = client.query(42).unwrap();
+            assert_eq!(client.recover(&server.answer(&query).unwrap()).unwrap(), items[42]);
+        }
+        let profile = server.autotune();
+        assert_eq!(server.chunk_cols(), profile.chunk_cols(server.params().m));
+        std::fs::remove_file(&path).unwrap();
+
+        // A database narrower than it is high holds fewer than m * m items
+        let params = simplepir::gen_insecure_test_params_for_width(16, 991);
+        let db = Matrix::gen_uniform_rand(params.p, 3, params.m);
+        let hint = simplepir::gen_hint(&params, &db);
+        let server = Server::from_database(params, db.clone(), hint).unwrap();
+        let mut client = Client::from_public_hint(server.public_hint()).unwrap();
+        assert_eq!(client.num_items(), 48);
+        let answer = server.answer(&client.query(47).unwrap()).unwrap();
+        assert_eq!(client.recover(&answer).unwrap(), db.get(2, 15));
+        let err = SimplePirError::IndexOutOfRange { idx: 48, len: 48 };
+        assert_eq!(client.query(48), Err(err));
+
+        // And a wider one holds more
+        let params = simplepir::gen_insecure_test_params_for_width(16, 991);
+        let db = Matrix::gen_uniform_rand(params.p, 20, params.m);
+        let hint = simplepir::gen_hint(&params, &db);
+        let server = Server::from_database(params, db.clone(), hint).unwrap();
+        let mut client = Client::from_public_hint(server.public_hint()).unwrap();
+        assert_eq!(client.num_items(), 320);
+        let answer = server.answer(&client.query(300).unwrap()).unwrap();
+        assert_eq!(client.recover(&answer).unwrap(), db.get(18, 12));
+        let queries = client.query_batch(&[319, 0]).unwrap();
+        let recovered = client.recover_batch(&server.answer_batch(&queries).unwrap()).unwrap();
+        assert_eq!(recovered, vec![db.get(19, 15), db.get(0, 0)]);
+    }
+
+    #[test]
+    fn test_setup_errors() {
+        // Items which do not fit the params
+        let params = simplepir::gen_insecure_test_params_for_width(4, 16);
+        let err = SimplePirError::IndexOutOfRange { idx: 16, len: 16 };
+        assert_eq!(Server::setup(params.clone(), &gen_items(17, 16)).err(), Some(err));
+        let err = SimplePirError::ModulusMismatch { expected: 16, actual: 17 };
+        assert_eq!(Server::setup(params, &gen_items(3, 17)).err(), Some(err));
+
+        // Records which do not match the layout, or a layout which does not match the params
+        let records: Vec<Vec<u8>> = (0..10).map(|i| vec![i as u8; 8]).collect();
+        let layout = RecordLayout::new(records.len(), 8, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let result = Server::setup_records(params.clone(), layout.clone(), &records[1..]);
+        let err = SimplePirError::LengthMismatch { expected: 10, actual: 9 };
+        assert_eq!(result.err(), Some(err));
+        let wide = simplepir::gen_insecure_test_params_for_width(layout.rows + 1, 256);
+        let result = Server::setup_records(wide, layout.clone(), &records);
+        let err = SimplePirError::LengthMismatch { expected: layout.rows, actual: layout.rows + 1 };
+        assert_eq!(result.err(), Some(err));
+        let result = Server::setup_authenticated_records(params, layout, &records);
+        let expected = merkle::authenticated_len(records.len(), 8);
+        assert_eq!(result.err(), Some(SimplePirError::LengthMismatch { expected, actual: 8 }));
+
+        // One set of params per bucket
+        let lengths: Vec<usize> = records.iter().map(|record| record.len()).collect();
+        let layout = VariableLayout::new(&lengths, &PaddingPolicy::Max, 256).unwrap();
+        let result = BucketedServer::setup(vec![], layout, &records);
+        let err = SimplePirError::LengthMismatch { expected: 1, actual: 0 };
+        assert_eq!(result.err(), Some(err));
+
+        // A hint which is not that of the database
+        let params = simplepir::gen_insecure_test_params_for_width(16, 991);
+        let db = Matrix::gen_uniform_rand(params.p, 3, params.m);
+        let hint = simplepir::gen_hint(&params, &Matrix::gen_uniform_rand(params.p, 4, params.m));
+        let result = Server::from_database(params, db, hint);
+        assert_eq!(result.err(), Some(SimplePirError::InvalidShape("hint")));
+    }
+
+    #[test]
+    fn test_client_server_sparse() {
+        let db_size = 100;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let mut items = vec![Element::zero(params.p); db_size];
+        for idx in [3, 50, 99] {
+            items[idx] = Element::from(params.p, idx as u64);
+        }
+
+        let mut server = Server::setup(params, &items).unwrap();
+        assert!(server.is_sparse());
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        for (idx, value) in [(3, 0), (4, 200), (99, 1)] {
+            items[idx] = Element::from(256, value);
+            let delta = server.update_item(idx, items[idx].clone()).unwrap();
+            assert_eq!(client.apply_hint_delta(&delta), Ok(true));
+        }
+        assert_eq!(server.hint(), &simplepir::gen_hint(server.params(), &db_matrix(&server)));
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+        let queries = client.query_batch(&[4, 50]).unwrap();
+        let answers = server.answer_batch(&queries).unwrap();
+        let recovered = client.recover_batch(&answers).unwrap();
+        assert_eq!(recovered, vec![items[4].clone(), items[50].clone()]);
+        let query = client.query(99).unwrap();
+        let answer = server.answer_compressed(&query, 1 << 24).unwrap();
+        assert_eq!(client.recover(&answer).unwrap(), items[99]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let db_size = 40;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+
+        let mut server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        assert_eq!((server.stats(), client.stats()), (None, None));
+        server.enable_stats();
+        client.enable_stats();
+
+        let query = client.query(3).unwrap();
+        let answer = server.answer(&query).unwrap();
+        client.recover(&answer).unwrap();
+        let queries = client.query_batch(&[1, 2]).unwrap();
+        let answers = server.answer_batch(&queries).unwrap();
+        client.recover_batch(&answers).unwrap();
+
+        let (server_stats, client_stats) = (server.stats().unwrap(), client.stats().unwrap());
+        assert!(server_stats.setup_time > Duration::ZERO);
+        assert_eq!((server_stats.num_answers(), client_stats.num_answers()), (3, 3));
+        let uploaded = wire::encode_query(&query).len() as u64 * 3;
+        let downloaded = wire::encode_answer(&answer).len() as u64 * 3;
+        for stats in [&server_stats, &client_stats] {
+            assert_eq!((stats.bytes_uploaded, stats.bytes_downloaded), (uploaded, downloaded));
+        }
+        let sizes = (query.wire_size() as u64 * 3, answer.wire_size() as u64 * 3);
+        assert_eq!(sizes, (uploaded, downloaded));
+        let hint_len = wire::encode_hint(server.hint()).len();
+        assert_eq!((server_stats.bytes_hint, client_stats.bytes_hint), (0, hint_len as u64));
+        assert_eq!(client_stats.total_bytes(), uploaded + downloaded + hint_len as u64);
+
+        // The single query and the batch each scanned the database once
+        let (m, _) = server.hint().0.dimensions();
+        assert_eq!(server_stats.items_scanned, 2 * (m * m) as u64);
+        assert!(server_stats.scan_throughput() > 0.0);
+    }
+
+    #[test]
+    fn test_query_log() {
+        let db_size = 40;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+        let mut server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        assert!(server.enable_query_log(-1.0).is_err());
+        server.enable_query_log(20.0).unwrap();
+
+        let queries = client.query_batch(&[1, 2, 3]).unwrap();
+        server.answer_batch(&queries).unwrap();
+        server.answer(&client.query(4).unwrap()).unwrap();
+        // Nothing is released until the epoch ends
+        assert_eq!(server.release_query_counts(), vec![]);
+
+        server.update_item(5, items[5].clone()).unwrap();
+        server.update_item(6, items[6].clone()).unwrap();
+        let counts = server.release_query_counts();
+        assert_eq!(counts.iter().map(|count| count.epoch).collect::<Vec<_>>(), vec![0, 1]);
+        assert!((counts[0].count - 4).abs() <= 1 && counts[1].count.abs() <= 1);
+        assert_eq!(server.release_query_counts(), vec![]);
+    }
+
+    #[test]
+    fn test_save_load_state() {
+        let path = std::env::temp_dir().join(format!("simplepir-state-{}", std::process::id()));
+        let records: Vec<Vec<u8>> = (0..10).map(|i| vec![i as u8; 16]).collect();
+        let layout = RecordLayout::new(records.len(), 16, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let mut server = Server::setup_records(params, layout, &records).unwrap();
+        server.update_record(3, &records[3]).unwrap();
+        server.save_state(&path).unwrap();
+
+        let loaded = Server::load_state(&path).unwrap();
+        assert_eq!((loaded.params(), loaded.hint()), (server.params(), server.hint()));
+        assert_eq!((loaded.layout(), loaded.epoch()), (server.layout(), 1));
+        assert_eq!(loaded.built_at(), server.built_at());
+        let mut client = Client::with_layout(
+            loaded.params().clone(),
+            loaded.hint().clone(),
+            loaded.layout().unwrap().clone(),
+        ).unwrap();
+        for (idx, record) in records.iter().enumerate() {
+            let query = client.query_record(idx).unwrap();
+            let answer = loaded.answer(&query).unwrap();
+            assert_eq!(client.recover_record(&answer).unwrap(), *record);
+        }
+
+        // Files from another version of the format are detected
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[8] = 3;
+        std::fs::write(&path, &bytes).unwrap();
+        let err = Server::load_state(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("version 3"));
+
+        bytes[8] = 5;
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Server::load_state(&path).is_err());
+        bytes.push(0);
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(Server::load_state(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        // Sections which do not fit together are rejected
+        let load = |hint: &Hint, layout: &RecordLayout, db: &Matrix| {
+            let sections = [
+                wire::encode_params(server.params()),
+                wire::encode_hint(hint),
+                wire::encode_layout(layout),
+                vec![],
+                0u64.to_le_bytes().to_vec(),
+                0u64.to_le_bytes().to_vec(),
+                wire::encode_matrix(db),
+            ];
+            let mut bytes = STATE_MAGIC.to_vec();
+            bytes.extend(STATE_VERSION.to_le_bytes());
+            for section in sections {
+                bytes.extend((section.len() as u64).to_le_bytes());
+                bytes.extend(section);
+            }
+            std::fs::write(&path, bytes).unwrap();
+            Server::load_state(&path).map(|_| ()).map_err(|err| err.kind())
+        };
+        let (hint, layout, db) = (server.hint(), server.layout().unwrap(), db_matrix(&server));
+        assert_eq!(load(hint, layout, &db), Ok(()));
+        let mut wrong_p = db.clone();
+        wrong_p.change_q(257);
+        assert_eq!(load(hint, layout, &wrong_p), Err(io::ErrorKind::InvalidData));
+        let wrong_q = Hint(hint.0.mod_switch(1 << 20));
+        assert_eq!(load(&wrong_q, layout, &db), Err(io::ErrorKind::InvalidData));
+        let wrong_rows = RecordLayout { rows: layout.rows + 1, ..layout.clone() };
+        assert_eq!(load(hint, &wrong_rows, &db), Err(io::ErrorKind::InvalidData));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_client_prepare() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let mut items = gen_items(db_size, params.p);
+
+        let mut server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        client.prepare(3).unwrap();
+        assert_eq!(client.num_prepared(), 3);
+
+        // Updates reach both prepared queries and the outstanding one
+        let query = client.query(12).unwrap();
+        items[12] = Element::from(256, (items[12].uint + 1) % 256);
+        let delta = server.update_item(12, items[12].clone()).unwrap();
+        assert_eq!(client.apply_hint_delta(&delta), Ok(true));
+        let answer = server.answer(&query).unwrap();
+        assert_eq!(client.recover(&answer).unwrap(), items[12]);
+
+        for idx in [0, 12, 29] {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), items[idx]);
+        }
+        assert_eq!(client.num_prepared(), 0);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_client_prepare_with_threads() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        client.prepare_with_threads(4, 2).unwrap();
+        assert_eq!(client.num_prepared(), 4);
+
+        // Every prepared query has its own secret
+        let mut queries: Vec<Query> = vec![];
+        for idx in [3, 3, 17, 29] {
+            let query = client.query(idx).unwrap();
+            assert!(queries.iter().all(|other| other.0 != query.0));
+            queries.push(query.clone());
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), items[idx]);
+        }
+        assert_eq!(client.num_prepared(), 0);
+    }
+
+    #[test]
+    fn test_self_test() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+        let mut server = Server::setup(params, &items).unwrap();
+        server.enable_stats();
+        let mean = server.self_test(5, Duration::from_secs(60)).unwrap();
+        assert!(mean < Duration::from_secs(60));
+        assert_eq!(server.stats().unwrap().num_answers(), 0);
+
+        let err = server.self_test(5, Duration::ZERO);
+        assert!(matches!(err, Err(SimplePirError::SlowSelfTest { limit: Duration::ZERO, .. })));
+
+        // A hint which does not match the database fails the retrievals
+        let mut hint = server.hint().clone();
+        hint.0.set(0, 0, &(hint.0.get(0, 0) + Element::from(hint.0.q(), 1 << 20)));
+        let broken = Server { hint, ..server };
+        let err = broken.self_test(20, Duration::from_secs(60));
+        assert_eq!(err, Err(SimplePirError::MalformedAnswer));
+    }
+
+    #[test]
+    fn test_arena_size() {
+        let db_size = 100;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+        let mut server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        assert_eq!(server.arena_size(), DEFAULT_ARENA_BYTES);
+
+        // Answers are the same whether the temporaries fit in the arena or not
+        for bytes in [DEFAULT_ARENA_BYTES, 0, 8] {
+            server.set_arena_size(bytes);
+            for idx in [0, 37, 99] {
+                let answer = server.answer(&client.query(idx).unwrap()).unwrap();
+                assert_eq!(client.recover(&answer).unwrap(), items[idx]);
+            }
+            // One arena serves the answers one after another
+            assert_eq!(server.arenas.lock().unwrap().len(), 1);
+        }
+
+        let db = db_matrix(&server);
+        let dense = Server { db: Storage::Dense(db), ..server };
+        let answer = dense.answer(&client.query(5).unwrap()).unwrap();
+        assert_eq!(client.recover(&answer).unwrap(), items[5]);
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn test_signed_answers() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+        let mut server = Server::setup(params, &items).unwrap();
+        let mut unkeyed = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        let query = unkeyed.query(3).unwrap();
+        assert_eq!(server.answer_signed(&query).err(), Some(SimplePirError::NoSigningKey));
+        assert_eq!(server.manifest().err(), Some(SimplePirError::NoSigningKey));
+        assert_eq!(server.public_hint().verifying_key, None);
+
+        // The verifying key travels in the public hint
+        server.set_signing_key(&[7; 32]);
+        let key = server.verifying_key().unwrap();
+        let public = PublicHint::from_bytes(&server.public_hint().to_bytes()).unwrap();
+        assert_eq!(public.verifying_key, Some(key));
+        let mut client = Client::from_public_hint(public).unwrap();
+        for idx in [0, 13, 29] {
+            let signed = server.answer_signed(&client.query(idx).unwrap()).unwrap();
+            let signed = wire::decode_signed_answer(&wire::encode_signed_answer(&signed)).unwrap();
+            assert_eq!(client.recover_signed(&signed), Ok(items[idx].clone()));
+        }
+
+        // Altering the answer, its tag or the signature is detected
+        let signed = server.answer_signed(&client.query(5).unwrap()).unwrap();
+        let mut tampered = signed.clone();
+        let entry = tampered.tagged.answer.0.get(0, 0);
+        tampered.tagged.answer.0.set(0, 0, &(entry + Element::from(server.params().q, 1)));
+        assert_eq!(client.recover_signed(&tampered), Err(SimplePirError::InvalidSignature));
+        let mut tampered = signed.clone();
+        tampered.tagged.epoch += 1;
+        assert_eq!(client.recover_signed(&tampered), Err(SimplePirError::InvalidSignature));
+        let mut tampered = signed.clone();
+        tampered.built_at += 1;
+        assert_eq!(client.recover_signed(&tampered), Err(SimplePirError::InvalidSignature));
+        let mut tampered = signed.clone();
+        tampered.signature[0] ^= 1;
+        assert_eq!(client.recover_signed(&tampered), Err(SimplePirError::InvalidSignature));
+        assert_eq!(client.recover_signed(&signed), Ok(items[5].clone()));
+
+        // A client without the key cannot check the signature
+        assert_eq!(unkeyed.recover_signed(&signed), Err(SimplePirError::NoSigningKey));
+        unkeyed.set_verifying_key(key);
+        let signed = server.answer_signed(&query).unwrap();
+        assert_eq!(unkeyed.recover_signed(&signed), Ok(items[3].clone()));
+
+        // The manifest signs the digest of the public hint
+        let manifest = server.manifest().unwrap();
+        assert_eq!(manifest.digest, server.public_hint().digest());
+        assert_eq!(wire::decode_manifest(&wire::encode_manifest(&manifest)), Ok(manifest.clone()));
+        assert_eq!(manifest.verify(&key), Ok(()));
+        let forged = crate::signing::SignedManifest { epoch: 1, ..manifest.clone() };
+        assert_eq!(forged.verify(&key), Err(SimplePirError::InvalidSignature));
+        // Nor does a manifest signature pass for an answer signature
+        let mut swapped = signed.clone();
+        swapped.signature = manifest.signature;
+        assert_eq!(unkeyed.recover_signed(&swapped), Err(SimplePirError::InvalidSignature));
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn test_freshness() {
+        let day = Duration::from_secs(24 * 3600);
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+        let mut server = Server::setup(params, &items).unwrap();
+        server.set_signing_key(&[7; 32]);
+        let now = server.built_at();
+        assert!(now > 0);
+
+        // Answers from a database built within the limit are accepted
+        let mut client = Client::from_public_hint(server.public_hint()).unwrap();
+        client.set_max_age(day);
+        let signed = server.answer_signed(&client.query(4).unwrap()).unwrap();
+        assert_eq!(signed.built_at, now);
+        assert_eq!(client.recover_signed(&signed), Ok(items[4].clone()));
+
+        // And those built two days ago rejected, while the query stays outstanding
+        server.set_built_at(now - 2 * 24 * 3600);
+        let query = client.query(4).unwrap();
+        let stale = server.answer_signed(&query).unwrap();
+        assert!(matches!(
+            client.recover_signed(&stale),
+            Err(SimplePirError::Stale { max_age, .. }) if max_age == day,
+        ));
+        server.set_built_at(now);
+        let fresh = server.answer_signed(&query).unwrap();
+        assert_eq!(client.recover_signed(&fresh), Ok(items[4].clone()));
+
+        // An update rebuilds the database
+        server.set_built_at(1);
+        server.update_item(0, items[1].clone()).unwrap();
+        assert!(server.built_at() >= now);
+
+        // The manifest attests to the build time too
+        let key = server.verifying_key().unwrap();
+        let manifest = server.manifest().unwrap();
+        assert_eq!(manifest.verify_fresh(&key, now + 3600, day), Ok(()));
+        assert!(manifest.verify_fresh(&key, manifest.built_at + 2 * 24 * 3600, day).is_err());
+        let forged = crate::signing::SignedManifest { built_at: now + 3600, ..manifest };
+        assert_eq!(forged.verify_fresh(&key, now, day), Err(SimplePirError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_tagged_answers() {
+        let db_size = 30;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let mut items = gen_items(db_size, params.p);
+        let mut server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+        let mut other = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        let query = client.query(5).unwrap();
+        let tagged = server.answer_tagged(&query).unwrap();
+        assert_eq!((tagged.epoch, tagged.digest), (0, answer_digest(&query.id(), 0)));
+        // A query sent again within the epoch gets the same answer
+        assert_eq!(server.answer_tagged(&query), Ok(tagged.clone()));
+
+        // The answer to another client's query is dropped without losing the outstanding query
+        let misrouted = server.answer_tagged(&other.query(9).unwrap()).unwrap();
+        assert_eq!(client.recover_tagged(&misrouted), Err(SimplePirError::AnswerMismatch));
+        let forged = TaggedAnswer { epoch: 1, ..tagged.clone() };
+        assert_eq!(client.recover_tagged(&forged), Err(SimplePirError::AnswerMismatch));
+        assert_eq!(client.recover_tagged(&tagged), Ok(items[5].clone()));
+        assert_eq!(client.recover_tagged(&tagged), Err(SimplePirError::NoPendingQuery));
+
+        // A duplicate of an earlier answer does not match the next query
+        let query = client.query(5).unwrap();
+        assert_eq!(client.recover_tagged(&tagged), Err(SimplePirError::AnswerMismatch));
+
+        items[5] = Element::from(256, (items[5].uint + 1) % 256);
+        let delta = server.update_item(5, items[5].clone()).unwrap();
+        assert_eq!(server.epoch(), 1);
+        assert_eq!(client.apply_hint_delta(&delta), Ok(true));
+        let tagged = server.answer_tagged(&query).unwrap();
+        assert_eq!(tagged.epoch, 1);
+        assert_eq!(client.recover_tagged(&tagged), Ok(items[5].clone()));
+    }
+
+    #[test]
+    fn test_client_server_record_updates() {
+        let mut records: Vec<Vec<u8>> = (0..10).map(|i| vec![i as u8; 16]).collect();
+        let layout = RecordLayout::new(records.len(), 16, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let mut server = Server::setup_records(params, layout, &records).unwrap();
+        let mut client = Client::with_layout(
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().unwrap().clone(),
+        ).unwrap();
+
+        records[4] = vec![0xab; 16];
+        let delta = server.update_record(4, &records[4]).unwrap();
+        assert_eq!(client.apply_hint_delta(&delta), Ok(true));
+
+        assert_eq!(
+            server.update_record(10, &records[4]),
+            Err(SimplePirError::IndexOutOfRange { idx: 10, len: 10 }),
+        );
+        assert_eq!(
+            server.update_record(4, &[0; 15]),
+            Err(SimplePirError::LengthMismatch { expected: 16, actual: 15 }),
+        );
+        assert_eq!(server.update_item(0, Element::zero(256)), Err(SimplePirError::HasRecordLayout));
+
+        for (idx, record) in records.iter().enumerate() {
+            let query = client.query_record(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover_record(&answer).unwrap(), *record);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_client_server_parallel() {
+        let db_size = 50;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+
+        let mut server = Server::setup(params, &items).unwrap();
+        server.set_threads(4);
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_client_server_gpu() {
+        let db_size = 50;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let mut items = gen_items(db_size, params.p);
+
+        let mut server = Server::setup(params, &items).unwrap();
+        if !server.enable_gpu() {
+            eprintln!("no GPU available, answering on the CPU");
+        }
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        items[7] = Element::from(256, (items[7].uint + 1) % 256);
+        let delta = server.update_item(7, items[7].clone()).unwrap();
+        assert_eq!(client.apply_hint_delta(&delta), Ok(true));
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_setup_with_threads() {
+        let db_size = 50;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+        let server = Server::setup(params.clone(), &items).unwrap();
+        for num_threads in [1, 4] {
+            let threaded = Server::setup_with_threads(params.clone(), &items, num_threads).unwrap();
+            assert_eq!(threaded.hint(), server.hint());
+        }
+
+        let records: Vec<Vec<u8>> = (0..10).map(|i| vec![i as u8; 16]).collect();
+        let layout = RecordLayout::new(records.len(), 16, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let server = Server::setup_records(params.clone(), layout.clone(), &records).unwrap();
+        let threaded = Server::setup_records_with_threads(params, layout, &records, 3).unwrap();
+        assert_eq!(threaded.hint(), server.hint());
+    }
+
+    #[test]
+    fn test_client_server_multi_bit() {
+        let db_size = 20;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 256);
+        let items = gen_items(db_size, params.p);
+
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+    }
+
+    #[test]
+    fn test_client_server_records() {
+        for (p, record_len) in [(256, 32), (991, 32), (2, 4)] {
+            let num_records = 12;
+            let records: Vec<Vec<u8>> = (0..num_records)
+                .map(|i| (0..record_len).map(|j| (i * 37 + j * 11) as u8).collect())
+                .collect();
+
+            let layout = RecordLayout::new(num_records, record_len, p).unwrap();
+            let params = simplepir::gen_insecure_test_params_for_width(layout.rows, p);
+            let server = Server::setup_records(params, layout, &records).unwrap();
+            let mut client = Client::with_layout(
+                server.params().clone(),
+                server.hint().clone(),
+                server.layout().unwrap().clone(),
+            ).unwrap();
+
+            for (idx, record) in records.iter().enumerate() {
+                let query = client.query_record(idx).unwrap();
+                let answer = server.answer(&query).unwrap();
+                assert_eq!(client.recover_record(&answer).unwrap(), *record);
+            }
+        }
+    }
+
+    #[test]
+    fn test_client_server_ranges() {
+        for (p, density) in [(256, 1), (991, 1), (991, 0)] {
+            let num_records = 40;
+            let records: Vec<Vec<u8>> = (0..num_records)
+                .map(|i| (0..64).map(|j| (i * 37 + j * 11) as u8 * density).collect())
+                .collect();
+            let layout = RecordLayout::new(num_records, 64, p).unwrap();
+            let params = simplepir::gen_insecure_test_params_for_width(layout.rows, p);
+            let server = Server::setup_records(params, layout.clone(), &records).unwrap();
+            let mut client = Client::with_layout(
+                server.params().clone(),
+                server.hint().clone(),
+                server.layout().unwrap().clone(),
+            ).unwrap();
+
+            for (idx, bytes) in [(0, 0..64), (7, 10..20), (39, 63..64)] {
+                let query = client.query_range(idx, bytes.clone()).unwrap();
+                let answer = server.answer_range(&query).unwrap();
+                assert_eq!(answer.0.num_cols(), layout.records_per_row() * query.limbs.len());
+                assert_eq!(client.recover_range(&answer).unwrap(), records[idx][bytes]);
+            }
+            assert!(client.query_range(0, 60..65).is_err());
+
+            let mut query = client.query_range(0, 0..1).unwrap();
+            query.limbs = 0..layout.limbs + 1;
+            assert!(server.answer_range(&query).is_err());
+
+            // Clients which check whole records cannot query ranges
+            client.set_check_bytes(4);
+            assert!(matches!(client.query_range(0, 0..1), Err(SimplePirError::UncheckableRange)));
+        }
+    }
+
+    #[test]
+    fn test_client_server_variable_records() {
+        let records: Vec<Vec<u8>> = [3, 0, 40, 9, 12, 40, 1, 20]
+            .iter()
+            .map(|&len| (0..len).map(|j| (len * 13 + j) as u8).collect())
+            .collect();
+        let lengths: Vec<usize> = records.iter().map(|record| record.len()).collect();
+        for policy in [PaddingPolicy::Max, PaddingPolicy::PowerOfTwo] {
+            let layout = VariableLayout::new(&lengths, &policy, 256).unwrap();
+            let params = layout.buckets.iter()
+                .map(|bucket| simplepir::gen_insecure_test_params_for_width(bucket.layout.rows, 256))
+                .collect();
+            let server = BucketedServer::setup(params, layout.clone(), &records).unwrap();
+            let buckets = server.buckets().iter()
+                .map(|server| (server.params().clone(), server.hint().clone()))
+                .collect();
+            let num_buckets = layout.buckets.len();
+            let mut client = BucketedClient::new(layout, buckets).unwrap();
+
+            // Every retrieval sends the server one query of the same shape per bucket, whichever
+            // record it is for
+            let shape = |queries: &[Query]| -> Vec<(usize, u64)> {
+                queries.iter().map(|query| (query.0.len(), query.0[0].q)).collect()
+            };
+            let mut shapes = vec![];
+            for (idx, record) in records.iter().enumerate() {
+                let queries = client.query_record(idx).unwrap();
+                assert_eq!(queries.len(), num_buckets);
+                shapes.push(shape(&queries));
+                let answers = server.answer(&queries).unwrap();
+                assert_eq!(client.recover_record(&answers).unwrap(), *record);
+            }
+            assert!(shapes.iter().all(|queries| *queries == shapes[0]));
+            assert!(client.query_record(records.len()).is_err());
+
+            // A retrieval needs the queries and answers of every bucket
+            let queries = client.query_record(0).unwrap();
+            let result = server.answer(&queries[1..]);
+            assert!(matches!(result, Err(SimplePirError::LengthMismatch { .. })));
+            let answers = server.answer(&queries).unwrap();
+            let result = client.recover_record(&answers[1..]);
+            assert!(matches!(result, Err(SimplePirError::LengthMismatch { .. })));
+        }
+    }
+
+    #[test]
+    fn test_client_server_authenticated_records() {
+        let records: Vec<Vec<u8>> = (0..10).map(|i| vec![i as u8 * 3; 8]).collect();
+        let record_len = merkle::authenticated_len(records.len(), 8);
+        let layout = RecordLayout::new(records.len(), record_len, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let mut server = Server::setup_authenticated_records(params, layout, &records).unwrap();
+        let mut client = Client::with_merkle_root(
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().unwrap().clone(),
+            *server.merkle_root().unwrap(),
+        ).unwrap();
+        for (idx, record) in records.iter().enumerate() {
+            let query = client.query_record(idx).unwrap();
+            let answer = server.answer(&query).unwrap();
+            assert_eq!(client.recover_record(&answer).unwrap(), *record);
+        }
+
+        assert_eq!(
+            server.update_record(4, &records[4]),
+            Err(SimplePirError::ReadOnly("authenticated records")),
+        );
+
+        // A server which modifies a record is caught, even if it serves a hint to match
+        let (col, row) = server.layout().unwrap().position(4);
+        server.set(col, row, Element::from(256, 1));
+        assert_eq!(client.apply_hint_delta(&server.apply_update(&[col])), Ok(true));
+        let query = client.query_record(4).unwrap();
+        let answer = server.answer(&query).unwrap();
+        assert_eq!(client.recover_record(&answer), Err(SimplePirError::Unauthenticated));
+    }
+
+    #[test]
+    fn test_client_server_check_bytes() {
+        let records: Vec<Vec<u8>> =
+            (0..10).map(|i| record::add_check_bytes(&[i as u8; 8], 4)).collect();
+        let layout = RecordLayout::new(records.len(), 12, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let mut server = Server::setup_records(params, layout, &records).unwrap();
+        let mut client = Client::with_layout(
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().unwrap().clone(),
+        ).unwrap();
+        client.set_check_bytes(4);
+        let query = client.query_record(7).unwrap();
+        let answer = server.answer(&query).unwrap();
+        assert_eq!(client.recover_record(&answer).unwrap(), vec![7; 8]);
+
+        // A modified record decrypts fine, but fails its check bytes
+        let delta = server.update_record(7, &[1; 12]).unwrap();
+        assert_eq!(client.apply_hint_delta(&delta), Ok(true));
+        let query = client.query_record(7).unwrap();
+        let answer = server.answer(&query).unwrap();
+        assert_eq!(client.recover_record(&answer), Err(SimplePirError::MalformedAnswer));
+    }
+
+    #[cfg(feature = "spir")]
+    #[test]
+    fn test_client_server_symmetric_records() {
+        let records: Vec<Vec<u8>> = (0..40).map(|i| vec![i as u8; 4]).collect();
+        let layout = RecordLayout::new(records.len(), 4, 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let server = Server::setup_symmetric_records(params, layout.clone(), &records).unwrap();
+        let mut client = Client::with_layout(
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().unwrap().clone(),
+        ).unwrap();
+        for (idx, record) in records.iter().enumerate() {
+            let (query, request) = client.query_record_symmetric(idx).unwrap();
+            let (answer, response) = server.answer_symmetric(&query, &request).unwrap();
+            assert_eq!(client.recover_record_symmetric(&answer, &response).unwrap(), *record);
+        }
+
+        // Servers without symmetric records refuse symmetric queries
+        let params = server.params().clone();
+        let plain = Server::setup_records(params, layout.clone(), &records).unwrap();
+        let (query, request) = client.query_record_symmetric(0).unwrap();
+        let result = plain.answer_symmetric(&query, &request);
+        assert_eq!(result.map(|_| ()), Err(SimplePirError::NoSymmetricRecords));
+
+        // The other records of the queried row stay masked
+        let (query, _) = client.query_record_symmetric(0).unwrap();
+        let answer = server.answer(&query).unwrap();
+        let other = layout.rows;
+        assert_eq!(layout.position(other).1, layout.position(0).1);
+        let row = simplepir::recover_prepared_row(
+            server.params(),
+            client.pending.take().unwrap().state,
+            &answer.0,
+        ).unwrap();
+        assert_ne!(layout.decode_from_row(&row, other), records[other]);
+        assert!(server.save_state(std::env::temp_dir().join("simplepir-spir-state")).is_err());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_client_server_sealed_records() {
+        use crate::encryption::{self, RecordKey, PUBLIC};
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let key = RecordKey::gen_with_rng(&mut rng);
+        // Every third record is encrypted
+        let records: Vec<Vec<u8>> = (0..12).map(|i| vec![i as u8; 8]).collect();
+        let sealed: Vec<Vec<u8>> = records.iter().enumerate().map(|(idx, record)| {
+            let key = (idx % 3 == 0).then_some((7, &key));
+            encryption::seal(idx, record, key, &mut rng)
+        }).collect();
+        let layout = RecordLayout::new(records.len(), encryption::sealed_len(8), 256).unwrap();
+        let params = simplepir::gen_insecure_test_params_for_width(layout.rows, 256);
+        let server = Server::setup_records(params, layout, &sealed).unwrap();
+
+        let mut client = Client::with_layout(
+            server.params().clone(),
+            server.hint().clone(),
+            server.layout().unwrap().clone(),
+        ).unwrap();
+        client.set_keyring(encryption::Keyring::new());
+        let mut recover = |client: &mut Client, idx| {
+            let query = client.query_record_with_rng(idx, &mut rng).unwrap();
+            client.recover_record(&server.answer(&query).unwrap())
+        };
+        assert_eq!(recover(&mut client, 3), Err(SimplePirError::UnknownRecordKey(7)));
+        // Public records are only opened when the client expects them to be public
+        assert_eq!(recover(&mut client, 1), Err(SimplePirError::UnexpectedRecordKey(PUBLIC)));
+
+        let mut keyring = encryption::Keyring::new();
+        keyring.insert(7, key.clone());
+        client.set_keyring(keyring);
+        let mut recover_sealed = |client: &mut Client, idx, expected| {
+            let query = client.query_record_with_rng(idx, &mut rng).unwrap();
+            client.recover_sealed_record(&server.answer(&query).unwrap(), expected)
+        };
+        for (idx, record) in records.iter().enumerate() {
+            let expected = if idx % 3 == 0 { Expected::Key(7) } else { Expected::Public };
+            assert_eq!(recover_sealed(&mut client, idx, expected).as_ref(), Ok(record));
+        }
+        let result = recover_sealed(&mut client, 4, Expected::Sealed);
+        assert_eq!(result, Err(SimplePirError::UnexpectedRecordKey(PUBLIC)));
+        let result = recover_sealed(&mut client, 6, Expected::Key(8));
+        assert_eq!(result, Err(SimplePirError::UnexpectedRecordKey(7)));
+        assert_eq!(recover_sealed(&mut client, 6, Expected::Sealed).as_ref(), Ok(&records[6]));
+    }
+
+    #[test]
+    fn test_malformed_messages() {
+        let params = simplepir::gen_insecure_test_params_for_size(16, 2);
+        let (m, q) = (params.m, params.q);
+        let items = gen_items(16, params.p);
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        // Queries which do not match the params are rejected by the server
+        let short = Query(vec![Element::zero(q); m - 1]);
+        assert_eq!(
+            server.answer(&short),
+            Err(SimplePirError::LengthMismatch { expected: m, actual: m - 1 }),
+        );
+        let wrong_q = Query(vec![Element::zero(q - 2); m]);
+        assert_eq!(
+            server.answer(&wrong_q),
+            Err(SimplePirError::ModulusMismatch { expected: q, actual: q - 2 }),
+        );
+        let mut mixed = vec![Element::zero(q); m];
+        mixed[1] = Element::zero(q - 2);
+        assert!(server.answer(&Query(mixed)).is_err());
+        assert_eq!(server.answer(&Query(vec![])), Err(SimplePirError::Empty("query")));
+
+        assert_eq!(client.query(16), Err(SimplePirError::IndexOutOfRange { idx: 16, len: 16 }));
+
+        // Answers which do not match the hint are rejected by the client
+        let query = client.query(5).unwrap();
+        let answer = server.answer(&query).unwrap();
+        let mut truncated = answer.clone();
+        truncated.0.pop_col();
+        assert!(client.recover(&truncated).is_err());
+
+        let query = client.query(5).unwrap();
+        let answer = server.answer(&query).unwrap();
+        assert_eq!(client.recover(&answer), Ok(items[5].clone()));
+    }
+
+    #[test]
+    fn test_recover_without_query() {
+        let params = simplepir::gen_insecure_test_params_for_size(4, 2);
+        let items = gen_items(4, params.p);
+        let server = Server::setup(params, &items).unwrap();
+        let mut client = Client::new(server.params().clone(), server.hint().clone()).unwrap();
+
+        let query = client.query(1).unwrap();
+        let answer = server.answer(&query).unwrap();
+        client.recover(&answer).unwrap();
+        assert_eq!(client.recover(&answer), Err(SimplePirError::NoPendingQuery));
+    }
+}
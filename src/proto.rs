@@ -0,0 +1,381 @@
+//! Protobuf messages matching proto/simplepir.proto, with conversions to and from the crate's
+//! types. Non-Rust clients can generate their own bindings from the .proto file and interoperate
+//! with a Rust server.
+
+use std::fmt::{Display, Formatter};
+
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::{matrix, pir, regev, simplepir};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Vector {
+    #[prost(uint64, tag = "1")]
+    pub q: u64,
+    #[prost(uint64, repeated, tag = "2")]
+    pub values: Vec<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Matrix {
+    #[prost(uint64, tag = "1")]
+    pub q: u64,
+    #[prost(uint64, tag = "2")]
+    pub cols: u64,
+    #[prost(uint64, tag = "3")]
+    pub rows: u64,
+    #[prost(uint64, repeated, tag = "4")]
+    pub values: Vec<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SecretDistribution {
+    #[prost(enumeration = "secret_distribution::Kind", tag = "1")]
+    pub kind: i32,
+    #[prost(double, tag = "2")]
+    pub std_dev: f64,
+}
+
+pub mod secret_distribution {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Kind {
+        Uniform = 0,
+        Binary = 1,
+        Ternary = 2,
+        Gaussian = 3,
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Params {
+    #[prost(message, optional, tag = "1")]
+    pub a: Option<Matrix>,
+    #[prost(uint64, tag = "2")]
+    pub q: u64,
+    #[prost(uint64, tag = "3")]
+    pub p: u64,
+    #[prost(uint64, tag = "4")]
+    pub n: u64,
+    #[prost(uint64, tag = "5")]
+    pub m: u64,
+    #[prost(double, tag = "6")]
+    pub std_dev: f64,
+    #[prost(message, optional, tag = "7")]
+    pub secret_dist: Option<SecretDistribution>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Query {
+    #[prost(message, optional, tag = "1")]
+    pub query: Option<Vector>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub params_digest: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Answer {
+    #[prost(message, optional, tag = "1")]
+    pub answer: Option<Matrix>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Hint {
+    #[prost(message, optional, tag = "1")]
+    pub hint: Option<Matrix>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HintChunk {
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Empty {}
+
+/// Why a protobuf message could not be converted.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ProtoError {
+    // A required message field is not set
+    MissingField(&'static str),
+    // The modulus is not a valid element modulus
+    InvalidModulus(u64),
+    // A value is not below the modulus
+    OutOfRange(u64),
+    // The dimensions do not match the number of values or the params
+    InvalidShape,
+    // The secret distribution kind is unknown
+    InvalidSecretDistribution(i32),
+}
+
+impl From<SimplePirError> for ProtoError {
+    fn from(err: SimplePirError) -> Self {
+        match err {
+            SimplePirError::InvalidModulus(q) => ProtoError::InvalidModulus(q),
+            SimplePirError::OutOfRange { uint, .. } => ProtoError::OutOfRange(uint),
+            _ => ProtoError::InvalidShape,
+        }
+    }
+}
+
+impl Display for ProtoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtoError::MissingField(name) => write!(f, "the field {} is missing", name),
+            ProtoError::InvalidModulus(q) => write!(f, "{} is not a valid modulus", q),
+            ProtoError::OutOfRange(v) => write!(f, "the value {} is out of range", v),
+            ProtoError::InvalidShape => write!(f, "the dimensions are inconsistent"),
+            ProtoError::InvalidSecretDistribution(k) => {
+                write!(f, "{} is not a secret distribution", k)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtoError {}
+
+fn to_elements(q: u64, values: &[u64]) -> Result<Vec<Element>, ProtoError> {
+    if q < 2 || q == u64::MAX {
+        return Err(ProtoError::InvalidModulus(q));
+    }
+    values.iter().map(|&v| {
+        if v >= q {
+            return Err(ProtoError::OutOfRange(v));
+        }
+        Ok(Element::from(q, v))
+    }).collect()
+}
+
+fn to_usize(v: u64) -> Result<usize, ProtoError> {
+    usize::try_from(v).map_err(|_| ProtoError::InvalidShape)
+}
+
+impl From<&[Element]> for Vector {
+    fn from(v: &[Element]) -> Self {
+        Self {
+            q: v.first().map_or(0, |e| e.q),
+            values: v.iter().map(|e| e.uint).collect(),
+        }
+    }
+}
+
+impl TryFrom<Vector> for Vec<Element> {
+    type Error = ProtoError;
+
+    fn try_from(v: Vector) -> Result<Self, Self::Error> {
+        if v.values.is_empty() {
+            return Ok(vec![]);
+        }
+        to_elements(v.q, &v.values)
+    }
+}
+
+impl From<&matrix::Matrix> for Matrix {
+    fn from(m: &matrix::Matrix) -> Self {
+        let (cols, rows) = m.dimensions();
+        if cols * rows == 0 {
+            return Self::default();
+        }
+        Self {
+            q: m.q(),
+            cols: cols as u64,
+            rows: rows as u64,
+            values: m.values().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<Matrix> for matrix::Matrix {
+    type Error = ProtoError;
+
+    fn try_from(m: Matrix) -> Result<Self, Self::Error> {
+        if m == Matrix::default() {
+            return Ok(matrix::Matrix::new());
+        }
+        let len = m.cols.checked_mul(m.rows).ok_or(ProtoError::InvalidShape)?;
+        if m.rows == 0 || len != m.values.len() as u64 {
+            return Err(ProtoError::InvalidShape);
+        }
+
+        let vals = to_elements(m.q, &m.values)?;
+        Ok(matrix::Matrix::from_flat(to_usize(m.cols)?, to_usize(m.rows)?, vals))
+    }
+}
+
+impl From<regev::SecretDistribution> for SecretDistribution {
+    fn from(dist: regev::SecretDistribution) -> Self {
+        use secret_distribution::Kind;
+        let (kind, std_dev) = match dist {
+            regev::SecretDistribution::Uniform => (Kind::Uniform, 0.0),
+            regev::SecretDistribution::Binary => (Kind::Binary, 0.0),
+            regev::SecretDistribution::Ternary => (Kind::Ternary, 0.0),
+            regev::SecretDistribution::Gaussian(std_dev) => (Kind::Gaussian, std_dev),
+        };
+        Self { kind: kind as i32, std_dev }
+    }
+}
+
+impl TryFrom<SecretDistribution> for regev::SecretDistribution {
+    type Error = ProtoError;
+
+    fn try_from(dist: SecretDistribution) -> Result<Self, Self::Error> {
+        use secret_distribution::Kind;
+        let kind = Kind::try_from(dist.kind)
+            .map_err(|_| ProtoError::InvalidSecretDistribution(dist.kind))?;
+        Ok(match kind {
+            Kind::Uniform => regev::SecretDistribution::Uniform,
+            Kind::Binary => regev::SecretDistribution::Binary,
+            Kind::Ternary => regev::SecretDistribution::Ternary,
+            Kind::Gaussian => regev::SecretDistribution::Gaussian(dist.std_dev),
+        })
+    }
+}
+
+impl From<&simplepir::SimplePIRParams> for Params {
+    fn from(params: &simplepir::SimplePIRParams) -> Self {
+        Self {
+            a: Some(Matrix::from(&params.a)),
+            q: params.q,
+            p: params.p,
+            n: params.n as u64,
+            m: params.m as u64,
+            std_dev: params.std_dev,
+            secret_dist: Some(params.secret_dist.into()),
+        }
+    }
+}
+
+impl TryFrom<Params> for simplepir::SimplePIRParams {
+    type Error = ProtoError;
+
+    fn try_from(params: Params) -> Result<Self, Self::Error> {
+        let a: matrix::Matrix = params.a.ok_or(ProtoError::MissingField("a"))?.try_into()?;
+        let (n, m) = (to_usize(params.n)?, to_usize(params.m)?);
+        let secret_dist = match params.secret_dist {
+            Some(dist) => dist.try_into()?,
+            None => regev::SecretDistribution::Uniform,
+        };
+        let (q, p, std_dev) = (params.q, params.p, params.std_dev);
+        let params = Self { a, q, p, n, m, std_dev, secret_dist, seed: None };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+impl From<&pir::Query> for Query {
+    fn from(query: &pir::Query) -> Self {
+        Self { query: Some(query.0.as_slice().into()), params_digest: vec![] }
+    }
+}
+
+impl TryFrom<Query> for pir::Query {
+    type Error = ProtoError;
+
+    fn try_from(query: Query) -> Result<Self, Self::Error> {
+        let query = query.query.ok_or(ProtoError::MissingField("query"))?;
+        Ok(pir::Query(query.try_into()?))
+    }
+}
+
+impl From<&pir::Answer> for Answer {
+    fn from(answer: &pir::Answer) -> Self {
+        Self { answer: Some((&answer.0).into()) }
+    }
+}
+
+impl TryFrom<Answer> for pir::Answer {
+    type Error = ProtoError;
+
+    fn try_from(answer: Answer) -> Result<Self, Self::Error> {
+        let answer = answer.answer.ok_or(ProtoError::MissingField("answer"))?;
+        Ok(pir::Answer(answer.try_into()?))
+    }
+}
+
+impl From<&simplepir::Hint> for Hint {
+    fn from(hint: &simplepir::Hint) -> Self {
+        Self { hint: Some((&hint.0).into()) }
+    }
+}
+
+impl TryFrom<Hint> for simplepir::Hint {
+    type Error = ProtoError;
+
+    fn try_from(hint: Hint) -> Result<Self, Self::Error> {
+        let hint = hint.hint.ok_or(ProtoError::MissingField("hint"))?;
+        Ok(simplepir::Hint(hint.try_into()?))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn test_vector_encoding() {
+        // The bytes a protoc-generated parser expects: a varint q and packed varint values
+        let v = Vector::from(&[Element::from(3329, 1), Element::from(3329, 2)][..]);
+        assert_eq!(v.encode_to_vec(), vec![0x08, 0x81, 0x1a, 0x12, 0x02, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_client_server() {
+        let db_size = 20;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items: Vec<Element> = (0..db_size).map(|_| Element::gen_uniform_rand(2)).collect();
+        let server = pir::Server::setup(params, &items).unwrap();
+
+        let params = Params::from(server.params()).encode_to_vec();
+        let hint = Hint::from(server.hint()).encode_to_vec();
+        let params: simplepir::SimplePIRParams =
+            Params::decode(params.as_slice()).unwrap().try_into().unwrap();
+        assert_eq!(&params, server.params());
+        let hint = Hint::decode(hint.as_slice()).unwrap().try_into().unwrap();
+        let mut client = pir::Client::new(params, hint).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let query = Query::from(&client.query(idx).unwrap()).encode_to_vec();
+            let query = Query::decode(query.as_slice()).unwrap().try_into().unwrap();
+            let answer = Answer::from(&server.answer(&query).unwrap()).encode_to_vec();
+            let answer = Answer::decode(answer.as_slice()).unwrap().try_into().unwrap();
+            assert_eq!(client.recover(&answer).unwrap(), *item);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = pir::Query::try_from(Query { query: None, params_digest: vec![] });
+        assert_eq!(err, Err(ProtoError::MissingField("query")));
+
+        let v = Vector { q: 3329, values: vec![3329] };
+        let query = Query { query: Some(v), params_digest: vec![] };
+        assert_eq!(pir::Query::try_from(query), Err(ProtoError::OutOfRange(3329)));
+
+        let v = Vector { q: 1, values: vec![0] };
+        assert_eq!(Vec::<Element>::try_from(v), Err(ProtoError::InvalidModulus(1)));
+
+        let m = Matrix { q: 3329, cols: 2, rows: 2, values: vec![1, 2, 3] };
+        assert_eq!(matrix::Matrix::try_from(m), Err(ProtoError::InvalidShape));
+        let m = Matrix { q: 3329, cols: u64::MAX, rows: 2, values: vec![] };
+        assert_eq!(matrix::Matrix::try_from(m), Err(ProtoError::InvalidShape));
+
+        let dist = SecretDistribution { kind: 7, std_dev: 0.0 };
+        let err = regev::SecretDistribution::try_from(dist);
+        assert_eq!(err, Err(ProtoError::InvalidSecretDistribution(7)));
+
+        let mut params = Params::from(&simplepir::gen_insecure_test_params());
+        params.n += 1;
+        let err = simplepir::SimplePIRParams::try_from(params.clone());
+        assert_eq!(err, Err(ProtoError::InvalidShape));
+
+        // Params are checked as by SimplePIRParams::validate(), so A cannot be left out
+        params.n -= 1;
+        let empty = Params { a: Some(Matrix::default()), ..params.clone() };
+        let err = simplepir::SimplePIRParams::try_from(empty);
+        assert_eq!(err, Err(ProtoError::InvalidShape));
+        let err = simplepir::SimplePIRParams::try_from(Params { std_dev: f64::NAN, ..params });
+        assert_eq!(err, Err(ProtoError::InvalidShape));
+    }
+}
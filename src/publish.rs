@@ -0,0 +1,263 @@
+//! Publishing public hints as immutable, content-addressed files, so that the bulk of what a client
+//! downloads before querying can be hosted on a CDN or IPFS rather than by the query server.
+//!
+//! publish() writes the params (with only the seed of A, if they were constructed from one), the
+//! hint and the record layout each to a file named by the hex SHA-256 digest of its contents, and
+//! then a manifest naming them along with the epoch and the verifying key, itself named by its
+//! digest. A file is never rewritten once published, so every name can be cached forever. The
+//! client obtains the digest of the current manifest from the query server (or checks the public
+//! hint against a signed manifest) and passes it to load(), which checks every file it fetches
+//! against its name.
+//!
+//! A manifest is encoded as a version byte, the epoch (u64), the 32-byte digests of the params and
+//! hint files, then a byte which is 1 if the layout digest follows and 0 otherwise, and the same
+//! way for the 32-byte verifying key.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::merkle::Digest;
+use crate::pir::PublicHint;
+use crate::wire::{self, WireError};
+
+/// The version of the manifest encoding, to be bumped whenever it changes.
+pub const MANIFEST_VERSION: u8 = 1;
+
+/// The files which make up a published public hint in a given epoch, by digest.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HintManifest {
+    pub epoch: u64,
+    pub params: Digest,
+    pub hint: Digest,
+    pub layout: Option<Digest>,
+    pub verifying_key: Option<[u8; 32]>,
+}
+
+impl HintManifest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![MANIFEST_VERSION];
+        out.extend_from_slice(&self.epoch.to_le_bytes());
+        out.extend_from_slice(&self.params);
+        out.extend_from_slice(&self.hint);
+        for field in [self.layout, self.verifying_key] {
+            match field {
+                Some(bytes) => {
+                    out.push(1);
+                    out.extend_from_slice(&bytes);
+                }
+                None => out.push(0),
+            }
+        }
+        out
+    }
+
+    /// Decode a manifest encoded by to_bytes(), rejecting other versions of the format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        let mut rest = bytes;
+        let mut take = |len: usize| {
+            if rest.len() < len {
+                return Err(WireError::Truncated);
+            }
+            let (head, tail) = rest.split_at(len);
+            rest = tail;
+            Ok(head)
+        };
+        let version = take(1)?[0];
+        if version != MANIFEST_VERSION {
+            return Err(WireError::UnsupportedVersion(version));
+        }
+        let epoch = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let params = take(32)?.try_into().unwrap();
+        let hint = take(32)?.try_into().unwrap();
+        let mut optional = || match take(1)?[0] {
+            0 => Ok(None),
+            1 => Ok(Some(<[u8; 32]>::try_from(take(32)?).unwrap())),
+            _ => Err(WireError::InvalidShape),
+        };
+        let layout = optional()?;
+        let verifying_key = optional()?;
+        if !rest.is_empty() {
+            return Err(WireError::TrailingBytes);
+        }
+        Ok(Self { epoch, params, hint, layout, verifying_key })
+    }
+
+    /// The SHA-256 digest of to_bytes(), which names the manifest's file.
+    pub fn digest(&self) -> Digest {
+        Sha256::digest(self.to_bytes()).into()
+    }
+}
+
+/// The name of the file holding the contents with the given digest: the digest in lowercase hex.
+pub fn file_name(digest: &Digest) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Write the public hint of the given epoch to the directory, which must exist, and return the
+/// digest of its manifest. Files which are already published are left alone.
+pub fn publish<P: AsRef<Path>>(dir: P, public: &PublicHint, epoch: u64) -> io::Result<Digest> {
+    let dir = dir.as_ref();
+    let put = |digest: &Digest, contents: &[u8]| write_file(dir, digest, contents);
+    Ok(publish_with(put, public, epoch)?.digest())
+}
+
+/// Publish the public hint of the given epoch by passing each file to put along with its digest,
+/// the manifest last, and return the manifest.
+pub fn publish_with<F>(mut put: F, public: &PublicHint, epoch: u64) -> io::Result<HintManifest>
+where
+    F: FnMut(&Digest, &[u8]) -> io::Result<()>,
+{
+    let mut put_digested = |contents: &[u8]| {
+        let digest: Digest = Sha256::digest(contents).into();
+        put(&digest, contents).map(|_| digest)
+    };
+    let layout = match &public.layout {
+        Some(layout) => Some(put_digested(&wire::encode_layout(layout))?),
+        None => None,
+    };
+    let manifest = HintManifest {
+        epoch,
+        params: put_digested(&wire::encode_params(&public.params))?,
+        hint: put_digested(&wire::encode_hint(&public.hint))?,
+        layout,
+        verifying_key: public.verifying_key,
+    };
+    put_digested(&manifest.to_bytes())?;
+    Ok(manifest)
+}
+
+// Write the contents to the file in dir named by their digest, unless it exists. The contents are
+// written to a temporary file first and renamed, so that a file is either absent or whole.
+pub(crate) fn write_file(dir: &Path, digest: &Digest, contents: &[u8]) -> io::Result<()> {
+    let path = dir.join(file_name(digest));
+    if !path.exists() {
+        let tmp = dir.join(format!(".{}.{}", file_name(digest), std::process::id()));
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &path)?;
+    }
+    Ok(())
+}
+
+/// Load the public hint published in the directory under the manifest with the given digest,
+/// along with the manifest. See load_with().
+pub fn load<P: AsRef<Path>>(dir: P, manifest: &Digest) -> io::Result<(HintManifest, PublicHint)> {
+    let dir = dir.as_ref();
+    load_with(|name| fs::read(dir.join(name)), manifest)
+}
+
+/// Load a published public hint, fetching each file by name with fetch (e.g. from a CDN). Every
+/// file is checked against the digest it is named by, and the public hint is validated, so that
+/// files which do not match the manifest or do not make a valid hint are rejected with an
+/// InvalidData error.
+pub fn load_with<F>(mut fetch: F, manifest: &Digest) -> io::Result<(HintManifest, PublicHint)>
+where
+    F: FnMut(&str) -> io::Result<Vec<u8>>,
+{
+    load_digested(|digest| fetch(&file_name(digest)), manifest)
+}
+
+/// As load_with(), fetching each file by its digest rather than its name.
+pub fn load_digested<F>(mut fetch: F, manifest: &Digest) -> io::Result<(HintManifest, PublicHint)>
+where
+    F: FnMut(&Digest) -> io::Result<Vec<u8>>,
+{
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let wire_err = |err: WireError| invalid(err.to_string());
+    let mut fetch_checked = |digest: &Digest| {
+        let contents = fetch(digest)?;
+        if Sha256::digest(&contents).as_slice() != digest {
+            return Err(invalid(format!("the file {} does not match its name", file_name(digest))));
+        }
+        Ok(contents)
+    };
+
+    let manifest = HintManifest::from_bytes(&fetch_checked(manifest)?).map_err(wire_err)?;
+    let params = wire::decode_params(&fetch_checked(&manifest.params)?).map_err(wire_err)?;
+    let hint = wire::decode_hint(&fetch_checked(&manifest.hint)?).map_err(wire_err)?;
+    let layout = match &manifest.layout {
+        Some(digest) => Some(wire::decode_layout(&fetch_checked(digest)?).map_err(wire_err)?),
+        None => None,
+    };
+    let public = PublicHint { params, hint, layout, verifying_key: manifest.verifying_key };
+    public.validate().map_err(|err| invalid(err.to_string()))?;
+    Ok((manifest, public))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::element::Element;
+    use crate::pir::{Client, Server};
+    use crate::record::RecordLayout;
+    use crate::simplepir::SimplePIRParams;
+
+    // A fresh directory to publish into
+    fn publish_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("simplepir-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_publish_and_load() {
+        let params = SimplePIRParams::from_seed([7; 32], 1 << 32, 256, 64, 16, 6.4);
+        let records: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 12]).collect();
+        let layout = RecordLayout::new(records.len(), 12, params.p).unwrap();
+        let server = Server::setup_records(params.clone(), layout, &records).unwrap();
+        let public = server.public_hint();
+
+        let dir = publish_dir("publish");
+        let digest = publish(&dir, &public, server.epoch()).unwrap();
+        // Publishing again writes nothing new
+        assert_eq!(publish(&dir, &public, server.epoch()).unwrap(), digest);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+
+        // Only the seed of A is published
+        let (manifest, loaded) = load(&dir, &digest).unwrap();
+        let params_file = fs::read(dir.join(file_name(&manifest.params))).unwrap();
+        assert!(params_file.len() < 100);
+        assert_eq!(manifest.digest(), digest);
+        assert_eq!(manifest.epoch, 0);
+        assert_eq!(loaded.digest(), public.digest());
+
+        let mut client = Client::from_public_hint(loaded).unwrap();
+        let answer = server.answer(&client.query_record(5).unwrap()).unwrap();
+        assert_eq!(client.recover_record(&answer).unwrap(), records[5]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_rejects_tampering() {
+        let params = SimplePIRParams::from_seed([7; 32], 1 << 32, 256, 64, 16, 6.4);
+        let items: Vec<Element> = (0..64).map(|_| Element::gen_uniform_rand(256)).collect();
+        let server = Server::setup(params, &items).unwrap();
+        let dir = publish_dir("tamper");
+        let digest = publish(&dir, &server.public_hint(), 3).unwrap();
+        let (manifest, _) = load(&dir, &digest).unwrap();
+        assert_eq!((manifest.epoch, manifest.layout), (3, None));
+
+        // A hint file which does not match its name
+        let path = dir.join(file_name(&manifest.hint));
+        let mut hint = fs::read(&path).unwrap();
+        *hint.last_mut().unwrap() ^= 1;
+        fs::write(&path, &hint).unwrap();
+        assert_eq!(load(&dir, &digest).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A manifest which is missing
+        assert_eq!(load(&dir, &[0; 32]).unwrap_err().kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let manifest = HintManifest { verifying_key: Some([9; 32]), ..manifest };
+        assert_eq!(HintManifest::from_bytes(&manifest.to_bytes()), Ok(manifest.clone()));
+        let mut bytes = manifest.to_bytes();
+        bytes[0] = 2;
+        assert_eq!(HintManifest::from_bytes(&bytes), Err(WireError::UnsupportedVersion(2)));
+        bytes.pop();
+        assert!(HintManifest::from_bytes(&bytes[1..]).is_err());
+    }
+}
@@ -0,0 +1,481 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
+use sha2::{Digest, Sha256};
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::matrix::Matrix;
+
+/// The number of bits packed into each limb mod p, i.e. ⌊log2(p)⌋.
+pub fn bits_per_limb(p: u64) -> usize {
+    assert!(p >= 2);
+    (u64::BITS - 1 - p.leading_zeros()) as usize
+}
+
+/// The number of limbs mod p needed to store a record of record_len bytes.
+pub fn limbs_per_record(record_len: usize, p: u64) -> usize {
+    (record_len * 8).div_ceil(bits_per_limb(p))
+}
+
+/// Split a record into limbs mod p, packing the bits of the record (least significant first)
+/// into ⌊log2(p)⌋ bits per limb.
+pub fn encode_record(record: &[u8], p: u64) -> Vec<Element> {
+    let bits = bits_per_limb(p);
+    let mut limbs = vec![0u64; limbs_per_record(record.len(), p)];
+    for i in 0..record.len() * 8 {
+        let bit = (record[i / 8] >> (i % 8)) & 1;
+        limbs[i / bits] |= (bit as u64) << (i % bits);
+    }
+    limbs.into_iter().map(|limb| Element::from(p, limb)).collect()
+}
+
+/// Reassemble a record of record_len bytes from its limbs.
+pub fn decode_record(limbs: &[Element], record_len: usize) -> Vec<u8> {
+    assert!(!limbs.is_empty());
+    let p = limbs[0].q;
+    let bits = bits_per_limb(p);
+    assert_eq!(limbs.len(), limbs_per_record(record_len, p));
+
+    let mut record = vec![0u8; record_len];
+    for i in 0..record_len * 8 {
+        let bit = (limbs[i / bits].uint >> (i % bits)) & 1;
+        record[i / 8] |= (bit as u8) << (i % 8);
+    }
+    record
+}
+
+/// The limbs mod p which hold the given byte range of a record.
+pub fn limb_range(bytes: Range<usize>, p: u64) -> Range<usize> {
+    assert!(bytes.start < bytes.end);
+    let bits = bits_per_limb(p);
+    bytes.start * 8 / bits..(bytes.end * 8).div_ceil(bits)
+}
+
+/// Reassemble a byte range of a record from the limbs returned by limb_range().
+pub fn decode_range(limbs: &[Element], bytes: Range<usize>) -> Vec<u8> {
+    assert!(!limbs.is_empty());
+    let p = limbs[0].q;
+    let bits = bits_per_limb(p);
+    let first = limb_range(bytes.clone(), p).start;
+    assert_eq!(limbs.len(), limb_range(bytes.clone(), p).len());
+
+    let mut range = vec![0u8; bytes.len()];
+    for i in 0..bytes.len() * 8 {
+        let bit_idx = bytes.start * 8 + i;
+        let bit = (limbs[bit_idx / bits - first].uint >> (bit_idx % bits)) & 1;
+        range[i / 8] |= (bit as u8) << (i % 8);
+    }
+    range
+}
+
+/// Append check_len bytes of redundancy to a record, for strip_check_bytes() to check after the
+/// record was retrieved. The check bytes are the start of the SHA-256 of the record. A server
+/// which shifts the limbs of a column shifts them in every row, and unlike with a linear checksum
+/// the shift of the check bytes which would hide this differs from record to record.
+pub fn add_check_bytes(record: &[u8], check_len: usize) -> Vec<u8> {
+    assert!(check_len <= 32);
+    let mut checked = record.to_vec();
+    checked.extend_from_slice(&Sha256::digest(record)[..check_len]);
+    checked
+}
+
+/// Check and remove the check bytes appended by add_check_bytes(), rejecting records whose
+/// check bytes do not match as malformed answers.
+pub fn strip_check_bytes(checked: &[u8], check_len: usize) -> Result<Vec<u8>, SimplePirError> {
+    assert!(check_len <= 32);
+    if checked.len() < check_len {
+        return Err(SimplePirError::LengthMismatch { expected: check_len, actual: checked.len() });
+    }
+    let (record, check) = checked.split_at(checked.len() - check_len);
+    if Sha256::digest(record)[..check_len] != *check {
+        return Err(SimplePirError::MalformedAnswer);
+    }
+    Ok(record.to_vec())
+}
+
+// The number of database rows for num_records records of `limbs` limbs each, which keeps the
+// database roughly square. The number of limbs in the database is counted in u64, as it can
+// overflow a 32-bit usize while the number of rows, its square root, does not.
+pub(crate) fn num_rows(num_records: usize, limbs: usize) -> usize {
+    let total = num_records as u64 * limbs as u64;
+    let mut rows = (total as f64).sqrt() as u64;
+    while rows * rows < total {
+        rows += 1;
+    }
+    rows.min(num_records as u64) as usize
+}
+
+/// Where fixed-length records are stored in the database. Each record occupies `limbs`
+/// consecutive columns of a single row, so that one query retrieves all of its limbs.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordLayout {
+    // The number of records in the database
+    pub num_records: usize,
+    // The length of each record in bytes
+    pub record_len: usize,
+    // The number of limbs mod p per record
+    pub limbs: usize,
+    // The number of database rows, which is the length of a query
+    pub rows: usize,
+}
+
+impl RecordLayout {
+    /// Choose a layout which keeps the database roughly square. Layouts without records or of
+    /// empty records are rejected, as is a plaintext modulus below 2.
+    pub fn new(num_records: usize, record_len: usize, p: u64) -> Result<Self, SimplePirError> {
+        if num_records == 0 {
+            return Err(SimplePirError::Empty("database"));
+        }
+        if record_len == 0 {
+            return Err(SimplePirError::Empty("record"));
+        }
+        if p < 2 {
+            return Err(SimplePirError::InvalidModulus(p));
+        }
+        let limbs = limbs_per_record(record_len, p);
+        let rows = num_rows(num_records, limbs);
+
+        Ok(Self { num_records, record_len, limbs, rows })
+    }
+
+    pub fn records_per_row(&self) -> usize {
+        self.num_records.div_ceil(self.rows)
+    }
+
+    /// The number of database columns, which is the length of an answer.
+    pub fn num_cols(&self) -> usize {
+        self.records_per_row() * self.limbs
+    }
+
+    /// The (first column, row) position of record idx.
+    pub fn position(&self, idx: usize) -> (usize, usize) {
+        assert!(idx < self.num_records);
+        ((idx / self.rows) * self.limbs, idx % self.rows)
+    }
+
+    /// The database columns which hold the given limbs of every record in a row, record by
+    /// record. Answering only these columns returns the limbs of whichever record was queried,
+    /// while revealing which limbs were requested but not which record.
+    pub fn range_columns(&self, limbs: Range<usize>) -> Vec<usize> {
+        assert!(limbs.end <= self.limbs);
+        (0..self.records_per_row())
+            .flat_map(|k| limbs.clone().map(move |limb| k * self.limbs + limb))
+            .collect()
+    }
+
+    /// Encode the records into the database matrix, filling unused positions with zeros. There
+    /// must be num_records records of record_len bytes each, or they are rejected with
+    /// LengthMismatch.
+    pub fn encode_db(&self, records: &[Vec<u8>], p: u64) -> Result<Matrix, SimplePirError> {
+        if records.len() != self.num_records {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.num_records,
+                actual: records.len(),
+            });
+        }
+        let mut db = Matrix::from_val(self.num_cols(), self.rows, Element::zero(p));
+        for (idx, record) in records.iter().enumerate() {
+            if record.len() != self.record_len {
+                return Err(SimplePirError::LengthMismatch {
+                    expected: self.record_len,
+                    actual: record.len(),
+                });
+            }
+            let (col, row) = self.position(idx);
+            for (i, limb) in encode_record(record, p).into_iter().enumerate() {
+                db.set(col + i, row, &limb);
+            }
+        }
+        Ok(db)
+    }
+
+    /// Extract and decode record idx from a recovered database row.
+    pub fn decode_from_row(&self, row: &[Element], idx: usize) -> Vec<u8> {
+        assert_eq!(row.len(), self.num_cols());
+        let (col, _) = self.position(idx);
+        decode_record(&row[col..col + self.limbs], self.record_len)
+    }
+}
+
+/// The number of bytes of the length header stored in front of each variable-length record.
+pub const LEN_HEADER: usize = 4;
+
+/// How variable-length records are padded into size classes. Each size class is stored as its
+/// own database (a bucket) of fixed-length records, and every retrieval queries every bucket, so
+/// the server does not learn the size class of the retrieved record. More classes pad less but
+/// cost a query and an answer per class: Max pads every record to the longest, so there is a
+/// single bucket.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PaddingPolicy {
+    // Pad every record to the length of the longest
+    Max,
+    // Pad each record to the next power of two
+    PowerOfTwo,
+    // Pad each record to the smallest of the given lengths which holds it
+    Classes(Vec<usize>),
+}
+
+impl PaddingPolicy {
+    // The length of the size class of a record of len bytes, given the longest record
+    fn class_len(&self, len: usize, max_len: usize) -> Result<usize, SimplePirError> {
+        match self {
+            PaddingPolicy::Max => Ok(max_len.max(1)),
+            PaddingPolicy::PowerOfTwo => Ok(len.max(1).next_power_of_two()),
+            PaddingPolicy::Classes(classes) => {
+                let class = classes.iter().filter(|&&class| class >= len.max(1)).min();
+                let capacity = classes.iter().max().copied().unwrap_or(0);
+                class.copied().ok_or(SimplePirError::TooManyBytes { len, capacity })
+            }
+        }
+    }
+}
+
+/// A bucket of records padded to the same size class.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bucket {
+    // The length records in the bucket are padded to
+    pub class_len: usize,
+    // The layout of the bucket's database, whose records hold the length header as well
+    pub layout: RecordLayout,
+}
+
+/// Where variable-length records are stored: the buckets of each size class, and which bucket
+/// and slot each record is in. This is public metadata, which clients need to query records.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VariableLayout {
+    pub buckets: Vec<Bucket>,
+    // The (bucket, slot) of each record
+    pub slots: Vec<(usize, usize)>,
+}
+
+impl VariableLayout {
+    /// Bucket records of the given lengths according to the padding policy. Records longer than
+    /// every class of the policy are rejected with TooManyBytes.
+    pub fn new(
+        lengths: &[usize],
+        policy: &PaddingPolicy,
+        p: u64,
+    ) -> Result<Self, SimplePirError> {
+        if lengths.is_empty() {
+            return Err(SimplePirError::Empty("records"));
+        }
+        let max_len = lengths.iter().copied().max().unwrap();
+        let classes: Vec<usize> = lengths.iter()
+            .map(|&len| policy.class_len(len, max_len))
+            .collect::<Result<_, _>>()?;
+
+        let mut class_lens = classes.clone();
+        class_lens.sort_unstable();
+        class_lens.dedup();
+        let mut counts = vec![0; class_lens.len()];
+        let slots = classes.iter().map(|class_len| {
+            let bucket = class_lens.binary_search(class_len).unwrap();
+            counts[bucket] += 1;
+            (bucket, counts[bucket] - 1)
+        }).collect();
+        let buckets = class_lens.iter().zip(counts).map(|(&class_len, count)| {
+            let layout = RecordLayout::new(count, LEN_HEADER + class_len, p)?;
+            Ok(Bucket { class_len, layout })
+        }).collect::<Result<_, SimplePirError>>()?;
+
+        Ok(Self { buckets, slots })
+    }
+
+    pub fn num_records(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The (bucket, slot) of record idx.
+    pub fn locate(&self, idx: usize) -> Result<(usize, usize), SimplePirError> {
+        self.slots.get(idx).copied().ok_or(SimplePirError::IndexOutOfRange {
+            idx,
+            len: self.num_records(),
+        })
+    }
+
+    /// Split the records into the fixed-length records of each bucket, each prefixed with its
+    /// length and padded with zeros to its size class. The records must be those whose lengths
+    /// the layout was built from: a different number of records is rejected with
+    /// LengthMismatch, and a record longer than its size class with TooManyBytes.
+    pub fn pad_records(&self, records: &[Vec<u8>]) -> Result<Vec<Vec<Vec<u8>>>, SimplePirError> {
+        if records.len() != self.num_records() {
+            let (expected, actual) = (self.num_records(), records.len());
+            return Err(SimplePirError::LengthMismatch { expected, actual });
+        }
+        let mut padded: Vec<Vec<Vec<u8>>> = self.buckets.iter()
+            .map(|bucket| Vec::with_capacity(bucket.layout.num_records))
+            .collect();
+        for (record, &(bucket, _)) in records.iter().zip(&self.slots) {
+            let class_len = self.buckets[bucket].class_len;
+            if record.len() > class_len {
+                return Err(SimplePirError::TooManyBytes { len: record.len(), capacity: class_len });
+            }
+            let mut stored = (record.len() as u32).to_le_bytes().to_vec();
+            stored.extend_from_slice(record);
+            stored.resize(self.buckets[bucket].layout.record_len, 0);
+            padded[bucket].push(stored);
+        }
+        Ok(padded)
+    }
+}
+
+/// Strip the length header and padding from a record retrieved from a bucket. Records whose
+/// header claims more bytes than they hold are rejected as malformed answers.
+pub fn unpad_record(padded: &[u8]) -> Result<Vec<u8>, SimplePirError> {
+    if padded.len() < LEN_HEADER {
+        return Err(SimplePirError::LengthMismatch { expected: LEN_HEADER, actual: padded.len() });
+    }
+    let (header, body) = padded.split_at(LEN_HEADER);
+    let len = u32::from_le_bytes(header.try_into().unwrap()) as usize;
+    if len > body.len() {
+        return Err(SimplePirError::MalformedAnswer);
+    }
+    Ok(body[..len].to_vec())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn gen_record(len: usize, seed: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 + seed * 7) as u8).collect()
+    }
+
+    #[test]
+    fn test_bits_per_limb() {
+        assert_eq!(bits_per_limb(2), 1);
+        assert_eq!(bits_per_limb(3), 1);
+        assert_eq!(bits_per_limb(256), 8);
+        assert_eq!(bits_per_limb(991), 9);
+        assert_eq!(limbs_per_record(32, 991), 29);
+        assert_eq!(limbs_per_record(32, 256), 32);
+    }
+
+    #[test]
+    fn test_encode_decode_record() {
+        for p in [2, 3, 256, 991, 4294967291] {
+            for len in [1, 7, 32, 1024] {
+                let record = gen_record(len, len);
+                let limbs = encode_record(&record, p);
+                assert_eq!(limbs.len(), limbs_per_record(len, p));
+                assert!(limbs.iter().all(|limb| limb.q == p));
+                assert_eq!(decode_record(&limbs, len), record);
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_bytes() {
+        let record = gen_record(20, 3);
+        let checked = add_check_bytes(&record, 4);
+        assert_eq!(checked.len(), 24);
+        assert_eq!(strip_check_bytes(&checked, 4), Ok(record.clone()));
+        assert_eq!(strip_check_bytes(&add_check_bytes(&record, 0), 0), Ok(record));
+
+        for i in [0, 19, 23] {
+            let mut corrupted = checked.clone();
+            corrupted[i] ^= 0x10;
+            assert_eq!(strip_check_bytes(&corrupted, 4), Err(SimplePirError::MalformedAnswer));
+        }
+        assert!(strip_check_bytes(&checked[..3], 4).is_err());
+    }
+
+    #[test]
+    fn test_decode_range() {
+        for p in [2, 256, 991] {
+            let record = gen_record(40, 5);
+            let limbs = encode_record(&record, p);
+            for bytes in [0..40, 3..4, 10..21, 39..40] {
+                let range = limb_range(bytes.clone(), p);
+                assert_eq!(decode_range(&limbs[range], bytes.clone()), record[bytes]);
+            }
+        }
+        assert_eq!(limb_range(1..3, 991), 0..3);
+
+        let layout = RecordLayout::new(100, 2, 256).unwrap();
+        assert_eq!(layout.range_columns(1..2), [1, 3, 5, 7, 9, 11, 13]);
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        assert_eq!(RecordLayout::new(0, 2, 256), Err(SimplePirError::Empty("database")));
+        assert_eq!(RecordLayout::new(10, 0, 256), Err(SimplePirError::Empty("record")));
+        assert_eq!(RecordLayout::new(10, 2, 1), Err(SimplePirError::InvalidModulus(1)));
+        let layout = RecordLayout::new(1, 1, 2).unwrap();
+        assert_eq!((layout.limbs, layout.rows, layout.num_cols()), (8, 1, 8));
+    }
+
+    #[test]
+    fn test_variable_layout() {
+        let records: Vec<Vec<u8>> =
+            [3, 0, 17, 8, 5, 17].iter().map(|&len| gen_record(len, len)).collect();
+        let lengths: Vec<usize> = records.iter().map(|record| record.len()).collect();
+
+        let layout = VariableLayout::new(&lengths, &PaddingPolicy::PowerOfTwo, 256).unwrap();
+        let class_lens: Vec<usize> = layout.buckets.iter().map(|bucket| bucket.class_len).collect();
+        assert_eq!(class_lens, [1, 4, 8, 32]);
+        assert_eq!(layout.locate(5), Ok((3, 1)));
+        let padded = layout.pad_records(&records).unwrap();
+        for (idx, record) in records.iter().enumerate() {
+            let (bucket, slot) = layout.locate(idx).unwrap();
+            assert_eq!(padded[bucket][slot].len(), layout.buckets[bucket].layout.record_len);
+            assert_eq!(unpad_record(&padded[bucket][slot]), Ok(record.clone()));
+        }
+
+        // Records which do not match the lengths the layout was built from are rejected
+        let mut longer = records.clone();
+        longer[3].push(0);
+        let result = layout.pad_records(&longer);
+        assert_eq!(result, Err(SimplePirError::TooManyBytes { len: 9, capacity: 8 }));
+        let result = layout.pad_records(&records[1..]);
+        assert_eq!(result, Err(SimplePirError::LengthMismatch { expected: 6, actual: 5 }));
+
+        let layout = VariableLayout::new(&lengths, &PaddingPolicy::Max, 256).unwrap();
+        assert_eq!(layout.buckets.len(), 1);
+        let policy = PaddingPolicy::Classes(vec![16, 8]);
+        assert_eq!(
+            VariableLayout::new(&lengths, &policy, 256),
+            Err(SimplePirError::TooManyBytes { len: 17, capacity: 16 }),
+        );
+        assert_eq!(unpad_record(&[9, 0, 0, 0, 1]), Err(SimplePirError::MalformedAnswer));
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = RecordLayout::new(10, 32, 991).unwrap();
+        assert_eq!(layout.limbs, 29);
+        assert_eq!(layout.rows, 10);
+        assert_eq!(layout.num_cols(), 29);
+
+        let layout = RecordLayout::new(100, 2, 256).unwrap();
+        assert_eq!(layout.rows, 15);
+        assert_eq!(layout.records_per_row(), 7);
+        assert_eq!(layout.position(16), (2, 1));
+
+        // 2^30 records of 64 bytes have 2^36 limbs, more than a 32-bit usize counts
+        let large = RecordLayout::new(1 << 30, 64, 256).unwrap();
+        assert_eq!((large.limbs, large.rows, large.num_cols()), (64, 1 << 18, 1 << 18));
+
+        let records: Vec<Vec<u8>> = (0..100).map(|i| gen_record(2, i)).collect();
+        let db = layout.encode_db(&records, 256).unwrap();
+        assert_eq!(db.dimensions(), (14, 15));
+        let err = SimplePirError::LengthMismatch { expected: 100, actual: 99 };
+        assert_eq!(layout.encode_db(&records[1..], 256), Err(err));
+        let mut longer = records.clone();
+        longer[5].push(0);
+        let err = SimplePirError::LengthMismatch { expected: 2, actual: 3 };
+        assert_eq!(layout.encode_db(&longer, 256), Err(err));
+
+        let rows = db.rotated();
+        for (idx, record) in records.iter().enumerate() {
+            let (_, row) = layout.position(idx);
+            assert_eq!(layout.decode_from_row(&rows.col(row), idx), *record);
+        }
+    }
+}
@@ -1,7 +1,21 @@
 use crate::matrix::Matrix;
-use crate::element::Element;
+use crate::element::{Element, TAIL_CUT};
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
+use crate::error::SimplePirError;
+use crate::scaling::{scale_floor, unscale_round};
+use crate::vector::Vector;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use core::ops::{Add, Deref, Mul};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+#[cfg(feature = "std")]
+use rand::rngs::StdRng;
+use rand_chacha::ChaCha20Rng;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Params {
     // Public A matrix
     pub a: Matrix,
@@ -15,156 +29,511 @@ pub struct Params {
     pub m: usize,
     // The standard deviation for sampling random elements
     pub std_dev: f64,
+    // The distribution from which LWE secrets are sampled
+    pub secret_dist: SecretDistribution,
 }
 
-pub fn simple_params() -> Params {
-    let m = 1;
-    let n = 512;
-    let q = 3329;
-    let p = 2;
-    let std_dev = 6.4;
+/// The distribution of LWE secret coefficients. Uniform secrets make no assumptions, while small
+/// secrets are what practical parameter sets assume.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SecretDistribution {
+    // Uniform mod q
+    #[default]
+    Uniform,
+    // Uniform over {0, 1}
+    Binary,
+    // Uniform over {-1, 0, 1}
+    Ternary,
+    // A centered discrete Gaussian with the given standard deviation
+    Gaussian(f64),
+}
+
+/// An LWE secret. It is wiped from memory when dropped, and is redacted from Debug output. It
+/// cannot be cloned: the query functions take it by value, so that it encrypts one query only.
+pub struct SecretKey(Vector);
+
+impl From<Vector> for SecretKey {
+    fn from(coeffs: Vector) -> Self {
+        Self(coeffs)
+    }
+}
+
+impl Deref for SecretKey {
+    type Target = Vector;
+
+    fn deref(&self) -> &Vector {
+        &self.0
+    }
+}
+
+impl SecretKey {
+    /// The coefficients as elements, for the functions which take them. The copy is wiped from
+    /// memory when dropped as well.
+    pub fn elements(&self) -> Zeroizing<Vec<Element>> {
+        Zeroizing::new(self.0.to_elements())
+    }
+}
+
+impl Debug for SecretKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "SecretKey([REDACTED; {}])", self.0.len())
+    }
+}
+
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretKey {}
+
+/// The secret of a query which was sent, kept until its answer is recovered. Only the query
+/// functions make one, from a secret they take by value, and recovering the answer consumes it.
+pub struct QuerySecret(SecretKey);
+
+impl QuerySecret {
+    pub(crate) fn new(s: SecretKey) -> Self {
+        Self(s)
+    }
+
+    /// The secret, e.g. to generate a packing key for the answer.
+    pub fn secret(&self) -> &SecretKey {
+        &self.0
+    }
+}
+
+impl Debug for QuerySecret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "QuerySecret({:?})", self.0)
+    }
+}
 
-    let a = Matrix::gen_uniform_rand(q, m, n);
+impl Params {
+    /// Construct parameters whose public A matrix is deterministically expanded from a 32-byte
+    /// seed with ChaCha20, so that only the seed and the moduli need to be shared.
+    pub fn from_seed(seed: [u8; 32], q: u64, p: u64, n: usize, m: usize, std_dev: f64) -> Self {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        let a = Matrix::gen_uniform_rand_with_rng(q, m, n, &mut rng);
+        Params { a, q, p, n, m, std_dev, secret_dist: SecretDistribution::Uniform }
+    }
 
-    Params { a, q, p, n, m, std_dev }
+    /// A small, insecure parameter set with a single sample, n = 512, q = 3329 and p = 2, which
+    /// keeps tests fast.
+    #[cfg(all(feature = "std", any(test, feature = "testing")))]
+    pub fn toy() -> Self {
+        let (q, p, n, m, std_dev) = (3329, 2, 512, 1, 6.4);
+        let a = Matrix::gen_uniform_rand(q, m, n);
+        Params { a, q, p, n, m, std_dev, secret_dist: SecretDistribution::Uniform }
+    }
 }
 
-fn check_secret_length(params: &Params, secret: &Vec<Element>) {
+pub(crate) fn check_secret_length(params: &Params, secret: &Vector) -> Result<(), SimplePirError> {
     // Check that the secret has the correct number of elements
-    assert_eq!(secret.len(), params.n);
+    if params.n == 0 {
+        return Err(SimplePirError::Empty("LWE secret"));
+    }
+    if secret.len() != params.n {
+        return Err(SimplePirError::LengthMismatch { expected: params.n, actual: secret.len() });
+    }
+    check_vector_mod(secret, params.q)
 }
 
-fn check_plaintext_mod(params: &Params, plaintext: &Element) {
-    // Check that each element of the plaintext is within range
-    assert!(plaintext.uint < params.p);
-    assert_eq!(plaintext.q, params.p);
+fn check_plaintext_mod(params: &Params, plaintext: &Element) -> Result<(), SimplePirError> {
+    // Check that the plaintext is an element mod p
+    check_moduli(core::slice::from_ref(plaintext), params.p)
 }
 
-fn check_ciphertext_mod(params: &Params, ciphertext: &Element) {
-    // Check that the ciphertext is in range
-    assert!(ciphertext.uint < params.q);
+pub(crate) fn check_ciphertext_mod(
+    params: &Params,
+    ciphertext: &Element,
+) -> Result<(), SimplePirError> {
+    // Check that the ciphertext is an element mod q
+    check_moduli(core::slice::from_ref(ciphertext), params.q)
 }
 
-fn check_error_length(params: &Params, error: &Vec<Element>) {
+fn check_error_length(params: &Params, error: &Vector) -> Result<(), SimplePirError> {
     // Check that the error has the correct number of elements
-    assert_eq!(error.len(), params.m);
+    if error.len() != params.m {
+        return Err(SimplePirError::LengthMismatch { expected: params.m, actual: error.len() });
+    }
+    check_vector_mod(error, params.q)
+}
+
+/// Check that a vector is mod q.
+pub(crate) fn check_vector_mod(v: &Vector, q: u64) -> Result<(), SimplePirError> {
+    match v.q() == q {
+        true => Ok(()),
+        false => Err(SimplePirError::ModulusMismatch { expected: q, actual: v.q() }),
+    }
+}
+
+/// Check that a matrix is mod q.
+pub(crate) fn check_matrix_mod(m: &Matrix, q: u64) -> Result<(), SimplePirError> {
+    match m.has_modulus(q) {
+        true => Ok(()),
+        false => Err(SimplePirError::ModulusMismatch { expected: q, actual: m.q() }),
+    }
+}
+
+/// Check that every element is mod q.
+pub(crate) fn check_moduli(elements: &[Element], q: u64) -> Result<(), SimplePirError> {
+    match elements.iter().find(|e| e.q != q) {
+        Some(e) => Err(SimplePirError::ModulusMismatch { expected: q, actual: e.q }),
+        None => Ok(()),
+    }
 }
 
 pub fn encrypt(
     params: &Params,
-    secret: &Vec<Element>,
-    e: &Vec<Element>,
+    secret: &SecretKey,
+    e: &Vector,
     plaintext: &Element,
-) -> Element {
-    check_secret_length(params, secret);
-    check_plaintext_mod(params, plaintext);
-    check_error_length(params, e);
+) -> Result<Element, SimplePirError> {
+    check_secret_length(params, secret)?;
+    check_plaintext_mod(params, plaintext)?;
+    check_error_length(params, e)?;
     // TODO: check error range
 
-    // Compute As
-    let a_s = params.a.to_owned().mul_vec(secret);
-
     // Compute b = As + e
-    let b = a_s + Matrix::from(&vec![e.clone()]).rotated();
-
-    let floor = params.q / params.p;
-    let floor = Matrix::from_single(&Element::from(params.q, floor));
+    let mut b = params.a.mul_vector(secret);
+    b += e;
 
-    // Convert the plaintext to a matrix with Element mod q instead of p
-    let plaintext_as_matrix = Matrix::from_single(&Element::from(params.q, plaintext.uint));
-
-    // Compute the ciphertext As + e + round(q / p) * plaintext
-    let c = b + (floor * plaintext_as_matrix);
-    
-    c[0][0].clone()
+    // Compute the ciphertext As + e + floor(q / p) * plaintext
+    Ok(b.get(0) + scale_floor(plaintext, params.q))
 }
 
 pub fn decrypt(
     params: &Params,
-    secret: &Vec<Element>,
+    secret: &SecretKey,
     ciphertext: &Element,
-) -> Element {
-    check_secret_length(params, secret);
-    check_ciphertext_mod(params, ciphertext);
-    // Compute As
-    let a_s = params.a.clone().mul_vec(secret);
-
-    assert_eq!(ciphertext.q, params.q);
-    assert_eq!(a_s[0][0].q, params.q);
-
+) -> Result<Element, SimplePirError> {
+    check_secret_length(params, secret)?;
+    check_ciphertext_mod(params, ciphertext)?;
     // Compute c - As
-    let raw = Matrix::from_single(ciphertext) - a_s;
+    let raw = ciphertext.clone() - params.a.mul_vector(secret).get(0);
 
     // Round to the nearest q / p
-    let x = ((raw[0][0].uint * params.p) as f64 / params.q as f64).round() as u64 % params.p;
-
-    Element::from(params.p, x)
+    Ok(unscale_round(&raw, params.p))
 }
 
+#[cfg(feature = "std")]
 pub fn gen_random_normal_matrix(
     q: u64,
     std_dev: f64,
     num_rows: usize,
     num_cols: usize,
 ) -> Matrix {
-    let v: Vec<Vec<Element>> = vec![
-        vec![Element::new(q); num_rows];
-        num_cols
-    ];
-
-    let mut matrix = Matrix::from(&v);
+    let mut matrix = Matrix::from_val(num_cols, num_rows, Element::new(q));
 
     for i in 0..num_cols {
         for j in 0..num_rows {
-            matrix[i][j] = Element::gen_normal_rand(q, std_dev);
+            matrix.set(i, j, &Element::gen_normal_rand(q, std_dev));
         }
     }
     matrix
 }
 
-pub fn gen_secret(q: u64, n: usize) -> Vec<Element> {
-    let mut secret = Vec::with_capacity(n);
-    for _ in 0..n {
-        secret.push(Element::gen_uniform_rand(q));
+impl SecretDistribution {
+    /// Sample n coefficients mod q from this distribution using the given RNG.
+    pub fn sample<R: CryptoRng + RngCore>(&self, q: u64, n: usize, rng: &mut R) -> Vector {
+        let values = (0..n).map(|_| match *self {
+            SecretDistribution::Uniform => Element::gen_uniform_rand_with_rng(q, rng),
+            SecretDistribution::Binary => Element::from(q, rng.gen_range(0..2)),
+            SecretDistribution::Ternary => Element::from(q, rng.gen_range(0..3)) - 1u64,
+            SecretDistribution::Gaussian(std_dev) => Element::gen_normal_rand_with_rng(q, std_dev, rng),
+        }.uint).collect();
+        Vector::from(q, values)
     }
-    secret
 }
 
+#[cfg(feature = "std")]
+pub fn gen_secret(q: u64, n: usize) -> SecretKey {
+    gen_secret_with_dist(q, n, SecretDistribution::Uniform)
+}
 
-pub fn gen_error_vec(q: u64, m: usize) -> Vec<Element> {
-    let sample_space = 6;
-    let half_sample_space = sample_space / 2;
-    let mut error_vec = Vec::with_capacity(m);
-    for _ in 0..m {
-        let rand = Element::gen_uniform_rand(sample_space);
-        let mut e = Element::from(q, rand.uint);
-        e -= Element::from(q, half_sample_space);
-        error_vec.push(e);
+/// Sample a uniform secret of n coefficients mod q using the given RNG.
+pub fn gen_secret_with_rng<R: CryptoRng + RngCore>(q: u64, n: usize, rng: &mut R) -> SecretKey {
+    SecretDistribution::Uniform.sample(q, n, rng).into()
+}
+
+/// Sample a secret of n coefficients mod q from the given distribution.
+#[cfg(feature = "std")]
+pub fn gen_secret_with_dist(q: u64, n: usize, dist: SecretDistribution) -> SecretKey {
+    let mut rng = StdRng::from_entropy();
+    dist.sample(q, n, &mut rng).into()
+}
+
+/// The tail bound of errors sampled with standard deviation std_dev, which are truncated at
+/// TAIL_CUT standard deviations as by Element::gen_normal_rand(). The Gaussian mass beyond it is
+/// below 2^-28.
+pub fn error_bound(std_dev: f64) -> u64 {
+    (TAIL_CUT * std_dev).ceil() as u64
+}
+
+/// A ciphertext value c = <a, s> + e + Δm, where a is public, together with an estimate of its
+/// error e. Homomorphic additions and multiplications by plaintext scalars update the estimate,
+/// which tracks both the variance of e (assuming independent errors) and its largest possible
+/// magnitude.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ciphertext {
+    // The ciphertext value
+    pub c: Element,
+    // The variance of the error
+    variance: f64,
+    // The largest possible magnitude of the error
+    bound: f64,
+}
+
+impl Ciphertext {
+    /// A fresh ciphertext, whose error was sampled with standard deviation std_dev.
+    pub fn fresh(c: Element, std_dev: f64) -> Self {
+        Self { c, variance: std_dev * std_dev, bound: error_bound(std_dev) as f64 }
+    }
+
+    /// The standard deviation of the error.
+    pub fn estimated_noise(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// The largest possible magnitude of the error.
+    pub fn noise_bound(&self) -> f64 {
+        self.bound
+    }
+}
+
+impl Add for Ciphertext {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            c: self.c + rhs.c,
+            variance: self.variance + rhs.variance,
+            bound: self.bound + rhs.bound,
+        }
+    }
+}
+
+impl Mul<u64> for Ciphertext {
+    type Output = Self;
+    fn mul(self, rhs: u64) -> Self::Output {
+        let k = rhs as f64;
+        Self { c: self.c * rhs, variance: self.variance * k * k, bound: self.bound * k }
     }
-    error_vec
+}
+
+/// The noise in a decrypted value, compared with the noise which decryption tolerates.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NoiseBudget {
+    // The standard deviation of the noise
+    pub std_dev: f64,
+    // The largest possible magnitude of the noise
+    pub max: f64,
+    // Decryption rounds correctly while the noise stays below this
+    pub threshold: f64,
+}
+
+impl NoiseBudget {
+    /// Whether decryption is correct even for the largest possible noise.
+    pub fn is_guaranteed(&self) -> bool {
+        self.max < self.threshold
+    }
+
+    /// The number of bits between the standard deviation of the noise and the threshold.
+    /// Decryption starts to fail noticeably once fewer than about 2 bits remain.
+    pub fn margin_bits(&self) -> f64 {
+        (self.threshold / self.std_dev).log2()
+    }
+}
+
+/// Sample m errors mod q with the standard deviation std_dev of the params in use, truncated at
+/// error_bound(std_dev), which the noise analysis of the PIR schemes assumes. The sampled error
+/// vectors are wiped from memory when dropped.
+#[cfg(feature = "std")]
+pub fn gen_error_vec(q: u64, m: usize, std_dev: f64) -> Zeroizing<Vector> {
+    let mut rng = StdRng::from_entropy();
+    gen_error_vec_with_rng(q, m, std_dev, &mut rng)
+}
+
+/// Like gen_error_vec(), but using the given RNG.
+pub fn gen_error_vec_with_rng<R: CryptoRng + RngCore>(
+    q: u64,
+    m: usize,
+    std_dev: f64,
+    rng: &mut R,
+) -> Zeroizing<Vector> {
+    gen_gaussian_error_vec_with_rng(q, m, std_dev, error_bound(std_dev), rng)
+}
+
+/// Sample m errors mod q from the centered discrete Gaussian with standard deviation std_dev,
+/// truncated at bound.
+#[cfg(feature = "std")]
+pub fn gen_gaussian_error_vec(
+    q: u64,
+    m: usize,
+    std_dev: f64,
+    bound: u64,
+) -> Zeroizing<Vector> {
+    let mut rng = StdRng::from_entropy();
+    gen_gaussian_error_vec_with_rng(q, m, std_dev, bound, &mut rng)
+}
+
+/// Like gen_gaussian_error_vec(), but using the given RNG.
+pub fn gen_gaussian_error_vec_with_rng<R: CryptoRng + RngCore>(
+    q: u64,
+    m: usize,
+    std_dev: f64,
+    bound: u64,
+    rng: &mut R,
+) -> Zeroizing<Vector> {
+    let values = (0..m)
+        .map(|_| Element::gen_discrete_gaussian_with_rng(q, std_dev, bound, rng).uint)
+        .collect();
+    Zeroizing::new(Vector::from(q, values))
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use rand_chacha::ChaCha20Rng;
 
     #[test]
     fn test_gen_random_normal_matrix() {
         let num_rows = 9;
         let num_cols = 10;
-        let matrix = gen_random_normal_matrix(101u64, 6.4 as f64, num_rows, num_cols);
+        let matrix = gen_random_normal_matrix(101u64, 6.4, num_rows, num_cols);
         assert_eq!(matrix.num_rows(), num_rows);
         assert_eq!(matrix.num_cols(), num_cols);
     }
 
+    #[test]
+    fn test_secret_distributions() {
+        let q = 3329;
+        let small =
+            |s: &Vector, bound: u64| s.values().iter().all(|&v| v <= bound || v >= q - bound);
+
+        let s = gen_secret_with_dist(q, 512, SecretDistribution::Binary);
+        assert!(s.values().iter().all(|&v| v <= 1));
+        let s = gen_secret_with_dist(q, 512, SecretDistribution::Ternary);
+        assert!(small(&s, 1));
+        assert!(s.values().contains(&(q - 1)));
+        let s = gen_secret_with_dist(q, 512, SecretDistribution::Gaussian(2.0));
+        assert!(small(&s, 12));
+        let s = gen_secret_with_dist(q, 512, SecretDistribution::Uniform);
+        assert!(!small(&s, 12));
+
+        for dist in [
+            SecretDistribution::Binary,
+            SecretDistribution::Ternary,
+            SecretDistribution::Gaussian(3.2),
+        ] {
+            let mut params = Params::toy();
+            params.secret_dist = dist;
+            let secret = gen_secret_with_dist(params.q, params.n, params.secret_dist);
+            for m in 0..params.p {
+                let e = gen_error_vec(params.q, params.m, params.std_dev);
+                let plaintext = Element::from(params.p, m);
+                let ciphertext = encrypt(&params, &secret, &e, &plaintext).unwrap();
+                assert_eq!(plaintext, decrypt(&params, &secret, &ciphertext).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sampling_with_rng() {
+        let q = 3329;
+        let sample = |seed: u8| {
+            let mut rng = ChaCha20Rng::from_seed([seed; 32]);
+            let s = gen_secret_with_rng(q, 16, &mut rng);
+            let t = SecretDistribution::Ternary.sample(q, 16, &mut rng);
+            let e = gen_error_vec_with_rng(q, 16, 6.4, &mut rng);
+            let g = gen_gaussian_error_vec_with_rng(q, 16, 6.4, 39, &mut rng);
+            (s.values().to_vec(), t, e, g)
+        };
+        assert_eq!(sample(1), sample(1));
+        assert_ne!(sample(1), sample(2));
+    }
+
+    #[test]
+    fn test_ciphertext_noise() {
+        let params = Params::toy();
+        let secret = gen_secret(params.q, params.n);
+        let encrypt_bit = |bit| {
+            let e = gen_error_vec(params.q, params.m, params.std_dev);
+            let c = encrypt(&params, &secret, &e, &Element::from(params.p, bit)).unwrap();
+            Ciphertext::fresh(c, params.std_dev)
+        };
+
+        // Summing 4 encryptions of 1 and scaling by 3 encrypts 12 = 0 mod 2 under 12 * A
+        let mut sum = encrypt_bit(1);
+        for _ in 0..3 {
+            sum = sum + encrypt_bit(1);
+        }
+        let ct = sum * 3;
+        assert_eq!(ct.estimated_noise(), 6.0 * params.std_dev);
+        assert_eq!(ct.noise_bound(), 12.0 * error_bound(params.std_dev) as f64);
+
+        let mut p = params.clone();
+        p.a = params.a.clone().mul_elem(&Element::from(params.q, 12));
+        assert_eq!(decrypt(&p, &secret, &ct.c).unwrap(), Element::from(params.p, 0));
+    }
+
+    #[test]
+    fn test_secret_key() {
+        let q = 3329;
+        let mut s = gen_secret(q, 16);
+        assert_eq!(format!("{:?}", s), "SecretKey([REDACTED; 16])");
+
+        s.zeroize();
+        assert_eq!(s.len(), 0);
+
+        let mut e = gen_error_vec(q, 16, 6.4);
+        e.zeroize();
+        assert!(e.is_empty());
+    }
+
+    #[test]
+    fn test_gen_error_vec() {
+        let q = 3329;
+        let e = gen_error_vec(q, 1000, 6.4);
+        assert_eq!(e.len(), 1000);
+        assert_eq!(error_bound(6.4), 39);
+        assert!(e.values().iter().all(|&v| v <= 39 || v >= q - 39));
+        assert!(e.values().iter().any(|&v| v > 6 && v < q - 6));
+        assert!(e.values().iter().any(|&v| v != 0));
+
+        let e = gen_gaussian_error_vec(q, 1000, 6.4, 39);
+        assert!(e.values().iter().all(|&v| v <= 39 || v >= q - 39));
+    }
+
+    #[test]
+    fn test_params_from_seed() {
+        let params = Params::from_seed([7u8; 32], 3329, 2, 512, 1, 6.4);
+        assert_eq!(params, Params::from_seed([7u8; 32], 3329, 2, 512, 1, 6.4));
+        assert_ne!(params.a, Params::from_seed([8u8; 32], 3329, 2, 512, 1, 6.4).a);
+        assert_eq!(params.a.dimensions(), (1, 512));
+
+        let secret = gen_secret(params.q, params.n);
+        let e = gen_error_vec(params.q, params.m, params.std_dev);
+        let plaintext = Element::from(params.p, 1);
+        let ciphertext = encrypt(&params, &secret, &e, &plaintext).unwrap();
+        assert_eq!(plaintext, decrypt(&params, &secret, &ciphertext).unwrap());
+    }
+
     fn encrypt_and_decrypt_impl(pu: u64) {
-        let params = simple_params();
+        let params = Params::toy();
         let secret = gen_secret(params.q, params.n);
-        let e = gen_error_vec(params.q, params.m);
+        let e = gen_error_vec(params.q, params.m, params.std_dev);
 
         let plaintext = Element::from(params.p, pu);
-        let ciphertext = encrypt(&params, &secret, &e, &plaintext);
-        assert_eq!(plaintext, decrypt(&params, &secret, &ciphertext));
+        let ciphertext = encrypt(&params, &secret, &e, &plaintext).unwrap();
+        assert_eq!(plaintext, decrypt(&params, &secret, &ciphertext).unwrap());
     }
 
     #[test]
@@ -177,42 +546,42 @@ pub mod tests {
 
     fn homomorphic_addition_impl(params: &Params) {
         let secret = gen_secret(params.q, params.n);
-        let e_0 = gen_error_vec(params.q, params.m);
-        let e_1 = gen_error_vec(params.q, params.m);
+        let e_0 = gen_error_vec(params.q, params.m, params.std_dev);
+        let e_1 = gen_error_vec(params.q, params.m, params.std_dev);
 
         let plaintext_0 = Element::from(params.p, 0);
-        let ciphertext_0 = encrypt(&params, &secret, &e_0, &plaintext_0);
+        let ciphertext_0 = encrypt(params, &secret, &e_0, &plaintext_0).unwrap();
 
         let plaintext_1 = Element::from(params.p, 1);
-        let ciphertext_1 = encrypt(&params, &secret, &e_1, &plaintext_1);
+        let ciphertext_1 = encrypt(params, &secret, &e_1, &plaintext_1).unwrap();
 
         let a_n = params.a.clone() + params.a.clone();
         let mut params = params.clone();
         params.a = a_n;
         let ciphertext_n = ciphertext_0 + ciphertext_1;
         let plaintext_n = plaintext_0 + plaintext_1;
-        assert_eq!(plaintext_n, decrypt(&params, &secret, &ciphertext_n));
+        assert_eq!(plaintext_n, decrypt(&params, &secret, &ciphertext_n).unwrap());
     }
 
     #[test]
     fn test_homomorphic_addition() {
-        let params = simple_params();
+        let params = Params::toy();
         for _ in 0..50 {
             homomorphic_addition_impl(&params);
         }
     }
 
     fn test_homomorphic_multiplication_impl() {
-        let mut params = simple_params();
+        let mut params = Params::toy();
         params.p = 3;
         let secret = gen_secret(params.q, params.n);
-        let e = gen_error_vec(params.q, params.m);
+        let e = gen_error_vec(params.q, params.m, params.std_dev);
 
         // Encrypt and decrypt the value 1 mod 3
         let plaintext_1 = Element::from(params.p, 1);
-        let ciphertext_1 = encrypt(&params, &secret, &e, &plaintext_1);
+        let ciphertext_1 = encrypt(&params, &secret, &e, &plaintext_1).unwrap();
 
-        let decryption_1 = decrypt(&params, &secret, &ciphertext_1);
+        let decryption_1 = decrypt(&params, &secret, &ciphertext_1).unwrap();
         assert_eq!(decryption_1, plaintext_1);
 
         // two = 2 mod 3
@@ -223,7 +592,7 @@ pub mod tests {
         let mut params_2 = params.clone();
         params_2.a = params.a.mul_elem(&two);
 
-        let result = decrypt(&params_2, &secret, &ciphertext_2);
+        let result = decrypt(&params_2, &secret, &ciphertext_2).unwrap();
         assert_eq!(
             Element::from(
                 params.p, 
@@ -0,0 +1,268 @@
+//! Reports on parameter presets, for choosing parameters: for each preset and database size, the
+//! security, the bound on and simulated rate of decryption failures, the sizes of the hint, query
+//! and answer, and the measured answer time and scan throughput. The simplepir-report binary
+//! sweeps a grid of presets and sizes and prints the report as JSON or CSV.
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::error::SimplePirError;
+use crate::pir::{Client, Server};
+use crate::record::RecordLayout;
+use crate::simplepir::{self, SimplePIRParams};
+
+// The seed of the records and simulated failures, so that reports can be reproduced
+const REPORT_SEED: u64 = 0x5245504f5254;
+
+/// A way of choosing parameters for a database.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Preset {
+    /// SimplePIRParams::for_security() with the given bits of security.
+    ForSecurity(u32),
+    /// SimplePIRParams::paper_preset() with the given plaintext modulus.
+    Paper(u64),
+}
+
+impl Preset {
+    /// Every preset: for_security() at the default security level and each of the paper's.
+    pub fn all() -> Vec<Preset> {
+        let mut presets = vec![Preset::ForSecurity(simplepir::DEFAULT_SECURITY)];
+        presets.extend(simplepir::paper_moduli().into_iter().map(Preset::Paper));
+        presets
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Preset::ForSecurity(bits) => format!("security-{}", bits),
+            Preset::Paper(p) => format!("paper-p{}", p),
+        }
+    }
+
+    /// The preset with the given name.
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Self::all().into_iter().find(|preset| preset.name() == name)
+    }
+
+    /// The params and layout of a database of num_records records of record_len bytes. Databases
+    /// too large for the preset are rejected with TooManySamples.
+    pub fn params(
+        self,
+        num_records: usize,
+        record_len: usize,
+    ) -> Result<(SimplePIRParams, RecordLayout), SimplePirError> {
+        match self {
+            Preset::ForSecurity(bits) => {
+                let params = SimplePIRParams::for_security(bits, num_records, 8 * record_len)?;
+                let layout = RecordLayout::new(num_records, record_len, params.p)?;
+                Ok((params, layout))
+            }
+            Preset::Paper(p) => {
+                let layout = RecordLayout::new(num_records, record_len, p)?;
+                Ok((SimplePIRParams::paper_preset(layout.rows, p)?, layout))
+            }
+        }
+    }
+}
+
+/// What a report measures of one preset and database size.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReportRow {
+    pub preset: Preset,
+    pub num_records: usize,
+    pub record_len: usize,
+    pub n: usize,
+    pub q: u64,
+    pub p: u64,
+    pub m: usize,
+    // The bits of security, or None for params of no vetted set
+    pub security_bits: Option<u32>,
+    // The bound of failure_probability() on incorrect decryption of an answer entry, which holds
+    // for the worst case of items of p - 1 and so is loose for typical items
+    pub failure_probability: f64,
+    // The rate of incorrect decryption over the simulated answer entries
+    pub simulated_failure_rate: f64,
+    pub hint_bytes: usize,
+    pub query_bytes: usize,
+    pub answer_bytes: usize,
+    pub mean_answer_time: Duration,
+    // The database items read per second by answers
+    pub scan_throughput: f64,
+}
+
+/// Set up a server over random records with the preset, and measure the row of the report:
+/// `trials` simulated answer entries give the failure rate, and `rounds` retrievals give the
+/// answer time. A retrieval which recovers another record fails with MalformedAnswer.
+pub fn measure(
+    preset: Preset,
+    num_records: usize,
+    record_len: usize,
+    trials: usize,
+    rounds: usize,
+) -> Result<ReportRow, SimplePirError> {
+    let (params, layout) = preset.params(num_records, record_len)?;
+    let mut rng = ChaCha20Rng::seed_from_u64(REPORT_SEED);
+    let records: Vec<Vec<u8>> = (0..num_records)
+        .map(|_| (0..record_len).map(|_| rng.gen()).collect())
+        .collect();
+    let failure_probability = params.failure_probability(params.m, params.p);
+    let simulated_failure_rate = params.simulate_failure_rate(params.m, params.p, trials, &mut rng);
+
+    let mut server = Server::setup_records(params.clone(), layout, &records)?;
+    server.enable_stats();
+    let mut client = Client::from_public_hint(server.public_hint())?;
+    let (mut query_bytes, mut answer_bytes) = (0, 0);
+    for _ in 0..rounds {
+        let idx = rng.gen_range(0..num_records);
+        let query = client.query_record_with_rng(idx, &mut rng)?;
+        let answer = server.answer(&query)?;
+        if client.recover_record(&answer)? != records[idx] {
+            return Err(SimplePirError::MalformedAnswer);
+        }
+        (query_bytes, answer_bytes) = (query.wire_size(), answer.wire_size());
+    }
+    let stats = server.stats().unwrap();
+    Ok(ReportRow {
+        preset,
+        num_records,
+        record_len,
+        n: params.n,
+        q: params.q,
+        p: params.p,
+        m: params.m,
+        security_bits: params.security_bits(),
+        failure_probability,
+        simulated_failure_rate,
+        hint_bytes: server.hint().wire_size(),
+        query_bytes,
+        answer_bytes,
+        mean_answer_time: stats.mean_answer_time(),
+        scan_throughput: stats.scan_throughput(),
+    })
+}
+
+// The columns of a report, in order
+const COLUMNS: [&str; 15] = [
+    "preset",
+    "num_records",
+    "record_len",
+    "n",
+    "q",
+    "p",
+    "m",
+    "security_bits",
+    "failure_probability",
+    "simulated_failure_rate",
+    "hint_bytes",
+    "query_bytes",
+    "answer_bytes",
+    "mean_answer_secs",
+    "scan_throughput",
+];
+
+impl ReportRow {
+    // The values of the columns, formatted as JSON values (which CSV takes as they are but for
+    // the quotes around the preset name). Floats are in exponent notation, which JSON accepts.
+    fn values(&self) -> [String; 15] {
+        let security_bits = match self.security_bits {
+            Some(bits) => bits.to_string(),
+            None => "null".to_string(),
+        };
+        [
+            format!("\"{}\"", self.preset.name()),
+            self.num_records.to_string(),
+            self.record_len.to_string(),
+            self.n.to_string(),
+            self.q.to_string(),
+            self.p.to_string(),
+            self.m.to_string(),
+            security_bits,
+            format!("{:e}", self.failure_probability),
+            format!("{:e}", self.simulated_failure_rate),
+            self.hint_bytes.to_string(),
+            self.query_bytes.to_string(),
+            self.answer_bytes.to_string(),
+            format!("{:e}", self.mean_answer_time.as_secs_f64()),
+            format!("{:e}", self.scan_throughput),
+        ]
+    }
+}
+
+/// The report as a JSON array with one object per row.
+pub fn to_json(rows: &[ReportRow]) -> String {
+    let mut out = "[".to_string();
+    for (i, row) in rows.iter().enumerate() {
+        out.push_str(if i == 0 { "\n  {" } else { ",\n  {" });
+        for (j, (column, value)) in COLUMNS.iter().zip(row.values()).enumerate() {
+            let sep = if j == 0 { "" } else { ", " };
+            write!(out, "{}\"{}\": {}", sep, column, value).unwrap();
+        }
+        out.push('}');
+    }
+    out.push_str("\n]\n");
+    out
+}
+
+/// The report as CSV with a header row. A missing security level is an empty field.
+pub fn to_csv(rows: &[ReportRow]) -> String {
+    let mut out = COLUMNS.join(",") + "\n";
+    for row in rows {
+        let values = row.values().map(|value| match value.as_str() {
+            "null" => String::new(),
+            _ => value.trim_matches('"').to_string(),
+        });
+        out.push_str(&values.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let presets = Preset::all();
+        assert_eq!(presets.len(), 9);
+        for preset in presets {
+            assert_eq!(Preset::from_name(&preset.name()), Some(preset));
+        }
+        assert_eq!(Preset::from_name("paper-p2"), None);
+
+        let (params, layout) = Preset::Paper(991).params(1000, 4).unwrap();
+        assert_eq!((params.p, params.m, params.security_bits()), (991, layout.rows, Some(128)));
+        let too_many = Preset::Paper(991).params(1 << 30, 1).unwrap_err();
+        assert!(matches!(too_many, SimplePirError::TooManySamples { max: 8192, .. }));
+    }
+
+    #[test]
+    fn test_report() {
+        let rows: Vec<ReportRow> = [Preset::ForSecurity(128), Preset::Paper(294)]
+            .into_iter()
+            .map(|preset| measure(preset, 64, 3, 100, 2).unwrap())
+            .collect();
+        for row in &rows {
+            assert_eq!((row.n, row.security_bits), (1024, Some(128)));
+            assert!(row.failure_probability < 1e-3);
+            assert_eq!(row.simulated_failure_rate, 0.0);
+            assert!(row.query_bytes >= 4 * row.m && row.answer_bytes > 0);
+            assert!(row.hint_bytes >= 4 * 1024 * row.m && row.scan_throughput > 0.0);
+        }
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&rows)).unwrap();
+        assert_eq!(json[1]["preset"], "paper-p294");
+        assert_eq!(json[0]["security_bits"], 128);
+        assert_eq!(json[0]["simulated_failure_rate"], 0.0);
+        assert_eq!(json.as_array().unwrap().len(), 2);
+
+        let csv = to_csv(&rows);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("preset,num_records,"));
+        assert!(lines[2].starts_with("paper-p294,64,3,1024,4294967296,294,"));
+        assert_eq!(to_json(&[]), "[\n]\n");
+    }
+}
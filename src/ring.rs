@@ -0,0 +1,341 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, SeedableRng};
+use zeroize::Zeroizing;
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::ntt::NttTables;
+use crate::regev::{check_moduli, error_bound, gen_gaussian_error_vec_with_rng, SecretDistribution};
+use crate::scaling::{scale_floor, unscale_round};
+
+/// A polynomial in Z_q[X]/(X^d + 1) for a power of two d, stored as its d coefficients from the
+/// constant term up.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Poly {
+    pub coeffs: Vec<Element>,
+}
+
+/// A ring ciphertext (a, b) with b = a * z + e + m for the ring secret z, a small error e and a
+/// message m.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ciphertext {
+    pub a: Poly,
+    pub b: Poly,
+}
+
+/// The ring Z_q[X]/(X^d + 1) which polynomials are multiplied in. Its NTT tables, when q is an
+/// NTT-friendly prime for d, are computed once here and used for every product, since computing
+/// them costs about as much as a product.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ring {
+    q: u64,
+    d: usize,
+    // None when q is not NTT-friendly for d, in which case products are computed directly
+    ntt: Option<NttTables>,
+}
+
+impl Ring {
+    pub fn new(q: u64, d: usize) -> Self {
+        assert!(d.is_power_of_two(), "the ring dimension must be a power of two");
+        Self { q, d, ntt: NttTables::new(q, d).ok() }
+    }
+
+    pub fn q(&self) -> u64 {
+        self.q
+    }
+
+    /// The ring dimension d.
+    pub fn d(&self) -> usize {
+        self.d
+    }
+
+    /// The negacyclic convolution: the product mod X^d + 1, where X^d wraps around to -1. It goes
+    /// through the NTT when q is an NTT-friendly prime for d, and multiplies directly otherwise.
+    /// Panics unless both polynomials are in the ring.
+    pub fn mul(&self, lhs: &Poly, rhs: &Poly) -> Poly {
+        assert!(lhs.d() == self.d && rhs.d() == self.d);
+        assert!(lhs.q() == self.q && rhs.q() == self.q);
+        match &self.ntt {
+            Some(tables) => tables.mul(lhs, rhs),
+            None => mul_schoolbook(lhs, rhs),
+        }
+    }
+}
+
+impl Poly {
+    pub fn zero(q: u64, d: usize) -> Self {
+        Self::from_coeffs(vec![Element::zero(q); d])
+    }
+
+    /// The polynomial with the given coefficients, which must all be mod the same q. Panics unless
+    /// the number of coefficients is a power of two.
+    pub fn from_coeffs(coeffs: Vec<Element>) -> Self {
+        assert!(coeffs.len().is_power_of_two(), "the ring dimension must be a power of two");
+        assert!(coeffs.iter().all(|c| c.q == coeffs[0].q));
+        Self { coeffs }
+    }
+
+    /// The ring dimension d.
+    pub fn d(&self) -> usize {
+        self.coeffs.len()
+    }
+
+    pub fn q(&self) -> u64 {
+        self.coeffs[0].q
+    }
+
+    pub fn gen_uniform_rand_with_rng<R: RngCore>(q: u64, d: usize, rng: &mut R) -> Self {
+        Self::from_coeffs((0..d).map(|_| Element::gen_uniform_rand_with_rng(q, rng)).collect())
+    }
+
+    /// A secret polynomial with coefficients uniform over {-1, 0, 1}.
+    pub fn gen_ternary_with_rng<R: CryptoRng + RngCore>(q: u64, d: usize, rng: &mut R) -> Self {
+        Self::from_coeffs(SecretDistribution::Ternary.sample(q, d, rng).to_elements())
+    }
+
+    /// An error polynomial with coefficients from the discrete Gaussian with standard deviation
+    /// std_dev, truncated at error_bound(std_dev).
+    pub fn gen_error_with_rng<R: CryptoRng + RngCore>(
+        q: u64,
+        d: usize,
+        std_dev: f64,
+        rng: &mut R,
+    ) -> Self {
+        let bound = error_bound(std_dev);
+        Self::from_coeffs(gen_gaussian_error_vec_with_rng(q, d, std_dev, bound, rng).to_elements())
+    }
+
+    /// Decompose each coefficient into digits in base `base`, returning the polynomial of the
+    /// i-th digits for each i, so that the sum of the digit polynomials times base^i gives back
+    /// this polynomial. The digits are at most base - 1.
+    pub fn decomposed(&self, base: u64) -> Vec<Poly> {
+        let q = self.q();
+        let digits: Vec<Vec<u64>> =
+            self.coeffs.iter().map(|c| c.clone().decomposed(base)).collect();
+        (0..digits[0].len()).map(|i| {
+            Self::from_coeffs(digits.iter().map(|c| Element::from(q, c[i])).collect())
+        }).collect()
+    }
+
+    /// Rescale every coefficient from Z_q to Z_new_q, as Element::mod_switch() does.
+    pub fn mod_switch(&self, new_q: u64) -> Self {
+        Self::from_coeffs(self.coeffs.iter().map(|c| c.mod_switch(new_q)).collect())
+    }
+}
+
+impl Ciphertext {
+    /// Multiply by a plaintext polynomial, lifted from mod p to mod q without scaling, which
+    /// multiplies the message (and the error) by it.
+    pub fn mul_plain(&self, ring: &Ring, m: &Poly) -> Ciphertext {
+        let q = self.a.q();
+        let m = Poly::from_coeffs(m.coeffs.iter().map(|c| Element::from(q, c.uint)).collect());
+        Ciphertext { a: ring.mul(&self.a, &m), b: ring.mul(&self.b, &m) }
+    }
+}
+
+impl Add for Ciphertext {
+    type Output = Ciphertext;
+    fn add(self, rhs: Ciphertext) -> Self::Output {
+        Ciphertext { a: self.a + rhs.a, b: self.b + rhs.b }
+    }
+}
+
+/// Sample a ring secret of dimension d mod q, with ternary coefficients.
+#[cfg(feature = "std")]
+pub fn gen_secret(q: u64, d: usize) -> Zeroizing<Poly> {
+    gen_secret_with_rng(q, d, &mut StdRng::from_entropy())
+}
+
+/// Like gen_secret(), but using the given RNG.
+pub fn gen_secret_with_rng<R: CryptoRng + RngCore>(
+    q: u64,
+    d: usize,
+    rng: &mut R,
+) -> Zeroizing<Poly> {
+    Zeroizing::new(Poly::gen_ternary_with_rng(q, d, rng))
+}
+
+/// Encrypt a message polynomial mod p under the ring secret z: a is uniform and
+/// b = a * z + e + ⌊q / p⌋ m, with the errors sampled with standard deviation std_dev. z must be
+/// in the ring.
+pub fn encrypt_with_rng<R: CryptoRng + RngCore>(
+    ring: &Ring,
+    z: &Poly,
+    m: &Poly,
+    std_dev: f64,
+    rng: &mut R,
+) -> Result<Ciphertext, SimplePirError> {
+    if m.d() != z.d() {
+        return Err(SimplePirError::LengthMismatch { expected: z.d(), actual: m.d() });
+    }
+    let (q, d) = (z.q(), z.d());
+    if m.q() > q {
+        return Err(SimplePirError::ModulusMismatch { expected: q, actual: m.q() });
+    }
+    let a = Poly::gen_uniform_rand_with_rng(q, d, rng);
+    let mut b = ring.mul(&a, z) + Poly::gen_error_with_rng(q, d, std_dev, rng);
+    for (b, m) in b.coeffs.iter_mut().zip(&m.coeffs) {
+        *b += scale_floor(m, q);
+    }
+    Ok(Ciphertext { a, b })
+}
+
+/// Decrypt a ciphertext under the ring secret z, which must be in the ring, to its message mod p,
+/// by rounding b - a * z.
+pub fn decrypt(ring: &Ring, z: &Poly, ct: &Ciphertext, p: u64) -> Result<Poly, SimplePirError> {
+    check_ciphertext(z.q(), z.d(), ct)?;
+    let v = ct.b.clone() - ring.mul(&ct.a, z);
+    Ok(Poly::from_coeffs(v.coeffs.iter().map(|c| unscale_round(c, p)).collect()))
+}
+
+/// Check that a ciphertext is mod q and of dimension d, since operations on any other panic.
+pub(crate) fn check_ciphertext(q: u64, d: usize, ct: &Ciphertext) -> Result<(), SimplePirError> {
+    for poly in [&ct.a, &ct.b] {
+        if poly.d() != d {
+            return Err(SimplePirError::LengthMismatch { expected: d, actual: poly.d() });
+        }
+        check_moduli(&poly.coeffs, q)?;
+    }
+    Ok(())
+}
+
+impl Zeroize for Poly {
+    fn zeroize(&mut self) {
+        self.coeffs.zeroize();
+    }
+}
+
+impl Add for Poly {
+    type Output = Poly;
+    fn add(mut self, rhs: Poly) -> Self::Output {
+        self += &rhs;
+        self
+    }
+}
+
+impl AddAssign<&Poly> for Poly {
+    fn add_assign(&mut self, rhs: &Poly) {
+        assert_eq!(self.d(), rhs.d());
+        for (a, b) in self.coeffs.iter_mut().zip(&rhs.coeffs) {
+            *a += b.clone();
+        }
+    }
+}
+
+impl Sub for Poly {
+    type Output = Poly;
+    fn sub(mut self, rhs: Poly) -> Self::Output {
+        self -= &rhs;
+        self
+    }
+}
+
+impl SubAssign<&Poly> for Poly {
+    fn sub_assign(&mut self, rhs: &Poly) {
+        assert_eq!(self.d(), rhs.d());
+        for (a, b) in self.coeffs.iter_mut().zip(&rhs.coeffs) {
+            *a -= b.clone();
+        }
+    }
+}
+
+// The negacyclic convolution in O(d^2), for any q
+pub(crate) fn mul_schoolbook(lhs: &Poly, rhs: &Poly) -> Poly {
+    let (d, q) = (lhs.d(), lhs.q());
+    // Each product is reduced before accumulating, so that sums of up to d products fit
+    let mut acc = vec![0u128; 2 * d];
+    for (i, a) in lhs.coeffs.iter().enumerate() {
+        for (j, b) in rhs.coeffs.iter().enumerate() {
+            acc[i + j] += (a.uint as u128 * b.uint as u128) % q as u128;
+        }
+    }
+    let reduce = |x: u128| Element::from(q, (x % q as u128) as u64);
+    Poly::from_coeffs((0..d).map(|k| reduce(acc[k]) - reduce(acc[k + d])).collect())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn poly(q: u64, coeffs: &[u64]) -> Poly {
+        Poly::from_coeffs(coeffs.iter().map(|&c| Element::from(q, c)).collect())
+    }
+
+    #[test]
+    fn test_negacyclic_mul() {
+        // (1 + X) * (X^3) = X^3 + X^4 = X^3 - 1 mod X^4 + 1
+        let q = 97;
+        let ring = Ring::new(q, 4);
+        let product = ring.mul(&poly(q, &[1, 1, 0, 0]), &poly(q, &[0, 0, 0, 1]));
+        assert_eq!(product, poly(q, &[96, 0, 0, 1]));
+
+        // Multiplication by X rotates the coefficients and negates the one which wraps around
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let ring = Ring::new(q, 8);
+        let a = Poly::gen_uniform_rand_with_rng(q, 8, &mut rng);
+        let x = poly(q, &[0, 1, 0, 0, 0, 0, 0, 0]);
+        let mut rotated = a.coeffs.clone();
+        rotated.rotate_right(1);
+        rotated[0] = Element::zero(q) - rotated[0].clone();
+        assert_eq!(ring.mul(&a, &x), Poly::from_coeffs(rotated));
+
+        // Multiplication distributes over addition
+        let b = Poly::gen_uniform_rand_with_rng(q, 8, &mut rng);
+        let c = Poly::gen_uniform_rand_with_rng(q, 8, &mut rng);
+        assert_eq!(ring.mul(&a, &(b.clone() + c.clone())), ring.mul(&a, &b) + ring.mul(&a, &c));
+
+        // Products in a ring with NTT tables match those computed directly
+        let ring = Ring::new(crate::ntt::NTT_Q32, 8);
+        assert!(ring.ntt.is_some());
+        let a = Poly::gen_uniform_rand_with_rng(ring.q(), 8, &mut rng);
+        let b = Poly::gen_uniform_rand_with_rng(ring.q(), 8, &mut rng);
+        assert_eq!(ring.mul(&a, &b), mul_schoolbook(&a, &b));
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let (q, p, d) = (4294967291, 256, 32);
+        let ring = Ring::new(q, d);
+        let z = gen_secret_with_rng(q, d, &mut rng);
+        let m1 = Poly::gen_uniform_rand_with_rng(p, d, &mut rng);
+        let m2 = Poly::gen_uniform_rand_with_rng(p, d, &mut rng);
+        let ct1 = encrypt_with_rng(&ring, &z, &m1, 6.4, &mut rng).unwrap();
+        let ct2 = encrypt_with_rng(&ring, &z, &m2, 6.4, &mut rng).unwrap();
+        assert_eq!(decrypt(&ring, &z, &ct1, p).unwrap(), m1);
+
+        // Ciphertexts add, and multiply by plaintexts, homomorphically
+        assert_eq!(decrypt(&ring, &z, &(ct1.clone() + ct2), p).unwrap(), m1.clone() + m2.clone());
+        let x = poly(p, &[0, 1].iter().chain(&[0; 30]).copied().collect::<Vec<_>>());
+        let product = decrypt(&ring, &z, &ct1.mul_plain(&ring, &x), p).unwrap();
+        assert_eq!(product, Ring::new(p, d).mul(&m1, &x));
+
+        // Ciphertexts of another ring are rejected
+        let short = Ciphertext { a: Poly::zero(q, 16), b: Poly::zero(q, 16) };
+        assert!(decrypt(&ring, &z, &short, p).is_err());
+        assert!(encrypt_with_rng(&ring, &z, &Poly::zero(p, 16), 6.4, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_decomposed() {
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let q = 4294967291;
+        let a = Poly::gen_uniform_rand_with_rng(q, 16, &mut rng);
+        let digits = a.decomposed(16);
+        assert_eq!(digits.len(), 8);
+        assert!(digits.iter().all(|d| d.coeffs.iter().all(|c| c.uint < 16 && c.q == q)));
+
+        let mut sum = Poly::zero(q, 16);
+        for (i, digit) in digits.iter().enumerate() {
+            let power = 16u64.pow(i as u32);
+            sum += &Poly::from_coeffs(digit.coeffs.iter().map(|c| c.clone() * power).collect());
+        }
+        assert_eq!(sum, a);
+    }
+}
@@ -0,0 +1,200 @@
+// RingPIR: SimplePIR with ring-Regev in place of LWE.
+//
+// The items are packed d to a polynomial mod p and the polynomials laid out as an m x cols matrix.
+// The query holds one ring ciphertext per row, encrypting 1 at the queried row and 0 elsewhere,
+// and answer entry i is the sum over the rows j of db[i][j] times query ciphertext j. It encrypts
+// the whole polynomial of column i at the queried row, so each answer ciphertext returns d items.
+// Unlike SimplePIR there is no hint: the client decrypts with its ring secret alone.
+
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, SeedableRng};
+use subtle::ConstantTimeEq;
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::regev::{error_bound, NoiseBudget};
+use crate::ring::{self, Ciphertext, Poly, Ring};
+#[cfg(feature = "std")]
+use crate::ntt::NTT_Q32;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RingPIRParams {
+    // The integer modulus
+    pub q: u64,
+    // The plaintext modulus
+    pub p: u64,
+    // The ring dimension, a power of two, which is the number of items per polynomial
+    pub d: usize,
+    // The number of rows of polynomials, which is the number of query ciphertexts
+    pub m: usize,
+    // The standard deviation of the query errors
+    pub std_dev: f64,
+}
+
+impl RingPIRParams {
+    /// Estimate the noise in each coefficient of an answer ciphertext. A coefficient accumulates
+    /// one query error per row and per coefficient of the row's polynomial, multiplied by an item.
+    /// Flooring q / p adds at most p more.
+    pub fn noise_budget(&self) -> NoiseBudget {
+        let terms = (self.m * self.d) as f64;
+        let p = self.p as f64;
+        // The mean square of an item uniform in [0, p)
+        let item_square = (p - 1.0) * (2.0 * p - 1.0) / 6.0;
+        NoiseBudget {
+            std_dev: self.std_dev * (terms * item_square).sqrt(),
+            max: error_bound(self.std_dev) as f64 * terms * (p - 1.0) + p,
+            threshold: self.q as f64 / (2.0 * p),
+        }
+    }
+
+    /// The number of polynomial columns, which is the number of answer ciphertexts, for a
+    /// database of db_size items.
+    pub fn num_cols(&self, db_size: usize) -> usize {
+        db_size.div_ceil(self.d).div_ceil(self.m)
+    }
+}
+
+/// Generate parameters for a database of db_size items mod p in a ring of dimension d, laying
+/// the polynomials out as a square matrix. The modulus is NTT-friendly, so that answers multiply
+/// polynomials through the NTT. Panics if answers would not decrypt correctly.
+#[cfg(feature = "std")]
+pub fn gen_params_for_size(db_size: usize, p: u64, d: usize) -> RingPIRParams {
+    assert!(p >= 2);
+    assert!(d.is_power_of_two(), "the ring dimension must be a power of two");
+    let num_polys = db_size.div_ceil(d).max(1);
+    let mut m = (num_polys as f64).sqrt() as usize;
+    while m * m < num_polys {
+        m += 1;
+    }
+    let params = RingPIRParams { q: NTT_Q32, p, d, m, std_dev: 6.4 };
+    assert!(params.noise_budget().is_guaranteed(), "the plaintext modulus is too large");
+    params
+}
+
+/// The (column, row, coefficient) position of item idx.
+pub fn position(params: &RingPIRParams, idx: usize) -> (usize, usize, usize) {
+    let poly = idx / params.d;
+    (poly / params.m, poly % params.m, idx % params.d)
+}
+
+/// Lay out a list of items mod p as polynomials, indexed by column and then row, such that each
+/// item is stored at position(). Unused positions are filled with zeros.
+pub fn encode_db(params: &RingPIRParams, items: &[Element]) -> Vec<Vec<Poly>> {
+    let num_cols = params.num_cols(items.len());
+    let mut db = vec![vec![Poly::zero(params.p, params.d); params.m]; num_cols];
+    for (i, item) in items.iter().enumerate() {
+        assert_eq!(item.q, params.p);
+        let (col, row, coeff) = position(params, i);
+        db[col][row].coeffs[coeff] = item.clone();
+    }
+    db
+}
+
+/// Generate a query for the given row under the ring secret z.
+#[cfg(feature = "std")]
+pub fn query(
+    params: &RingPIRParams,
+    row: usize,
+    z: &Poly,
+) -> Result<Vec<Ciphertext>, SimplePirError> {
+    query_with_rng(params, row, z, &mut StdRng::from_entropy())
+}
+
+/// Like query(), but sampling the errors with the given RNG.
+pub fn query_with_rng<R: CryptoRng + RngCore>(
+    params: &RingPIRParams,
+    row: usize,
+    z: &Poly,
+    rng: &mut R,
+) -> Result<Vec<Ciphertext>, SimplePirError> {
+    if row >= params.m {
+        return Err(SimplePirError::IndexOutOfRange { idx: row, len: params.m });
+    }
+    // Every row is encrypted the same way, so that the timing does not depend on row
+    let ring = Ring::new(z.q(), z.d());
+    (0..params.m).map(|j| {
+        let mut m = Poly::zero(params.p, params.d);
+        m.coeffs[0] = Element::from(params.p, j.ct_eq(&row).unwrap_u8() as u64);
+        ring::encrypt_with_rng(&ring, z, &m, params.std_dev, rng)
+    }).collect()
+}
+
+/// Answer a query over the database returned by encode_db(), with one ciphertext per column.
+pub fn answer(query: &[Ciphertext], db: &[Vec<Poly>]) -> Result<Vec<Ciphertext>, SimplePirError> {
+    let first = query.first().ok_or(SimplePirError::Empty("query"))?;
+    let (q, d) = (first.a.q(), first.a.d());
+    let num_rows = db.first().map_or(0, |col| col.len());
+    if query.len() != num_rows {
+        return Err(SimplePirError::LengthMismatch { expected: num_rows, actual: query.len() });
+    }
+    if db[0][0].d() != d {
+        return Err(SimplePirError::LengthMismatch { expected: db[0][0].d(), actual: d });
+    }
+    for ct in query {
+        ring::check_ciphertext(q, d, ct)?;
+    }
+
+    let ring = Ring::new(q, d);
+    Ok(db.iter().map(|col| {
+        let mut sum = Ciphertext { a: Poly::zero(q, d), b: Poly::zero(q, d) };
+        for (ct, poly) in query.iter().zip(col) {
+            sum = sum + ct.mul_plain(&ring, poly);
+        }
+        sum
+    }).collect())
+}
+
+/// Recover item idx from the answer to a query for its row.
+pub fn recover(
+    params: &RingPIRParams,
+    z: &Poly,
+    idx: usize,
+    answer: &[Ciphertext],
+) -> Result<Element, SimplePirError> {
+    let (col, _, coeff) = position(params, idx);
+    let len = answer.len();
+    let ct = answer.get(col).ok_or(SimplePirError::IndexOutOfRange { idx: col, len })?;
+    let ring = Ring::new(z.q(), z.d());
+    Ok(ring::decrypt(&ring, z, ct, params.p)?.coeffs.swap_remove(coeff))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ringpir() {
+        let db_size = 300;
+        let params = gen_params_for_size(db_size, 16, 16);
+        assert_eq!((params.m, params.num_cols(db_size)), (5, 4));
+        let items: Vec<Element> = (0..db_size).map(|_| Element::gen_uniform_rand(16)).collect();
+        let db = encode_db(&params, &items);
+
+        let z = ring::gen_secret(params.q, params.d);
+        for idx in [0, 17, 150, 299] {
+            let (_, row, _) = position(&params, idx);
+            let query = query(&params, row, &z).unwrap();
+            let answer = answer(&query, &db).unwrap();
+            assert_eq!(answer.len(), 4);
+            assert_eq!(recover(&params, &z, idx, &answer).unwrap(), items[idx]);
+        }
+    }
+
+    #[test]
+    fn test_ringpir_malformed() {
+        let params = gen_params_for_size(64, 2, 8);
+        let db = encode_db(&params, &vec![Element::zero(2); 64]);
+        let z = ring::gen_secret(params.q, params.d);
+        assert!(query(&params, params.m, &z).is_err());
+
+        let mut query = query(&params, 0, &z).unwrap();
+        assert_eq!(answer(&[], &db), Err(SimplePirError::Empty("query")));
+        assert!(answer(&query[1..], &db).is_err());
+        query[1].b = Poly::zero(params.q, 4);
+        assert!(answer(&query, &db).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+// A residue number system for moduli Q beyond 64 bits, for schemes which need q between 2^64 and
+// 2^128. Q is the product of two or three pairwise coprime word-sized moduli q_i, and an element
+// of Z_Q is stored as its residues mod each q_i, which are plain Elements. By the Chinese
+// remainder theorem, adding and multiplying residue-wise adds and multiplies mod Q, so no
+// arithmetic ever needs more than a u128 product. Values are reconstructed with Garner's mixed
+// radix algorithm, whose partial sums stay below Q and so fit in a u128.
+//
+// Polynomials over Z_Q are stored as one Poly per modulus and multiplied in a Ring per modulus,
+// which goes through the NTT for the moduli which are NTT-friendly for the ring dimension.
+
+use alloc::vec::Vec;
+use core::ops::{Add, Mul, Sub};
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::ring::{Poly, Ring};
+
+/// The most moduli a basis may have.
+pub const MAX_MODULI: usize = 3;
+
+/// A composite modulus Q = q_1 ⋯ q_k of two or three pairwise coprime moduli below 2^64 whose
+/// product is below 2^128, with the constants of CRT reconstruction precomputed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RnsBasis {
+    moduli: Vec<u64>,
+    modulus: u128,
+    // inv[i][j] = q_j^-1 mod q_i for j < i
+    inv: Vec<Vec<u64>>,
+}
+
+/// An element of Z_Q, stored as its residues mod each modulus of its basis, in order.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RnsElement {
+    pub residues: Vec<Element>,
+}
+
+/// A polynomial in Z_Q[X]/(X^d + 1), stored as its reductions mod each modulus of its basis.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RnsPoly {
+    pub limbs: Vec<Poly>,
+}
+
+// The inverse of a mod q, for a coprime to q, by the extended Euclidean algorithm
+fn inv_mod(a: u64, q: u64) -> Option<u64> {
+    let (mut r0, mut r1) = (q as i128, (a % q) as i128);
+    let (mut t0, mut t1) = (0i128, 1i128);
+    while r1 != 0 {
+        let quotient = r0 / r1;
+        (r0, r1) = (r1, r0 - quotient * r1);
+        (t0, t1) = (t1, t0 - quotient * t1);
+    }
+    (r0 == 1).then(|| t0.rem_euclid(q as i128) as u64)
+}
+
+impl RnsBasis {
+    /// The basis of the given moduli, which must be two or three valid element moduli, pairwise
+    /// coprime and with a product below 2^128.
+    pub fn new(moduli: &[u64]) -> Result<Self, SimplePirError> {
+        if !(2..=MAX_MODULI).contains(&moduli.len()) {
+            return Err(SimplePirError::InvalidShape("RNS basis"));
+        }
+        if let Some(&q) = moduli.iter().find(|&&q| q < 2 || q == u64::MAX) {
+            return Err(SimplePirError::InvalidModulus(q));
+        }
+        let modulus = moduli.iter()
+            .try_fold(1u128, |acc, &q| acc.checked_mul(q as u128))
+            .ok_or(SimplePirError::InvalidShape("RNS basis"))?;
+        let inv = moduli.iter().enumerate()
+            .map(|(i, &q)| moduli[..i].iter().map(|&q_j| inv_mod(q_j, q)).collect())
+            .collect::<Option<Vec<Vec<u64>>>>()
+            .ok_or(SimplePirError::InvalidShape("RNS basis"))?;
+        Ok(Self { moduli: moduli.to_vec(), modulus, inv })
+    }
+
+    pub fn moduli(&self) -> &[u64] {
+        &self.moduli
+    }
+
+    /// The composite modulus Q.
+    pub fn modulus(&self) -> u128 {
+        self.modulus
+    }
+
+    /// The element x mod Q.
+    pub fn element(&self, x: u128) -> RnsElement {
+        let residues = self.moduli.iter()
+            .map(|&q| Element::from(q, (x % q as u128) as u64))
+            .collect();
+        RnsElement { residues }
+    }
+
+    /// The value below Q of an element of this basis. Panics if the element is of another basis.
+    pub fn reconstruct(&self, e: &RnsElement) -> u128 {
+        self.check(e);
+        // The mixed radix digits v_i < q_i of x = v_1 + v_2 q_1 + v_3 q_1 q_2
+        let mut digits: Vec<u64> = Vec::with_capacity(self.moduli.len());
+        for (i, (&q, residue)) in self.moduli.iter().zip(&e.residues).enumerate() {
+            let digit = digits.iter().zip(&self.inv[i]).fold(residue.uint, |t, (&v, &inv)| {
+                let t = Element::from(q, t) - Element::from(q, v % q);
+                (t * Element::from(q, inv)).uint
+            });
+            digits.push(digit);
+        }
+        digits.iter().zip(&self.moduli).rev()
+            .fold(0u128, |x, (&v, &q)| x * q as u128 + v as u128)
+    }
+
+    /// The polynomial with the given coefficients mod Q.
+    pub fn poly(&self, coeffs: &[u128]) -> RnsPoly {
+        let limbs = self.moduli.iter()
+            .map(|&q| {
+                Poly::from_coeffs(coeffs.iter()
+                    .map(|&c| Element::from(q, (c % q as u128) as u64))
+                    .collect())
+            })
+            .collect();
+        RnsPoly { limbs }
+    }
+
+    /// The coefficients below Q of a polynomial of this basis.
+    pub fn coeffs(&self, poly: &RnsPoly) -> Vec<u128> {
+        assert!(poly.limbs.len() == self.moduli.len());
+        (0..poly.limbs[0].d())
+            .map(|k| {
+                let residues = poly.limbs.iter().map(|limb| limb.coeffs[k].clone()).collect();
+                self.reconstruct(&RnsElement { residues })
+            })
+            .collect()
+    }
+
+    // Panic unless the element has one residue mod each modulus of the basis
+    fn check(&self, e: &RnsElement) {
+        assert!(e.residues.len() == self.moduli.len());
+        assert!(e.residues.iter().zip(&self.moduli).all(|(r, &q)| r.q == q));
+    }
+}
+
+// Combine two elements residue by residue, panicking unless they are of the same basis
+fn zip_residues(
+    lhs: RnsElement,
+    rhs: RnsElement,
+    op: impl Fn(Element, Element) -> Element,
+) -> RnsElement {
+    assert_eq!(lhs.residues.len(), rhs.residues.len());
+    let residues = lhs.residues.into_iter().zip(rhs.residues).map(|(a, b)| op(a, b)).collect();
+    RnsElement { residues }
+}
+
+impl Add for RnsElement {
+    type Output = RnsElement;
+    fn add(self, rhs: RnsElement) -> Self::Output {
+        zip_residues(self, rhs, |a, b| a + b)
+    }
+}
+
+impl Sub for RnsElement {
+    type Output = RnsElement;
+    fn sub(self, rhs: RnsElement) -> Self::Output {
+        zip_residues(self, rhs, |a, b| a - b)
+    }
+}
+
+impl Mul for RnsElement {
+    type Output = RnsElement;
+    fn mul(self, rhs: RnsElement) -> Self::Output {
+        zip_residues(self, rhs, |a, b| a * b)
+    }
+}
+
+impl Add for RnsPoly {
+    type Output = RnsPoly;
+    fn add(self, rhs: RnsPoly) -> Self::Output {
+        assert_eq!(self.limbs.len(), rhs.limbs.len());
+        RnsPoly { limbs: self.limbs.into_iter().zip(rhs.limbs).map(|(a, b)| a + b).collect() }
+    }
+}
+
+/// The ring Z_Q[X]/(X^d + 1) for the composite modulus Q of a basis, holding a Ring for each of
+/// its moduli.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RnsRing {
+    rings: Vec<Ring>,
+}
+
+impl RnsRing {
+    pub fn new(basis: &RnsBasis, d: usize) -> Self {
+        Self { rings: basis.moduli().iter().map(|&q| Ring::new(q, d)).collect() }
+    }
+
+    /// The negacyclic convolution mod Q, computed mod each modulus with its Ring. Panics unless
+    /// both polynomials are in the ring.
+    pub fn mul(&self, lhs: &RnsPoly, rhs: &RnsPoly) -> RnsPoly {
+        assert!(lhs.limbs.len() == self.rings.len() && rhs.limbs.len() == self.rings.len());
+        let limbs = self.rings.iter().zip(lhs.limbs.iter().zip(&rhs.limbs))
+            .map(|(ring, (a, b))| ring.mul(a, b))
+            .collect();
+        RnsPoly { limbs }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::ntt::{is_ntt_friendly, NTT_Q32};
+    use num::BigUint;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    // The largest prime below 2^62 which is NTT-friendly for dimension d
+    fn ntt_prime_below_2_62(d: usize) -> u64 {
+        let step = 2 * d as u64;
+        (1..).map(|k| (1u64 << 62) - k * step + 1).find(|&q| is_ntt_friendly(q, d)).unwrap()
+    }
+
+    fn big(x: u128) -> BigUint {
+        BigUint::from(x)
+    }
+
+    fn bases() -> Vec<RnsBasis> {
+        let q62 = ntt_prime_below_2_62(16);
+        vec![
+            // About 2^94, both NTT-friendly
+            RnsBasis::new(&[q62, NTT_Q32]).unwrap(),
+            // About 2^106, the last not NTT-friendly
+            RnsBasis::new(&[q62, NTT_Q32, 4093]).unwrap(),
+            // About 2^128, neither prime
+            RnsBasis::new(&[u64::MAX - 1, u64::MAX - 2]).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_rns_arithmetic() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        for basis in bases() {
+            let q = big(basis.modulus());
+            let mut values: Vec<u128> =
+                (0..200).map(|_| rng.gen::<u128>() % basis.modulus()).collect();
+            values.extend([0, 1, basis.modulus() - 1]);
+            for pair in values.windows(2) {
+                let (x, y) = (pair[0], pair[1]);
+                let (ex, ey) = (basis.element(x), basis.element(y));
+                assert_eq!(basis.reconstruct(&ex), x);
+
+                let sum = basis.reconstruct(&(ex.clone() + ey.clone()));
+                assert_eq!(big(sum), (big(x) + big(y)) % &q);
+                let diff = basis.reconstruct(&(ex.clone() - ey.clone()));
+                assert_eq!(big(diff), (big(x) + &q - big(y)) % &q);
+                let product = basis.reconstruct(&(ex * ey));
+                assert_eq!(big(product), big(x) * big(y) % &q);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rns_ring() {
+        let d = 16;
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        for basis in bases() {
+            let q = big(basis.modulus());
+            let ring = RnsRing::new(&basis, d);
+            let a: Vec<u128> = (0..d).map(|_| rng.gen::<u128>() % basis.modulus()).collect();
+            let b: Vec<u128> = (0..d).map(|_| rng.gen::<u128>() % basis.modulus()).collect();
+            let product = basis.coeffs(&ring.mul(&basis.poly(&a), &basis.poly(&b)));
+
+            // The negacyclic convolution computed with big integers
+            let mut expected = vec![BigUint::from(0u32); d];
+            for (i, &a) in a.iter().enumerate() {
+                for (j, &b) in b.iter().enumerate() {
+                    let term = big(a) * big(b) % &q;
+                    let k = (i + j) % d;
+                    expected[k] = match i + j < d {
+                        true => (&expected[k] + term) % &q,
+                        false => (&expected[k] + &q - term) % &q,
+                    };
+                }
+            }
+            assert_eq!(product.into_iter().map(big).collect::<Vec<_>>(), expected);
+
+            let sum = basis.coeffs(&(basis.poly(&a) + basis.poly(&b)));
+            assert_eq!(big(sum[3]), (big(a[3]) + big(b[3])) % &q);
+        }
+    }
+
+    #[test]
+    fn test_rns_basis_errors() {
+        let shape = Err(SimplePirError::InvalidShape("RNS basis"));
+        assert_eq!(RnsBasis::new(&[NTT_Q32]), shape);
+        assert_eq!(RnsBasis::new(&[3, 5, 7, 11]), shape);
+        assert_eq!(RnsBasis::new(&[6, 9]), shape);
+        assert_eq!(RnsBasis::new(&[u64::MAX - 1, u64::MAX - 2, 3]), shape);
+        assert_eq!(RnsBasis::new(&[1, 3]), Err(SimplePirError::InvalidModulus(1)));
+        assert_eq!(inv_mod(3, 7), Some(5));
+        assert_eq!(inv_mod(4, 8), None);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::element::Element;
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, ConstantTimeGreater};
+
+/// Compute the scaling factor Δ = ⌊q / p⌋ used to lift a plaintext mod p into Z_q.
+pub fn delta_floor(q: u64, p: u64) -> u64 {
+    assert!(p > 0 && p <= q);
+    q / p
+}
+
+/// Compute the scaling factor Δ = round(q / p), rounding halves up.
+pub fn delta_round(q: u64, p: u64) -> u64 {
+    assert!(p > 0 && p <= q);
+    ((2 * q as u128 + p as u128) / (2 * p as u128)) as u64
+}
+
+/// Lift a plaintext mod p to ⌊q / p⌋ * m mod q. The product is below q, so no reduction (and no
+/// division depending on m) is needed.
+pub fn scale_floor(m: &Element, q: u64) -> Element {
+    Element::from(q, delta_floor(q, m.q) * m.uint)
+}
+
+/// Lift a plaintext mod p to round(q / p) * m mod q.
+pub fn scale_round(m: &Element, q: u64) -> Element {
+    Element::zero(q) + delta_round(q, m.q) * m.uint
+}
+
+// Compute (n / d, n % d) for d < 2^127 by binary long division. It always runs 128 iterations
+// and selects rather than branches, unlike hardware division whose timing depends on n.
+fn ct_div_rem(n: u128, d: u128) -> (u128, u128) {
+    let mut quo = 0u128;
+    let mut rem = 0u128;
+    for i in (0..128).rev() {
+        rem = (rem << 1) | ((n >> i) & 1);
+        let (diff, borrow) = rem.overflowing_sub(d);
+        let fits = !Choice::from(borrow as u8);
+        rem = u128::conditional_select(&rem, &diff, fits);
+        quo |= (fits.unwrap_u8() as u128) << i;
+    }
+    (quo, rem)
+}
+
+/// Recover a plaintext mod p from a noisy value v mod q by computing round(p * v / q) mod p. This
+/// is the inverse of scale_floor() and scale_round() as long as the noise is below q / 2p. The
+/// rounding runs in constant time, since v is a decryption of secret data.
+pub fn unscale_round(v: &Element, p: u64) -> Element {
+    let q = v.q as u128;
+    let (x, _) = ct_div_rem(2 * v.uint as u128 * p as u128 + q, 2 * q);
+    // x is at most p, which wraps around to 0
+    let x = x as u64;
+    Element::from(p, u64::conditional_select(&x, &0, x.ct_eq(&p)))
+}
+
+/// Recover a plaintext mod p from a value v mod q by computing ⌊v / ⌊q / p⌋⌋ mod p. This is the
+/// inverse of scale_floor() when the noise is known to be non-negative and below ⌊q / p⌋.
+pub fn unscale_floor(v: &Element, p: u64) -> Element {
+    let x = v.uint / delta_floor(v.q, p);
+    Element::from(p, x % p)
+}
+
+/// Whether v mod q lies within bound of round(q / p) * m, i.e. whether a value which decrypts to
+/// the plaintext m mod p carries noise of at most bound. Runs in constant time, like
+/// unscale_round().
+pub fn noise_within(v: &Element, m: &Element, bound: u64) -> Choice {
+    let noise = v.clone() - scale_round(m, v.q);
+    let negated = v.q - noise.uint;
+    let magnitude = u64::conditional_select(&noise.uint, &negated, noise.uint.ct_gt(&(v.q / 2)));
+    !magnitude.ct_gt(&bound)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta() {
+        assert_eq!(delta_floor(3329, 2), 1664);
+        assert_eq!(delta_round(3329, 2), 1665);
+        assert_eq!(delta_floor(3329, 3), 1109);
+        assert_eq!(delta_round(3329, 3), 1110);
+        assert_eq!(delta_floor(3328, 2), 1664);
+        assert_eq!(delta_round(3328, 2), 1664);
+        assert_eq!(delta_round(1 << 32, 991), 4333973);
+    }
+
+    #[test]
+    fn test_ct_div_rem() {
+        let n = (1u128 << 100) + 12345;
+        for d in [1u128, 2, 3, 3329, 1 << 64, (1 << 65) - 1] {
+            assert_eq!(ct_div_rem(n, d), (n / d, n % d));
+            assert_eq!(ct_div_rem(d - 1, d), (0, d - 1));
+        }
+    }
+
+    #[test]
+    fn test_scale_unscale() {
+        for (q, p) in [(3329, 2), (3329, 3), (3329, 16), (1 << 32, 991)] {
+            for m in 0..p {
+                let m = Element::from(p, m);
+                for noise in [0u64, 1, 5] {
+                    let f = scale_floor(&m, q);
+                    assert_eq!(unscale_round(&(f.clone() + noise), p), m);
+                    assert_eq!(unscale_round(&(f.clone() - noise), p), m);
+                    assert_eq!(unscale_floor(&(f + noise), p), m);
+
+                    let r = scale_round(&m, q);
+                    assert_eq!(unscale_round(&(r.clone() + noise), p), m);
+                    assert_eq!(unscale_round(&(r - noise), p), m);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Range;
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::matrix::Matrix;
+use crate::pir::{Answer, PublicHint, Query};
+use crate::regev::check_matrix_mod;
+use crate::simplepir::{self, Hint, SimplePIRParams};
+
+/// A worker which holds one row shard of the database, i.e. a run of consecutive rows of every
+/// column, and answers the slice of a query covering those rows. Remote nodes implement this by
+/// forwarding the slice over their own transport.
+pub trait ShardWorker: Send + Sync {
+    /// The partial answer for the query slice: one entry per database column, mod the modulus of
+    /// the query.
+    fn answer(&self, query: &[Element]) -> Result<Matrix, SimplePirError>;
+}
+
+/// A worker which scans its shard on the calling thread.
+pub struct LocalShard(pub Matrix);
+
+impl ShardWorker for LocalShard {
+    fn answer(&self, query: &[Element]) -> Result<Matrix, SimplePirError> {
+        simplepir::answer(query, &self.0)
+    }
+}
+
+/// Split the rows of a database into num_shards runs of about the same length, returning each
+/// run and the database restricted to it. Rounding the run length up can leave fewer shards, as
+/// does a database with fewer rows than num_shards.
+pub fn split_rows(
+    db: &Matrix,
+    num_shards: usize,
+) -> Result<Vec<(Range<usize>, Matrix)>, SimplePirError> {
+    if num_shards == 0 {
+        return Err(SimplePirError::Empty("shard set"));
+    }
+    let num_rows = db.num_rows();
+    let shard_rows = num_rows.div_ceil(num_shards).max(1);
+    Ok((0..num_rows).step_by(shard_rows).map(|start| {
+        let rows = start..(start + shard_rows).min(num_rows);
+        let values = db.cols().flat_map(|col| &col[rows.clone()]).copied().collect();
+        let shard = Matrix::from_values(db.q(), db.num_cols(), rows.len(), values);
+        (rows, shard)
+    }).collect())
+}
+
+/// A server whose database is split into row shards, each answered by its own worker. Answers
+/// are linear in the query, so each worker answers the slice of the query covering its rows and
+/// the partial answers sum to the answer over the whole database. The workers run concurrently,
+/// so the latency of an answer is that of scanning the largest shard.
+pub struct ShardedServer {
+    params: SimplePIRParams,
+    hint: Hint,
+    // The rows of each shard, which tile 0..m in order
+    rows: Vec<Range<usize>>,
+    workers: Vec<Box<dyn ShardWorker>>,
+}
+
+impl ShardedServer {
+    /// Lay out the items as Server::setup() does, compute the hint over the whole database and
+    /// split the database into num_shards row shards, each answered by a LocalShard.
+    pub fn setup(
+        params: SimplePIRParams,
+        items: &[Element],
+        num_shards: usize,
+    ) -> Result<Self, SimplePirError> {
+        let (db, hint) = simplepir::setup(&params, items)?;
+        let (rows, workers) = split_rows(&db, num_shards)?.into_iter()
+            .map(|(rows, shard)| (rows, Box::new(LocalShard(shard)) as Box<dyn ShardWorker>))
+            .unzip();
+        Self::with_workers(params, hint, rows, workers)
+    }
+
+    /// Serve a database whose shards are held by the given workers, e.g. on remote nodes which
+    /// were each sent their part of split_rows(). The hint must be that of the whole database.
+    /// Shards which do not tile the m rows of the database in order, with one worker each, are
+    /// rejected as an invalid shard layout.
+    pub fn with_workers(
+        params: SimplePIRParams,
+        hint: Hint,
+        rows: Vec<Range<usize>>,
+        workers: Vec<Box<dyn ShardWorker>>,
+    ) -> Result<Self, SimplePirError> {
+        if rows.len() != workers.len() {
+            return Err(SimplePirError::LengthMismatch {
+                expected: rows.len(),
+                actual: workers.len(),
+            });
+        }
+        if rows.is_empty() {
+            return Err(SimplePirError::Empty("shard set"));
+        }
+        let mut end = 0;
+        for range in &rows {
+            if range.start != end || range.end <= end {
+                return Err(SimplePirError::InvalidShape("shard layout"));
+            }
+            end = range.end;
+        }
+        if end != params.m {
+            return Err(SimplePirError::InvalidShape("shard layout"));
+        }
+        simplepir::check_hint(&params, &hint)?;
+        Ok(Self { params, hint, rows, workers })
+    }
+
+    /// The parameters which clients must use.
+    pub fn params(&self) -> &SimplePIRParams {
+        &self.params
+    }
+
+    /// The hint which clients must download before querying.
+    pub fn hint(&self) -> &Hint {
+        &self.hint
+    }
+
+    /// The params and hint as a single artifact, which can be served to every client.
+    pub fn public_hint(&self) -> PublicHint {
+        let (params, hint) = (self.params.clone(), self.hint.clone());
+        PublicHint { params, hint, layout: None, verifying_key: None }
+    }
+
+    /// The number of shards, and so of workers.
+    pub fn num_shards(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Answer a query by fanning its slices out to the workers, one thread each, and summing
+    /// their partial answers. The answer is the same as that of a Server over the whole database.
+    pub fn answer(&self, query: &Query) -> Result<Answer, SimplePirError> {
+        let first = query.0.first().ok_or(SimplePirError::Empty("query"))?;
+        if first.q != self.params.q {
+            let expected = self.params.q;
+            return Err(SimplePirError::ModulusMismatch { expected, actual: first.q });
+        }
+        if query.0.len() != self.params.m {
+            return Err(SimplePirError::LengthMismatch {
+                expected: self.params.m,
+                actual: query.0.len(),
+            });
+        }
+
+        let partials: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = self.rows.iter().zip(&self.workers)
+                .map(|(rows, worker)| scope.spawn(|| worker.answer(&query.0[rows.clone()])))
+                .collect();
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| {
+                    Err(SimplePirError::Storage("a shard worker panicked".into()))
+                }))
+                .collect()
+        });
+
+        let mut answer: Option<Matrix> = None;
+        for partial in partials {
+            let partial = partial?;
+            check_matrix_mod(&partial, self.params.q)?;
+            answer = Some(match answer {
+                Some(answer) => answer.try_add(partial)?,
+                None => partial,
+            });
+        }
+        Ok(Answer(answer.unwrap()))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::pir::{Client, Server};
+
+    fn gen_items(db_size: usize, p: u64) -> Vec<Element> {
+        (0..db_size).map(|_| Element::gen_uniform_rand(p)).collect()
+    }
+
+    #[test]
+    fn test_split_rows() {
+        let db = Matrix::gen_uniform_rand(7, 3, 10);
+        let shards = split_rows(&db, 4).unwrap();
+        let rows: Vec<_> = shards.iter().map(|(rows, _)| rows.clone()).collect();
+        assert_eq!(rows, vec![0..3, 3..6, 6..9, 9..10]);
+        for (rows, shard) in &shards {
+            assert_eq!(shard.dimensions(), (3, rows.len()));
+            for i in 0..3 {
+                assert_eq!(shard.col(i), db.col(i)[rows.clone()]);
+            }
+        }
+        assert_eq!(split_rows(&db, 20).unwrap().len(), 10);
+        assert_eq!(split_rows(&db, 0), Err(SimplePirError::Empty("shard set")));
+    }
+
+    #[test]
+    fn test_sharded_server() {
+        let db_size = 200;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items = gen_items(db_size, params.p);
+        let server = Server::setup(params.clone(), &items).unwrap();
+
+        for num_shards in [1, 3, params.m] {
+            let sharded = ShardedServer::setup(params.clone(), &items, num_shards).unwrap();
+            assert_eq!(sharded.num_shards(), num_shards);
+            assert_eq!(sharded.hint(), server.hint());
+            let mut client = Client::from_public_hint(sharded.public_hint()).unwrap();
+            for idx in [0, 57, 199] {
+                let query = client.query(idx).unwrap();
+                let answer = sharded.answer(&query).unwrap();
+                assert_eq!(answer, server.answer(&query).unwrap());
+                assert_eq!(client.recover(&answer).unwrap(), items[idx]);
+            }
+        }
+    }
+
+    struct FailingShard;
+
+    impl ShardWorker for FailingShard {
+        fn answer(&self, _: &[Element]) -> Result<Matrix, SimplePirError> {
+            Err(SimplePirError::Storage("the node is unreachable".into()))
+        }
+    }
+
+    struct PanickingShard;
+
+    impl ShardWorker for PanickingShard {
+        fn answer(&self, _: &[Element]) -> Result<Matrix, SimplePirError> {
+            panic!("the shard is corrupt")
+        }
+    }
+
+    #[test]
+    fn test_sharded_server_errors() {
+        let params = simplepir::gen_insecure_test_params_for_size(16, 2);
+        let (db, hint) = simplepir::setup(&params, &gen_items(16, 2)).unwrap();
+        let mut shards = split_rows(&db, 2).unwrap();
+        let rows: Vec<_> = shards.iter().map(|(rows, _)| rows.clone()).collect();
+        let first = LocalShard(shards.swap_remove(0).1);
+        let workers: Vec<Box<dyn ShardWorker>> = vec![Box::new(first), Box::new(FailingShard)];
+        let with_workers = |rows: &[Range<usize>], workers| {
+            ShardedServer::with_workers(params.clone(), hint.clone(), rows.to_vec(), workers)
+        };
+        let sharded = with_workers(&rows, workers).unwrap();
+
+        let query = Query(vec![Element::from(params.q, 1); params.m]);
+        assert!(matches!(sharded.answer(&query), Err(SimplePirError::Storage(_))));
+        let short = Query(vec![Element::from(params.q, 1); params.m - 1]);
+        assert!(matches!(sharded.answer(&short), Err(SimplePirError::LengthMismatch { .. })));
+        assert!(matches!(sharded.answer(&Query(vec![])), Err(SimplePirError::Empty(_))));
+
+        // A worker which panics fails the answer rather than the server
+        let workers: Vec<Box<dyn ShardWorker>> =
+            vec![Box::new(PanickingShard), Box::new(FailingShard)];
+        let sharded = with_workers(&rows, workers).unwrap();
+        let err = SimplePirError::Storage("a shard worker panicked".into());
+        assert_eq!(sharded.answer(&query).err(), Some(err));
+
+        // Shards which do not tile the rows, or have no worker each, are refused
+        let layout = Some(SimplePirError::InvalidShape("shard layout"));
+        let one = || -> Vec<Box<dyn ShardWorker>> { vec![Box::new(FailingShard)] };
+        let two = || -> Vec<Box<dyn ShardWorker>> {
+            vec![Box::new(FailingShard), Box::new(FailingShard)]
+        };
+        assert_eq!(with_workers(&rows[..1], one()).err(), layout);
+        assert_eq!(with_workers(&[rows[1].clone(), rows[0].clone()], two()).err(), layout);
+        assert_eq!(
+            with_workers(&rows, one()).err(),
+            Some(SimplePirError::LengthMismatch { expected: 2, actual: 1 }),
+        );
+        assert_eq!(with_workers(&[], vec![]).err(), Some(SimplePirError::Empty("shard set")));
+        let result = ShardedServer::with_workers(params, Hint(Matrix::new()), rows, two());
+        assert_eq!(result.err(), Some(SimplePirError::InvalidShape("hint")));
+    }
+}
@@ -0,0 +1,162 @@
+// Ed25519 signatures over answers and public hint manifests, so that a client reached through an
+// untrusted transport or CDN can detect answers and hints which were tampered with on the way.
+//
+// A signed answer covers the params digest, the epoch, the time the epoch's database was built, the
+// tag binding the answer to its query and the SHA-256 digest of the answer's wire encoding. A
+// manifest covers the epoch, the build time and the digest of the public hint. The build time
+// attests to the freshness of the data, so that a client can reject answers and hints built longer
+// ago than its policy allows (e.g. a day for a blocklist). Each message starts with its own domain,
+// so that a signature over one can never pass as the other. The verifying key is published in the
+// public hint, whose digest the client checks against the one obtained from the server.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest as _, Sha256};
+use crate::error::SimplePirError;
+use crate::merkle::Digest;
+use crate::pir::TaggedAnswer;
+use crate::wire;
+
+// The domains of the two kinds of signed message
+const ANSWER_DOMAIN: &[u8] = b"simplepir-rs/signed-answer";
+const MANIFEST_DOMAIN: &[u8] = b"simplepir-rs/signed-manifest";
+
+/// The bytes of an Ed25519 verifying key.
+pub type VerifyingKeyBytes = [u8; 32];
+
+/// A tagged answer signed by the server.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignedAnswer {
+    pub tagged: TaggedAnswer,
+    /// When the database of the answer's epoch was built, in seconds since the Unix epoch.
+    pub built_at: u64,
+    #[cfg_attr(feature = "serde", serde(with = "signature_bytes"))]
+    pub signature: [u8; 64],
+}
+
+/// The digest of a public hint in a given epoch, signed by the server. Clients downloading the
+/// public hint from a CDN check its digest against a manifest fetched from the server or the CDN.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignedManifest {
+    pub epoch: u64,
+    /// When the database of the epoch was built, in seconds since the Unix epoch.
+    pub built_at: u64,
+    pub digest: Digest,
+    #[cfg_attr(feature = "serde", serde(with = "signature_bytes"))]
+    pub signature: [u8; 64],
+}
+
+// The message signed for an answer under params with the given digest
+fn answer_message(params_digest: &Digest, built_at: u64, tagged: &TaggedAnswer) -> Vec<u8> {
+    let answer: Digest = Sha256::digest(wire::encode_answer(&tagged.answer)).into();
+    let mut message = ANSWER_DOMAIN.to_vec();
+    message.extend_from_slice(params_digest);
+    message.extend_from_slice(&tagged.epoch.to_le_bytes());
+    message.extend_from_slice(&built_at.to_le_bytes());
+    message.extend_from_slice(&tagged.digest);
+    message.extend_from_slice(&answer);
+    message
+}
+
+// The message signed for a manifest
+fn manifest_message(epoch: u64, built_at: u64, digest: &Digest) -> Vec<u8> {
+    let mut message = MANIFEST_DOMAIN.to_vec();
+    message.extend_from_slice(&epoch.to_le_bytes());
+    message.extend_from_slice(&built_at.to_le_bytes());
+    message.extend_from_slice(digest);
+    message
+}
+
+/// Sign a tagged answer computed under params with the given digest, from a database built at
+/// the given time.
+pub fn sign_answer(
+    key: &SigningKey,
+    params_digest: &Digest,
+    built_at: u64,
+    tagged: TaggedAnswer,
+) -> SignedAnswer {
+    let signature = key.sign(&answer_message(params_digest, built_at, &tagged)).to_bytes();
+    SignedAnswer { tagged, built_at, signature }
+}
+
+/// Check the signature of an answer computed under params with the given digest.
+pub fn verify_answer(
+    key: &VerifyingKeyBytes,
+    params_digest: &Digest,
+    signed: &SignedAnswer,
+) -> Result<(), SimplePirError> {
+    let message = answer_message(params_digest, signed.built_at, &signed.tagged);
+    verify(key, &message, &signed.signature)
+}
+
+/// Sign the digest of a public hint in the given epoch, whose database was built at the given
+/// time.
+pub fn sign_manifest(
+    key: &SigningKey,
+    epoch: u64,
+    built_at: u64,
+    digest: Digest,
+) -> SignedManifest {
+    let signature = key.sign(&manifest_message(epoch, built_at, &digest)).to_bytes();
+    SignedManifest { epoch, built_at, digest, signature }
+}
+
+/// Check that data built at built_at is at most max_age old at now, both in seconds since the
+/// Unix epoch, failing with Stale otherwise. Data built after now (e.g. by a server whose clock
+/// is ahead) counts as new.
+pub fn check_freshness(built_at: u64, now: u64, max_age: Duration) -> Result<(), SimplePirError> {
+    let age = Duration::from_secs(now.saturating_sub(built_at));
+    match age <= max_age {
+        true => Ok(()),
+        false => Err(SimplePirError::Stale { age, max_age }),
+    }
+}
+
+impl SignedManifest {
+    /// Check the signature of the manifest.
+    pub fn verify(&self, key: &VerifyingKeyBytes) -> Result<(), SimplePirError> {
+        let message = manifest_message(self.epoch, self.built_at, &self.digest);
+        verify(key, &message, &self.signature)
+    }
+
+    /// Check the signature of the manifest, and that the hint it covers was built at most
+    /// max_age before now, in seconds since the Unix epoch.
+    pub fn verify_fresh(
+        &self,
+        key: &VerifyingKeyBytes,
+        now: u64,
+        max_age: Duration,
+    ) -> Result<(), SimplePirError> {
+        self.verify(key)?;
+        check_freshness(self.built_at, now, max_age)
+    }
+}
+
+// Check a signature over a message, rejecting keys which are not curve points
+fn verify(
+    key: &VerifyingKeyBytes,
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Result<(), SimplePirError> {
+    let key = VerifyingKey::from_bytes(key).map_err(|_| SimplePirError::InvalidSignature)?;
+    key.verify(message, &Signature::from_bytes(signature))
+        .map_err(|_| SimplePirError::InvalidSignature)
+}
+
+// Serde only derives arrays of up to 32 elements, so signatures go through a byte vector
+#[cfg(feature = "serde")]
+mod signature_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 64], D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        bytes.try_into().map_err(|_| serde::de::Error::custom("a signature is 64 bytes"))
+    }
+}
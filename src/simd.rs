@@ -0,0 +1,116 @@
+// Vectorized inner products mod q for q < 2^32.
+//
+// Each product of two values below 2^32 fits in a u64. Rather than reducing after every
+// multiply-add, the low and high 32-bit halves of each product are accumulated separately in u64
+// lanes, which cannot overflow for fewer than 2^32 terms. The two sums are combined and reduced
+// once at the end.
+
+// The largest modulus supported by the kernels
+pub(crate) const MAX_Q: u64 = 1 << 32;
+
+/// Compute sum(a[i] * b[i]) mod q. All values must be below q and q must not exceed 2^32.
+pub(crate) fn dot_mod(a: &[u64], b: &[u64], q: u64) -> u64 {
+    assert_eq!(a.len(), b.len());
+    assert!(q <= MAX_Q);
+    assert!(a.len() < (1 << 32));
+
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safety: AVX2 support was just checked
+        let (lo, hi) = unsafe { dot_avx2(a, b) };
+        return combine(lo, hi, q);
+    }
+
+    let (lo, hi) = dot_scalar(a, b);
+    combine(lo, hi, q)
+}
+
+fn combine(lo: u64, hi: u64, q: u64) -> u64 {
+    let q = q as u128;
+    (((hi as u128 % q) << 32) + lo as u128 % q) as u64 % q as u64
+}
+
+// Four independent lanes so that the compiler can vectorize the loop on any target
+fn dot_scalar(a: &[u64], b: &[u64]) -> (u64, u64) {
+    let mut lo = [0u64; 4];
+    let mut hi = [0u64; 4];
+    let mut a_chunks = a.chunks_exact(4);
+    let mut b_chunks = b.chunks_exact(4);
+    for (x, y) in (&mut a_chunks).zip(&mut b_chunks) {
+        for l in 0..4 {
+            let p = x[l] * y[l];
+            lo[l] += p & 0xffffffff;
+            hi[l] += p >> 32;
+        }
+    }
+    let mut lo_sum: u64 = lo.iter().sum();
+    let mut hi_sum: u64 = hi.iter().sum();
+    for (x, y) in a_chunks.remainder().iter().zip(b_chunks.remainder()) {
+        let p = x * y;
+        lo_sum += p & 0xffffffff;
+        hi_sum += p >> 32;
+    }
+    (lo_sum, hi_sum)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_avx2(a: &[u64], b: &[u64]) -> (u64, u64) {
+    use std::arch::x86_64::*;
+
+    let mask = _mm256_set1_epi64x(0xffffffff);
+    let mut lo = _mm256_setzero_si256();
+    let mut hi = _mm256_setzero_si256();
+
+    let chunks = a.len() / 4;
+    for i in 0..chunks {
+        let x = _mm256_loadu_si256(a.as_ptr().add(4 * i) as *const __m256i);
+        let y = _mm256_loadu_si256(b.as_ptr().add(4 * i) as *const __m256i);
+        // Multiplies the low 32 bits of each 64-bit lane, which hold the whole value
+        let p = _mm256_mul_epu32(x, y);
+        lo = _mm256_add_epi64(lo, _mm256_and_si256(p, mask));
+        hi = _mm256_add_epi64(hi, _mm256_srli_epi64(p, 32));
+    }
+
+    let mut lo_lanes = [0u64; 4];
+    let mut hi_lanes = [0u64; 4];
+    _mm256_storeu_si256(lo_lanes.as_mut_ptr() as *mut __m256i, lo);
+    _mm256_storeu_si256(hi_lanes.as_mut_ptr() as *mut __m256i, hi);
+
+    let (tail_lo, tail_hi) = dot_scalar(&a[4 * chunks..], &b[4 * chunks..]);
+    (
+        lo_lanes.iter().sum::<u64>() + tail_lo,
+        hi_lanes.iter().sum::<u64>() + tail_hi,
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    fn reference(a: &[u64], b: &[u64], q: u64) -> u64 {
+        a.iter().zip(b).fold(0u128, |acc, (x, y)| {
+            (acc + *x as u128 * *y as u128) % q as u128
+        }) as u64
+    }
+
+    #[test]
+    fn test_dot_mod() {
+        let mut rng = ChaCha20Rng::from_seed([1u8; 32]);
+        for q in [2, 3329, 4294967291, 1 << 32] {
+            for len in [0, 1, 3, 4, 5, 64, 1001] {
+                let a: Vec<u64> = (0..len).map(|_| rng.gen_range(0..q)).collect();
+                let b: Vec<u64> = (0..len).map(|_| rng.gen_range(0..q)).collect();
+                assert_eq!(dot_mod(&a, &b, q), reference(&a, &b, q));
+
+                let max = vec![q - 1; len];
+                assert_eq!(dot_mod(&max, &max, q), reference(&max, &max, q));
+
+                let (lo, hi) = dot_scalar(&a, &b);
+                assert_eq!(combine(lo, hi, q), reference(&a, &b, q));
+            }
+        }
+    }
+}
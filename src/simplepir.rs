@@ -1,7 +1,44 @@
+use alloc::borrow::ToOwned;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+use rand::{CryptoRng, RngCore, SeedableRng};
+#[cfg(feature = "std")]
+use rand::rngs::StdRng;
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest as _, Sha256};
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::Zeroizing;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::arena::Arena;
+use crate::bitpacked::BitPackedMatrix;
+use crate::compact::CompactMatrix;
+use crate::sparse::SparseMatrix;
+use crate::database::{check_range, Database};
+#[cfg(feature = "gpu")]
+use crate::gpu::{self, GpuDatabase};
 use crate::matrix::Matrix;
+use crate::merkle::Digest;
 use crate::element::Element;
-use crate::regev::gen_error_vec;
+use crate::error::{ShapeError, SimplePirError};
+#[cfg(feature = "std")]
+use crate::element::Q32;
+#[cfg(feature = "std")]
+use crate::record::{bits_per_limb, num_rows};
+use crate::regev::{
+    check_matrix_mod, check_moduli, check_vector_mod, error_bound, gen_gaussian_error_vec_with_rng,
+    NoiseBudget, QuerySecret, SecretDistribution, SecretKey,
+};
+use crate::scaling::{delta_floor, noise_within, scale_floor, unscale_round};
+use crate::wire::{self, WireError};
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SimplePIRParams {
     // Public A matrix
     pub a: Matrix,
@@ -13,22 +50,542 @@ pub struct SimplePIRParams {
     pub n: usize,
     // The number of samples or the width of the (square) database
     pub m: usize,
-    // The standard deviation for sampling random elements
+    // The standard deviation of the query errors
     pub std_dev: f64,
+    // The distribution from which LWE secrets are sampled
+    pub secret_dist: SecretDistribution,
+    // The seed which A was expanded from, if any, so that the seed can be sent instead of A
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seed: Option<[u8; 32]>,
 }
 
-pub fn gen_params() -> SimplePIRParams {
-    let m = 8;
+// A vetted LWE parameter set, secure for up to max_samples samples (i.e. database rows)
+#[cfg(feature = "std")]
+pub(crate) struct SecurityLevel {
+    bits: u32,
+    pub(crate) n: usize,
+    pub(crate) q: u64,
+    pub(crate) std_dev: f64,
+    pub(crate) max_samples: usize,
+    // The plaintext moduli which the paper pairs with the set, each with the log2 of the most
+    // samples for which it decrypts correctly. See SimplePIRParams::simplepir_1024_q32_p991().
+    paper_moduli: &'static [(u64, u32)],
+}
+
+// LWE parameter sets by increasing security. These are the sets chosen in the SimplePIR paper
+// (Henzinger et al., "One Server for the Price of Two: Simple and Fast Single-Server Private
+// Information Retrieval", USENIX Security 2023, Section 4.2), whose security was estimated with
+// the lattice-estimator of Albrecht, Player and Scott for a Gaussian error and at most max_samples
+// samples. Add sets here only together with their estimator output.
+#[cfg(feature = "std")]
+const SECURITY_LEVELS: &[SecurityLevel] = &[
+    SecurityLevel {
+        bits: 128,
+        n: 1024,
+        q: Q32,
+        std_dev: 6.4,
+        max_samples: 1 << 20,
+        paper_moduli: &[
+            (991, 13), (833, 14), (701, 15), (589, 16), (495, 17), (416, 18), (350, 19), (294, 20),
+        ],
+    },
+];
+
+// The noise of an answer entry is a sum of m errors of standard deviation std_dev, each multiplied
+// by an item below p, so it is close to a Gaussian with standard deviation at most
+// std_dev * sqrt(m) * p / sqrt(3). Flooring q / p adds at most p more, and decryption is correct
+// while the total stays below q / 2p. A Gaussian exceeds CORRECTNESS_Z standard deviations with
+// probability below 2^-40.
+const CORRECTNESS_Z: f64 = 7.2;
+
+// The largest plaintext modulus for which answers with m rows decrypt correctly, except with
+// probability 2^-40 per entry.
+#[cfg(feature = "std")]
+pub(crate) fn max_plaintext_mod(q: u64, m: usize, std_dev: f64) -> u64 {
+    let spread = CORRECTNESS_Z * std_dev * (m as f64).sqrt() / 3f64.sqrt();
+    let fits = |p: u64| (spread + 1.0) * (p as f64) < q as f64 / (2 * p) as f64;
+    let mut p = (q as f64 / (2.0 * spread)).sqrt() as u64;
+    while p > 2 && !fits(p) {
+        p -= 1;
+    }
+    p
+}
+
+/// The plaintext moduli of the SimplePIR paper's presets, see SimplePIRParams::paper_preset().
+#[cfg(feature = "std")]
+pub fn paper_moduli() -> Vec<u64> {
+    SECURITY_LEVELS.iter().flat_map(|level| level.paper_moduli.iter().map(|&(p, _)| p)).collect()
+}
+
+// The weakest vetted parameter set with at least `bits` of security
+#[cfg(feature = "std")]
+pub(crate) fn security_level(bits: u32) -> Result<&'static SecurityLevel, SimplePirError> {
+    SECURITY_LEVELS.iter()
+        .find(|level| level.bits >= bits)
+        .ok_or(SimplePirError::UnsupportedSecurity(bits))
+}
+
+impl SimplePIRParams {
+    /// Estimate the noise in each entry of an answer over a database of db_size items. An entry
+    /// accumulates one query error per row of its column, multiplied by the item in that row.
+    /// The standard deviation assumes items uniform mod p and the maximum assumes items of p - 1.
+    /// Flooring q / p adds at most p more.
+    pub fn noise_budget(&self, db_size: usize) -> NoiseBudget {
+        let rows = db_size.min(self.m) as f64;
+        let p = self.p as f64;
+        // The mean square of an item uniform in [0, p)
+        let item_square = (p - 1.0) * (2.0 * p - 1.0) / 6.0;
+        NoiseBudget {
+            std_dev: self.std_dev * (rows * item_square).sqrt(),
+            max: error_bound(self.std_dev) as f64 * rows * (p - 1.0) + p,
+            threshold: self.q as f64 / (2.0 * p),
+        }
+    }
+
+    /// The noise budget of an answer over db_size items to a query which the client mod-switched
+    /// from q down to q_prime and the server answered mod q_prime. Switching scales the noise of
+    /// the query by q_prime / q and rounds each entry by at most 1/2, which adds up to (p - 1) / 2
+    /// per row once multiplied by the items. Switching hint * s down adds at most 1 more. A
+    /// q_prime above q or not above p is rejected as an invalid modulus.
+    pub fn noise_budget_switched(
+        &self,
+        db_size: usize,
+        q_prime: u64,
+    ) -> Result<NoiseBudget, SimplePirError> {
+        check_switch_mod(q_prime, self.q, self.p)?;
+        let budget = self.noise_budget(db_size);
+        let rows = db_size.min(self.m) as f64;
+        let p = self.p as f64;
+        let scale = q_prime as f64 / self.q as f64;
+        // The rounding of each entry is close to uniform in [-1/2, 1/2], of variance 1/12
+        let item_square = (p - 1.0) * (2.0 * p - 1.0) / 6.0;
+        let rounding_var = rows * item_square / 12.0;
+        Ok(NoiseBudget {
+            std_dev: (scale * scale * budget.std_dev * budget.std_dev + rounding_var).sqrt(),
+            max: scale * budget.max + rows * (p - 1.0) / 2.0 + 1.0,
+            threshold: q_prime as f64 / (2.0 * p),
+        })
+    }
+
+    /// An upper bound on the probability that an answer entry over a database of db_size items
+    /// mod record_mod decrypts incorrectly. The noise is a sum of independent Gaussian errors
+    /// weighted by the items, so it exceeds t with probability at most 2 exp(-t^2 / 2v), where v
+    /// is its variance when every item is record_mod - 1 and t is the noise which decryption
+    /// tolerates. The bound is 0 if even the largest possible noise is tolerated.
+    pub fn failure_probability(&self, db_size: usize, record_mod: u64) -> f64 {
+        assert!(record_mod >= 2 && record_mod <= self.q);
+        let rows = db_size.min(self.m) as f64;
+        let item = (record_mod - 1) as f64;
+        // Flooring q / p takes up to p of the tolerated noise
+        let t = self.q as f64 / (2 * record_mod) as f64 - record_mod as f64;
+        if t <= 0.0 {
+            return 1.0;
+        }
+        if error_bound(self.std_dev) as f64 * rows * item < t {
+            return 0.0;
+        }
+        let variance = self.std_dev * self.std_dev * rows * item * item;
+        (2.0 * (-t * t / (2.0 * variance)).exp()).min(1.0)
+    }
+
+    /// Estimate the failure probability by decrypting `trials` simulated answer entries, with
+    /// errors sampled as in queries and items uniform mod record_mod. This measures the actual
+    /// rate for typical items, to validate failure_probability() against.
+    pub fn simulate_failure_rate<R: CryptoRng + RngCore>(
+        &self,
+        db_size: usize,
+        record_mod: u64,
+        trials: usize,
+        rng: &mut R,
+    ) -> f64 {
+        assert!(record_mod >= 2 && record_mod <= self.q);
+        let rows = db_size.min(self.m);
+        let bound = error_bound(self.std_dev);
+        let mut failures = 0;
+        for _ in 0..trials {
+            let e = gen_gaussian_error_vec_with_rng(self.q, rows, self.std_dev, bound, rng);
+            let noise = e.to_elements().iter().fold(Element::zero(self.q), |acc, e| {
+                acc + e.clone() * Element::gen_uniform_rand_with_rng(record_mod, rng).uint
+            });
+            let item = Element::gen_uniform_rand_with_rng(record_mod, rng);
+            if unscale_round(&(scale_floor(&item, self.q) + noise), record_mod) != item {
+                failures += 1;
+            }
+        }
+        failures as f64 / trials as f64
+    }
+
+    /// Choose parameters with at least `bits` of security for a database of db_size records of
+    /// record_bits bits each. The LWE dimension, modulus and error are taken from a vetted set
+    /// (see SECURITY_LEVELS), and p is the largest plaintext modulus for which answers decrypt
+    /// correctly except with probability 2^-40 per entry. Records which do not fit in one limb
+    /// mod p are laid out as by RecordLayout, so pass record_bits = 8 * record_len for byte
+    /// records.
+    #[cfg(feature = "std")]
+    pub fn for_security(
+        bits: u32,
+        db_size: usize,
+        record_bits: usize,
+    ) -> Result<Self, SimplePirError> {
+        if db_size == 0 {
+            return Err(SimplePirError::Empty("database"));
+        }
+        if record_bits == 0 {
+            return Err(SimplePirError::Empty("record"));
+        }
+        let level = security_level(bits)?;
+
+        // p shrinks as the number of rows grows, which can split records into more limbs and so
+        // need more rows. Grow the number of rows until the two agree.
+        let mut rows = num_rows(db_size, 1);
+        let p = loop {
+            if rows > level.max_samples {
+                return Err(SimplePirError::TooManySamples { rows, max: level.max_samples });
+            }
+            let p = max_plaintext_mod(level.q, rows, level.std_dev);
+            let needed = num_rows(db_size, record_bits.div_ceil(bits_per_limb(p)));
+            if needed <= rows {
+                break p;
+            }
+            rows = needed;
+        };
+        Ok(Self::with_level(level, rows, p))
+    }
+
+    /// Choose parameters with at least `bits` of security for a database with m rows of items
+    /// mod p. The LWE dimension, modulus and error are taken from a vetted set (see
+    /// SECURITY_LEVELS). p is rejected as an invalid modulus if it is below 2 or too large for
+    /// answers over m rows to decrypt correctly except with probability 2^-40 per entry.
+    #[cfg(feature = "std")]
+    pub fn for_security_with_p(bits: u32, m: usize, p: u64) -> Result<Self, SimplePirError> {
+        if m == 0 {
+            return Err(SimplePirError::Empty("database"));
+        }
+        let level = security_level(bits)?;
+        if m > level.max_samples {
+            return Err(SimplePirError::TooManySamples { rows: m, max: level.max_samples });
+        }
+        if p < 2 || p > max_plaintext_mod(level.q, m, level.std_dev) {
+            return Err(SimplePirError::InvalidModulus(p));
+        }
+        Ok(Self::with_level(level, m, p))
+    }
+
+    /// The parameters of the SimplePIR paper with n = 1024, q = 2^32, σ = 6.4 and p = 991, for a
+    /// database of m ≤ 2^13 rows. The paper's bound on decryption errors is less conservative than
+    /// that of for_security(), which picks a slightly smaller p for the same number of rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p991(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 991)
+    }
+
+    /// As simplepir_1024_q32_p991(), with p = 833 for up to 2^14 rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p833(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 833)
+    }
+
+    /// As simplepir_1024_q32_p991(), with p = 701 for up to 2^15 rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p701(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 701)
+    }
+
+    /// As simplepir_1024_q32_p991(), with p = 589 for up to 2^16 rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p589(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 589)
+    }
+
+    /// As simplepir_1024_q32_p991(), with p = 495 for up to 2^17 rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p495(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 495)
+    }
+
+    /// As simplepir_1024_q32_p991(), with p = 416 for up to 2^18 rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p416(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 416)
+    }
+
+    /// As simplepir_1024_q32_p991(), with p = 350 for up to 2^19 rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p350(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 350)
+    }
+
+    /// As simplepir_1024_q32_p991(), with p = 294 for up to 2^20 rows.
+    #[cfg(feature = "std")]
+    pub fn simplepir_1024_q32_p294(m: usize) -> Result<Self, SimplePirError> {
+        Self::paper_preset(m, 294)
+    }
+
+    /// The vetted set which the paper pairs with plaintext modulus p (see paper_moduli()), with m
+    /// rows. A database with more rows than p is paired with is rejected with TooManySamples, and
+    /// a p which the paper does not use with InvalidModulus.
+    #[cfg(feature = "std")]
+    pub fn paper_preset(m: usize, p: u64) -> Result<Self, SimplePirError> {
+        if m == 0 {
+            return Err(SimplePirError::Empty("database"));
+        }
+        let (level, log_samples) = SECURITY_LEVELS.iter()
+            .find_map(|level| {
+                let &(_, log_samples) = level.paper_moduli.iter().find(|(q, _)| *q == p)?;
+                Some((level, log_samples))
+            })
+            .ok_or(SimplePirError::InvalidModulus(p))?;
+        let max = level.max_samples.min(1 << log_samples);
+        if m > max {
+            return Err(SimplePirError::TooManySamples { rows: m, max });
+        }
+        Ok(Self::with_level(level, m, p))
+    }
+
+    /// The bits of security of the params: those of the vetted set with their LWE dimension,
+    /// modulus and error, if there is one and it is secure for m samples. None for any other
+    /// params, e.g. insecure test params.
+    #[cfg(feature = "std")]
+    pub fn security_bits(&self) -> Option<u32> {
+        if self.secret_dist != SecretDistribution::Uniform {
+            return None;
+        }
+        SECURITY_LEVELS.iter()
+            .find(|level| {
+                (level.n, level.q, level.std_dev) == (self.n, self.q, self.std_dev)
+                    && self.m <= level.max_samples
+            })
+            .map(|level| level.bits)
+    }
+
+    // Parameters of the given set with m rows and plaintext modulus p
+    #[cfg(feature = "std")]
+    fn with_level(level: &SecurityLevel, m: usize, p: u64) -> Self {
+        let a = Matrix::gen_uniform_rand(level.q, m, level.n);
+        SimplePIRParams {
+            a,
+            q: level.q,
+            p,
+            n: level.n,
+            m,
+            std_dev: level.std_dev,
+            secret_dist: SecretDistribution::Uniform,
+            seed: None,
+        }
+    }
+
+    /// Construct parameters whose public A matrix is deterministically expanded from a 32-byte
+    /// seed with ChaCha20, so that clients only need the seed and the moduli.
+    pub fn from_seed(seed: [u8; 32], q: u64, p: u64, n: usize, m: usize, std_dev: f64) -> Self {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        let a = Matrix::gen_uniform_rand_with_rng(q, m, n, &mut rng);
+        let secret_dist = SecretDistribution::Uniform;
+        SimplePIRParams { a, q, p, n, m, std_dev, secret_dist, seed: Some(seed) }
+    }
+
+    /// Encode the params with the current version of the wire format, see wire::encode_params().
+    pub fn to_bytes(&self) -> Vec<u8> {
+        wire::encode_params(self)
+    }
+
+    /// Decode params encoded by to_bytes(), rejecting other versions of the format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        wire::decode_params(bytes)
+    }
+
+    /// A SHA-256 digest of the params, which a client and server compare to make sure that they
+    /// were set up with the same params. It covers the whole of A, so that params sent with their
+    /// seed and with A itself have the same digest.
+    pub fn digest(&self) -> Digest {
+        let mut hasher = Sha256::new();
+        hasher.update([wire::PARAMS_VERSION]);
+        for v in [self.q, self.p, self.n as u64, self.m as u64, self.std_dev.to_bits()] {
+            hasher.update(v.to_le_bytes());
+        }
+        let (kind, std_dev) = wire::secret_dist_kind(self.secret_dist);
+        hasher.update([kind]);
+        hasher.update(std_dev.to_bits().to_le_bytes());
+        hasher.update((self.a.num_cols() as u64).to_le_bytes());
+        for &v in self.a.values() {
+            hasher.update(v.to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+
+    /// Check that the params are consistent, as params received from the other party must be
+    /// before they are used: q and p are valid moduli with p ≤ q, n and m are non-zero, A is an
+    /// m x n matrix mod q, and the standard deviations are finite and positive.
+    pub fn validate(&self) -> Result<(), SimplePirError> {
+        if self.q < 2 || self.q == u64::MAX {
+            return Err(SimplePirError::InvalidModulus(self.q));
+        }
+        if self.p < 2 || self.p > self.q {
+            return Err(SimplePirError::InvalidModulus(self.p));
+        }
+        if self.n == 0 || self.m == 0 {
+            return Err(SimplePirError::InvalidShape("params"));
+        }
+        if self.a.dimensions() != (self.m, self.n) {
+            return Err(SimplePirError::InvalidShape("A matrix"));
+        }
+        check_matrix_mod(&self.a, self.q)?;
+        if !(self.std_dev.is_finite() && self.std_dev > 0.0) {
+            return Err(SimplePirError::InvalidStdDev(self.std_dev));
+        }
+        match self.secret_dist {
+            SecretDistribution::Gaussian(std_dev) if !(std_dev.is_finite() && std_dev > 0.0) => {
+                Err(SimplePirError::InvalidStdDev(std_dev))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The client's hint, which is the database multiplied by A. It is computed once by the server
+/// and does not depend on any query.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hint(pub Matrix);
+
+impl Hint {
+    /// The bytes the hint takes on the wire, see wire::encode_hint().
+    pub fn wire_size(&self) -> usize {
+        crate::wire::encoded_hint_len(self)
+    }
+}
+
+/// Check that a hint belongs to the params, as a hint received from the server must be before it
+/// is used: it is a non-empty matrix mod q with one row per coefficient of the LWE secret.
+pub fn check_hint(params: &SimplePIRParams, hint: &Hint) -> Result<(), SimplePirError> {
+    if hint.0.num_vals() == 0 || hint.0.num_rows() != params.n {
+        return Err(SimplePirError::InvalidShape("hint"));
+    }
+    check_matrix_mod(&hint.0, params.q)
+}
+
+// The largest prime below 2^32, so that products take the 32-bit fast paths
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+const LARGE_Q: u64 = 4294967291;
+
+/// The security level in bits of the params generated by gen_params_for_size() and
+/// gen_params_for_width().
+pub const DEFAULT_SECURITY: u32 = 128;
+
+/// Generate parameters with DEFAULT_SECURITY bits of security for a database of db_size items
+/// mod p, laid out as a square matrix of width ⌈√db_size⌉.
+#[cfg(feature = "std")]
+pub fn gen_params_for_size(db_size: usize, p: u64) -> Result<SimplePIRParams, SimplePirError> {
+    gen_params_for_width(square_width(db_size), p)
+}
+
+/// Generate parameters with DEFAULT_SECURITY bits of security for a database with m rows of
+/// items mod p, see SimplePIRParams::for_security_with_p().
+#[cfg(feature = "std")]
+pub fn gen_params_for_width(m: usize, p: u64) -> Result<SimplePIRParams, SimplePirError> {
+    SimplePIRParams::for_security_with_p(DEFAULT_SECURITY, m, p)
+}
+
+// The width of the smallest square matrix which holds db_size items
+#[cfg(feature = "std")]
+fn square_width(db_size: usize) -> usize {
+    let mut m = (db_size as f64).sqrt() as usize;
+    while m * m < db_size {
+        m += 1;
+    }
+    m.max(1)
+}
+
+/// Generate small, insecure parameters with n = 64, which keep tests fast. Never use these
+/// outside of tests.
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+pub fn gen_insecure_test_params() -> SimplePIRParams {
+    gen_insecure_test_params_for_width(8, 2)
+}
+
+/// As gen_insecure_test_params(), for a database of db_size items mod p laid out as a square
+/// matrix of width ⌈√db_size⌉.
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+pub fn gen_insecure_test_params_for_size(db_size: usize, p: u64) -> SimplePIRParams {
+    gen_insecure_test_params_for_width(square_width(db_size), p)
+}
+
+/// As gen_insecure_test_params(), for a database with m rows of items mod p.
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+pub fn gen_insecure_test_params_for_width(m: usize, p: u64) -> SimplePIRParams {
+    assert!(p >= 2);
+    let q = if 2 * p * max_noise(m, p) < 3329 { 3329 } else { LARGE_Q };
+    gen_insecure_test_params_with_q(m, p, q)
+}
+
+/// As gen_insecure_test_params_for_width(), with the power-of-two modulus q = 2^32, for which
+/// Element arithmetic reduces by wrapping instead of dividing.
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+pub fn gen_insecure_test_params_q32(m: usize, p: u64) -> SimplePIRParams {
+    gen_insecure_test_params_with_q(m, p, Q32)
+}
+
+// The standard deviation of the query errors of insecure test params
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+const GEN_STD_DEV: f64 = 6.4;
+
+// Each answer entry accumulates m error terms of magnitude at most error_bound(GEN_STD_DEV), each
+// multiplied by an item of at most p - 1. Flooring q / p adds at most p more. Decryption is
+// correct as long as the sum stays below q / 2p.
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+fn max_noise(m: usize, p: u64) -> u64 {
+    error_bound(GEN_STD_DEV) * m as u64 * (p - 1) + p
+}
+
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+fn gen_insecure_test_params_with_q(m: usize, p: u64, q: u64) -> SimplePIRParams {
+    assert!(p >= 2);
+    assert!(2 * p * max_noise(m, p) < q, "the plaintext modulus is too large for the database width");
     let n = 64;
-    let q = 3329;
-    let p = 2;
-    let std_dev = 6.4;
+    let std_dev = GEN_STD_DEV;
     let a = Matrix::gen_uniform_rand(q, m, n);
 
-    SimplePIRParams { a, q, p, n, m, std_dev }
+    let secret_dist = SecretDistribution::Uniform;
+    SimplePIRParams { a, q, p, n, m, std_dev, secret_dist, seed: None }
+}
+
+/// The (column, row) position of item idx in the square database layout. An idx past the m * m
+/// items of the layout is rejected with IndexOutOfRange.
+pub fn position(params: &SimplePIRParams, idx: usize) -> Result<(usize, usize), SimplePirError> {
+    let len = params.m * params.m;
+    if idx >= len {
+        return Err(SimplePirError::IndexOutOfRange { idx, len });
+    }
+    Ok((idx / params.m, idx % params.m))
+}
+
+/// Lay out a list of items mod p as the m x m database matrix, such that each item is stored at
+/// position(). Unused positions are filled with zeros. More items than fit in the layout are
+/// rejected with IndexOutOfRange, and items mod another modulus than p with ModulusMismatch.
+pub fn encode_db(params: &SimplePIRParams, items: &[Element]) -> Result<Matrix, SimplePirError> {
+    let mut db = Matrix::from_val(params.m, params.m, Element::zero(params.p));
+    for (i, item) in items.iter().enumerate() {
+        if item.q != params.p {
+            return Err(SimplePirError::ModulusMismatch { expected: params.p, actual: item.q });
+        }
+        let (col, row) = position(params, i)?;
+        db.set(col, row, item);
+    }
+    Ok(db)
+}
+
+/// The server's setup: lay out the items as a square database and compute the hint. Items are
+/// rejected as by encode_db().
+pub fn setup(
+    params: &SimplePIRParams,
+    items: &[Element],
+) -> Result<(Matrix, Hint), SimplePirError> {
+    let db = encode_db(params, items)?;
+    let hint = gen_hint(params, &db);
+    Ok((db, hint))
 }
 
 /// Generate a database of random values mod the plaintext modulus p
+#[cfg(feature = "std")]
 pub fn gen_db(params: &SimplePIRParams) -> Matrix {
     Matrix::gen_uniform_rand(
         params.p,
@@ -37,103 +594,903 @@ pub fn gen_db(params: &SimplePIRParams) -> Matrix {
     )
 }
 
-/// Generates the client's hint, which is the database multiplied by A. Also known as the setup.
-pub fn gen_hint(params: &SimplePIRParams, db: &Matrix) -> Matrix {
-    let mut db_q = db.clone();
-    db_q.change_q(params.q);
-    db_q.to_owned() * params.a.to_owned()
+/// Generates the client's hint, which is the database multiplied by A.
+pub fn gen_hint(params: &SimplePIRParams, db: &Matrix) -> Hint {
+    let mut db_q = db.clone();
+    db_q.change_q(params.q);
+    Hint(&db_q * &params.a)
+}
+
+/// Like gen_hint(), but with the product split across the threads of the given pool. Each thread
+/// computes the hint rows of a contiguous block of database columns.
+#[cfg(feature = "parallel")]
+pub fn gen_hint_parallel(params: &SimplePIRParams, db: &Matrix, pool: &rayon::ThreadPool) -> Hint {
+    let block = db.num_cols().div_ceil(pool.current_num_threads()).max(1);
+    let starts: Vec<usize> = (0..db.num_cols()).step_by(block).collect();
+    let blocks: Vec<Matrix> = pool.install(|| {
+        starts.par_iter()
+            .map(|&start| {
+                gen_hint(params, &db.select_cols(start..(start + block).min(db.num_cols()))).0
+            })
+            .collect()
+    });
+    Hint(Matrix::concat(blocks))
+}
+
+/// Like gen_hint(), but reading the database from any backend, chunk_cols columns at a time, so
+/// that only one chunk needs to be in memory at once.
+pub fn gen_hint_db<D: Database + ?Sized>(
+    params: &SimplePIRParams,
+    db: &D,
+    chunk_cols: usize,
+) -> Result<Hint, SimplePirError> {
+    if db.num_rows() != params.m {
+        return Err(SimplePirError::LengthMismatch { expected: params.m, actual: db.num_rows() });
+    }
+    assert!(chunk_cols > 0);
+
+    let blocks = (0..db.len()).step_by(chunk_cols)
+        .map(|start| Ok(gen_hint(params, &db.chunk(start..(start + chunk_cols).min(db.len()))?).0))
+        .collect::<Result<Vec<_>, SimplePirError>>()?;
+    Ok(Hint(Matrix::concat(blocks)))
+}
+
+/// A compact update to a cached hint after some database columns changed.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HintDelta {
+    // The new hint row for each changed database column
+    Rows(Vec<(usize, Vec<Element>)>),
+    // Too many columns changed, so the client should download the full hint again
+    Full,
+}
+
+/// Compare two versions of the database and produce the hint rows of the columns which changed.
+/// If more than max_rows columns changed, the delta instructs a full re-download instead.
+pub fn gen_hint_delta(
+    params: &SimplePIRParams,
+    old_db: &Matrix,
+    new_db: &Matrix,
+    max_rows: usize,
+) -> Result<HintDelta, SimplePirError> {
+    if old_db.dimensions() != new_db.dimensions() {
+        return Err(ShapeError {
+            op: "compare",
+            lhs: old_db.dimensions(),
+            rhs: new_db.dimensions(),
+        }.into());
+    }
+
+    let changed: Vec<usize> = (0..new_db.num_cols())
+        .filter(|&i| old_db.col_values(i) != new_db.col_values(i))
+        .collect();
+    if changed.len() > max_rows {
+        return Ok(HintDelta::Full);
+    }
+
+    Ok(gen_hint_rows(params, new_db, &changed))
+}
+
+/// Recompute the hint rows of the given database columns, e.g. after updating them in place.
+pub fn gen_hint_rows(params: &SimplePIRParams, db: &Matrix, cols: &[usize]) -> HintDelta {
+    HintDelta::Rows(cols.iter().map(|&i| (i, gen_hint_row(params, &db.col(i)))).collect())
+}
+
+/// The hint row for a single database column.
+pub fn gen_hint_row(params: &SimplePIRParams, col: &[Element]) -> Vec<Element> {
+    let mut col = Matrix::from_col(col);
+    col.change_q(params.q);
+    let hint_row = &col * &params.a;
+    hint_row.col(0)
+}
+
+/// Apply a hint delta to a cached hint. Returns false, leaving the hint untouched, if the delta
+/// requires the full hint to be downloaded again. A delta whose rows do not fit the hint is
+/// rejected before any row is applied.
+pub fn apply_hint_delta(hint: &mut Hint, delta: &HintDelta) -> Result<bool, SimplePirError> {
+    check_hint_delta(delta, hint.0.num_cols(), hint.0.num_rows(), hint.0.q())?;
+    match delta {
+        HintDelta::Rows(rows) => {
+            for (i, row) in rows {
+                for (j, value) in row.iter().enumerate() {
+                    hint.0.set(*i, j, value);
+                }
+            }
+            Ok(true)
+        }
+        HintDelta::Full => Ok(false),
+    }
+}
+
+// Check that every row of a delta replaces one of num_cols hint rows of num_rows elements mod q
+fn check_hint_delta(
+    delta: &HintDelta,
+    num_cols: usize,
+    num_rows: usize,
+    q: u64,
+) -> Result<(), SimplePirError> {
+    if let HintDelta::Rows(rows) = delta {
+        for (i, row) in rows {
+            if *i >= num_cols {
+                return Err(SimplePirError::IndexOutOfRange { idx: *i, len: num_cols });
+            }
+            if row.len() != num_rows {
+                return Err(SimplePirError::LengthMismatch {
+                    expected: num_rows,
+                    actual: row.len(),
+                });
+            }
+            if let Some(e) = row.iter().find(|e| e.q != q) {
+                return Err(SimplePirError::ModulusMismatch { expected: q, actual: e.q });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A subset of the hint covering a contiguous range of database columns. Clients which only ever
+/// query those columns can download this instead of the full hint.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PartialHint {
+    // The range of database columns covered by this hint (the manifest)
+    pub cols: Range<usize>,
+    // The number of columns in the full database
+    pub num_cols: usize,
+    // The rows of the full hint corresponding to `cols`
+    pub hint: Matrix,
+}
+
+/// Extract the rows of the hint which correspond to the given range of database columns.
+pub fn gen_partial_hint(hint: &Hint, cols: Range<usize>) -> Result<PartialHint, SimplePirError> {
+    check_range(&cols, hint.0.num_cols())?;
+
+    Ok(PartialHint {
+        num_cols: hint.0.num_cols(),
+        hint: hint.0.select_cols(cols.clone()),
+        cols,
+    })
+}
+
+impl PartialHint {
+    // Check that the partial hint has the n rows of the hint for each of its columns, which lie
+    // within the database, mod q
+    fn check(&self, params: &SimplePIRParams) -> Result<(), SimplePirError> {
+        check_range(&self.cols, self.num_cols)?;
+        if self.hint.dimensions() != (self.cols.len(), params.n) {
+            return Err(SimplePirError::InvalidShape("partial hint"));
+        }
+        check_matrix_mod(&self.hint, params.q)
+    }
+}
+
+// The part of a query which does not depend on the queried index: A * s + e. Each blank is
+// turned into one query.
+struct QueryBlank(Vec<Element>);
+
+// Generate a query blank. This is where the cost of query generation lies.
+fn gen_query_blank<R: CryptoRng + RngCore>(
+    params: &SimplePIRParams,
+    s: &SecretKey,
+    rng: &mut R,
+) -> Result<QueryBlank, SimplePirError> {
+    if params.m == 0 {
+        return Err(SimplePirError::Empty("database"));
+    }
+    if s.len() != params.n {
+        return Err(SimplePirError::LengthMismatch { expected: params.n, actual: s.len() });
+    }
+    check_vector_mod(s, params.q)?;
+
+    // The error term
+    let bound = error_bound(params.std_dev);
+    let e = gen_gaussian_error_vec_with_rng(params.q, params.m, params.std_dev, bound, rng);
+    let err_matrix = Matrix::from_col(&e.to_elements());
+
+    // blank = A * s + e
+    let mut blank = params.a.to_owned().mul_vec(&s.elements());
+    blank += err_matrix.rotated();
+
+    Ok(QueryBlank(blank.rotated().col(0)))
+}
+
+// Turn a query blank into a query for idx by adding q/p * u_i_col.
+fn finish_query(
+    params: &SimplePIRParams,
+    blank: QueryBlank,
+    idx: usize,
+) -> Result<Vec<Element>, SimplePirError> {
+    let db_size = params.m;
+    if idx >= db_size {
+        return Err(SimplePirError::IndexOutOfRange { idx, len: db_size });
+    }
+    // q / p
+    let floor = delta_floor(params.q, params.p);
+
+    // Add q/p * 1 only to the index corresponding to the desired column. Every entry is updated
+    // so that neither the timing nor the memory access pattern depends on idx.
+    let zero = Element::zero(params.q);
+    let delta = Element::from(params.q, floor);
+    let mut query = blank.0;
+    for (i, v) in query.iter_mut().enumerate() {
+        *v += Element::conditional_select(&zero, &delta, i.ct_eq(&idx));
+    }
+    Ok(query)
+}
+
+/// Generate a query to be sent to the server with the secret s, which is moved into the returned
+/// QuerySecret so that it cannot encrypt another query.
+#[cfg(feature = "std")]
+pub fn query(
+    params: &SimplePIRParams,
+    idx: usize,
+    s: SecretKey,
+) -> Result<(Vec<Element>, QuerySecret), SimplePirError> {
+    query_with_rng(params, idx, s, &mut StdRng::from_entropy())
+}
+
+/// Like query(), but sampling the error with the given RNG.
+pub fn query_with_rng<R: CryptoRng + RngCore>(
+    params: &SimplePIRParams,
+    idx: usize,
+    s: SecretKey,
+    rng: &mut R,
+) -> Result<(Vec<Element>, QuerySecret), SimplePirError> {
+    let query = finish_query(params, gen_query_blank(params, &s, rng)?, idx)?;
+    Ok((query, QuerySecret::new(s)))
+}
+
+/// The offline part of a query: a fresh secret s, the blank A * s + e and hint * s. Preparing
+/// queries ahead of time leaves only the addition of q/p at the queried row for query time and
+/// only the subtraction of hint * s for recovery, so neither online step touches A or the hint.
+///
+/// The secret only ever moves forward: finish() consumes the prepared query, and the recover
+/// functions consume the resulting QueryState. Neither can be cloned, so a secret cannot serve two
+/// queries or decrypt two answers, which would otherwise only show up as a loss of privacy.
+pub struct PreparedQuery {
+    blank: QueryBlank,
+    state: QueryState,
+}
+
+/// What the client keeps between sending a query and recovering its answer: the secret and
+/// hint * s. It is consumed by recovery.
+pub struct QueryState {
+    s: SecretKey,
+    hint_s: Zeroizing<Vec<Element>>,
+}
+
+/// Prepare a query, sampling the secret from the distribution in the params.
+#[cfg(feature = "std")]
+pub fn prepare_query(
+    params: &SimplePIRParams,
+    hint: &Hint,
+) -> Result<PreparedQuery, SimplePirError> {
+    prepare_query_with_rng(params, hint, &mut StdRng::from_entropy())
+}
+
+/// Like prepare_query(), but sampling the secret and error with the given RNG.
+pub fn prepare_query_with_rng<R: CryptoRng + RngCore>(
+    params: &SimplePIRParams,
+    hint: &Hint,
+    rng: &mut R,
+) -> Result<PreparedQuery, SimplePirError> {
+    let s = params.secret_dist.sample(params.q, params.n, rng).into();
+    prepare_query_with_secret(params, hint, s, rng)
+}
+
+/// Like prepare_query_with_rng(), but with a secret supplied by the caller. The secret is moved
+/// into the prepared query, so that it serves exactly one query.
+pub fn prepare_query_with_secret<R: CryptoRng + RngCore>(
+    params: &SimplePIRParams,
+    hint: &Hint,
+    s: SecretKey,
+    rng: &mut R,
+) -> Result<PreparedQuery, SimplePirError> {
+    let blank = gen_query_blank(params, &s, rng)?;
+    let hint_s = hint.0.to_owned().mul_vec(&s.elements()).elements().collect();
+    Ok(PreparedQuery { blank, state: QueryState { s, hint_s: Zeroizing::new(hint_s) } })
+}
+
+impl PreparedQuery {
+    /// Turn the prepared query into a query for the given row, and the state needed to recover
+    /// its answer with recover_prepared_row().
+    pub fn finish(
+        self,
+        params: &SimplePIRParams,
+        row: usize,
+    ) -> Result<(Vec<Element>, QueryState), SimplePirError> {
+        Ok((finish_query(params, self.blank, row)?, self.state))
+    }
+
+    /// Bring hint * s up to date after the hint was patched with delta. See
+    /// QueryState::apply_hint_delta().
+    pub fn apply_hint_delta(&mut self, delta: &HintDelta) -> Result<(), SimplePirError> {
+        self.state.apply_hint_delta(delta)
+    }
+}
+
+impl QueryState {
+    /// The query's secret, e.g. to generate a packing key for its answer.
+    pub fn secret(&self) -> &SecretKey {
+        &self.s
+    }
+
+    /// Bring hint * s up to date after the hint was patched with delta. A full delta requires a new
+    /// hint and so new queries, and is ignored. A delta whose rows do not fit the hint is rejected
+    /// before any row is applied.
+    pub fn apply_hint_delta(&mut self, delta: &HintDelta) -> Result<(), SimplePirError> {
+        check_hint_delta(delta, self.hint_s.len(), self.s.len(), self.s.q())?;
+        if let HintDelta::Rows(rows) = delta {
+            for (i, hint_row) in rows {
+                let hint_s = Matrix::from_col(hint_row).mul_vec(&self.s.elements());
+                self.hint_s[*i] = hint_s.get(0, 0);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mod-switch a query from q down to q_prime before it is uploaded, shrinking each entry from
+/// log2(q) to log2(q_prime) bits. The server answers it mod q_prime (answer() works at the
+/// modulus of the query) and the recover functions switch hint * s to match. This adds noise,
+/// see SimplePIRParams::noise_budget_switched(). A q_prime above the modulus of the query or
+/// below 2 is rejected as an invalid modulus.
+pub fn compress_query(query: &[Element], q_prime: u64) -> Result<Vec<Element>, SimplePirError> {
+    for e in query {
+        check_switch_mod(q_prime, e.q, 1)?;
+    }
+    Ok(query.iter().map(|e| e.mod_switch(q_prime)).collect())
+}
+
+// Check that values mod q can be switched down to q_prime and still hold values mod p
+fn check_switch_mod(q_prime: u64, q: u64, p: u64) -> Result<(), SimplePirError> {
+    match q_prime <= q && q_prime > p {
+        true => Ok(()),
+        false => Err(SimplePirError::InvalidModulus(q_prime)),
+    }
+}
+
+// Check that a query is non-empty, has one element per database row and uses a single modulus,
+// since answering any other query panics
+fn check_query(query: &[Element], num_rows: usize) -> Result<(), SimplePirError> {
+    let first = query.first().ok_or(SimplePirError::Empty("query"))?;
+    if query.len() != num_rows {
+        return Err(SimplePirError::LengthMismatch { expected: num_rows, actual: query.len() });
+    }
+    check_moduli(query, first.q)
+}
+
+pub fn answer(query: &[Element], db: &Matrix) -> 
+    Result<Matrix, SimplePirError>
+{
+    answer_in(query, db, &mut Arena::default())
+}
+
+/// Like answer(), but taking the temporaries from an arena, which a server reuses across
+/// queries.
+pub fn answer_in(
+    query: &[Element],
+    db: &Matrix,
+    arena: &mut Arena,
+) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+    let [rhs] = arena.alloc([query.len()]);
+    query_words(query, rhs);
+    Ok(db.mul_words(rhs, query[0].q))
+}
+
+// Write the values of a checked query into words
+fn query_words(query: &[Element], words: &mut [u64]) {
+    words.iter_mut().zip(query).for_each(|(word, e)| *word = e.uint);
+}
+
+/// Like answer(), but scanning a database stored as u32s. This halves the memory traffic of the
+/// scan when q fits in 32 bits.
+pub fn answer_compact(query: &[Element], db: &CompactMatrix) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+    if query[0].q > 1 << 32 {
+        return Err(SimplePirError::InvalidModulus(query[0].q));
+    }
+    Ok(db.mul_vec(query))
+}
+
+/// Like answer(), but scanning a database uploaded to the GPU. Requires q ≤ 2^32.
+#[cfg(feature = "gpu")]
+pub fn answer_gpu(query: &[Element], db: &GpuDatabase) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+    if query[0].q > gpu::MAX_Q {
+        return Err(SimplePirError::InvalidModulus(query[0].q));
+    }
+    Ok(db.mul_vec(query))
+}
+
+/// Like answer(), but scanning a sparse database, which only touches its non-zero items.
+pub fn answer_sparse(query: &[Element], db: &SparseMatrix) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+    Ok(db.mul_vec(query))
+}
+
+/// Like answer(), but scanning a bit-packed database, which reads each value straight out of its
+/// packed word.
+pub fn answer_bitpacked(
+    query: &[Element],
+    db: &BitPackedMatrix,
+) -> Result<Matrix, SimplePirError> {
+    answer_bitpacked_in(query, db, &mut Arena::default())
+}
+
+/// Like answer_bitpacked(), but taking the temporaries from an arena.
+pub fn answer_bitpacked_in(
+    query: &[Element],
+    db: &BitPackedMatrix,
+    arena: &mut Arena,
+) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+    let [rhs] = arena.alloc([query.len()]);
+    query_words(query, rhs);
+    Ok(db.mul_words(rhs, query[0].q))
+}
+
+/// Like answer_parallel(), but scanning a bit-packed database.
+#[cfg(feature = "parallel")]
+pub fn answer_bitpacked_parallel(
+    query: &[Element],
+    db: &BitPackedMatrix,
+    pool: &rayon::ThreadPool,
+) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+
+    let block = db.num_cols().div_ceil(pool.current_num_threads()).max(1);
+    let starts: Vec<usize> = (0..db.num_cols()).step_by(block).collect();
+    let blocks: Vec<Matrix> = pool.install(|| {
+        starts.par_iter()
+            .map(|&start| db.mul_vec_cols(query, start..(start + block).min(db.num_cols())))
+            .collect()
+    });
+    Ok(Matrix::concat(blocks))
+}
+
+/// Like answer_batch(), but scanning a bit-packed database.
+pub fn answer_batch_bitpacked(
+    queries: &[Vec<Element>],
+    db: &BitPackedMatrix,
+) -> Result<Vec<Matrix>, SimplePirError> {
+    if queries.is_empty() {
+        return Ok(vec![]);
+    }
+    for query in queries {
+        check_query(query, db.num_rows())?;
+        check_moduli(query, queries[0][0].q)?;
+    }
+    Ok(db.mul_batch(queries, 0..db.num_cols()))
+}
+
+/// The state of an answer computation which ran out of time: the answer rows computed so far.
+/// Passing it back to answer_within() resumes the computation where it stopped. It records the
+/// query and the shape of the database, so that it cannot be resumed with any other. It can be
+/// handed to a client with wire::encode_partial_answer() and sent back with the retried query.
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PartialAnswer {
+    // A digest of the query which the rows answer
+    pub(crate) query_digest: Digest,
+    // The (columns, rows) of the database
+    pub(crate) db_shape: (usize, usize),
+    // One single-entry row per database column answered so far
+    pub(crate) rows: Vec<Vec<Element>>,
+}
+
+#[cfg(feature = "std")]
+impl PartialAnswer {
+    /// The number of answer rows computed so far.
+    pub fn progress(&self) -> usize {
+        self.rows.len()
+    }
+
+    // Whether the rows can be resumed with a query mod q with this digest on a database of this
+    // shape. A partial answer received from a client is checked in full, not just its header.
+    fn fits(&self, query_digest: &Digest, db_shape: (usize, usize), q: u64) -> bool {
+        self.query_digest == *query_digest
+            && self.db_shape == db_shape
+            && self.rows.len() <= db_shape.0
+            && self.rows.iter().all(|row| row.len() == 1 && row[0].q == q)
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnswerProgress {
+    Done(Matrix),
+    Partial(PartialAnswer),
+}
+
+// A digest of a query, which binds a partial answer to it
+pub(crate) fn query_digest(query: &[Element]) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(query.first().map_or(0, |e| e.q).to_le_bytes());
+    for e in query {
+        hasher.update(e.uint.to_le_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Compute the answer to a query, stopping once the time budget is spent. At least one answer
+/// row is computed per call so that resuming always makes progress. A partial answer resumed
+/// with another query or another shape of database is rejected with PartialAnswerMismatch.
+#[cfg(feature = "std")]
+pub fn answer_within(
+    query: &[Element],
+    db: &Matrix,
+    budget: Duration,
+    resume: Option<PartialAnswer>,
+) -> Result<AnswerProgress, SimplePirError> {
+    check_query(query, db.num_rows())?;
+
+    let start = Instant::now();
+    let q = query[0].q;
+    let query_digest = query_digest(query);
+    let db_shape = db.dimensions();
+    let mut rows = match resume {
+        Some(partial) if !partial.fits(&query_digest, db_shape, q) => {
+            return Err(SimplePirError::PartialAnswerMismatch);
+        }
+        Some(partial) => partial.rows,
+        None => vec![],
+    };
+
+    while rows.len() < db.num_cols() {
+        let mut db_row = db.select_cols(rows.len()..rows.len() + 1);
+        db_row.change_q(q);
+        rows.push(db_row.mul_vec(query).col(0));
+
+        if rows.len() < db.num_cols() && start.elapsed() >= budget {
+            return Ok(AnswerProgress::Partial(PartialAnswer { query_digest, db_shape, rows }));
+        }
+    }
+    Ok(AnswerProgress::Done(Matrix::from(&rows)))
+}
+
+/// Answer a query over a database supplied as a stream of chunks, each holding a run of
+/// consecutive database columns (e.g. read from disk one at a time). Only one chunk needs to be in
+/// memory at once; the answer entries for each chunk are accumulated as it arrives.
+pub fn answer_chunked<I>(query: &[Element], chunks: I) -> Result<Matrix, SimplePirError>
+where
+    I: IntoIterator<Item = Matrix>,
+{
+    if query.is_empty() {
+        return Err(SimplePirError::Empty("query"));
+    }
+
+    let mut result = Matrix::new();
+    for chunk in chunks {
+        if chunk.num_cols() == 0 {
+            continue;
+        }
+        result.append(answer(query, &chunk)?);
+    }
+    Ok(result)
+}
+
+/// Like answer(), but reading the database from any backend, chunk_cols columns at a time, so
+/// that only one chunk needs to be in memory at once.
+pub fn answer_db<D: Database + ?Sized>(
+    query: &[Element],
+    db: &D,
+    chunk_cols: usize,
+) -> Result<Matrix, SimplePirError> {
+    answer_db_in(query, db, chunk_cols, &mut Arena::default())
+}
+
+/// Like answer_db(), but taking the temporaries from an arena. The query is converted once for
+/// all the chunks.
+pub fn answer_db_in<D: Database + ?Sized>(
+    query: &[Element],
+    db: &D,
+    chunk_cols: usize,
+    arena: &mut Arena,
+) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+    assert!(chunk_cols > 0);
+
+    let [rhs] = arena.alloc([query.len()]);
+    query_words(query, rhs);
+    let mut result = Matrix::new();
+    for start in (0..db.len()).step_by(chunk_cols) {
+        let chunk = db.chunk(start..(start + chunk_cols).min(db.len()))?;
+        result.append(chunk.mul_words(rhs, query[0].q));
+    }
+    Ok(result)
+}
+
+/// Answer a query with the database scan split across the threads of the given pool. Each thread
+/// computes the answer entries for a contiguous block of database columns.
+#[cfg(feature = "parallel")]
+pub fn answer_parallel(
+    query: &[Element],
+    db: &Matrix,
+    pool: &rayon::ThreadPool,
+) -> Result<Matrix, SimplePirError> {
+    check_query(query, db.num_rows())?;
+
+    let block = db.num_cols().div_ceil(pool.current_num_threads()).max(1);
+    let starts: Vec<usize> = (0..db.num_cols()).step_by(block).collect();
+    let blocks: Vec<Matrix> = pool.install(|| {
+        starts.par_iter()
+            .map(|&start| answer(query, &db.select_cols(start..(start + block).min(db.num_cols()))))
+            .collect::<Result<_, _>>()
+    })?;
+    Ok(Matrix::concat(blocks))
+}
+
+/// Answer a batch of queries in a single pass over the database, by multiplying the database with
+/// the matrix whose columns are the queries. The answers are returned in the order of the queries.
+pub fn answer_batch(queries: &[Vec<Element>], db: &Matrix) -> Result<Vec<Matrix>, SimplePirError> {
+    if queries.is_empty() {
+        return Ok(vec![]);
+    }
+    for query in queries {
+        check_query(query, db.num_rows())?;
+        check_moduli(query, queries[0][0].q)?;
+    }
+
+    let mut db_q = db.clone();
+    db_q.change_q(queries[0][0].q);
+    let query_matrix = Matrix::from(&queries.to_vec()).rotated();
+
+    // Column j of the product is the answer to query j
+    let product = (db_q * query_matrix).rotated();
+    Ok((0..product.num_cols()).map(|j| product.select_cols(j..j + 1).rotated()).collect())
+}
+
+/// Answer a query and mod-switch the answer from q down to the smaller modulus q_prime before it
+/// is sent, shrinking each entry from log2(q) to log2(q_prime) bits. The recover functions detect
+/// the smaller modulus and switch the client's hint component to match. A q_prime above the
+/// modulus of the query or not above that of the database is rejected as an invalid modulus.
+pub fn answer_compressed(
+    query: &[Element],
+    db: &Matrix,
+    q_prime: u64,
+) -> Result<Matrix, SimplePirError> {
+    let answer = answer(query, db)?;
+    check_switch_mod(q_prime, query[0].q, db.q())?;
+    Ok(answer.mod_switch(q_prime))
 }
 
-/// Generate a query to be sent to the server.
-pub fn query(
+// Check that an answer has one element per database column, all mod the same modulus, which is
+// at most q (it is smaller for compressed answers) and above p, since recovering any other answer
+// panics
+fn check_answer(
     params: &SimplePIRParams,
-    idx: usize,
-    s: &Vec<Element>,
-) -> Vec<Element> {
-    let db_size = params.m;
-    assert!(idx < db_size);
-    // q / p
-    let floor = params.q / params.p;
-
-    // The error term
-    let e = gen_error_vec(params.q, params.m);
-    let err_matrix = Matrix::from_col(&e);
+    num_cols: usize,
+    answer: &Matrix,
+) -> Result<(), SimplePirError> {
+    if answer.num_cols() != num_cols {
+        return Err(SimplePirError::LengthMismatch { expected: num_cols, actual: answer.num_cols() });
+    }
+    if answer.num_vals() == 0 {
+        return Err(SimplePirError::Empty("answer"));
+    }
+    if answer.q() > params.q {
+        return Err(SimplePirError::ModulusMismatch { expected: params.q, actual: answer.q() });
+    }
+    if answer.q() <= params.p {
+        return Err(SimplePirError::InvalidModulus(answer.q()));
+    }
+    if answer.num_rows() != 1 {
+        return Err(SimplePirError::LengthMismatch { expected: 1, actual: answer.num_rows() });
+    }
+    Ok(())
+}
 
-    // query = A * s + e + q/p * u_i_col
-    let mut query = params.a.to_owned().mul_vec(s);
-    query += err_matrix.rotated();
+/// Recover every item in the row selected by the query, one per database column. The answer
+/// already encrypts the whole row, so this costs the same as recover() and saves applications that
+/// need several items of the same row (e.g. bucket scans) from issuing one query per item. The
+/// secret is consumed, even when the answer is rejected.
+pub fn recover_row(
+    params: &SimplePIRParams,
+    s: QuerySecret,
+    hint: &Hint,
+    answer: &Matrix,
+) -> Result<Vec<Element>, SimplePirError> {
+    check_answer(params, hint.0.num_cols(), answer)?;
+    decode_row(params, hint.0.to_owned().mul_vec(&s.secret().elements()), answer)
+}
 
-    // Add q/p * 1 only to the index corresponding to the desired column
-    query[idx][0] += Element::from(params.q, floor);
+/// Like recover_row(), but for a prepared query, whose hint * s was computed offline. The state is
+/// consumed, even when the answer is rejected.
+pub fn recover_prepared_row(
+    params: &SimplePIRParams,
+    state: QueryState,
+    answer: &Matrix,
+) -> Result<Vec<Element>, SimplePirError> {
+    check_answer(params, state.hint_s.len(), answer)?;
+    let interim: Vec<Vec<Element>> = state.hint_s.iter().map(|e| vec![e.clone()]).collect();
+    decode_row(params, Matrix::from(&interim), answer)
+}
 
-    query.rotated()[0].to_owned()
+// The largest noise an honest answer mod answer_q carries, except with probability 2^-40 per entry:
+// CORRECTNESS_Z standard deviations of the noise budget, or its maximum if that is smaller. This
+// is the bound under which for_security() picks p, so it stays below the threshold for vetted
+// params. It is measured from round(q / p) * x for each item x, which adds at most p / 2. An answer
+// mod a smaller modulus was compressed, either by the server or by the client (which adds more
+// noise, see SimplePIRParams::noise_budget_switched()).
+fn plausible_noise(params: &SimplePIRParams, answer_q: u64) -> Result<u64, SimplePirError> {
+    let budget = if answer_q == params.q {
+        params.noise_budget(params.m)
+    } else {
+        params.noise_budget_switched(params.m, answer_q)?
+    };
+    let noise = (CORRECTNESS_Z * budget.std_dev).min(budget.max);
+    Ok((noise + params.p as f64 / 2.0).ceil() as u64 + 1)
 }
 
-pub fn answer(query: &Vec<Element>, db: &Matrix) -> 
-    Matrix
-{
-    let mut db_q = db.clone();
-    db_q.change_q(query[0].q);
-    db_q.to_owned().mul_vec(query)
+// Decode each entry of an answer from which hint * s was removed. An entry whose noise exceeds
+// what an honest answer plausibly carries means that the answer was tampered with (or was
+// computed against a different database than the hint), so the answer is rejected as malformed
+// rather than decoded to wrong items. Noise beyond the threshold decodes to another item and
+// cannot be told apart, so only tampering which lands between the two bounds is caught.
+fn decode_entries(params: &SimplePIRParams, ans: &Matrix) -> Result<Vec<Element>, SimplePirError> {
+    let bound = plausible_noise(params, ans.q())?;
+    let mut plausible = Choice::from(1);
+    let row = (0..ans.num_cols()).map(|i| {
+        let v = ans.get(i, 0);
+        let x = unscale_round(&v, params.p);
+        plausible &= noise_within(&v, &x, bound);
+        x
+    }).collect();
+    if !bool::from(plausible) {
+        return Err(SimplePirError::MalformedAnswer);
+    }
+    Ok(row)
 }
 
-pub fn recover_row(
+// Remove hint * s (as a column) from a checked answer and decode each entry
+fn decode_row(
     params: &SimplePIRParams,
-    s: &Vec<Element>,
-    hint: &Matrix,
+    mut interim: Matrix,
     answer: &Matrix,
-) -> Vec<Element> {
-    let p = params.p;
-    let q = params.q as f64;
+) -> Result<Vec<Element>, SimplePirError> {
+    // A compressed answer is mod q_prime, so the hint component is switched down to match. The
+    // two switches add at most 1 of rounding error to each entry.
+    let answer_q = answer.q();
+    if answer_q != params.q {
+        interim = interim.mod_switch(answer_q);
+    }
 
-    let interim = hint.to_owned().mul_vec(s);
     let mut ans = answer.to_owned();
     ans -= interim;
+    decode_entries(params, &ans)
+}
+
+/// Answer a query over only the given database columns, for clients which need a few items of
+/// the queried row. The answer holds one entry per column, in the order given.
+pub fn answer_cols(
+    query: &[Element],
+    db: &Matrix,
+    cols: &[usize],
+) -> Result<Matrix, SimplePirError> {
+    if let Some(&col) = cols.iter().find(|&&col| col >= db.num_cols()) {
+        return Err(SimplePirError::IndexOutOfRange { idx: col, len: db.num_cols() });
+    }
+    answer(query, &Matrix::concat(cols.iter().map(|&col| db.select_cols(col..col + 1))))
+}
 
-    ans.data.iter().map(
-        |v| Element::from(p, ((v[0].uint * p) as f64 / q).round() as u64 % p)
-    ).collect()
+/// Like recover_prepared_row(), but for an answer over only the given database columns, such as
+/// returned by answer_cols(). The items are returned in the order of the columns.
+pub fn recover_prepared_cols(
+    params: &SimplePIRParams,
+    state: QueryState,
+    cols: &[usize],
+    answer: &Matrix,
+) -> Result<Vec<Element>, SimplePirError> {
+    check_answer(params, cols.len(), answer)?;
+    let num_cols = state.hint_s.len();
+    if let Some(&col) = cols.iter().find(|&&col| col >= num_cols) {
+        return Err(SimplePirError::IndexOutOfRange { idx: col, len: num_cols });
+    }
+    let interim: Vec<Vec<Element>> =
+        cols.iter().map(|&col| vec![state.hint_s[col].clone()]).collect();
+    decode_row(params, Matrix::from(&interim), answer)
 }
 
+/// Recover the item in column idx of the row selected by the query.
 pub fn recover(
     params: &SimplePIRParams,
-    s: &Vec<Element>,
+    s: QuerySecret,
     idx: usize,
-    hint: &Matrix,
+    hint: &Hint,
     answer: &Matrix,
-) -> Element {
-    let p = params.p;
-    let q = params.q as f64;
+) -> Result<Element, SimplePirError> {
+    if idx >= answer.num_cols() {
+        return Err(SimplePirError::IndexOutOfRange { idx, len: answer.num_cols() });
+    }
+    Ok(recover_row(params, s, hint, answer)?[idx].clone())
+}
 
-    let interim = hint.to_owned().mul_vec(s);
-    let mut ans = answer.to_owned();
-    ans -= interim;
+/// Recover the item in column idx using a partial hint. Returns UncoveredColumn if the partial
+/// hint does not cover idx.
+pub fn recover_partial(
+    params: &SimplePIRParams,
+    s: QuerySecret,
+    idx: usize,
+    partial_hint: &PartialHint,
+    answer: &Matrix,
+) -> Result<Element, SimplePirError> {
+    partial_hint.check(params)?;
+    if !partial_hint.cols.contains(&idx) {
+        return Err(SimplePirError::UncoveredColumn {
+            idx,
+            start: partial_hint.cols.start,
+            end: partial_hint.cols.end,
+        });
+    }
+    check_answer(params, partial_hint.num_cols, answer)?;
+
+    let hint_row = partial_hint.hint.col(idx - partial_hint.cols.start);
+    let interim = Matrix::from_col(&hint_row).mul_vec(&s.secret().elements());
+    let v = answer.get(idx, 0) - interim.get(0, 0);
+    Ok(decode_entries(params, &Matrix::from_single(&v))?.swap_remove(0))
+}
 
-    let x = ((ans[idx][0].uint * p) as f64 / q).round() as u64 % p;
-    Element::from(p, x)
+/// Check that an answer decodes to the same item as a direct lookup into the plaintext database.
+/// Intended for integration tests of applications which wire up the PIR protocol themselves. An
+/// answer which cannot be recovered at all returns the recovery error rather than false, so that
+/// a malformed answer can be told apart from a wrong item.
+#[cfg(any(test, feature = "testing"))]
+pub fn verify_against_plain(
+    params: &SimplePIRParams,
+    db: &Matrix,
+    col: usize,
+    row: usize,
+    answer: &Matrix,
+    s: QuerySecret,
+    hint: &Hint,
+) -> Result<bool, SimplePirError> {
+    Ok(recover(params, s, col, hint, answer)? == db.get(col, row))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::regev::gen_secret;
+    use crate::scaling::scale_round;
+    use crate::vector::Vector;
     use super::*;
 
+    fn new_secret(params: &SimplePIRParams) -> SecretKey {
+        gen_secret(params.q, params.n)
+    }
+
     fn test_simplepir_impl(desired_col: usize, desired_row: usize) {
-        let params = gen_params();
+        let params = gen_insecure_test_params();
         let db = gen_db(&params);
 
-        let db_item = &db[desired_col][desired_row];
+        let db_item = db.get(desired_col, desired_row);
 
-        let secret = gen_secret(params.q, params.n);
         let hint = gen_hint(&params, &db);
 
-        let query = query(&params, desired_row, &secret);
-        let answer = answer(&query, &db);
-        let recovered_item = recover(&params, &secret, desired_col, &hint, &answer);
-        assert_eq!(recovered_item, *db_item);
+        let (query, secret) = query(&params, desired_row, new_secret(&params)).unwrap();
+        let answer = answer(&query, &db).unwrap();
+        let recovered_item = recover(&params, secret, desired_col, &hint, &answer).unwrap();
+        assert_eq!(recovered_item, db_item);
+
+        let (query, secret) = super::query(&params, desired_row, new_secret(&params)).unwrap();
+        let answer = super::answer(&query, &db).unwrap();
+        assert!(verify_against_plain(
+            &params, &db, desired_col, desired_row, &answer, secret, &hint
+        ).unwrap());
 
-        let recovered_row = recover_row(&params, &secret, &hint, &answer);
-        assert_eq!(recovered_row, db.rotated()[desired_row]);
+        let (query, secret) = super::query(&params, desired_row, new_secret(&params)).unwrap();
+        let truncated = super::answer(&query, &db).unwrap().select_cols(0..1);
+        let result =
+            verify_against_plain(&params, &db, desired_col, desired_row, &truncated, secret, &hint);
+        assert!(result.is_err());
+
+        let (query, secret) = super::query(&params, desired_row, new_secret(&params)).unwrap();
+        let answer = super::answer(&query, &db).unwrap();
+        let recovered_row = recover_row(&params, secret, &hint, &answer).unwrap();
+        assert_eq!(recovered_row, db.rotated().col(desired_row));
     }
 
     #[test]
@@ -145,19 +1502,765 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn test_simplepir_partial_hint() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let partial_hint = gen_partial_hint(&hint, 2..5).unwrap();
+        assert_eq!(partial_hint.hint.num_cols(), 3);
+
+        for desired_col in 2..5 {
+            for desired_row in 0..params.m {
+                let (query, secret) = query(&params, desired_row, new_secret(&params)).unwrap();
+                let answer = answer(&query, &db).unwrap();
+                let recovered = recover_partial(
+                    &params, secret, desired_col, &partial_hint, &answer
+                ).unwrap();
+                assert_eq!(recovered, db.get(desired_col, desired_row));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_simplepir_partial_hint_errors() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let partial_hint = gen_partial_hint(&hint, 2..5).unwrap();
+        let recover = |partial_hint: &PartialHint, idx| {
+            let (query, secret) = query(&params, 0, new_secret(&params)).unwrap();
+            let answer = answer(&query, &db).unwrap();
+            recover_partial(&params, secret, idx, partial_hint, &answer)
+        };
+
+        for idx in [1, 5] {
+            let err = SimplePirError::UncoveredColumn { idx, start: 2, end: 5 };
+            assert_eq!(recover(&partial_hint, idx), Err(err));
+        }
+
+        // A partial hint which does not match its columns or the params is rejected, as one
+        // received from a server might be
+        let m = params.m;
+        let with_hint = |hint| PartialHint { hint, ..partial_hint.clone() };
+        let shape = SimplePirError::InvalidShape("partial hint");
+        let bad_hints = [
+            (PartialHint { num_cols: 4, ..partial_hint.clone() },
+                SimplePirError::IndexOutOfRange { idx: 4, len: 4 }),
+            (PartialHint { cols: 2..6, ..partial_hint.clone() }, shape.clone()),
+            (with_hint(Matrix::from_val(3, params.n - 1, Element::zero(params.q))), shape),
+            (with_hint(Matrix::from_val(3, params.n, Element::zero(params.p))),
+                SimplePirError::ModulusMismatch { expected: params.q, actual: params.p }),
+        ];
+        for (bad_hint, err) in bad_hints {
+            assert_eq!(recover(&bad_hint, 3), Err(err));
+        }
+
+        assert_eq!(
+            gen_partial_hint(&hint, 2..m + 1).err(),
+            Some(SimplePirError::IndexOutOfRange { idx: m, len: m }),
+        );
+    }
+
+    #[test]
+    pub fn test_prepared_query() {
+        let params = gen_insecure_test_params();
+        let mut db = gen_db(&params);
+        let mut hint = gen_hint(&params, &db);
+        let rows = db.transpose();
+
+        let mut prepared: Vec<PreparedQuery> =
+            (0..3).map(|_| prepare_query(&params, &hint).unwrap()).collect();
+        let (query, state) = prepared.pop().unwrap().finish(&params, 5).unwrap();
+        let answer = answer(&query, &db).unwrap();
+        assert_eq!(recover_prepared_row(&params, state, &answer).unwrap(), rows.col(5));
+
+        // Prepared queries survive hint updates
+        db.set(2, 1, &(Element::from(params.p, 1) - db.get(2, 1)));
+        let delta = gen_hint_rows(&params, &db, &[2]);
+        assert_eq!(apply_hint_delta(&mut hint, &delta), Ok(true));
+        for prepared in &mut prepared {
+            prepared.apply_hint_delta(&delta).unwrap();
+        }
+        let rows = db.transpose();
+        for (row, prepared) in [1, 6].into_iter().zip(prepared) {
+            let (query, state) = prepared.finish(&params, row).unwrap();
+            let answer = super::answer(&query, &db).unwrap();
+            assert_eq!(recover_prepared_row(&params, state, &answer).unwrap(), rows.col(row));
+        }
+
+        // A secret supplied by the caller is moved into the query
+        let mut rng = ChaCha20Rng::from_seed([3; 32]);
+        let s = gen_secret(params.q, params.n);
+        let values = s.values().to_vec();
+        let prepared = prepare_query_with_secret(&params, &hint, s, &mut rng).unwrap();
+        let (query, state) = prepared.finish(&params, 4).unwrap();
+        assert_eq!(state.secret().values(), values);
+        let answer = super::answer(&query, &db).unwrap();
+        assert_eq!(recover_prepared_row(&params, state, &answer).unwrap(), rows.col(4));
+    }
+
+    #[test]
+    pub fn test_simplepir_hint_delta() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let mut hint = gen_hint(&params, &db);
+
+        // Flip one item in each of two columns
+        let mut new_db = db.clone();
+        for col in [1, 6] {
+            new_db.set(col, 3, &(new_db.get(col, 3) - Element::from(params.p, 1)));
+        }
+
+        assert_eq!(gen_hint_delta(&params, &db, &new_db, 1), Ok(HintDelta::Full));
+        assert_eq!(apply_hint_delta(&mut hint, &HintDelta::Full), Ok(false));
+
+        let delta = gen_hint_delta(&params, &db, &new_db, 2).unwrap();
+        assert!(matches!(&delta, HintDelta::Rows(rows) if rows.len() == 2));
+        assert_eq!(apply_hint_delta(&mut hint, &delta), Ok(true));
+        assert_eq!(hint, gen_hint(&params, &new_db));
+
+        // Deltas which do not fit the hint are rejected without touching it
+        let row = hint.0.col(0);
+        let bad_deltas = [
+            (HintDelta::Rows(vec![(0, row.clone()), (params.m, row.clone())]),
+                SimplePirError::IndexOutOfRange { idx: params.m, len: params.m }),
+            (HintDelta::Rows(vec![(0, row[1..].to_vec())]),
+                SimplePirError::LengthMismatch { expected: params.n, actual: params.n - 1 }),
+            (HintDelta::Rows(vec![(0, vec![Element::zero(params.p); params.n])]),
+                SimplePirError::ModulusMismatch { expected: params.q, actual: params.p }),
+        ];
+        for (delta, err) in bad_deltas {
+            assert_eq!(apply_hint_delta(&mut hint, &delta), Err(err));
+        }
+        assert_eq!(hint, gen_hint(&params, &new_db));
+        assert!(matches!(
+            gen_hint_delta(&params, &db, &db.chunk(0..1).unwrap(), 2),
+            Err(SimplePirError::Shape(_)),
+        ));
+
+        let (query, secret) = query(&params, 3, new_secret(&params)).unwrap();
+        let answer = answer(&query, &new_db).unwrap();
+        assert_eq!(recover_row(&params, secret, &hint, &answer).unwrap(), new_db.rotated().col(3));
+    }
+
+    #[test]
+    pub fn test_simplepir_answer_within() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let (query, _) = query(&params, 2, new_secret(&params)).unwrap();
+        let expected = answer(&query, &db).unwrap();
+
+        match answer_within(&query, &db, Duration::from_secs(60), None).unwrap() {
+            AnswerProgress::Done(ans) => assert_eq!(ans, expected),
+            AnswerProgress::Partial(_) => panic!("the answer should complete"),
+        }
+
+        // With no time budget, each call computes a single row
+        let mut resume = None;
+        let mut calls = 0;
+        let ans = loop {
+            calls += 1;
+            match answer_within(&query, &db, Duration::ZERO, resume).unwrap() {
+                AnswerProgress::Done(ans) => break ans,
+                AnswerProgress::Partial(partial) => {
+                    assert_eq!(partial.progress(), calls);
+                    resume = Some(partial);
+                }
+            }
+        };
+        assert_eq!(calls, params.m);
+        assert_eq!(ans, expected);
+
+        // Partial answers only resume with the query and shape of database they started with
+        let partial = |query| match answer_within(query, &db, Duration::ZERO, None).unwrap() {
+            AnswerProgress::Partial(partial) => Some(partial),
+            AnswerProgress::Done(_) => panic!("the answer should not complete"),
+        };
+        let (other, _) = super::query(&params, 3, new_secret(&params)).unwrap();
+        let mismatch = Err(SimplePirError::PartialAnswerMismatch);
+        let result = answer_within(&other, &db, Duration::ZERO, partial(&query));
+        assert_eq!(result.map(|_| ()), mismatch);
+        let wider = Matrix::gen_uniform_rand(params.p, db.num_cols() + 1, db.num_rows());
+        let result = answer_within(&query, &wider, Duration::ZERO, partial(&query));
+        assert_eq!(result.map(|_| ()), mismatch);
+
+        // As do partial answers whose rows do not fit, which a client could send back
+        let mut forged = partial(&query).unwrap();
+        for rows in [vec![vec![Element::zero(params.q)]; db.num_cols() + 1],
+            vec![vec![Element::zero(params.p)]], vec![vec![]]] {
+            forged.rows = rows;
+            let result = answer_within(&query, &db, Duration::ZERO, Some(forged.clone()));
+            assert_eq!(result.map(|_| ()), mismatch);
+        }
+    }
+
+    #[test]
+    pub fn test_simplepir_single_item() {
+        let q = 3329;
+        let n = 64;
+        let params = SimplePIRParams {
+            a: Matrix::gen_uniform_rand(q, 1, n), q, p: 2, n, m: 1, std_dev: 6.4,
+            secret_dist: SecretDistribution::Uniform, seed: None,
+        };
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+
+        let (query, secret) = query(&params, 0, new_secret(&params)).unwrap();
+        let answer = answer(&query, &db).unwrap();
+        assert_eq!(recover(&params, secret, 0, &hint, &answer).unwrap(), db.get(0, 0));
+    }
+
+    #[test]
+    pub fn test_simplepir_empty_db() {
+        let q = 3329;
+        let n = 64;
+        let params = SimplePIRParams {
+            a: Matrix::gen_uniform_rand(q, 0, n), q, p: 2, n, m: 0, std_dev: 6.4,
+            secret_dist: SecretDistribution::Uniform, seed: None,
+        };
+        let db = gen_db(&params);
+        assert_eq!(gen_hint(&params, &db), Hint(Matrix::new()));
+
+        let result = query(&params, 0, new_secret(&params));
+        assert!(matches!(result, Err(SimplePirError::Empty("database"))));
+    }
+
+    #[test]
+    pub fn test_simplepir_setup() {
+        let db_size = 50;
+        let params = gen_insecure_test_params_for_size(db_size, 2);
+        assert_eq!(params.m, 8);
+
+        let items: Vec<Element> = (0..db_size)
+            .map(|_| Element::gen_uniform_rand(params.p))
+            .collect();
+        let (db, hint) = setup(&params, &items).unwrap();
+
+        for (idx, item) in items.iter().enumerate() {
+            let (col, row) = position(&params, idx).unwrap();
+            let (query, secret) = query(&params, row, new_secret(&params)).unwrap();
+            let answer = answer(&query, &db).unwrap();
+            assert_eq!(recover(&params, secret, col, &hint, &answer).unwrap(), *item);
+        }
+    }
+
+    #[test]
+    pub fn test_encode_db_errors() {
+        let params = gen_insecure_test_params_for_width(4, 16);
+        let items: Vec<Element> = (0..17).map(|i| Element::from(16, i % 16)).collect();
+        let err = SimplePirError::IndexOutOfRange { idx: 16, len: 16 };
+        assert_eq!(position(&params, 16), Err(err.clone()));
+        assert_eq!(encode_db(&params, &items), Err(err));
+        assert!(encode_db(&params, &items[..16]).is_ok());
+        let err = SimplePirError::ModulusMismatch { expected: 16, actual: 17 };
+        assert_eq!(setup(&params, &[Element::from(17, 1)]).err(), Some(err));
+    }
+
+    #[test]
+    pub fn test_simplepir_multi_bit() {
+        for p in [3, 256, 991] {
+            let params = gen_insecure_test_params_for_size(64, p);
+            let db = gen_db(&params);
+            let hint = gen_hint(&params, &db);
+
+            for desired_row in 0..params.m {
+                let (query, secret) = query(&params, desired_row, new_secret(&params)).unwrap();
+                let answer = answer(&query, &db).unwrap();
+                let recovered_row = recover_row(&params, secret, &hint, &answer).unwrap();
+                for (col, item) in recovered_row.iter().enumerate() {
+                    assert_eq!(*item, db.get(col, desired_row));
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_simplepir_q32() {
+        let params = gen_insecure_test_params_q32(8, 16);
+        assert_eq!(params.q, Q32);
+        let items: Vec<Element> = (0..64).map(|i| Element::from(16, i % 16)).collect();
+        let (db, hint) = setup(&params, &items).unwrap();
+
+        for idx in [0, 17, 63] {
+            let (col, row) = position(&params, idx).unwrap();
+            let (query, secret) = query(&params, row, new_secret(&params)).unwrap();
+            let answer = answer(&query, &db).unwrap();
+            assert_eq!(recover(&params, secret, col, &hint, &answer).unwrap(), items[idx]);
+        }
+    }
+
+    #[test]
+    pub fn test_simplepir_query_with_rng() {
+        let params = gen_insecure_test_params();
+        let values = new_secret(&params).values().to_vec();
+        let gen = |seed: u8| {
+            let mut rng = ChaCha20Rng::from_seed([seed; 32]);
+            let s = Vector::from(params.q, values.clone()).into();
+            query_with_rng(&params, 3, s, &mut rng).unwrap()
+        };
+        assert_eq!(gen(1).0, gen(1).0);
+        assert_ne!(gen(1).0, gen(2).0);
+
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let (query, secret) = gen(1);
+        let answer = answer(&query, &db).unwrap();
+        assert_eq!(recover_row(&params, secret, &hint, &answer).unwrap(), db.rotated().col(3));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_simplepir_serde() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+
+        let json = serde_json::to_string(&params).unwrap();
+        assert_eq!(serde_json::from_str::<SimplePIRParams>(&json).unwrap(), params);
+        let json = serde_json::to_string(&hint).unwrap();
+        assert_eq!(serde_json::from_str::<Hint>(&json).unwrap(), hint);
+
+        let delta = gen_hint_rows(&params, &db, &[1, 5]);
+        let json = serde_json::to_string(&delta).unwrap();
+        assert_eq!(serde_json::from_str::<HintDelta>(&json).unwrap(), delta);
+
+        let (query, _) = query(&params, 2, new_secret(&params)).unwrap();
+        let progress = answer_within(&query, &db, Duration::ZERO, None).unwrap();
+        let json = serde_json::to_string(&progress).unwrap();
+        assert_eq!(serde_json::from_str::<AnswerProgress>(&json).unwrap(), progress);
+    }
+
+    #[test]
+    pub fn test_simplepir_params_from_seed() {
+        let seed = [3u8; 32];
+        let server_params = SimplePIRParams::from_seed(seed, 3329, 2, 64, 8, 6.4);
+        let client_params = SimplePIRParams::from_seed(seed, 3329, 2, 64, 8, 6.4);
+        assert_eq!(server_params, client_params);
+
+        let db = gen_db(&server_params);
+        let hint = gen_hint(&server_params, &db);
+
+        let (query, secret) = query(&client_params, 5, new_secret(&client_params)).unwrap();
+        let answer = answer(&query, &db).unwrap();
+        let recovered_row = recover_row(&client_params, secret, &hint, &answer).unwrap();
+        assert_eq!(recovered_row, db.rotated().col(5));
+    }
+
+    #[test]
+    pub fn test_noise_budget() {
+        let params = gen_insecure_test_params_for_width(16, 2);
+        let budget = params.noise_budget(256);
+        assert!(budget.is_guaranteed());
+        assert_eq!(budget.max, error_bound(6.4) as f64 * 16.0 + 2.0);
+        assert_eq!(budget.threshold, 3329.0 / 4.0);
+
+        // Only the rows which hold items add noise
+        assert!(params.noise_budget(4).std_dev < budget.std_dev);
+        assert_eq!(params.noise_budget(1000), budget);
+    }
+
+    #[test]
+    pub fn test_compressed_query() {
+        let params = gen_insecure_test_params_with_q(16, 4, LARGE_Q);
+        let db = Matrix::gen_uniform_rand(params.p, 16, 16);
+        let hint = gen_hint(&params, &db);
+        let rows = db.transpose();
+
+        let q_prime = 1 << 16;
+        let budget = params.noise_budget_switched(256, q_prime).unwrap();
+        assert!(budget.is_guaranteed());
+        // Switching to the same modulus only adds the bound on rounding
+        let full = params.noise_budget(256);
+        let same = params.noise_budget_switched(256, params.q).unwrap();
+        assert_eq!((same.max, same.threshold), (full.max + 16.0 * 1.5 + 1.0, full.threshold));
+        assert!(!params.noise_budget_switched(256, 64).unwrap().is_guaranteed());
+
+        // Moduli which are larger than q or cannot hold items mod p are rejected
+        let query = vec![Element::zero(params.q); 16];
+        for bad in [params.q + 1, params.p, 1] {
+            let err = SimplePirError::InvalidModulus(bad);
+            assert_eq!(params.noise_budget_switched(256, bad).err(), Some(err.clone()));
+            assert_eq!(answer_compressed(&query, &db, bad), Err(err));
+        }
+        assert_eq!(compress_query(&query, 1), Err(SimplePirError::InvalidModulus(1)));
+        let err = Err(SimplePirError::InvalidModulus(params.q + 1));
+        assert_eq!(compress_query(&query, params.q + 1), err);
+
+        for row in [0, 9, 15] {
+            let prepared = prepare_query(&params, &hint).unwrap();
+            let (query, state) = prepared.finish(&params, row).unwrap();
+            let query = compress_query(&query, q_prime).unwrap();
+            assert!(query.iter().all(|e| e.q == q_prime));
+            let answer = answer(&query, &db).unwrap();
+            assert_eq!(recover_prepared_row(&params, state, &answer).unwrap(), rows.col(row));
+        }
+    }
+
+    #[test]
+    pub fn test_failure_probability() {
+        let mut rng = ChaCha20Rng::from_seed([5u8; 32]);
+        let params = SimplePIRParams::from_seed([1u8; 32], 3329, 2, 64, 16, 6.4);
+
+        // The worst-case noise is tolerated for p = 2, and rarely exceeded for p = 4
+        assert_eq!(params.failure_probability(1024, 2), 0.0);
+        assert_eq!(params.simulate_failure_rate(1024, 2, 1000, &mut rng), 0.0);
+        let bound = params.failure_probability(1024, 4);
+        assert!(bound > 0.0 && bound < 0.1);
+        assert!(params.simulate_failure_rate(1024, 4, 1000, &mut rng) <= bound);
+
+        // Far too much noise for p = 16
+        assert_eq!(params.failure_probability(1024, 16), 1.0);
+        assert!(params.simulate_failure_rate(1024, 16, 1000, &mut rng) > 0.5);
+    }
+
+    // Sample random parameter sets and check end to end that retrievals fail no more often than
+    // failure_probability() allows, with slack for sampling, which guards the estimator, the
+    // error sampler and the rounding together. The thousands of retrievals per set make this
+    // slow, so it only runs with the long-tests feature.
+    #[cfg(feature = "long-tests")]
+    #[test]
+    pub fn test_failure_rate_within_estimate() {
+        use crate::pir::{Client, Server};
+        use rand::Rng;
+
+        const SETS: usize = 24;
+        const RETRIEVALS: usize = 2000;
+        let mut rng = ChaCha20Rng::from_seed([9u8; 32]);
+        for _ in 0..SETS {
+            let q = [3329, 65537, Q32][rng.gen_range(0..3)];
+            let m = rng.gen_range(4..=48);
+            let n = rng.gen_range(16..=64);
+            let std_dev = [3.2, 6.4][rng.gen_range(0..2)];
+            let mut params = SimplePIRParams::from_seed(rng.gen(), q, 2, n, m, std_dev);
+
+            // Most sets take a p whose bound is strictly between 0 and 1, where the estimate is
+            // tested hardest, and the rest the largest p claimed never to fail
+            let db_size = m * m;
+            let max_p = (q as f64 / 2.0).sqrt() as u64;
+            let bounds: Vec<(u64, f64)> =
+                (2..=max_p).map(|p| (p, params.failure_probability(db_size, p))).collect();
+            let partial: Vec<u64> =
+                bounds.iter().filter(|(_, b)| *b > 0.0 && *b < 1.0).map(|(p, _)| *p).collect();
+            params.p = match rng.gen_bool(0.75) && !partial.is_empty() {
+                true => partial[rng.gen_range(0..partial.len())],
+                false => bounds.iter().rev().find(|(_, b)| *b == 0.0).unwrap().0,
+            };
+            let p = params.p;
+
+            let items: Vec<Element> = (0..db_size)
+                .map(|_| Element::gen_uniform_rand_with_rng(p, &mut rng))
+                .collect();
+            let bound = params.failure_probability(items.len(), p);
+            let server = Server::setup(params.clone(), &items).unwrap();
+            let mut client = Client::new(params, server.hint().clone()).unwrap();
+            let mut failures = 0;
+            for _ in 0..RETRIEVALS {
+                let idx = rng.gen_range(0..items.len());
+                let answer = server.answer(&client.query_with_rng(idx, &mut rng).unwrap()).unwrap();
+                if client.recover(&answer).ok() != Some(items[idx].clone()) {
+                    failures += 1;
+                }
+            }
+
+            // Four standard deviations above the expected count fails a correct bound with
+            // probability below 2^-14 per set
+            let expected = RETRIEVALS as f64 * bound;
+            let allowed = expected + 4.0 * expected.sqrt() + 1.0;
+            assert!(
+                failures as f64 <= allowed,
+                "q = {}, p = {}, n = {}, m = {}, std_dev = {}: {} failures, bound {}",
+                q, p, n, m, std_dev, failures, bound,
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_params_for_security() {
+        let params = SimplePIRParams::for_security(128, 1000, 8).unwrap();
+        assert_eq!((params.n, params.q, params.m, params.std_dev), (1024, Q32, 32, 6.4));
+        assert!(params.p >= 256);
+
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let (query, secret) = query(&params, 7, new_secret(&params)).unwrap();
+        let answer = answer(&query, &db).unwrap();
+        assert_eq!(recover_row(&params, secret, &hint, &answer).unwrap(), db.rotated().col(7));
+
+        // Only the expected noise is within budget, since the error is Gaussian
+        let budget = params.noise_budget(1000);
+        assert!(!budget.is_guaranteed());
+        assert!(budget.margin_bits() > 2.8);
+
+        // Records wider than a limb take several columns, as laid out by RecordLayout
+        let params = SimplePIRParams::for_security(128, 100, 256).unwrap();
+        assert_eq!(params.m, crate::record::RecordLayout::new(100, 32, params.p).unwrap().rows);
+
+        assert_eq!(
+            SimplePIRParams::for_security(192, 1000, 8),
+            Err(SimplePirError::UnsupportedSecurity(192)),
+        );
+        assert_eq!(
+            SimplePIRParams::for_security(128, 1 << 42, 1),
+            Err(SimplePirError::TooManySamples { rows: 1 << 21, max: 1 << 20 }),
+        );
+    }
+
+    #[test]
+    pub fn test_params_for_security_with_p() {
+        let params = gen_params_for_size(1000, 256).unwrap();
+        assert_eq!((params.n, params.q, params.m, params.p), (1024, Q32, 32, 256));
+
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let (query, secret) = query(&params, 3, new_secret(&params)).unwrap();
+        let answer = answer(&query, &db).unwrap();
+        assert_eq!(recover_row(&params, secret, &hint, &answer).unwrap(), db.rotated().col(3));
+
+        // p must be small enough for answers over m rows to decrypt
+        let max = SimplePIRParams::for_security(128, 32 * 32, 1).unwrap().p;
+        assert!(SimplePIRParams::for_security_with_p(128, 32, max).is_ok());
+        assert_eq!(
+            SimplePIRParams::for_security_with_p(128, 32, max + 1),
+            Err(SimplePirError::InvalidModulus(max + 1)),
+        );
+        assert_eq!(gen_params_for_width(32, 1), Err(SimplePirError::InvalidModulus(1)));
+        assert_eq!(gen_params_for_width(0, 2), Err(SimplePirError::Empty("database")));
+        assert_eq!(
+            gen_params_for_width((1 << 20) + 1, 2),
+            Err(SimplePirError::TooManySamples { rows: (1 << 20) + 1, max: 1 << 20 }),
+        );
+        assert_eq!(
+            SimplePIRParams::for_security_with_p(192, 32, 2),
+            Err(SimplePirError::UnsupportedSecurity(192)),
+        );
+    }
+
+    #[test]
+    pub fn test_paper_presets() {
+        let presets: [fn(usize) -> Result<SimplePIRParams, SimplePirError>; 8] = [
+            SimplePIRParams::simplepir_1024_q32_p991,
+            SimplePIRParams::simplepir_1024_q32_p833,
+            SimplePIRParams::simplepir_1024_q32_p701,
+            SimplePIRParams::simplepir_1024_q32_p589,
+            SimplePIRParams::simplepir_1024_q32_p495,
+            SimplePIRParams::simplepir_1024_q32_p416,
+            SimplePIRParams::simplepir_1024_q32_p350,
+            SimplePIRParams::simplepir_1024_q32_p294,
+        ];
+        for (log_samples, preset) in (13..).zip(presets) {
+            let params = preset(16).unwrap();
+            assert_eq!((params.q, params.n, params.m, params.std_dev), (Q32, 1024, 16, 6.4));
+            assert_eq!(params.a.dimensions(), (16, 1024));
+            assert_eq!(params.validate(), Ok(()));
+
+            let db = gen_db(&params);
+            let hint = gen_hint(&params, &db);
+            let (query, secret) = query(&params, 5, new_secret(&params)).unwrap();
+            let answer = answer(&query, &db).unwrap();
+            let row = recover_row(&params, secret, &hint, &answer).unwrap();
+            assert_eq!(row, db.rotated().col(5));
+
+            let max = 1 << log_samples;
+            assert_eq!(preset(max + 1), Err(SimplePirError::TooManySamples { rows: max + 1, max }));
+            assert_eq!(preset(0), Err(SimplePirError::Empty("database")));
+            assert_eq!(params.security_bits(), Some(128));
+        }
+        assert_eq!(paper_moduli(), [991, 833, 701, 589, 495, 416, 350, 294]);
+        assert_eq!(gen_insecure_test_params_for_width(16, 991).security_bits(), None);
+    }
+
+    #[test]
+    pub fn test_simplepir_compressed_answer() {
+        for (p, q_prime) in [(2, 512), (991, 1 << 20)] {
+            let params = gen_insecure_test_params_for_width(8, p);
+            let db = gen_db(&params);
+            let hint = gen_hint(&params, &db);
+
+            for desired_row in 0..params.m {
+                let (query, secret) = query(&params, desired_row, new_secret(&params)).unwrap();
+                let answer = answer_compressed(&query, &db, q_prime).unwrap();
+                assert_eq!(answer.q(), q_prime);
+
+                let recovered_row = recover_row(&params, secret, &hint, &answer).unwrap();
+                for (col, item) in recovered_row.iter().enumerate() {
+                    assert_eq!(*item, db.get(col, desired_row));
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_simplepir_malformed_answer() {
+        let params = gen_insecure_test_params_for_width(16, 16);
+        assert!(params.noise_budget(params.m).is_guaranteed());
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let new_query = || {
+            let (query, secret) = query(&params, 3, new_secret(&params)).unwrap();
+            let answer = answer(&query, &db).unwrap();
+            (query, secret, answer)
+        };
+        let (_, secret, answer) = new_query();
+        assert!(recover_row(&params, secret, &hint, &answer).is_ok());
+
+        // Shifting an entry by half of q / p makes it undecodable
+        let half = Element::from(params.q, delta_floor(params.q, params.p) / 2);
+        let shift = |mut answer: Matrix| {
+            answer.set(5, 0, &(answer.get(5, 0) + half.clone()));
+            answer
+        };
+        let err = SimplePirError::MalformedAnswer;
+        let (_, secret, answer) = new_query();
+        assert_eq!(recover_row(&params, secret, &hint, &shift(answer)), Err(err.clone()));
+        let partial_hint = gen_partial_hint(&hint, 4..6).unwrap();
+        let (_, secret, answer) = new_query();
+        let result = recover_partial(&params, secret, 5, &partial_hint, &shift(answer));
+        assert_eq!(result, Err(err.clone()));
+
+        // As are random answers and answers against a different database
+        let random = Matrix::gen_uniform_rand(params.q, params.m, 1);
+        let (_, secret, _) = new_query();
+        assert_eq!(recover_row(&params, secret, &hint, &random), Err(err.clone()));
+        let other_hint = gen_hint(&params, &gen_db(&params));
+        let (_, secret, answer) = new_query();
+        assert_eq!(recover_row(&params, secret, &other_hint, &answer), Err(err));
+
+        // Compressed answers and answers to compressed queries carry more noise, but pass
+        let (query, secret, _) = new_query();
+        let compressed = answer_compressed(&query, &db, 1 << 20).unwrap();
+        assert!(recover_row(&params, secret, &hint, &compressed).is_ok());
+        let (query, secret, _) = new_query();
+        let answer = super::answer(&compress_query(&query, 1 << 20).unwrap(), &db).unwrap();
+        assert!(recover_row(&params, secret, &hint, &answer).is_ok());
+    }
+
+    #[test]
+    pub fn test_simplepir_malformed_answer_secure_params() {
+        let params = SimplePIRParams::for_security(128, 1 << 12, 8).unwrap();
+        let budget = params.noise_budget(params.m);
+        assert!(!budget.is_guaranteed());
+        let bound = plausible_noise(&params, params.q).unwrap();
+        assert!((bound as f64) < budget.threshold);
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let new_query = || {
+            let prepared = prepare_query(&params, &hint).unwrap();
+            let (query, state) = prepared.finish(&params, 3).unwrap();
+            (state, answer(&query, &db).unwrap())
+        };
+        let (state, answer) = new_query();
+        let row = recover_prepared_row(&params, state, &answer).unwrap();
+        assert_eq!(row, db.clone().rotated().col(3));
+
+        // An entry whose noise lies just beyond the bound still decodes to its item, but no honest
+        // answer carries that much noise
+        let (state, mut answer) = new_query();
+        let noise = Element::from(params.q, bound + 1);
+        let noisy = scale_round(&db.get(5, 3), params.q) + noise;
+        answer.set(5, 0, &(state.hint_s[5].clone() + noisy));
+        let result = recover_prepared_row(&params, state, &answer);
+        assert_eq!(result, Err(SimplePirError::MalformedAnswer));
+    }
+
+    #[test]
+    pub fn test_simplepir_answer_chunked() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let (query, _) = query(&params, 4, new_secret(&params)).unwrap();
+        let expected = answer(&query, &db).unwrap();
+
+        for chunk_size in [1, 3, 8] {
+            let chunks = (0..db.num_cols()).step_by(chunk_size)
+                .map(|start| db.select_cols(start..(start + chunk_size).min(db.num_cols())));
+            assert_eq!(answer_chunked(&query, chunks).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    pub fn test_simplepir_answer_db() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let (query, _) = query(&params, 2, new_secret(&params)).unwrap();
+        let expected = answer(&query, &db).unwrap();
+
+        let sparse = SparseMatrix::from_matrix(&db, params.p);
+        for chunk_cols in [1, 3, params.m, params.m + 1] {
+            assert_eq!(answer_db(&query, &db, chunk_cols).unwrap(), expected);
+            assert_eq!(answer_db(&query, &sparse, chunk_cols).unwrap(), expected);
+        }
+        assert!(answer_db(&query[1..], &db, 4).is_err());
+    }
+
+    #[test]
+    pub fn test_simplepir_answer_compact() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let (query, _) = query(&params, 5, new_secret(&params)).unwrap();
+
+        let compact = CompactMatrix::from_matrix(&db, params.p);
+        assert_eq!(answer_compact(&query, &compact).unwrap(), answer(&query, &db).unwrap());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    pub fn test_simplepir_answer_parallel() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let (query, _) = query(&params, 6, new_secret(&params)).unwrap();
+        let expected = answer(&query, &db).unwrap();
+
+        for num_threads in [1, 3, 16] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build().unwrap();
+            assert_eq!(answer_parallel(&query, &db, &pool).unwrap(), expected);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    pub fn test_gen_hint_parallel() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let expected = gen_hint(&params, &db);
+        for num_threads in [1, 3, 16] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build().unwrap();
+            assert_eq!(gen_hint_parallel(&params, &db, &pool), expected);
+        }
+    }
+
+    #[test]
+    pub fn test_simplepir_answer_batch() {
+        let params = gen_insecure_test_params();
+        let db = gen_db(&params);
+        let hint = gen_hint(&params, &db);
+        let rows = db.transpose();
+
+        let desired_rows = [0, 3, 3, 7];
+        let (queries, secrets): (Vec<Vec<Element>>, Vec<QuerySecret>) = desired_rows.iter()
+            .map(|&row| query(&params, row, new_secret(&params)).unwrap())
+            .unzip();
+        let answers = answer_batch(&queries, &db).unwrap();
+        assert_eq!(answers.len(), desired_rows.len());
+
+        for (i, (&row, secret)) in desired_rows.iter().zip(secrets).enumerate() {
+            assert_eq!(answers[i], answer(&queries[i], &db).unwrap());
+            assert_eq!(recover_row(&params, secret, &hint, &answers[i]).unwrap(), rows.col(row));
+        }
+        assert!(answer_batch(&[], &db).unwrap().is_empty());
+    }
+
     fn test_simplepir_updates_impl(desired_col: usize, desired_row: usize) {
-        let params = gen_params();
+        let params = gen_insecure_test_params();
         let db = gen_db(&params);
         let hint = gen_hint(&params, &db);
 
-        let secret = gen_secret(params.q, params.n);
 
-        let query = query(&params, desired_row, &secret);
-        let ans = answer(&query, &db);
-        let recovered = recover(&params, &secret, desired_col, &hint, &ans);
+        let (query, secret) = query(&params, desired_row, new_secret(&params)).unwrap();
+        let ans = answer(&query, &db).unwrap();
+        let recovered = recover(&params, secret, desired_col, &hint, &ans).unwrap();
 
-        let db_item = &db[desired_col][desired_row];
-        assert_eq!(recovered, *db_item);
+        let db_item = db.get(desired_col, desired_row);
+        assert_eq!(recovered, db_item);
 
         // Flip all bits of one row
         let row_to_flip = desired_col;
@@ -166,9 +2269,9 @@ mod tests {
         let mut updated_row = Vec::with_capacity(params.m);
         for i in 0..db.num_rows() {
             // Flip the bits in the row
-            db[row_to_flip][i] -= Element::from(params.p, 1);
+            db.set(row_to_flip, i, &(db.get(row_to_flip, i) - Element::from(params.p, 1)));
             updated_row.push(
-                Element::from(params.q, db[row_to_flip][i].uint)
+                Element::from(params.q, db.col_values(row_to_flip)[i])
             );
         }
         db.change_q(params.q);
@@ -177,15 +2280,16 @@ mod tests {
         let mut hint = hint.clone();
 
         // This operation is much more efficient than regenerating the whole hint matrix
-        let updated_hint_row = Matrix::from_col(&updated_row) * params.a.to_owned();
+        let updated_hint_row = &Matrix::from_col(&updated_row) * &params.a;
 
-        for j in 0..hint.num_rows() {
-            hint[row_to_flip][j] = updated_hint_row[0][j].clone();
+        for j in 0..hint.0.num_rows() {
+            hint.0.set(row_to_flip, j, &updated_hint_row.get(0, j));
         }
 
-        let ans = answer(&query, &db);
-        let recovered = recover(&params, &secret, desired_col, &hint, &ans);
-        let db_item = &db[desired_col][desired_row];
+        let (query, secret) = super::query(&params, desired_row, new_secret(&params)).unwrap();
+        let ans = answer(&query, &db).unwrap();
+        let recovered = recover(&params, secret, desired_col, &hint, &ans).unwrap();
+        let db_item = db.get(desired_col, desired_row);
         assert_eq!(recovered.uint, db_item.uint);
     }
 
@@ -0,0 +1,172 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::element::Element;
+use crate::matrix::Matrix;
+
+/// A matrix mod q which only stores its non-zero values, column by column (CSR over the columns
+/// of the Matrix layout, where data[i] is column i). Scanning it touches only the non-zero
+/// values, so it is much faster than a Matrix for databases which are mostly zeros.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SparseMatrix {
+    q: u64,
+    num_rows: usize,
+    // col_starts[i]..col_starts[i + 1] are the indices of the values of column i
+    col_starts: Vec<usize>,
+    // The row of each value, increasing within each column
+    rows: Vec<usize>,
+    // The non-zero values
+    values: Vec<u64>,
+}
+
+impl SparseMatrix {
+    /// Convert a matrix whose values are all mod q.
+    pub fn from_matrix(m: &Matrix, q: u64) -> Self {
+        let mut col_starts = vec![0];
+        let mut rows = vec![];
+        let mut values = vec![];
+        assert!(m.has_modulus(q));
+        for col in m.cols() {
+            for (j, &v) in col.iter().enumerate() {
+                if v != 0 {
+                    rows.push(j);
+                    values.push(v);
+                }
+            }
+            col_starts.push(values.len());
+        }
+        Self { q, num_rows: m.num_rows(), col_starts, rows, values }
+    }
+
+    pub fn to_matrix(&self) -> Matrix {
+        let data: Vec<Vec<Element>> = (0..self.num_cols()).map(|i| self.column(i)).collect();
+        Matrix::from(&data)
+    }
+
+    pub fn q(&self) -> u64 {
+        self.q
+    }
+
+    pub fn num_cols(&self) -> usize {
+        self.col_starts.len() - 1
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.num_cols(), self.num_rows())
+    }
+
+    /// The number of non-zero values.
+    pub fn num_nonzero(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The fraction of values which are non-zero.
+    pub fn density(&self) -> f64 {
+        let total = self.num_cols() * self.num_rows();
+        if total == 0 {
+            return 0.0;
+        }
+        self.num_nonzero() as f64 / total as f64
+    }
+
+    /// Column i, with the zeros filled in.
+    pub fn column(&self, i: usize) -> Vec<Element> {
+        let mut col = vec![Element::zero(self.q); self.num_rows];
+        for k in self.col_starts[i]..self.col_starts[i + 1] {
+            col[self.rows[k]] = Element::from(self.q, self.values[k]);
+        }
+        col
+    }
+
+    /// Set the value at [col][row], inserting or removing it from the stored values as needed.
+    pub fn set(&mut self, col: usize, row: usize, value: &Element) {
+        assert_eq!(value.q, self.q);
+        assert!(row < self.num_rows);
+        let (start, end) = (self.col_starts[col], self.col_starts[col + 1]);
+        match self.rows[start..end].binary_search(&row) {
+            Ok(k) if value.is_zero() => {
+                self.rows.remove(start + k);
+                self.values.remove(start + k);
+                self.col_starts[col + 1..].iter_mut().for_each(|s| *s -= 1);
+            }
+            Ok(k) => self.values[start + k] = value.uint,
+            Err(_) if value.is_zero() => {}
+            Err(k) => {
+                self.rows.insert(start + k, row);
+                self.values.insert(start + k, value.uint);
+                self.col_starts[col + 1..].iter_mut().for_each(|s| *s += 1);
+            }
+        }
+    }
+
+    /// Multiply by a vector mod the vector's modulus, with the same output shape as
+    /// Matrix::mul_vec(). Values are reinterpreted mod that modulus, which must not be below q.
+    pub fn mul_vec(&self, rhs: &[Element]) -> Matrix {
+        assert!(!rhs.is_empty());
+        assert_eq!(self.num_rows, rhs.len());
+        let q = rhs[0].q;
+        assert!(q >= self.q);
+
+        let rhs: Vec<u64> = rhs.iter().map(|e| {
+            assert_eq!(e.q, q);
+            e.uint
+        }).collect();
+        let data: Vec<Vec<Element>> = (0..self.num_cols()).map(|i| {
+            // Each reduced product is below 2^64, so fewer than 2^64 of them sum exactly in a u128
+            let range = self.col_starts[i]..self.col_starts[i + 1];
+            let sum = self.rows[range.clone()].iter()
+                .zip(&self.values[range])
+                .fold(0u128, |acc, (&j, &v)| acc + (v as u128 * rhs[j] as u128) % q as u128);
+            vec![Element::from(q, (sum % q as u128) as u64)]
+        }).collect();
+        Matrix::from(&data)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // A matrix mod q with roughly one in `every` values non-zero
+    fn gen_sparse(q: u64, cols: usize, rows: usize, every: usize) -> Matrix {
+        let mut m = Matrix::gen_uniform_rand(q, cols, rows);
+        for i in 0..cols {
+            for j in 0..rows {
+                if !(i * rows + j).is_multiple_of(every) {
+                    m.set(i, j, &Element::zero(q));
+                }
+            }
+        }
+        m
+    }
+
+    #[test]
+    fn test_sparse_mul_vec() {
+        for q in [3329, 4294967291, (1 << 56) - 5] {
+            let m = gen_sparse(q, 7, 300, 10);
+            let v = Matrix::gen_uniform_rand(q, 1, 300).col(0);
+            let sparse = SparseMatrix::from_matrix(&m, q);
+            assert_eq!(sparse.dimensions(), m.dimensions());
+            assert_eq!(sparse.to_matrix(), m);
+            assert!(sparse.density() <= 0.1);
+            assert_eq!(sparse.mul_vec(&v), m.clone().mul_vec(&v));
+        }
+    }
+
+    #[test]
+    fn test_sparse_set() {
+        let q = 991;
+        let mut m = gen_sparse(q, 4, 10, 3);
+        let mut sparse = SparseMatrix::from_matrix(&m, q);
+        for (col, row, value) in [(1, 4, 7), (1, 4, 0), (3, 9, 990), (0, 0, 0), (2, 5, 1), (2, 5, 2)] {
+            m.set(col, row, &Element::from(q, value));
+            sparse.set(col, row, &Element::from(q, value));
+            assert_eq!(sparse.to_matrix(), m);
+            assert_eq!(sparse, SparseMatrix::from_matrix(&m, q));
+        }
+    }
+}
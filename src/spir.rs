@@ -0,0 +1,190 @@
+// Symmetric PIR for byte records: the client learns the record it queried and nothing about the
+// others.
+//
+// An answer decrypts to a whole database row, so PIR alone hands the client every record stored
+// in the queried row, and a client which sends a malformed query can learn linear combinations of
+// other rows as well. In symmetric mode each record is therefore stored XORed with a pad derived
+// from an oblivious PRF of its index, F_k(idx) = H(idx, k * H'(idx)) in the Ristretto group,
+// under a key k which only the server holds. Along with its query the client sends a blinded
+// point r * H'(idx) for a random scalar r, the server multiplies it by k, and the client unblinds
+// the result with 1 / r to obtain F_k(idx). The blinded point is uniformly random, so the server
+// learns nothing about idx, while each evaluation gives the client the pad of a single record.
+// This only holds if the server evaluates the PRF at most once per query.
+
+use alloc::vec::Vec;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, SeedableRng};
+use sha2::{Digest as _, Sha256, Sha512};
+use zeroize::Zeroize;
+use crate::error::SimplePirError;
+use crate::merkle::Digest;
+
+// Separates the hashes of this module from any other use of the same hash functions
+const DOMAIN: &[u8] = b"simplepir-spir";
+
+/// The server's OPRF key.
+#[derive(Clone)]
+pub struct OprfKey(Scalar);
+
+impl Drop for OprfKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// A blinded index, sent by the client along with its query.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OprfRequest(pub [u8; 32]);
+
+/// The server's evaluation of the OPRF on a blinded index.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OprfResponse(pub [u8; 32]);
+
+/// What the client keeps to unblind the response to its request.
+pub struct OprfState {
+    idx: usize,
+    // The inverse of the blinding scalar
+    r_inv: Scalar,
+}
+
+impl Drop for OprfState {
+    fn drop(&mut self) {
+        self.r_inv.zeroize();
+    }
+}
+
+// A uniformly random non-zero scalar
+fn random_scalar<R: CryptoRng + RngCore>(rng: &mut R) -> Scalar {
+    loop {
+        let mut bytes = [0u8; 64];
+        rng.fill_bytes(&mut bytes);
+        let scalar = Scalar::from_bytes_mod_order_wide(&bytes);
+        bytes.zeroize();
+        if scalar != Scalar::ZERO {
+            return scalar;
+        }
+    }
+}
+
+// H'(idx), a point whose discrete logarithm nobody knows
+fn hash_to_group(idx: usize) -> RistrettoPoint {
+    let hash = Sha512::new().chain_update(DOMAIN).chain_update((idx as u64).to_le_bytes());
+    RistrettoPoint::from_uniform_bytes(&hash.finalize().into())
+}
+
+// F_k(idx) = H(idx, k * H'(idx))
+fn prf_output(idx: usize, point: &RistrettoPoint) -> Digest {
+    let hash = Sha256::new()
+        .chain_update(DOMAIN)
+        .chain_update((idx as u64).to_le_bytes())
+        .chain_update(point.compress().as_bytes());
+    hash.finalize().into()
+}
+
+#[cfg(feature = "std")]
+pub fn gen_oprf_key() -> OprfKey {
+    gen_oprf_key_with_rng(&mut StdRng::from_entropy())
+}
+
+pub fn gen_oprf_key_with_rng<R: CryptoRng + RngCore>(rng: &mut R) -> OprfKey {
+    OprfKey(random_scalar(rng))
+}
+
+/// Evaluate the PRF directly, as the server does to derive the pads.
+pub fn eval(key: &OprfKey, idx: usize) -> Digest {
+    prf_output(idx, &(key.0 * hash_to_group(idx)))
+}
+
+/// Blind an index for the server to evaluate the PRF on.
+#[cfg(feature = "std")]
+pub fn blind(idx: usize) -> (OprfRequest, OprfState) {
+    blind_with_rng(idx, &mut StdRng::from_entropy())
+}
+
+/// Like blind(), but sampling the blinding scalar with the given RNG.
+pub fn blind_with_rng<R: CryptoRng + RngCore>(
+    idx: usize,
+    rng: &mut R,
+) -> (OprfRequest, OprfState) {
+    let r = random_scalar(rng);
+    let request = OprfRequest((r * hash_to_group(idx)).compress().to_bytes());
+    (request, OprfState { idx, r_inv: r.invert() })
+}
+
+/// Evaluate the PRF on a blinded index. Requests which are not valid points are rejected.
+pub fn evaluate(key: &OprfKey, request: &OprfRequest) -> Result<OprfResponse, SimplePirError> {
+    let point = decompress(&request.0)?;
+    Ok(OprfResponse((key.0 * point).compress().to_bytes()))
+}
+
+/// Unblind the server's response, giving the PRF output for the blinded index.
+pub fn finalize(state: &OprfState, response: &OprfResponse) -> Result<Digest, SimplePirError> {
+    let point = decompress(&response.0)?;
+    Ok(prf_output(state.idx, &(state.r_inv * point)))
+}
+
+fn decompress(bytes: &[u8; 32]) -> Result<RistrettoPoint, SimplePirError> {
+    CompressedRistretto(*bytes).decompress().ok_or(SimplePirError::InvalidGroupElement)
+}
+
+/// XOR a record with the pad derived from a PRF output, which masks and unmasks it alike. The
+/// pad is SHA-256 of the output and a block counter, one 32-byte block at a time.
+pub fn apply_pad(output: &Digest, record: &[u8]) -> Vec<u8> {
+    let mut padded = record.to_vec();
+    for (i, chunk) in padded.chunks_mut(32).enumerate() {
+        let hash = Sha256::new().chain_update(output).chain_update((i as u64).to_le_bytes());
+        let block = hash.finalize();
+        chunk.iter_mut().zip(block).for_each(|(byte, pad)| *byte ^= pad);
+    }
+    padded
+}
+
+/// Mask every record with the pad of its index, to be stored in the database in its place.
+pub fn mask_records(key: &OprfKey, records: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    records.iter().enumerate().map(|(idx, record)| apply_pad(&eval(key, idx), record)).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_oprf() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let key = gen_oprf_key_with_rng(&mut rng);
+        for idx in [0, 1, 1000] {
+            let (request, state) = blind_with_rng(idx, &mut rng);
+            let response = evaluate(&key, &request).unwrap();
+            assert_eq!(finalize(&state, &response), Ok(eval(&key, idx)));
+
+            // Requests for the same index look unrelated
+            let (other, _) = blind_with_rng(idx, &mut rng);
+            assert_ne!(request, other);
+        }
+        assert_ne!(eval(&key, 0), eval(&key, 1));
+        assert_ne!(eval(&key, 0), eval(&gen_oprf_key_with_rng(&mut rng), 0));
+        assert_eq!(
+            evaluate(&key, &OprfRequest([0xff; 32])),
+            Err(SimplePirError::InvalidGroupElement),
+        );
+    }
+
+    #[test]
+    fn test_mask_records() {
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let key = gen_oprf_key_with_rng(&mut rng);
+        let records: Vec<Vec<u8>> = (0..3).map(|i| vec![i as u8; 40]).collect();
+        let masked = mask_records(&key, &records);
+        for (idx, record) in records.iter().enumerate() {
+            assert_ne!(masked[idx], *record);
+            assert_eq!(apply_pad(&eval(&key, idx), &masked[idx]), *record);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+/// Timings and traffic collected by a Server or Client once stats are enabled. The server records
+/// its setup, the time to compute each answer and the database items each answer read; the client
+/// records the time to recover each answer and the size of its hint. Both count the bytes of
+/// queries sent to the server (uploaded) and of answers sent back (downloaded), as encoded by the
+/// wire module, which lets an application hold a client or server to a data budget.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Stats {
+    // The time spent in setup, including generating the hint
+    pub setup_time: Duration,
+    // The time spent on each answer, in the order of the queries
+    pub answer_times: Vec<Duration>,
+    // The bytes of all queries
+    pub bytes_uploaded: u64,
+    // The bytes of all answers
+    pub bytes_downloaded: u64,
+    // The bytes of the hint held by a client
+    pub bytes_hint: u64,
+    // The number of database items read by all answers
+    pub items_scanned: u64,
+}
+
+impl Stats {
+    /// The number of answers recorded.
+    pub fn num_answers(&self) -> usize {
+        self.answer_times.len()
+    }
+
+    pub fn total_answer_time(&self) -> Duration {
+        self.answer_times.iter().sum()
+    }
+
+    /// The mean time per answer, or zero if nothing was answered.
+    pub fn mean_answer_time(&self) -> Duration {
+        match self.num_answers() {
+            0 => Duration::ZERO,
+            n => self.total_answer_time() / n as u32,
+        }
+    }
+
+    /// The bytes of all queries, answers and the hint, i.e. the traffic to hold to a budget.
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes_uploaded + self.bytes_downloaded + self.bytes_hint
+    }
+
+    /// The database scan throughput, in items per second, over all answers.
+    pub fn scan_throughput(&self) -> f64 {
+        let secs = self.total_answer_time().as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.items_scanned as f64 / secs
+    }
+
+    pub(crate) fn record_answer(&mut self, time: Duration, query_len: usize, answer_len: usize) {
+        self.answer_times.push(time);
+        self.bytes_uploaded += query_len as u64;
+        self.bytes_downloaded += answer_len as u64;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::default();
+        assert_eq!((stats.mean_answer_time(), stats.scan_throughput()), (Duration::ZERO, 0.0));
+
+        stats.record_answer(Duration::from_millis(10), 100, 40);
+        stats.record_answer(Duration::from_millis(30), 100, 40);
+        stats.items_scanned = 2000;
+        assert_eq!(stats.num_answers(), 2);
+        assert_eq!(stats.mean_answer_time(), Duration::from_millis(20));
+        assert_eq!((stats.bytes_uploaded, stats.bytes_downloaded), (200, 80));
+        stats.bytes_hint = 1000;
+        assert_eq!(stats.total_bytes(), 1280);
+        assert_eq!(stats.scan_throughput(), 50000.0);
+    }
+}
@@ -0,0 +1,405 @@
+//! Pluggable storage for the state a client keeps between sessions, so that it downloads a hint
+//! once rather than on every start.
+//!
+//! A ClientStore holds chunks of bytes by digest and one manifest, which is swapped atomically:
+//! a reader sees either the previous manifest or the new one, whole. save_hint() stores the files
+//! of a public hint as the publish module lays them out, then swaps in their manifest, and only
+//! then removes the chunks which the previous manifest named and the new one does not, so that a
+//! save which is interrupted leaves the previous hint loadable. FsStore keeps chunks as files in a
+//! directory and MemoryStore keeps them in memory.
+//!
+//! With the `encryption` feature, EncryptedStore encrypts chunks and the manifest with
+//! ChaCha20-Poly1305 under a key provided by the caller before passing them to another store, for
+//! devices whose storage other users or apps can read. Chunks are stored under a keyed hash of
+//! their digest, so that the names in the store do not reveal which hint is held either.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag};
+#[cfg(feature = "encryption")]
+use rand::rngs::StdRng;
+#[cfg(feature = "encryption")]
+use rand::{RngCore, SeedableRng};
+use sha2::{Digest as _, Sha256};
+#[cfg(feature = "encryption")]
+use zeroize::Zeroizing;
+
+use crate::merkle::Digest;
+use crate::pir::PublicHint;
+use crate::publish::{self, HintManifest};
+
+// The file of an FsStore holding the manifest
+const MANIFEST_FILE: &str = "MANIFEST";
+
+/// Storage for a client's state: chunks of bytes by digest, and a manifest naming the current
+/// ones.
+pub trait ClientStore {
+    /// The chunk stored under the digest, if there is one.
+    fn get_chunk(&self, digest: &Digest) -> io::Result<Option<Vec<u8>>>;
+
+    /// Store a chunk under its digest. Storing a chunk which is already held does nothing.
+    fn put_chunk(&mut self, digest: &Digest, chunk: &[u8]) -> io::Result<()>;
+
+    /// Remove the chunk stored under the digest, if there is one.
+    fn remove_chunk(&mut self, digest: &Digest) -> io::Result<()>;
+
+    /// The current manifest, if one was stored.
+    fn manifest(&self) -> io::Result<Option<Vec<u8>>>;
+
+    /// Replace the manifest atomically, so that a reader, or a store whose process is stopped
+    /// during the swap, sees either the previous manifest or this one.
+    fn swap_manifest(&mut self, manifest: &[u8]) -> io::Result<()>;
+}
+
+/// Store the public hint of the given epoch and make it the current one. Chunks of the hint it
+/// replaces are removed once the new manifest is in place, apart from those the two share (e.g.
+/// the params). Returns the new manifest.
+pub fn save_hint<S: ClientStore + ?Sized>(
+    store: &mut S,
+    public: &PublicHint,
+    epoch: u64,
+) -> io::Result<HintManifest> {
+    let previous = match store.manifest()? {
+        Some(bytes) => HintManifest::from_bytes(&bytes).ok(),
+        None => None,
+    };
+    let put = |digest: &Digest, chunk: &[u8]| store.put_chunk(digest, chunk);
+    let manifest = publish::publish_with(put, public, epoch)?;
+    store.swap_manifest(&manifest.to_bytes())?;
+    if let Some(previous) = previous {
+        let current = chunks(&manifest);
+        for digest in chunks(&previous).iter().filter(|digest| !current.contains(digest)) {
+            store.remove_chunk(digest)?;
+        }
+    }
+    Ok(manifest)
+}
+
+/// Load the current public hint from the store, or None if no hint was saved. Every chunk is
+/// checked against its digest and the public hint is validated, as by publish::load_with(), so
+/// that a store whose contents were modified fails with an InvalidData error.
+pub fn load_hint<S: ClientStore + ?Sized>(
+    store: &S,
+) -> io::Result<Option<(HintManifest, PublicHint)>> {
+    let Some(manifest) = store.manifest()? else { return Ok(None) };
+    let digest: Digest = Sha256::digest(&manifest).into();
+    let fetch = |digest: &Digest| {
+        store.get_chunk(digest)?.ok_or_else(|| {
+            let msg = format!("the chunk {} is missing", publish::file_name(digest));
+            io::Error::new(io::ErrorKind::NotFound, msg)
+        })
+    };
+    publish::load_digested(fetch, &digest).map(Some)
+}
+
+// The digests of the chunks a manifest names, itself included
+fn chunks(manifest: &HintManifest) -> Vec<Digest> {
+    let mut chunks = vec![manifest.digest(), manifest.params, manifest.hint];
+    chunks.extend(manifest.layout);
+    chunks
+}
+
+/// A store which keeps each chunk in a file of a directory named by its digest in hex, and the
+/// manifest in a file which is swapped by renaming a new file over it.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    dir: PathBuf,
+}
+
+impl FsStore {
+    /// Open the store in the directory, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir: dir.as_ref().to_path_buf() })
+    }
+
+    // Read a file of the store, or None if it does not exist
+    fn read(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.dir.join(name)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl ClientStore for FsStore {
+    fn get_chunk(&self, digest: &Digest) -> io::Result<Option<Vec<u8>>> {
+        self.read(&publish::file_name(digest))
+    }
+
+    fn put_chunk(&mut self, digest: &Digest, chunk: &[u8]) -> io::Result<()> {
+        publish::write_file(&self.dir, digest, chunk)
+    }
+
+    fn remove_chunk(&mut self, digest: &Digest) -> io::Result<()> {
+        match fs::remove_file(self.dir.join(publish::file_name(digest))) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn manifest(&self) -> io::Result<Option<Vec<u8>>> {
+        self.read(MANIFEST_FILE)
+    }
+
+    fn swap_manifest(&mut self, manifest: &[u8]) -> io::Result<()> {
+        let tmp = self.dir.join(format!(".{}.{}", MANIFEST_FILE, std::process::id()));
+        fs::write(&tmp, manifest)?;
+        fs::rename(&tmp, self.dir.join(MANIFEST_FILE))
+    }
+}
+
+/// A store which keeps its chunks and manifest in memory, e.g. for tests or for a client whose
+/// platform provides storage of its own to copy them to.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    chunks: BTreeMap<Digest, Vec<u8>>,
+    manifest: Option<Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of chunks held.
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+impl ClientStore for MemoryStore {
+    fn get_chunk(&self, digest: &Digest) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.chunks.get(digest).cloned())
+    }
+
+    fn put_chunk(&mut self, digest: &Digest, chunk: &[u8]) -> io::Result<()> {
+        self.chunks.entry(*digest).or_insert_with(|| chunk.to_vec());
+        Ok(())
+    }
+
+    fn remove_chunk(&mut self, digest: &Digest) -> io::Result<()> {
+        self.chunks.remove(digest);
+        Ok(())
+    }
+
+    fn manifest(&self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.manifest.clone())
+    }
+
+    fn swap_manifest(&mut self, manifest: &[u8]) -> io::Result<()> {
+        self.manifest = Some(manifest.to_vec());
+        Ok(())
+    }
+}
+
+// The domains of the keyed hash of chunk names and of the associated data of the manifest
+#[cfg(feature = "encryption")]
+const NAME_DOMAIN: &[u8] = b"simplepir-rs/store-name";
+#[cfg(feature = "encryption")]
+const MANIFEST_DOMAIN: &[u8] = b"simplepir-rs/store-manifest";
+
+/// A store which encrypts chunks and the manifest before passing them to an inner store. Each is
+/// stored as a random nonce, the tag and the ciphertext, with its digest (or a domain, for the
+/// manifest) as associated data, so that the inner store cannot swap them around undetected.
+/// Chunks which fail to decrypt, e.g. under another key, fail with an InvalidData error.
+#[cfg(feature = "encryption")]
+pub struct EncryptedStore<S> {
+    inner: S,
+    cipher: ChaCha20Poly1305,
+    // The key of the hash which chunks are named by in the inner store
+    name_key: Zeroizing<[u8; 32]>,
+    rng: StdRng,
+}
+
+#[cfg(feature = "encryption")]
+impl<S: ClientStore> EncryptedStore<S> {
+    /// Encrypt the contents of the inner store under the key.
+    pub fn new(inner: S, key: &[u8; 32]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(NAME_DOMAIN);
+        hasher.update(key);
+        Self {
+            inner,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            name_key: Zeroizing::new(hasher.finalize().into()),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // The name of a chunk in the inner store
+    fn name(&self, digest: &Digest) -> Digest {
+        let mut hasher = Sha256::new();
+        hasher.update(*self.name_key);
+        hasher.update(digest);
+        hasher.finalize().into()
+    }
+
+    fn encrypt(&mut self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; 12];
+        self.rng.fill_bytes(&mut nonce);
+        let mut body = plaintext.to_vec();
+        let tag = self.cipher
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce), aad, &mut body)
+            .expect("the chunk is too long to encrypt");
+        let mut out = Vec::with_capacity(12 + 16 + body.len());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&tag);
+        out.extend_from_slice(&body);
+        out
+    }
+
+    fn decrypt(&self, aad: &[u8], stored: &[u8]) -> io::Result<Vec<u8>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "the chunk does not decrypt");
+        if stored.len() < 12 + 16 {
+            return Err(invalid());
+        }
+        let (nonce, rest) = stored.split_at(12);
+        let (tag, body) = rest.split_at(16);
+        let mut body = body.to_vec();
+        let (nonce, tag) = (Nonce::from_slice(nonce), Tag::from_slice(tag));
+        self.cipher.decrypt_in_place_detached(nonce, aad, &mut body, tag).map_err(|_| invalid())?;
+        Ok(body)
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl<S: ClientStore> ClientStore for EncryptedStore<S> {
+    fn get_chunk(&self, digest: &Digest) -> io::Result<Option<Vec<u8>>> {
+        match self.inner.get_chunk(&self.name(digest))? {
+            Some(stored) => self.decrypt(digest, &stored).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn put_chunk(&mut self, digest: &Digest, chunk: &[u8]) -> io::Result<()> {
+        let name = self.name(digest);
+        if self.inner.get_chunk(&name)?.is_some() {
+            return Ok(());
+        }
+        let stored = self.encrypt(digest, chunk);
+        self.inner.put_chunk(&name, &stored)
+    }
+
+    fn remove_chunk(&mut self, digest: &Digest) -> io::Result<()> {
+        let name = self.name(digest);
+        self.inner.remove_chunk(&name)
+    }
+
+    fn manifest(&self) -> io::Result<Option<Vec<u8>>> {
+        match self.inner.manifest()? {
+            Some(stored) => self.decrypt(MANIFEST_DOMAIN, &stored).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn swap_manifest(&mut self, manifest: &[u8]) -> io::Result<()> {
+        let stored = self.encrypt(MANIFEST_DOMAIN, manifest);
+        self.inner.swap_manifest(&stored)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::pir::{Client, Server};
+    use crate::record::RecordLayout;
+    use crate::simplepir::SimplePIRParams;
+
+    // A server over byte records, and its records
+    fn setup() -> (Server, Vec<Vec<u8>>) {
+        let records: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 8]).collect();
+        let layout = RecordLayout::new(records.len(), 8, 256).unwrap();
+        let params = SimplePIRParams::from_seed([3; 32], 1 << 32, 256, 64, layout.rows, 6.4);
+        (Server::setup_records(params, layout, &records).unwrap(), records)
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let (mut server, records) = setup();
+        let mut store = MemoryStore::new();
+        assert!(load_hint(&store).unwrap().is_none());
+
+        save_hint(&mut store, &server.public_hint(), server.epoch()).unwrap();
+        let (manifest, public) = load_hint(&store).unwrap().unwrap();
+        assert_eq!((manifest.epoch, public.digest()), (0, server.public_hint().digest()));
+        assert_eq!(store.num_chunks(), 4);
+
+        // A new epoch replaces the hint and manifest but shares the params and layout
+        server.update_record(2, &[9; 8]).unwrap();
+        save_hint(&mut store, &server.public_hint(), server.epoch()).unwrap();
+        assert_eq!(store.num_chunks(), 4);
+        let (manifest, public) = load_hint(&store).unwrap().unwrap();
+        assert_eq!(manifest.epoch, 1);
+        let mut client = Client::from_public_hint(public).unwrap();
+        let answer = server.answer(&client.query_record(5).unwrap()).unwrap();
+        assert_eq!(client.recover_record(&answer).unwrap(), records[5]);
+
+        // A chunk which does not match its digest
+        let mut tampered = store.clone();
+        tampered.chunks.insert(manifest.hint, vec![0; 10]);
+        assert_eq!(load_hint(&tampered).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        tampered.chunks.remove(&manifest.hint);
+        assert_eq!(load_hint(&tampered).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_fs_store() {
+        let (server, _) = setup();
+        let dir = std::env::temp_dir().join(format!("simplepir-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut store = FsStore::open(&dir).unwrap();
+        assert!(load_hint(&store).unwrap().is_none());
+        let manifest = save_hint(&mut store, &server.public_hint(), 7).unwrap();
+
+        let store = FsStore::open(&dir).unwrap();
+        let (loaded, public) = load_hint(&store).unwrap().unwrap();
+        assert_eq!((loaded, public.digest()), (manifest, server.public_hint().digest()));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_store() {
+        let (server, _) = setup();
+        let public = server.public_hint();
+        let mut store = EncryptedStore::new(MemoryStore::new(), &[5; 32]);
+        let manifest = save_hint(&mut store, &public, 2).unwrap();
+        let (loaded, loaded_public) = load_hint(&store).unwrap().unwrap();
+        assert_eq!((loaded, loaded_public.digest()), (manifest.clone(), public.digest()));
+
+        // The inner store holds neither the chunks nor their digests
+        let inner = store.into_inner();
+        assert_eq!(inner.num_chunks(), 4);
+        let params = crate::wire::encode_params(&public.params);
+        for (name, chunk) in &inner.chunks {
+            assert!(!chunks(&manifest).contains(name));
+            assert!(!chunk.windows(params.len()).any(|window| window == params));
+        }
+
+        // Another key fails to decrypt the manifest
+        let other = EncryptedStore::new(inner.clone(), &[6; 32]);
+        assert_eq!(load_hint(&other).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A chunk stored under the name of another fails to decrypt
+        let mut swapped = inner;
+        let stored: Vec<Vec<u8>> = swapped.chunks.values().cloned().collect();
+        for chunk in swapped.chunks.values_mut() {
+            *chunk = stored[0].clone();
+        }
+        let store = EncryptedStore::new(swapped, &[5; 32]);
+        assert_eq!(load_hint(&store).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
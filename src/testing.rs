@@ -0,0 +1,253 @@
+// Statistical checks of the samplers, so that changes to them or to the parameters can be
+// validated. Each check compares a test statistic against a critical value chosen so that a
+// correct sampler fails it with probability about ALPHA, which keeps suites of checks from
+// flaking while still catching biases which matter at the sample sizes used.
+
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use rand_distr::num_traits::Float;
+use rand::{CryptoRng, RngCore};
+use crate::element::{Element, TAIL_CUT};
+use crate::regev::{error_bound, gen_error_vec_with_rng, SecretDistribution};
+
+/// The probability with which each check fails a correct sampler.
+pub const ALPHA: f64 = 6.3e-5;
+
+// The two-sided standard normal quantile of ALPHA
+const Z: f64 = 4.0;
+
+/// The outcome of one statistical check, which passes while the statistic stays at most the
+/// critical value.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Check {
+    // What was checked
+    pub name: &'static str,
+    pub statistic: f64,
+    pub critical: f64,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.statistic <= self.critical
+    }
+}
+
+/// Whether every check passed.
+pub fn all_passed(checks: &[Check]) -> bool {
+    checks.iter().all(Check::passed)
+}
+
+/// The value of an element as an integer in (-q/2, q/2].
+pub fn centered(e: &Element) -> i64 {
+    if e.uint > e.q / 2 { -((e.q - e.uint) as i64) } else { e.uint as i64 }
+}
+
+/// Pearson's chi-squared test of observed counts against expected counts. The critical value is
+/// the Wilson-Hilferty approximation of the chi-squared quantile for counts.len() - 1 degrees of
+/// freedom.
+pub fn chi_squared(observed: &[u64], expected: &[f64]) -> Check {
+    assert_eq!(observed.len(), expected.len());
+    assert!(observed.len() >= 2);
+    let statistic = observed.iter().zip(expected).map(|(&o, &e)| {
+        let diff = o as f64 - e;
+        diff * diff / e
+    }).sum();
+    let k = (observed.len() - 1) as f64;
+    let t = 2.0 / (9.0 * k);
+    let critical = k * (1.0 - t + Z * t.sqrt()).powi(3);
+    Check { name: "chi-squared", statistic, critical }
+}
+
+/// The chi-squared test of samples mod q against the uniform distribution, with the range split
+/// into the given number of buckets of (nearly) equal size.
+pub fn chi_squared_uniform(samples: &[Element], buckets: usize) -> Check {
+    let q = samples[0].q;
+    assert!(buckets >= 2 && buckets as u64 <= q);
+    let bucket = |x: u64| (x as u128 * buckets as u128 / q as u128) as usize;
+    // Bucket k holds the values from ⌈kq / buckets⌉ on
+    let start = |k: usize| (k as u128 * q as u128).div_ceil(buckets as u128) as f64;
+
+    let mut observed = vec![0u64; buckets];
+    samples.iter().for_each(|e| observed[bucket(e.uint)] += 1);
+    let n = samples.len() as f64;
+    let expected: Vec<f64> =
+        (0..buckets).map(|k| n * (start(k + 1) - start(k)) / q as f64).collect();
+    chi_squared(&observed, &expected)
+}
+
+/// The Kolmogorov-Smirnov test of samples mod q against the uniform distribution: the largest
+/// distance between the empirical distribution function and x / q.
+pub fn ks_uniform(samples: &[Element]) -> Check {
+    let q = samples[0].q as f64;
+    let n = samples.len() as f64;
+    let mut values: Vec<u64> = samples.iter().map(|e| e.uint).collect();
+    values.sort_unstable();
+    let statistic = values.iter().enumerate().map(|(i, &x)| {
+        let below = x as f64 / q;
+        let upto = (x + 1) as f64 / q;
+        ((i + 1) as f64 / n - upto).max(below - i as f64 / n)
+    }).fold(0.0, f64::max);
+    let critical = (-(ALPHA / 2.0).ln() / 2.0).sqrt() / n.sqrt();
+    Check { name: "Kolmogorov-Smirnov", statistic, critical }
+}
+
+/// The variance and fourth central moment of the discrete Gaussian with standard deviation
+/// std_dev truncated to [-bound, bound], as sampled by Element::gen_discrete_gaussian_with_rng().
+pub fn discrete_gaussian_moments(std_dev: f64, bound: u64) -> (f64, f64) {
+    let bound = bound as i64;
+    let weight = |x: i64| (-((x * x) as f64) / (2.0 * std_dev * std_dev)).exp();
+    let total: f64 = (-bound..=bound).map(weight).sum();
+    let moment = |k: i32| {
+        (-bound..=bound).map(|x| (x as f64).powi(k) * weight(x)).sum::<f64>() / total
+    };
+    (moment(2), moment(4))
+}
+
+/// Check that centered samples have mean zero and the given variance and fourth central moment,
+/// using the normal approximations of the sample mean and sample variance.
+pub fn moment_checks(samples: &[Element], variance: f64, fourth_moment: f64) -> Vec<Check> {
+    let n = samples.len() as f64;
+    let values: Vec<f64> = samples.iter().map(|e| centered(e) as f64).collect();
+    let mean = values.iter().sum::<f64>() / n;
+    let sample_variance = values.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+    vec![
+        Check { name: "mean", statistic: mean.abs() / (variance / n).sqrt(), critical: Z },
+        Check {
+            name: "variance",
+            statistic: (sample_variance - variance).abs()
+                / ((fourth_moment - variance * variance) / n).sqrt(),
+            critical: Z,
+        },
+    ]
+}
+
+/// Check num_samples samples of Element::gen_uniform_rand_with_rng() mod q with the chi-squared
+/// test (over at most 100 buckets) and the Kolmogorov-Smirnov test.
+pub fn check_uniform<R: RngCore>(q: u64, num_samples: usize, rng: &mut R) -> Vec<Check> {
+    let samples: Vec<Element> =
+        (0..num_samples).map(|_| Element::gen_uniform_rand_with_rng(q, rng)).collect();
+    vec![chi_squared_uniform(&samples, q.min(100) as usize), ks_uniform(&samples)]
+}
+
+/// Check num_samples samples of Element::gen_discrete_gaussian_with_rng() mod q: their mean,
+/// their variance, and that none exceeds the bound.
+pub fn check_gaussian<R: RngCore>(
+    q: u64,
+    std_dev: f64,
+    bound: u64,
+    num_samples: usize,
+    rng: &mut R,
+) -> Vec<Check> {
+    let samples: Vec<Element> = (0..num_samples)
+        .map(|_| Element::gen_discrete_gaussian_with_rng(q, std_dev, bound, rng))
+        .collect();
+    gaussian_checks(&samples, std_dev, bound)
+}
+
+fn gaussian_checks(samples: &[Element], std_dev: f64, bound: u64) -> Vec<Check> {
+    let (variance, fourth_moment) = discrete_gaussian_moments(std_dev, bound);
+    let mut checks = moment_checks(samples, variance, fourth_moment);
+    let largest = samples.iter().map(|e| centered(e).unsigned_abs()).max().unwrap_or(0);
+    checks.push(Check { name: "tail bound", statistic: largest as f64, critical: bound as f64 });
+    checks
+}
+
+/// Check num_samples errors from regev::gen_error_vec_with_rng() as check_gaussian() does, against
+/// std_dev and error_bound(std_dev), which the noise analysis of the PIR schemes assumes.
+pub fn check_error_vec<R: CryptoRng + RngCore>(
+    q: u64,
+    std_dev: f64,
+    num_samples: usize,
+    rng: &mut R,
+) -> Vec<Check> {
+    let samples = gen_error_vec_with_rng(q, num_samples, std_dev, rng);
+    gaussian_checks(&samples.to_elements(), std_dev, error_bound(std_dev))
+}
+
+/// Check num_samples secret coefficients mod q sampled from dist: uniform secrets as
+/// check_uniform() does, binary and ternary secrets with the chi-squared test over their support,
+/// and Gaussian secrets as check_gaussian() does.
+pub fn check_secret<R: CryptoRng + RngCore>(
+    dist: SecretDistribution,
+    q: u64,
+    num_samples: usize,
+    rng: &mut R,
+) -> Vec<Check> {
+    let samples = dist.sample(q, num_samples, rng).to_elements();
+    let n = num_samples as f64;
+    let support_check = |support: &[i64]| -> Vec<Check> {
+        let mut observed = vec![0u64; support.len()];
+        let mut outside = 0u64;
+        for x in samples.iter().map(centered) {
+            match support.iter().position(|&v| v == x) {
+                Some(i) => observed[i] += 1,
+                None => outside += 1,
+            }
+        }
+        let expected = vec![n / support.len() as f64; support.len()];
+        let support = Check { name: "support", statistic: outside as f64, critical: 0.0 };
+        vec![chi_squared(&observed, &expected), support]
+    };
+    match dist {
+        SecretDistribution::Uniform => {
+            vec![chi_squared_uniform(&samples, q.min(100) as usize), ks_uniform(&samples)]
+        }
+        SecretDistribution::Binary => support_check(&[0, 1]),
+        SecretDistribution::Ternary => support_check(&[-1, 0, 1]),
+        SecretDistribution::Gaussian(std_dev) => {
+            gaussian_checks(&samples, std_dev, (TAIL_CUT * std_dev).ceil() as u64)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_samplers() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        for q in [2, 3, 3329, 4294967291, 1 << 32, u64::MAX - 58] {
+            let checks = check_uniform(q, 20000, &mut rng);
+            assert!(all_passed(&checks), "uniform mod {}: {:?}", q, checks);
+        }
+        for std_dev in [1.0, 6.4] {
+            let checks = check_gaussian(3329, std_dev, (6.0 * std_dev) as u64, 20000, &mut rng);
+            assert!(all_passed(&checks), "Gaussian of {}: {:?}", std_dev, checks);
+        }
+        assert!(all_passed(&check_error_vec(1 << 32, 6.4, 20000, &mut rng)));
+
+        let dists = [
+            SecretDistribution::Uniform,
+            SecretDistribution::Binary,
+            SecretDistribution::Ternary,
+            SecretDistribution::Gaussian(3.2),
+        ];
+        for dist in dists {
+            let checks = check_secret(dist, 3329, 20000, &mut rng);
+            assert!(all_passed(&checks), "{:?}: {:?}", dist, checks);
+        }
+    }
+
+    #[test]
+    fn test_checks_detect_bias() {
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let q = 3329;
+
+        // Reducing a 12-bit value mod q makes the values below 2^12 - q twice as likely
+        let biased: Vec<Element> =
+            (0..20000).map(|_| Element::from(q, (rng.next_u32() & 0xfff) as u64 % q)).collect();
+        assert!(!chi_squared_uniform(&biased, 100).passed());
+        assert!(!ks_uniform(&biased).passed());
+
+        // A Gaussian of the wrong width, and one whose tail was not cut
+        let wide: Vec<Element> = (0..20000)
+            .map(|_| Element::gen_discrete_gaussian_with_rng(q, 1.2, 6, &mut rng))
+            .collect();
+        assert!(!all_passed(&moment_checks(&wide, discrete_gaussian_moments(1.0, 6).0, 3.0)));
+        assert!(!all_passed(&gaussian_checks(&wide, 1.2, 3)));
+    }
+}
@@ -1,12 +1,19 @@
 use crate::element::Element;
+use crate::error::{ShapeError, SimplePirError};
 use crate::matrix::Matrix;
 use crate::regev::{
     Params,
+    QuerySecret,
+    SecretKey,
+    check_ciphertext_mod,
+    check_matrix_mod,
+    check_secret_length,
     gen_error_vec,
-    encrypt,
 };
+use crate::scaling::{scale_floor, unscale_round};
+use subtle::{ConditionallySelectable, ConstantTimeEq};
 
-/// Generates a database of db_size item where each item is a bit.
+/// Generates a database of db_size item where each item is an element mod p.
 pub fn gen_db(db_size: usize, params: &Params) -> Vec<Element> {
     gen_db_q(db_size, params.p)
 }
@@ -21,30 +28,47 @@ pub fn gen_db_q(db_size: usize, q: u64) -> Vec<Element> {
     db
 }
 
+/// Encrypt a query for item idx with the secret s, which is moved into the returned QuerySecret.
+/// A·s, the part of every ciphertext which only depends on the secret, is computed once for the
+/// query, so that encrypting each entry only adds an error and the scaled bit.
 pub fn query(
     params: &Params,
     idx: usize,
-    s: &Vec<Element>,
+    s: SecretKey,
     db_size: usize,
-) -> Vec<Element> {
-    assert!(idx < db_size);
+) -> Result<(Vec<Element>, QuerySecret), SimplePirError> {
+    if idx >= db_size {
+        return Err(SimplePirError::IndexOutOfRange { idx, len: db_size });
+    }
+    check_secret_length(params, &s)?;
+    let q = params.q;
+    let a_s = params.a.mul_vector(&s).get(0);
     let mut query = Vec::with_capacity(db_size);
     for i in 0..db_size {
-        let bit = if i == idx {
-            1
-        } else {
-            0
-        };
-        let e = gen_error_vec(params.q, params.m);
-        let enc = encrypt(
-            params,
-            s,
-            &e,
-            &Element::from(params.p, bit)
-        );
-        query.push(enc);
+        // Select the bit in constant time so that the timing does not reveal idx
+        let bit = u64::conditional_select(&0, &1, i.ct_eq(&idx));
+        let e = gen_error_vec(q, 1, params.std_dev).get(0);
+        query.push(a_s.clone() + e + scale_floor(&Element::from(params.p, bit), q));
     }
-    query
+    Ok((query, QuerySecret::new(s)))
+}
+
+/// Decrypt an answer returned by answer(), answer_q() or answer_q_chunked(), consuming the secret
+/// of its query.
+pub fn recover(
+    params: &Params,
+    s: QuerySecret,
+    answer: &(Matrix, Element),
+) -> Result<Element, SimplePirError> {
+    let (a, c) = answer;
+    if a.dimensions() != params.a.dimensions() {
+        let rhs = (params.n, 1);
+        return Err(ShapeError { op: "multiply", lhs: a.dimensions(), rhs }.into());
+    }
+    check_matrix_mod(a, params.q)?;
+    check_ciphertext_mod(params, c)?;
+    let raw = c.clone() - a.mul_vector(s.secret()).get(0);
+    Ok(unscale_round(&raw, params.p))
 }
 
 /// The server returns the encrypted result of the query. The result is a single
@@ -62,10 +86,12 @@ pub fn query(
 /// result = enc(0) + enc(1)
 /// 
 /// This is much simpler than the scheme described in the SimplePIR paper where the
-/// database is multiplied by the query vector.
+/// database is multiplied by the query vector. It only supports databases of bits (p = 2); use
+/// answer_q() for larger plaintext moduli.
 pub fn answer(params: &Params, query: &[Element], db: &[Element]) ->
     (Matrix, Element)
 {
+    assert_eq!(params.p, 2);
     let zero = Element::zero(params.q);
     let mut summed_a = Matrix::from_val(params.m, params.n, zero);
     let mut summed_c = Element::zero(params.q);
@@ -102,54 +128,110 @@ pub fn answer_q(params: &Params, query: &[Element], db: &[Element]) ->
     (summed_a, summed_c)
 }
 
+/// Same as answer_q(), but over a database supplied as a stream of consecutive chunks, so that
+/// only one chunk needs to be in memory at a time. The partial sums are accumulated per chunk.
+pub fn answer_q_chunked<I>(params: &Params, query: &[Element], chunks: I) ->
+    (Matrix, Element)
+where
+    I: IntoIterator<Item = Vec<Element>>,
+{
+    let zero = Element::zero(params.q);
+    let mut summed_a = Matrix::from_val(params.m, params.n, zero);
+    let mut summed_c = Element::zero(params.q);
+    let mut offset = 0;
+    for chunk in chunks {
+        assert!(offset + chunk.len() <= query.len());
+        let (a, c) = answer_q(params, &query[offset..offset + chunk.len()], &chunk);
+        summed_a += a;
+        summed_c += c;
+        offset += chunk.len();
+    }
+
+    (summed_a, summed_c)
+}
+
 #[cfg(test)]
 pub mod tests {
-    use crate::regev::{
-        gen_secret,
-        simple_params,
-        decrypt,
-    };
+    use crate::regev::gen_secret;
     use super::{
+        Matrix,
         gen_db,
         query,
+        recover,
         answer,
         answer_q,
-        Element,
-        Params
+        answer_q_chunked,
+        Params,
     };
+    use crate::error::SimplePirError;
 
-    fn test_pir_impl(
-        params: &Params,
-        s: &Vec<Element>,
-    ) {
+    fn test_pir_impl(params: &Params) {
         let db_size = 50;
         let db = gen_db(db_size, params);
 
         let desired_idx = 24;
-        let query = query(&params, desired_idx, &s, db_size);
+        let new_query = || query(params, desired_idx, gen_secret(params.q, params.n), db_size);
 
         // Test answer_q()
-        let ans = answer_q(&params, &query, &db);
+        let (query, s) = new_query().unwrap();
+        let ans = answer_q(params, &query, &db);
+        assert_eq!(recover(params, s, &ans).unwrap(), db[desired_idx]);
 
-        let mut p = params.clone();
-        p.a = ans.0;
-        let result = decrypt(&p, &s, &ans.1);
-        assert_eq!(result, db[desired_idx]);
+        // Test answer_q_chunked()
+        let (query, s) = new_query().unwrap();
+        let ans = answer_q_chunked(params, &query, db.chunks(7).map(|c| c.to_vec()));
+        assert_eq!(recover(params, s, &ans).unwrap(), db[desired_idx]);
 
         // Test answer()
-        let ans = answer(&params, &query, &db);
-        let mut p = params.clone();
-        p.a = ans.0;
-        let result = decrypt(&p, &s, &ans.1);
-        assert_eq!(result, db[desired_idx]);
+        let (query, s) = new_query().unwrap();
+        let ans = answer(params, &query, &db);
+        assert_eq!(recover(params, s, &ans).unwrap(), db[desired_idx]);
+    }
+
+    #[test]
+    fn test_pir_multi_bit() {
+        let mut params = Params::toy();
+        params.q = 4294967291;
+        params.p = 991;
+        params.a = Matrix::gen_uniform_rand(params.q, params.m, params.n);
+
+        let db_size = 50;
+        let db = gen_db(db_size, &params);
+        for desired_idx in [0, 24, 49] {
+            let s = gen_secret(params.q, params.n);
+            let (query, s) = query(&params, desired_idx, s, db_size).unwrap();
+            let ans = answer_q(&params, &query, &db);
+            assert_eq!(recover(&params, s, &ans).unwrap(), db[desired_idx]);
+        }
     }
 
     #[test]
     fn test_pir() {
-        let params = simple_params();
-        let s = gen_secret(params.q, params.n);
+        let params = Params::toy();
         for _ in 0..50 {
-            test_pir_impl(&params, &s);
+            test_pir_impl(&params);
         }
     }
+
+    #[test]
+    fn test_query_errors() {
+        let params = Params::toy();
+        let s = gen_secret(params.q, params.n);
+        assert!(matches!(
+            query(&params, 50, s, 50),
+            Err(SimplePirError::IndexOutOfRange { idx: 50, len: 50 })
+        ));
+        let s = gen_secret(params.q, params.n + 1);
+        assert!(query(&params, 0, s, 50).is_err());
+        let mut empty = params.clone();
+        empty.n = 0;
+        let s = gen_secret(params.q, 0);
+        assert_eq!(query(&empty, 0, s, 50).err(), Some(SimplePirError::Empty("LWE secret")));
+
+        let db = gen_db(50, &params);
+        let (query, s) = query(&params, 0, gen_secret(params.q, params.n), 50).unwrap();
+        let (a, c) = answer(&params, &query, &db);
+        let a = a.select_cols(0..0);
+        assert!(matches!(recover(&params, s, &(a, c)), Err(SimplePirError::Shape(_))));
+    }
 }
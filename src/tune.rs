@@ -0,0 +1,141 @@
+//! Probing the machine at startup to size the chunks which a database backend is scanned in,
+//! rather than relying on a constant chosen for one machine.
+//!
+//! probe() measures the size of the largest cache, as the largest buffer whose random reads take
+//! at most half as long as those of a buffer in memory, and the bandwidth of streaming a buffer
+//! much larger than any cache. A chunk of a database scan is then sized to fill half of that
+//! cache, so that the query and the rest of the working set keep the other half, but to take at
+//! least MIN_CHUNK_TIME to stream at the measured bandwidth, so that the fixed cost of reading a
+//! chunk from a backend stays small even on machines with small caches.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+// The buffer sizes probed for the cache, from 16 KiB to 64 MiB
+const MIN_PROBE_BYTES: usize = 1 << 14;
+const MAX_PROBE_BYTES: usize = 1 << 26;
+
+// The random reads timed for each buffer size, and the size of the lines they are spread over
+const PROBE_READS: usize = 1 << 18;
+const CACHE_LINE_BYTES: usize = 64;
+
+// The size of the buffer streamed to measure bandwidth, and the times it is streamed
+const BANDWIDTH_BYTES: usize = 1 << 26;
+const BANDWIDTH_ROUNDS: usize = 3;
+
+/// The least time a chunk should take to stream at the measured bandwidth.
+pub const MIN_CHUNK_TIME: Duration = Duration::from_micros(20);
+
+/// The bounds of the chunk sizes chosen, in database columns.
+pub const MIN_CHUNK_COLS: usize = 16;
+pub const MAX_CHUNK_COLS: usize = 1 << 16;
+
+/// What probe() measured of the machine.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MachineProfile {
+    /// The size in bytes of the largest cache.
+    pub cache_bytes: usize,
+    /// The bandwidth of streaming reads from memory, in bytes per second.
+    pub bandwidth: f64,
+}
+
+impl MachineProfile {
+    /// The number of columns to scan a database of num_rows rows in at a time: enough to fill
+    /// half of the cache, but at least enough to take MIN_CHUNK_TIME to stream.
+    pub fn chunk_cols(&self, num_rows: usize) -> usize {
+        // Backends return chunks as matrices of one word per item
+        let col_bytes = 8.0 * num_rows.max(1) as f64;
+        let cached = self.cache_bytes as f64 / 2.0 / col_bytes;
+        let streamed = self.bandwidth * MIN_CHUNK_TIME.as_secs_f64() / col_bytes;
+        (cached.max(streamed) as usize).clamp(MIN_CHUNK_COLS, MAX_CHUNK_COLS)
+    }
+}
+
+/// Measure the cache size and memory bandwidth of the machine. This takes a fraction of a second
+/// and allocates 64 MiB for its duration.
+pub fn probe() -> MachineProfile {
+    MachineProfile { cache_bytes: probe_cache(), bandwidth: probe_bandwidth() }
+}
+
+// The largest probed buffer size whose random reads take at most half as long as those of the
+// largest buffer, which is taken to be in memory
+fn probe_cache() -> usize {
+    let sizes: Vec<usize> =
+        (0..).map(|i| MIN_PROBE_BYTES << i).take_while(|&size| size <= MAX_PROBE_BYTES).collect();
+    let times: Vec<Duration> = sizes.iter().map(|&size| time_random_reads(size)).collect();
+    let memory = *times.last().unwrap();
+    sizes.iter().zip(&times)
+        .filter(|(_, &time)| time * 2 <= memory)
+        .map(|(&size, _)| size)
+        .max()
+        .unwrap_or(MIN_PROBE_BYTES)
+}
+
+// The time of PROBE_READS dependent random reads from a buffer of the given size, each read
+// giving the index of the next so that they cannot overlap
+fn time_random_reads(bytes: usize) -> Duration {
+    // One read per cache line, so that each read misses if the buffer does not fit
+    let lines = bytes / CACHE_LINE_BYTES;
+    let stride = CACHE_LINE_BYTES / 8;
+    // Sattolo's algorithm gives a permutation which is a single cycle through all of the lines
+    let mut order: Vec<usize> = (0..lines).collect();
+    let mut rng = ChaCha20Rng::seed_from_u64(lines as u64);
+    for i in (1..lines).rev() {
+        order.swap(i, rng.gen_range(0..i));
+    }
+    let mut next = vec![0; bytes / 8];
+    for (i, &line) in order.iter().enumerate() {
+        next[stride * i] = stride * line;
+    }
+    let start = Instant::now();
+    let mut idx = 0;
+    for _ in 0..PROBE_READS {
+        idx = next[idx];
+    }
+    black_box(idx);
+    start.elapsed()
+}
+
+// The bytes per second of summing a buffer much larger than any cache, the best of a few rounds
+fn probe_bandwidth() -> f64 {
+    let buf: Vec<u64> = (0..BANDWIDTH_BYTES as u64 / 8).collect();
+    let best = (0..BANDWIDTH_ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(buf.iter().fold(0u64, |acc, &v| acc.wrapping_add(v)));
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    BANDWIDTH_BYTES as f64 / best.as_secs_f64().max(1e-9)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_cols() {
+        let profile = MachineProfile { cache_bytes: 32 << 20, bandwidth: 1e10 };
+        // Half of 32 MiB holds 1024 columns of 2048 items
+        assert_eq!(profile.chunk_cols(2048), 1024);
+        assert_eq!(profile.chunk_cols(1), MAX_CHUNK_COLS);
+        assert_eq!(profile.chunk_cols(1 << 24), MIN_CHUNK_COLS);
+
+        // With a small cache, chunks still take MIN_CHUNK_TIME to stream
+        let profile = MachineProfile { cache_bytes: 1 << 16, bandwidth: 1e10 };
+        assert_eq!(profile.chunk_cols(1000), 25);
+    }
+
+    #[test]
+    fn test_probe() {
+        let profile = probe();
+        assert!((MIN_PROBE_BYTES..=MAX_PROBE_BYTES).contains(&profile.cache_bytes));
+        assert!(profile.bandwidth > 1e6);
+        let cols = profile.chunk_cols(1024);
+        assert!((MIN_CHUNK_COLS..=MAX_CHUNK_COLS).contains(&cols));
+    }
+}
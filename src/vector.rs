@@ -0,0 +1,149 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{AddAssign, SubAssign};
+use zeroize::Zeroize;
+use crate::element::{ops, Element};
+use crate::error::SimplePirError;
+
+/// A vector mod q, such as an LWE secret or error. The modulus is stored once for the whole
+/// vector, and every value is below it, so that arithmetic on vectors needs no per-element checks.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "VectorRepr"))]
+pub struct Vector {
+    q: u64,
+    values: Vec<u64>,
+}
+
+// The serialized form of a Vector, which is checked to be in range when deserializing
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct VectorRepr {
+    q: u64,
+    values: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<VectorRepr> for Vector {
+    type Error = SimplePirError;
+
+    fn try_from(repr: VectorRepr) -> Result<Self, Self::Error> {
+        Vector::checked_from(repr.q, repr.values)
+    }
+}
+
+impl Vector {
+    /// A vector of values mod q. Panics if a value is not below q.
+    pub fn from(q: u64, values: Vec<u64>) -> Self {
+        Self::checked_from(q, values).expect("the value is not below the modulus")
+    }
+
+    /// Like from(), but returning an error for values which are not below q.
+    pub fn checked_from(q: u64, values: Vec<u64>) -> Result<Self, SimplePirError> {
+        if q < 2 {
+            return Err(SimplePirError::InvalidModulus(q));
+        }
+        if let Some(&uint) = values.iter().find(|&&v| v >= q) {
+            return Err(SimplePirError::OutOfRange { q, uint });
+        }
+        Ok(Self { q, values })
+    }
+
+    pub fn zero(q: u64, len: usize) -> Self {
+        Self::from(q, vec![0; len])
+    }
+
+    /// Convert elements which must all be mod q.
+    pub fn from_elements(q: u64, elements: &[Element]) -> Result<Self, SimplePirError> {
+        let values = elements.iter().map(|e| match e.q == q {
+            true => Ok(e.uint),
+            false => Err(SimplePirError::ModulusMismatch { expected: q, actual: e.q }),
+        }).collect::<Result<_, _>>()?;
+        Ok(Self { q, values })
+    }
+
+    pub fn to_elements(&self) -> Vec<Element> {
+        self.values.iter().map(|&v| Element::from(self.q, v)).collect()
+    }
+
+    pub fn q(&self) -> u64 {
+        self.q
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The values, each below q.
+    pub fn values(&self) -> &[u64] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<u64> {
+        self.values
+    }
+
+    pub fn get(&self, i: usize) -> Element {
+        Element::from(self.q, self.values[i])
+    }
+
+    /// The inner product with another vector of the same length and modulus.
+    pub fn dot(&self, rhs: &Vector) -> Element {
+        assert_eq!(self.q, rhs.q);
+        Element::from(self.q, ops::dot(&self.values, &rhs.values, self.q))
+    }
+
+    /// Multiply every value by c, which need not be below q.
+    pub fn scale(&mut self, c: u64) {
+        ops::scale_assign(&mut self.values, c, self.q);
+    }
+}
+
+impl AddAssign<&Vector> for Vector {
+    fn add_assign(&mut self, rhs: &Vector) {
+        assert_eq!(self.q, rhs.q);
+        ops::add_assign_slices(&mut self.values, &rhs.values, self.q);
+    }
+}
+
+impl SubAssign<&Vector> for Vector {
+    fn sub_assign(&mut self, rhs: &Vector) {
+        assert_eq!(self.q, rhs.q);
+        ops::sub_assign_slices(&mut self.values, &rhs.values, self.q);
+    }
+}
+
+impl Zeroize for Vector {
+    fn zeroize(&mut self) {
+        self.values.zeroize();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::wire;
+
+    #[test]
+    fn test_vector() {
+        let q = 3329;
+        let a = Vector::from(q, vec![1, 2, 3328]);
+        let mut b = Vector::from(q, vec![5, 0, 2]);
+        assert_eq!(a.dot(&b), Element::from(q, (5 + 3328 * 2) % q));
+        b += &a;
+        assert_eq!(b.values(), [6, 2, 1]);
+        b -= &a;
+        b.scale(q + 2);
+        assert_eq!(b.values(), [10, 0, 4]);
+
+        assert_eq!(Vector::from_elements(q, &a.to_elements()), Ok(a.clone()));
+        assert!(Vector::from_elements(q, &[Element::from(7, 1)]).is_err());
+        let err = SimplePirError::OutOfRange { q, uint: q };
+        assert_eq!(Vector::checked_from(q, vec![q]), Err(err));
+        assert_eq!(wire::decode_vector(&wire::encode_vector(&a)), Ok(a));
+    }
+}
@@ -0,0 +1,88 @@
+//! WebAssembly bindings for the client side of the protocol, so that a browser can query a server
+//! such as simplepir-server. Messages are passed as Uint8Arrays in the encodings of the wire
+//! module, and randomness comes from the browser's crypto.getRandomValues(). Indices are passed as
+//! BigInts, which wasm-bindgen does not truncate the way it truncates numbers to a 32-bit usize,
+//! and indices beyond the reach of wasm32 are rejected.
+
+use wasm_bindgen::prelude::*;
+
+use crate::pir::Client;
+use crate::wire;
+
+/// A pir::Client exported to JavaScript as `Client`.
+#[wasm_bindgen(js_name = Client)]
+pub struct WasmClient {
+    client: Client,
+}
+
+#[wasm_bindgen(js_class = Client)]
+impl WasmClient {
+    /// Create a client from the encoded params and hint downloaded from the server.
+    #[wasm_bindgen(constructor)]
+    pub fn new(params: &[u8], hint: &[u8]) -> Result<WasmClient, JsError> {
+        let params = wire::decode_params(params)?;
+        let hint = wire::decode_hint(hint)?;
+        Ok(Self { client: Client::new(params, hint)? })
+    }
+
+    /// Create a client for a server which was set up with byte records.
+    #[wasm_bindgen(js_name = withLayout)]
+    pub fn with_layout(params: &[u8], hint: &[u8], layout: &[u8]) -> Result<WasmClient, JsError> {
+        let params = wire::decode_params(params)?;
+        let hint = wire::decode_hint(hint)?;
+        let layout = wire::decode_layout(layout)?;
+        Ok(Self { client: Client::with_layout(params, hint, layout)? })
+    }
+
+    /// Generate an encoded query for item idx, preceded by the digest of the params.
+    pub fn query(&mut self, idx: u64) -> Result<Vec<u8>, JsError> {
+        let query = self.client.query(wire::to_usize(idx)?)?;
+        Ok(wire::encode_query_with_digest(self.client.params_digest(), &query))
+    }
+
+    /// Recover the item requested by the outstanding query from the encoded answer.
+    pub fn recover(&mut self, answer: &[u8]) -> Result<u64, JsError> {
+        let answer = wire::decode_answer(answer)?;
+        Ok(self.client.recover(&answer)?.uint)
+    }
+
+    /// Generate an encoded query for byte record idx, preceded by the digest of the params.
+    #[wasm_bindgen(js_name = queryRecord)]
+    pub fn query_record(&mut self, idx: u64) -> Result<Vec<u8>, JsError> {
+        let query = self.client.query_record(wire::to_usize(idx)?)?;
+        Ok(wire::encode_query_with_digest(self.client.params_digest(), &query))
+    }
+
+    /// Recover the byte record requested by the outstanding query from the encoded answer.
+    #[wasm_bindgen(js_name = recoverRecord)]
+    pub fn recover_record(&mut self, answer: &[u8]) -> Result<Vec<u8>, JsError> {
+        let answer = wire::decode_answer(answer)?;
+        Ok(self.client.recover_record(&answer)?)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::element::Element;
+    use crate::pir;
+    use crate::simplepir;
+
+    #[test]
+    fn test_wasm_client() {
+        let db_size = 20;
+        let params = simplepir::gen_insecure_test_params_for_size(db_size, 2);
+        let items: Vec<Element> = (0..db_size).map(|_| Element::gen_uniform_rand(2)).collect();
+        let server = pir::Server::setup(params, &items).unwrap();
+
+        let params = wire::encode_params(server.params());
+        let hint = wire::encode_hint(server.hint());
+        let mut client = WasmClient::new(&params, &hint).unwrap();
+        for (idx, item) in items.iter().enumerate() {
+            let query = client.query(idx as u64).unwrap();
+            let (digest, query) = wire::decode_query_with_digest(&query).unwrap();
+            let answer = wire::encode_answer(&server.answer_checked(&digest, &query).unwrap());
+            assert_eq!(client.recover(&answer).unwrap(), item.uint);
+        }
+    }
+}
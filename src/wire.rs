@@ -0,0 +1,983 @@
+//! A compact binary encoding for the messages exchanged by the PIR client and server. Each
+//! coefficient is packed into ⌈log2(q)⌉ bits, so a query or answer mod 2^32 takes 4 bytes per
+//! coefficient plus a fixed-size header, rather than the 16 bytes of an in-memory Element.
+//!
+//! A vector is encoded as its modulus (u64), its length (u64) and the packed coefficients. A
+//! matrix is encoded as its modulus, number of columns and number of rows (u64 each) followed by
+//! the packed coefficients, column by column. Integers are little-endian and the packed bits are
+//! padded with zeros to a whole byte. A matrix without entries is encoded as three zeros.
+//!
+//! Params are encoded as a version byte, then A, as a tag byte of 0 followed by the matrix or a tag
+//! byte of 1 followed by the 32-byte seed it is expanded from, then q, p, n and m (u64 each), the
+//! standard deviation (f64 bits), and the secret distribution as a kind byte and a standard
+//! deviation. A record layout is encoded as its four fields (u64 each). A public hint is encoded as
+//! its params, hint and layout (empty without one), each preceded by its length (u64), and then the
+//! same way by the 32-byte key its answers are signed with, if they are. A query sent to a server
+//! is preceded by the 32-byte digest of the client's params, which the server checks against its
+//! own. Matrices other than answers and hints, such as a server's database, use the matrix encoding
+//! directly. A partial answer is encoded as the 32-byte digest of its query, the columns and rows
+//! of the database (u64 each) and the answer entries computed so far as a matrix with one row. A
+//! tagged answer is encoded as its epoch (u64) and its 16-byte digest, followed by the answer, and
+//! a signed answer as its 64-byte signature and the time its database was built (u64) followed by
+//! the tagged answer. MatrixReader and public_hint_reader() produce the encodings of matrices and
+//! public hints a chunk at a time, for sending hints without encoding them whole.
+//!
+//! Lengths and dimensions are u64 whatever the target. A decoder on a 32-bit target such as
+//! wasm32 checks that each fits in a usize, and rejects those that do not with TooLarge rather
+//! than truncating them, e.g. the dimensions of a multi-GB database.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use crate::element::Element;
+use crate::error::SimplePirError;
+use crate::matrix::Matrix;
+use crate::merkle::Digest;
+use crate::pir::{Answer, PublicHint, Query, TaggedAnswer};
+use crate::record::RecordLayout;
+use crate::regev::SecretDistribution;
+#[cfg(feature = "signing")]
+use crate::signing::{SignedAnswer, SignedManifest};
+#[cfg(feature = "std")]
+use crate::simplepir::PartialAnswer;
+use crate::simplepir::{Hint, SimplePIRParams};
+use crate::vector::Vector;
+#[cfg(feature = "std")]
+use std::io::{Cursor, Read};
+
+/// Why a message could not be decoded.
+#[derive(Debug, PartialEq, Clone)]
+pub enum WireError {
+    // The message ended before all of its fields were read
+    Truncated,
+    // The message continued after all of its fields were read
+    TrailingBytes,
+    // The modulus is not a valid element modulus
+    InvalidModulus(u64),
+    // A coefficient is not below the modulus
+    OutOfRange(u64),
+    // The message describes an empty vector or matrix with a modulus, or a key of the wrong size
+    InvalidShape,
+    // The message was encoded with an unknown version of the format
+    UnsupportedVersion(u8),
+    // The message is compressed with a codec which this build does not support
+    UnsupportedCodec(u8),
+    // A compressed payload does not decompress to the chunk its frame describes
+    Corrupt,
+    // The message decompresses to more than the given number of bytes
+    TooLong(usize),
+    // A length or index does not fit in a usize on this target
+    TooLarge(u64),
+}
+
+impl From<SimplePirError> for WireError {
+    fn from(err: SimplePirError) -> Self {
+        match err {
+            SimplePirError::InvalidModulus(q) => WireError::InvalidModulus(q),
+            SimplePirError::OutOfRange { uint, .. } => WireError::OutOfRange(uint),
+            _ => WireError::InvalidShape,
+        }
+    }
+}
+
+impl Display for WireError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WireError::Truncated => write!(f, "the message is truncated"),
+            WireError::TrailingBytes => write!(f, "the message has trailing bytes"),
+            WireError::InvalidModulus(q) => write!(f, "{} is not a valid modulus", q),
+            WireError::OutOfRange(v) => write!(f, "the coefficient {} is out of range", v),
+            WireError::InvalidShape => write!(f, "the message has an invalid shape"),
+            WireError::UnsupportedVersion(v) => write!(f, "version {} is not supported", v),
+            WireError::UnsupportedCodec(c) => write!(f, "codec {} is not supported", c),
+            WireError::Corrupt => write!(f, "the compressed payload is corrupt"),
+            WireError::TooLong(max) => write!(f, "the message is longer than {} bytes", max),
+            WireError::TooLarge(n) => write!(f, "{} does not fit in a usize on this target", n),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WireError {}
+
+/// Convert a length or index to a usize, failing with TooLarge on targets whose usize is too
+/// narrow for it, e.g. the size of a multi-GB database on wasm32.
+pub fn to_usize(n: u64) -> Result<usize, WireError> {
+    usize::try_from(n).map_err(|_| WireError::TooLarge(n))
+}
+
+/// The number of bits used to encode each coefficient mod q, i.e. ⌈log2(q)⌉.
+pub fn bits_per_coeff(q: u64) -> usize {
+    assert!(q >= 2);
+    (u64::BITS - (q - 1).leading_zeros()) as usize
+}
+
+/// The length of encode_query(query), computed without encoding it.
+pub fn encoded_query_len(query: &Query) -> usize {
+    16 + packed_len(query.0.len(), query.0.first().map_or(2, |e| e.q))
+}
+
+/// The length of encode_query_with_digest() for a query of len coefficients mod q.
+pub fn encoded_query_with_digest_len(len: usize, q: u64) -> usize {
+    32 + 16 + packed_len(len, q)
+}
+
+/// The length of encode_answer(answer), computed without encoding it.
+pub fn encoded_answer_len(answer: &Answer) -> usize {
+    encoded_matrix_len(&answer.0)
+}
+
+/// The length of encode_hint(hint), computed without encoding it.
+pub fn encoded_hint_len(hint: &Hint) -> usize {
+    encoded_matrix_len(&hint.0)
+}
+
+/// The length of encode_matrix(m), computed without encoding it.
+pub fn encoded_matrix_len(m: &Matrix) -> usize {
+    let (cols, rows) = m.dimensions();
+    // The encoding of a matrix held in memory is no longer than the matrix itself
+    encoded_matrix_size(cols as u64, rows as u64, m.q()) as usize
+}
+
+/// The length of the encoding of a matrix of the given dimensions mod q, as a u64 so that it is
+/// exact on any target for matrices which a 32-bit target cannot hold, such as the hint of a
+/// multi-GB database.
+pub fn encoded_matrix_size(cols: u64, rows: u64, q: u64) -> u64 {
+    match cols * rows {
+        0 => 24,
+        len => 24 + packed_size(len, q),
+    }
+}
+
+// The bytes taken by len coefficients mod q once packed
+fn packed_len(len: usize, q: u64) -> usize {
+    packed_size(len as u64, q) as usize
+}
+
+// The bytes taken by len coefficients mod q once packed, computed in u64 since len times the bits
+// of each coefficient can overflow a 32-bit usize even for vectors which fit in memory
+fn packed_size(len: u64, q: u64) -> u64 {
+    (len * bits_per_coeff(q) as u64).div_ceil(8)
+}
+
+pub fn encode_query(query: &Query) -> Vec<u8> {
+    let mut out = vec![];
+    write_vec(&mut out, &query.0);
+    out
+}
+
+pub fn decode_query(bytes: &[u8]) -> Result<Query, WireError> {
+    let mut reader = Reader::new(bytes);
+    let query = reader.read_vec()?;
+    reader.finish()?;
+    Ok(Query(query))
+}
+
+/// Encode a query preceded by the digest of the params it was generated with.
+pub fn encode_query_with_digest(params_digest: &Digest, query: &Query) -> Vec<u8> {
+    let mut out = params_digest.to_vec();
+    write_vec(&mut out, &query.0);
+    out
+}
+
+/// Decode a query encoded with encode_query_with_digest(), returning the digest and the query.
+pub fn decode_query_with_digest(bytes: &[u8]) -> Result<(Digest, Query), WireError> {
+    let mut reader = Reader::new(bytes);
+    let digest = Digest::try_from(reader.take(32)?).unwrap();
+    let query = reader.read_vec()?;
+    reader.finish()?;
+    Ok((digest, Query(query)))
+}
+
+pub fn encode_vector(v: &Vector) -> Vec<u8> {
+    let mut out = vec![];
+    write_vec(&mut out, &v.to_elements());
+    out
+}
+
+pub fn decode_vector(bytes: &[u8]) -> Result<Vector, WireError> {
+    let mut reader = Reader::new(bytes);
+    let v = reader.read_vec()?;
+    reader.finish()?;
+    Ok(Vector::from(v[0].q, v.iter().map(|e| e.uint).collect()))
+}
+
+pub fn encode_answer(answer: &Answer) -> Vec<u8> {
+    let mut out = vec![];
+    write_matrix(&mut out, &answer.0);
+    out
+}
+
+pub fn decode_answer(bytes: &[u8]) -> Result<Answer, WireError> {
+    let mut reader = Reader::new(bytes);
+    let answer = reader.read_matrix()?;
+    reader.finish()?;
+    Ok(Answer(answer))
+}
+
+pub fn encode_tagged_answer(tagged: &TaggedAnswer) -> Vec<u8> {
+    let mut out = tagged.epoch.to_le_bytes().to_vec();
+    out.extend_from_slice(&tagged.digest);
+    write_matrix(&mut out, &tagged.answer.0);
+    out
+}
+
+pub fn decode_tagged_answer(bytes: &[u8]) -> Result<TaggedAnswer, WireError> {
+    let mut reader = Reader::new(bytes);
+    let epoch = reader.read_u64()?;
+    let digest = reader.take(16)?.try_into().unwrap();
+    let answer = Answer(reader.read_matrix()?);
+    reader.finish()?;
+    Ok(TaggedAnswer { epoch, digest, answer })
+}
+
+pub fn encode_hint(hint: &Hint) -> Vec<u8> {
+    let mut out = vec![];
+    write_matrix(&mut out, &hint.0);
+    out
+}
+
+pub fn decode_hint(bytes: &[u8]) -> Result<Hint, WireError> {
+    let mut reader = Reader::new(bytes);
+    let hint = reader.read_matrix()?;
+    reader.finish()?;
+    Ok(Hint(hint))
+}
+
+pub fn encode_matrix(m: &Matrix) -> Vec<u8> {
+    let mut out = vec![];
+    write_matrix(&mut out, m);
+    out
+}
+
+pub fn decode_matrix(bytes: &[u8]) -> Result<Matrix, WireError> {
+    let mut reader = Reader::new(bytes);
+    let m = reader.read_matrix()?;
+    reader.finish()?;
+    Ok(m)
+}
+
+/// The version of the params encoding, to be bumped whenever it changes.
+pub const PARAMS_VERSION: u8 = 1;
+
+/// The most entries of A which decode_params() expands from a seed, so that a 32-byte seed cannot
+/// make the decoder allocate more than 2 GiB.
+pub const MAX_SEEDED_LEN: usize = 1 << 28;
+
+// The tags of the two ways A can be encoded
+const A_MATRIX: u8 = 0;
+const A_SEED: u8 = 1;
+
+// The kind byte and standard deviation which encode a secret distribution
+pub(crate) fn secret_dist_kind(dist: SecretDistribution) -> (u8, f64) {
+    match dist {
+        SecretDistribution::Uniform => (0, 0.0),
+        SecretDistribution::Binary => (1, 0.0),
+        SecretDistribution::Ternary => (2, 0.0),
+        SecretDistribution::Gaussian(std_dev) => (3, std_dev),
+    }
+}
+
+/// Encode params, sending only the seed of A when they were constructed from one.
+pub fn encode_params(params: &SimplePIRParams) -> Vec<u8> {
+    let mut out = vec![PARAMS_VERSION];
+    match params.seed {
+        Some(seed) => {
+            out.push(A_SEED);
+            out.extend_from_slice(&seed);
+        }
+        None => {
+            out.push(A_MATRIX);
+            write_matrix(&mut out, &params.a);
+        }
+    }
+    for v in [params.q, params.p, params.n as u64, params.m as u64, params.std_dev.to_bits()] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    let (kind, std_dev) = secret_dist_kind(params.secret_dist);
+    out.push(kind);
+    out.extend_from_slice(&std_dev.to_bits().to_le_bytes());
+    out
+}
+
+/// Decode params, expanding A from its seed if it was sent as one. Params encoded with another
+/// version of the format are rejected.
+pub fn decode_params(bytes: &[u8]) -> Result<SimplePIRParams, WireError> {
+    let mut reader = Reader::new(bytes);
+    let version = reader.take(1)?[0];
+    if version != PARAMS_VERSION {
+        return Err(WireError::UnsupportedVersion(version));
+    }
+    let (a, seed) = match reader.take(1)?[0] {
+        A_MATRIX => (reader.read_matrix()?, None),
+        A_SEED => (Matrix::new(), Some(<[u8; 32]>::try_from(reader.take(32)?).unwrap())),
+        _ => return Err(WireError::InvalidShape),
+    };
+    let q = reader.read_modulus()?;
+    let p = reader.read_u64()?;
+    let n = reader.read_usize()?;
+    let m = reader.read_usize()?;
+    let std_dev = f64::from_bits(reader.read_u64()?);
+    let kind = reader.take(1)?[0];
+    let dist_std_dev = f64::from_bits(reader.read_u64()?);
+    reader.finish()?;
+
+    let secret_dist = match kind {
+        0 => SecretDistribution::Uniform,
+        1 => SecretDistribution::Binary,
+        2 => SecretDistribution::Ternary,
+        3 => SecretDistribution::Gaussian(dist_std_dev),
+        _ => return Err(WireError::InvalidShape),
+    };
+    let params = match seed {
+        Some(seed) => {
+            if !matches!(n.checked_mul(m), Some(len) if len > 0 && len <= MAX_SEEDED_LEN) {
+                return Err(WireError::InvalidShape);
+            }
+            if p < 2 || p > q {
+                return Err(WireError::InvalidModulus(p));
+            }
+            SimplePIRParams { secret_dist, ..SimplePIRParams::from_seed(seed, q, p, n, m, std_dev) }
+        }
+        None => SimplePIRParams { a, q, p, n, m, std_dev, secret_dist, seed: None },
+    };
+    params.validate()?;
+    Ok(params)
+}
+
+pub fn encode_layout(layout: &RecordLayout) -> Vec<u8> {
+    [layout.num_records, layout.record_len, layout.limbs, layout.rows].iter()
+        .flat_map(|v| (*v as u64).to_le_bytes())
+        .collect()
+}
+
+pub fn decode_layout(bytes: &[u8]) -> Result<RecordLayout, WireError> {
+    let mut reader = Reader::new(bytes);
+    let num_records = reader.read_usize()?;
+    let record_len = reader.read_usize()?;
+    let limbs = reader.read_usize()?;
+    let rows = reader.read_usize()?;
+    reader.finish()?;
+
+    if num_records == 0 || record_len == 0 || limbs == 0 || rows == 0 {
+        return Err(WireError::InvalidShape);
+    }
+    Ok(RecordLayout { num_records, record_len, limbs, rows })
+}
+
+pub fn encode_public_hint(public: &PublicHint) -> Vec<u8> {
+    let layout = public.layout.as_ref().map_or(vec![], encode_layout);
+    let mut out = vec![];
+    for section in [encode_params(&public.params), encode_hint(&public.hint), layout] {
+        out.extend_from_slice(&(section.len() as u64).to_le_bytes());
+        out.extend_from_slice(&section);
+    }
+    write_verifying_key(&mut out, public.verifying_key.as_ref());
+    out
+}
+
+// Append the section of a verifying key, which is left out without one
+fn write_verifying_key(out: &mut Vec<u8>, key: Option<&[u8; 32]>) {
+    if let Some(key) = key {
+        out.extend_from_slice(&32u64.to_le_bytes());
+        out.extend_from_slice(key);
+    }
+}
+
+pub fn decode_public_hint(bytes: &[u8]) -> Result<PublicHint, WireError> {
+    let mut reader = Reader::new(bytes);
+    let mut read_section = || {
+        let len = reader.read_usize()?;
+        reader.take(len)
+    };
+    let params = decode_params(read_section()?)?;
+    let hint = decode_hint(read_section()?)?;
+    let layout = match read_section()? {
+        [] => None,
+        layout => Some(decode_layout(layout)?),
+    };
+    let verifying_key = match reader.bytes.is_empty() {
+        true => None,
+        false => {
+            let len = reader.read_usize()?;
+            Some(reader.take(len)?.try_into().map_err(|_| WireError::InvalidShape)?)
+        }
+    };
+    reader.finish()?;
+
+    let public = PublicHint { params, hint, layout, verifying_key };
+    public.validate()?;
+    Ok(public)
+}
+
+/// Encode a signed answer as its 64-byte signature and build time (u64) followed by the tagged
+/// answer.
+#[cfg(feature = "signing")]
+pub fn encode_signed_answer(signed: &SignedAnswer) -> Vec<u8> {
+    let mut out = signed.signature.to_vec();
+    out.extend_from_slice(&signed.built_at.to_le_bytes());
+    out.extend_from_slice(&encode_tagged_answer(&signed.tagged));
+    out
+}
+
+#[cfg(feature = "signing")]
+pub fn decode_signed_answer(bytes: &[u8]) -> Result<SignedAnswer, WireError> {
+    let mut reader = Reader::new(bytes);
+    let signature = reader.take(64)?.try_into().unwrap();
+    let built_at = reader.read_u64()?;
+    let tagged = decode_tagged_answer(reader.bytes)?;
+    Ok(SignedAnswer { tagged, built_at, signature })
+}
+
+/// Encode a signed manifest as its epoch and build time (u64 each), the 32-byte digest and the
+/// 64-byte signature.
+#[cfg(feature = "signing")]
+pub fn encode_manifest(manifest: &SignedManifest) -> Vec<u8> {
+    let mut out = manifest.epoch.to_le_bytes().to_vec();
+    out.extend_from_slice(&manifest.built_at.to_le_bytes());
+    out.extend_from_slice(&manifest.digest);
+    out.extend_from_slice(&manifest.signature);
+    out
+}
+
+#[cfg(feature = "signing")]
+pub fn decode_manifest(bytes: &[u8]) -> Result<SignedManifest, WireError> {
+    let mut reader = Reader::new(bytes);
+    let epoch = reader.read_u64()?;
+    let built_at = reader.read_u64()?;
+    let digest = reader.take(32)?.try_into().unwrap();
+    let signature = reader.take(64)?.try_into().unwrap();
+    reader.finish()?;
+    Ok(SignedManifest { epoch, built_at, digest, signature })
+}
+
+/// Encode the state of an answer which ran out of time, for the client to send back with its
+/// retried query.
+#[cfg(feature = "std")]
+pub fn encode_partial_answer(partial: &PartialAnswer) -> Vec<u8> {
+    let mut out = partial.query_digest.to_vec();
+    out.extend_from_slice(&(partial.db_shape.0 as u64).to_le_bytes());
+    out.extend_from_slice(&(partial.db_shape.1 as u64).to_le_bytes());
+    write_matrix(&mut out, &Matrix::from(&partial.rows));
+    out
+}
+
+/// Decode a partial answer encoded with encode_partial_answer(). It may hold at most one answer
+/// entry per database column, and answer_within() checks it against the query and database
+/// before resuming it.
+#[cfg(feature = "std")]
+pub fn decode_partial_answer(bytes: &[u8]) -> Result<PartialAnswer, WireError> {
+    let mut reader = Reader::new(bytes);
+    let query_digest = Digest::try_from(reader.take(32)?).unwrap();
+    let db_shape = (reader.read_usize()?, reader.read_usize()?);
+    let entries = reader.read_matrix()?;
+    reader.finish()?;
+
+    let (cols, rows) = entries.dimensions();
+    if cols > db_shape.0 || (cols > 0 && rows != 1) {
+        return Err(WireError::InvalidShape);
+    }
+    let rows = (0..cols).map(|i| entries.col(i)).collect();
+    Ok(PartialAnswer { query_digest, db_shape, rows })
+}
+
+/// The entries a MatrixReader packs at a time. A multiple of 8, so that every chunk but the last
+/// packs into whole bytes.
+#[cfg(feature = "std")]
+pub const STREAM_CHUNK_ENTRIES: usize = 1 << 16;
+
+/// Reads the bytes of encode_matrix(m) without encoding the whole matrix up front, packing
+/// STREAM_CHUNK_ENTRIES entries at a time as they are read. A server copying it into a socket
+/// with blocking writes holds at most one chunk of a hint or answer, and encodes no faster than
+/// the client receives.
+#[cfg(feature = "std")]
+pub struct MatrixReader<'a> {
+    // The entries not yet packed
+    values: &'a [u64],
+    q: u64,
+    // The packed bytes not yet read start at pos
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+#[cfg(feature = "std")]
+impl<'a> MatrixReader<'a> {
+    pub fn new(m: &'a Matrix) -> Self {
+        let (cols, rows) = m.dimensions();
+        if cols * rows == 0 {
+            return Self { values: &[], q: 0, buf: vec![0; 24], pos: 0 };
+        }
+        let mut buf = m.q().to_le_bytes().to_vec();
+        buf.extend_from_slice(&(cols as u64).to_le_bytes());
+        buf.extend_from_slice(&(rows as u64).to_le_bytes());
+        Self { values: m.values(), q: m.q(), buf, pos: 0 }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Read for MatrixReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.buf.len() {
+            if self.values.is_empty() {
+                return Ok(0);
+            }
+            let (chunk, rest) = self.values.split_at(STREAM_CHUNK_ENTRIES.min(self.values.len()));
+            self.buf.clear();
+            self.pos = 0;
+            pack(&mut self.buf, chunk.iter().copied(), self.q);
+            self.values = rest;
+        }
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Read the bytes of encode_public_hint() for the given parts, streaming the hint through a
+/// MatrixReader. Returns the length of the encoding and the reader.
+#[cfg(feature = "std")]
+pub fn public_hint_reader<'a>(
+    params: &SimplePIRParams,
+    hint: &'a Hint,
+    layout: Option<&RecordLayout>,
+    verifying_key: Option<&[u8; 32]>,
+) -> (usize, impl Read + 'a) {
+    let params = encode_params(params);
+    let hint_len = encoded_hint_len(hint);
+    let layout = layout.map_or(vec![], encode_layout);
+
+    let mut head = (params.len() as u64).to_le_bytes().to_vec();
+    head.extend_from_slice(&params);
+    head.extend_from_slice(&(hint_len as u64).to_le_bytes());
+    let mut tail = (layout.len() as u64).to_le_bytes().to_vec();
+    tail.extend_from_slice(&layout);
+    write_verifying_key(&mut tail, verifying_key);
+
+    let len = head.len() + hint_len + tail.len();
+    let reader = Cursor::new(head)
+        .chain(MatrixReader::new(&hint.0))
+        .chain(Cursor::new(tail));
+    (len, reader)
+}
+
+fn write_vec(out: &mut Vec<u8>, v: &[Element]) {
+    assert!(!v.is_empty(), "cannot encode an empty vector");
+    let q = v[0].q;
+    assert!(v.iter().all(|e| e.q == q), "all coefficients must have the same modulus");
+    out.extend_from_slice(&q.to_le_bytes());
+    out.extend_from_slice(&(v.len() as u64).to_le_bytes());
+    pack(out, v.iter().map(|e| e.uint), q);
+}
+
+fn write_matrix(out: &mut Vec<u8>, m: &Matrix) {
+    let (cols, rows) = m.dimensions();
+    if cols * rows == 0 {
+        out.extend_from_slice(&[0; 24]);
+        return;
+    }
+    let q = m.q();
+    out.extend_from_slice(&q.to_le_bytes());
+    out.extend_from_slice(&(cols as u64).to_le_bytes());
+    out.extend_from_slice(&(rows as u64).to_le_bytes());
+    pack(out, m.values().iter().copied(), q);
+}
+
+// Pack values below q, bits_per_coeff(q) bits each
+fn pack<I: Iterator<Item = u64>>(out: &mut Vec<u8>, values: I, q: u64) {
+    let bits = bits_per_coeff(q);
+    let mut acc = 0u128;
+    let mut acc_bits = 0;
+    for v in values {
+        acc |= (v as u128) << acc_bits;
+        acc_bits += bits;
+        while acc_bits >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            acc_bits -= 8;
+        }
+    }
+    if acc_bits > 0 {
+        out.push(acc as u8);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if self.bytes.len() < len {
+            return Err(WireError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, WireError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_usize(&mut self) -> Result<usize, WireError> {
+        to_usize(self.read_u64()?)
+    }
+
+    fn read_modulus(&mut self) -> Result<u64, WireError> {
+        let q = self.read_u64()?;
+        if q < 2 || q == u64::MAX {
+            return Err(WireError::InvalidModulus(q));
+        }
+        Ok(q)
+    }
+
+    fn read_vec(&mut self) -> Result<Vec<Element>, WireError> {
+        let q = self.read_modulus()?;
+        let len = self.read_u64()?;
+        if len == 0 {
+            return Err(WireError::InvalidShape);
+        }
+        self.unpack(len, q)
+    }
+
+    fn read_matrix(&mut self) -> Result<Matrix, WireError> {
+        let q = self.read_u64()?;
+        let cols = self.read_u64()?;
+        let rows = self.read_u64()?;
+
+        if q == 0 && cols == 0 && rows == 0 {
+            return Ok(Matrix::new());
+        }
+        if q < 2 || q == u64::MAX {
+            return Err(WireError::InvalidModulus(q));
+        }
+        if cols == 0 || rows == 0 {
+            return Err(WireError::InvalidShape);
+        }
+
+        let len = cols.checked_mul(rows).ok_or(WireError::Truncated)?;
+        let vals = self.unpack(len, q)?;
+        Ok(Matrix::from_flat(to_usize(cols)?, to_usize(rows)?, vals))
+    }
+
+    fn unpack(&mut self, len: u64, q: u64) -> Result<Vec<Element>, WireError> {
+        let bits = bits_per_coeff(q);
+        // Check the length against the remaining bytes before allocating anything
+        let num_bytes = len.checked_mul(bits as u64).ok_or(WireError::Truncated)?.div_ceil(8);
+        if num_bytes > self.bytes.len() as u64 {
+            return Err(WireError::Truncated);
+        }
+        let bytes = self.take(num_bytes as usize)?;
+
+        let mask = (1u128 << bits) - 1;
+        let mut vals = Vec::with_capacity(to_usize(len)?);
+        let mut acc = 0u128;
+        let mut acc_bits = 0;
+        let mut bytes = bytes.iter();
+        for _ in 0..len {
+            while acc_bits < bits {
+                acc |= (*bytes.next().unwrap() as u128) << acc_bits;
+                acc_bits += 8;
+            }
+            let v = (acc & mask) as u64;
+            acc >>= bits;
+            acc_bits -= bits;
+            if v >= q {
+                return Err(WireError::OutOfRange(v));
+            }
+            vals.push(Element::from(q, v));
+        }
+        // The padding bits must be zero so that every message has a single encoding
+        if acc != 0 {
+            return Err(WireError::OutOfRange(acc as u64));
+        }
+        Ok(vals)
+    }
+
+    fn finish(&self) -> Result<(), WireError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(WireError::TrailingBytes)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_bits_per_coeff() {
+        assert_eq!(bits_per_coeff(2), 1);
+        assert_eq!(bits_per_coeff(3329), 12);
+        assert_eq!(bits_per_coeff(4294967291), 32);
+        assert_eq!(bits_per_coeff(1 << 32), 32);
+        assert_eq!(bits_per_coeff((1 << 32) + 1), 33);
+        assert_eq!(bits_per_coeff(u64::MAX - 58), 64);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for q in [2, 3329, 1 << 32, (1 << 40) - 87, u64::MAX - 58] {
+            for len in [1, 7, 64] {
+                let query = Query(Matrix::gen_uniform_rand(q, 1, len).col(0));
+                let bytes = encode_query(&query);
+                assert_eq!(bytes.len(), 16 + (len * bits_per_coeff(q)).div_ceil(8));
+                assert_eq!(decode_query(&bytes), Ok(query));
+            }
+
+            let answer = Answer(Matrix::gen_uniform_rand(q, 9, 1));
+            assert_eq!(decode_answer(&encode_answer(&answer)), Ok(answer.clone()));
+            let tagged = TaggedAnswer { epoch: 3, digest: [7; 16], answer };
+            let bytes = encode_tagged_answer(&tagged);
+            assert_eq!(bytes[24..], encode_answer(&tagged.answer));
+            assert_eq!(decode_tagged_answer(&bytes), Ok(tagged));
+            assert_eq!(decode_tagged_answer(&bytes[..23]), Err(WireError::Truncated));
+            let hint = Hint(Matrix::gen_uniform_rand(q, 5, 3));
+            assert_eq!(encode_hint(&hint).len(), encoded_hint_len(&hint));
+            assert_eq!(decode_hint(&encode_hint(&hint)), Ok(hint));
+        }
+
+        let empty = Hint(Matrix::new());
+        assert_eq!(encoded_hint_len(&empty), 24);
+        assert_eq!(decode_hint(&encode_hint(&empty)), Ok(empty));
+
+        let query = Query(Matrix::gen_uniform_rand(3329, 1, 7).col(0));
+        let bytes = encode_query_with_digest(&[9; 32], &query);
+        assert_eq!(bytes[32..], encode_query(&query));
+        assert_eq!(decode_query_with_digest(&bytes), Ok(([9; 32], query)));
+        assert_eq!(decode_query_with_digest(&bytes[..31]), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn test_sizes_beyond_usize() {
+        assert_eq!(to_usize(5), Ok(5));
+        assert_eq!(to_usize(u64::MAX).is_ok(), usize::BITS == 64);
+
+        // The hint of a 2^31-item database, whose 8 GiB encoding a 32-bit target cannot address
+        assert_eq!(encoded_matrix_size(1 << 21, 1024, 1 << 32), 24 + (1 << 33));
+        assert_eq!(encoded_matrix_size(0, 1024, 1 << 32), 24);
+        let hint = Hint(Matrix::gen_uniform_rand(1 << 32, 5, 3));
+        assert_eq!(encoded_hint_len(&hint) as u64, encoded_matrix_size(3, 5, 1 << 32));
+
+        // A header claiming more entries than a usize can count is rejected, not truncated
+        let mut bytes = (1u64 << 32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        bytes.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        assert_eq!(decode_hint(&bytes), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn test_params_layout() {
+        let mut params = crate::simplepir::gen_insecure_test_params();
+        assert_eq!(decode_params(&encode_params(&params)), Ok(params.clone()));
+        params.secret_dist = SecretDistribution::Gaussian(3.2);
+        let bytes = encode_params(&params);
+        assert_eq!(decode_params(&bytes), Ok(params));
+        for len in 0..bytes.len() {
+            assert!(decode_params(&bytes[..len]).is_err());
+        }
+
+        let layout = RecordLayout::new(100, 32, 256).unwrap();
+        assert_eq!(decode_layout(&encode_layout(&layout)), Ok(layout));
+        assert_eq!(decode_layout(&[0; 32]), Err(WireError::InvalidShape));
+    }
+
+    #[test]
+    fn test_params_versions() {
+        let params = SimplePIRParams::from_seed([7; 32], 3329, 2, 16, 100, 6.4);
+        let bytes = params.to_bytes();
+        // Only the seed of A is sent
+        assert_eq!(bytes.len(), 2 + 32 + 5 * 8 + 9);
+        assert_eq!(SimplePIRParams::from_bytes(&bytes), Ok(params));
+
+        let mut bytes = bytes;
+        bytes[0] = PARAMS_VERSION + 1;
+        assert_eq!(decode_params(&bytes), Err(WireError::UnsupportedVersion(PARAMS_VERSION + 1)));
+        bytes[0] = PARAMS_VERSION;
+        bytes[1] = 2;
+        assert_eq!(decode_params(&bytes), Err(WireError::InvalidShape));
+
+        // A seed cannot ask for a larger A than the decoder expands
+        bytes[1] = A_SEED;
+        let m = (MAX_SEEDED_LEN / 16) as u64;
+        bytes[58..66].copy_from_slice(&(m + 1).to_le_bytes());
+        assert_eq!(decode_params(&bytes), Err(WireError::InvalidShape));
+        bytes[58..66].copy_from_slice(&0u64.to_le_bytes());
+        assert_eq!(decode_params(&bytes), Err(WireError::InvalidShape));
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let valid = SimplePIRParams {
+            seed: None,
+            ..SimplePIRParams::from_seed([7; 32], 3329, 2, 4, 3, 6.4)
+        };
+        assert_eq!(decode_params(&valid.to_bytes()), Ok(valid.clone()));
+
+        // A must be sent unless its seed is
+        let params = SimplePIRParams { a: Matrix::new(), ..valid.clone() };
+        assert_eq!(decode_params(&params.to_bytes()), Err(WireError::InvalidShape));
+
+        for (n, m) in [(0, 3), (4, 0), (3, 4)] {
+            let params = SimplePIRParams { n, m, ..valid.clone() };
+            assert_eq!(decode_params(&params.to_bytes()), Err(WireError::InvalidShape));
+        }
+
+        for std_dev in [0.0, -6.4, f64::NAN, f64::INFINITY] {
+            let params = SimplePIRParams { std_dev, ..valid.clone() };
+            assert_eq!(decode_params(&params.to_bytes()), Err(WireError::InvalidShape));
+            let secret_dist = SecretDistribution::Gaussian(std_dev);
+            let params = SimplePIRParams { secret_dist, ..valid.clone() };
+            assert_eq!(decode_params(&params.to_bytes()), Err(WireError::InvalidShape));
+        }
+
+        let params = SimplePIRParams { p: 3330, ..valid };
+        assert_eq!(decode_params(&params.to_bytes()), Err(WireError::InvalidModulus(3330)));
+    }
+
+    #[test]
+    fn test_partial_answer() {
+        use crate::simplepir::{self, AnswerProgress};
+        use std::time::Duration;
+
+        let params = simplepir::gen_insecure_test_params();
+        let db = Matrix::gen_uniform_rand(params.p, params.m, params.m);
+        let s = crate::regev::gen_secret(params.q, params.n);
+        let (query, _) = simplepir::query(&params, 2, s).unwrap();
+        let partial = match simplepir::answer_within(&query, &db, Duration::ZERO, None).unwrap() {
+            AnswerProgress::Partial(partial) => partial,
+            AnswerProgress::Done(_) => panic!("the answer should not complete"),
+        };
+        let bytes = encode_partial_answer(&partial);
+        assert_eq!(bytes.len(), 48 + 24 + bits_per_coeff(params.q).div_ceil(8));
+        let decoded = decode_partial_answer(&bytes).unwrap();
+        assert_eq!(decoded, partial);
+
+        // The decoded state resumes where it stopped
+        let resumed = simplepir::answer_within(&query, &db, Duration::from_secs(60), Some(decoded));
+        let expected = simplepir::answer(&query, &db).unwrap();
+        assert_eq!(resumed, Ok(AnswerProgress::Done(expected)));
+
+        for len in 0..bytes.len() {
+            assert_eq!(decode_partial_answer(&bytes[..len]), Err(WireError::Truncated));
+        }
+        // More entries than the database has columns
+        let mut bytes = bytes;
+        bytes[32..40].copy_from_slice(&0u64.to_le_bytes());
+        assert_eq!(decode_partial_answer(&bytes), Err(WireError::InvalidShape));
+    }
+
+    #[test]
+    fn test_matrix_reader() {
+        use std::io::Read;
+
+        let read_all = |mut reader: MatrixReader| {
+            let mut out = vec![];
+            let mut buf = [0; 1000];
+            loop {
+                let len = reader.read(&mut buf).unwrap();
+                if len == 0 {
+                    break;
+                }
+                out.extend_from_slice(&buf[..len]);
+                // No more than one chunk is ever packed at once
+                assert!(reader.buf.capacity() <= 8 * STREAM_CHUNK_ENTRIES);
+            }
+            out
+        };
+        for q in [2, 3329, (1 << 40) - 87, u64::MAX - 58] {
+            for (cols, rows) in [(3, 5), (1, 8), (STREAM_CHUNK_ENTRIES / 4 + 3, 5)] {
+                let m = Matrix::gen_uniform_rand(q, cols, rows);
+                assert_eq!(read_all(MatrixReader::new(&m)), encode_matrix(&m));
+            }
+        }
+        assert_eq!(read_all(MatrixReader::new(&Matrix::new())), encode_matrix(&Matrix::new()));
+
+        // The public hint streams the same bytes as it encodes to
+        let params = crate::simplepir::gen_insecure_test_params_for_size(100, 256);
+        let items: Vec<Element> = (0..100).map(|i| Element::from(256, i)).collect();
+        let public = crate::pir::Server::setup(params, &items).unwrap().public_hint();
+        let layout = public.layout.as_ref();
+        let (len, mut reader) = public_hint_reader(&public.params, &public.hint, layout, None);
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, public.to_bytes());
+        assert_eq!(len, bytes.len());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let query = Query(Matrix::gen_uniform_rand(3329, 1, 10).col(0));
+        let bytes = encode_query(&query);
+
+        for len in 0..bytes.len() {
+            assert_eq!(decode_query(&bytes[..len]), Err(WireError::Truncated));
+        }
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(decode_query(&long), Err(WireError::TrailingBytes));
+
+        let mut bad_q = bytes.clone();
+        bad_q[..8].copy_from_slice(&1u64.to_le_bytes());
+        assert_eq!(decode_query(&bad_q), Err(WireError::InvalidModulus(1)));
+
+        // A length which would need far more data than is present
+        let mut huge = bytes.clone();
+        huge[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(decode_query(&huge), Err(WireError::Truncated));
+
+        // 12 bits of all ones is 4095, which is not below 3329
+        let mut out_of_range = bytes;
+        out_of_range[16] = 0xff;
+        out_of_range[17] |= 0x0f;
+        assert_eq!(decode_query(&out_of_range), Err(WireError::OutOfRange(4095)));
+    }
+
+    #[test]
+    fn test_fuzz() {
+        let mut rng = ChaCha20Rng::from_seed([5u8; 32]);
+        let answer = encode_answer(&Answer(Matrix::gen_uniform_rand(3329, 6, 1)));
+        let params = SimplePIRParams {
+            seed: None,
+            ..SimplePIRParams::from_seed([7; 32], 3329, 2, 4, 3, 6.4)
+        }.to_bytes();
+
+        for _ in 0..10000 {
+            // Random bytes, and valid encodings with random bytes flipped or cut off, must never
+            // make the decoders panic
+            let mut bytes = if rng.gen() {
+                (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect()
+            } else {
+                let mut bytes = if rng.gen() { answer.clone() } else { params.clone() };
+                for _ in 0..rng.gen_range(1..4) {
+                    let i = rng.gen_range(0..bytes.len());
+                    bytes[i] ^= 1 << rng.gen_range(0..8);
+                }
+                bytes
+            };
+            bytes.truncate(rng.gen_range(0..=bytes.len()));
+
+            let _ = decode_query(&bytes);
+            let _ = decode_answer(&bytes);
+            let _ = decode_tagged_answer(&bytes);
+            let _ = decode_hint(&bytes);
+            let _ = decode_layout(&bytes);
+
+            // Nor may params which decode make queries panic
+            if let Ok(params) = decode_params(&bytes) {
+                let s = params.secret_dist.sample(params.q, params.n, &mut rng).into();
+                let _ = crate::simplepir::query_with_rng(&params, 0, s, &mut rng);
+            }
+        }
+    }
+}